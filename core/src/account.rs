@@ -0,0 +1,271 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Accounts as authenticator apps show them: the `otpauth://` URI of the
+//! account along with what the user adds to it, and an [`AccountQuery`]
+//! to search them.
+//!
+//! ```
+//! use yotp_core::account::{Account, AccountQuery, SortBy};
+//! use yotp_core::uri::OtpAuthUri;
+//!
+//! let mut account = Account::new(OtpAuthUri::parse("otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP").unwrap());
+//! assert_eq!(account.display_label(), "GitHub: alice");
+//! account.label = Some("Work GitHub".into());
+//! account.add_tag("work");
+//! assert!(account.has_tag("Work"));
+//! assert_eq!(account.display_label(), "Work GitHub");
+//!
+//! let accounts = [account];
+//! let page = AccountQuery::new().tag("work").label_contains("git").sort_by(SortBy::Label).page(0, 20).run(&accounts);
+//! assert_eq!((page.total, page.accounts[0].display_label()), (1, "Work GitHub".to_string()));
+//! ```
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::otp::Algorithm;
+use crate::uri::OtpAuthUri;
+
+/// An account and its metadata. The issuer and the account name are those
+/// of [`Account::uri`], the metadata is for the user only and never sent
+/// to the provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    /// The secret and the parameters of the account.
+    pub uri: OtpAuthUri,
+    /// The name to show instead of the issuer and the account name.
+    pub label: Option<String>,
+    /// Free-form tags, e.g. `work`, in the order they were added.
+    pub tags: Vec<String>,
+    /// Free-form notes, e.g. where the recovery codes are.
+    pub notes: Option<String>,
+    /// An identifier of the icon to show, e.g. a slug of Simple Icons
+    /// such as `github`. The meaning is up to the UI.
+    pub icon: Option<String>,
+    /// When a code of the account was last shown or used, in seconds
+    /// since the UNIX epoch. Set by the caller, see [`SortBy::LastUsed`].
+    pub last_used: Option<u64>,
+}
+
+impl Account {
+    /// The account of `uri` without metadata.
+    pub fn new(uri: OtpAuthUri) -> Account {
+        Account { uri, label: None, tags: Vec::new(), notes: None, icon: None, last_used: None }
+    }
+
+    /// The issuer of the account, if any.
+    pub fn issuer(&self) -> Option<&str> {
+        self.uri.issuer.as_deref()
+    }
+
+    /// The name to show for the account: the label, or else the issuer and
+    /// the account name as in the label of the URI.
+    pub fn display_label(&self) -> String {
+        match (&self.label, &self.uri.issuer) {
+            (Some(label), _) => label.clone(),
+            (None, Some(issuer)) => format!("{}: {}", issuer, self.uri.account),
+            (None, None) => self.uri.account.clone(),
+        }
+    }
+
+    /// Whether the account has `tag`, ignoring case.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase())
+    }
+
+    /// Add `tag` unless the account has it already. Returns whether it was
+    /// added.
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        if self.has_tag(tag) {
+            return false;
+        }
+        self.tags.push(tag.into());
+        true
+    }
+
+    /// Remove `tag`, ignoring case. Returns whether the account had it.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let len = self.tags.len();
+        self.tags.retain(|t| t.to_lowercase() != tag.to_lowercase());
+        self.tags.len() != len
+    }
+}
+
+impl From<OtpAuthUri> for Account {
+    fn from(uri: OtpAuthUri) -> Account {
+        Account::new(uri)
+    }
+}
+
+/// The order of the accounts found by an [`AccountQuery`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SortBy {
+    /// The order of the accounts as given.
+    #[default]
+    Position,
+    /// The display labels, ignoring case.
+    Label,
+    /// The most recently used first, then the accounts never used.
+    LastUsed,
+}
+
+/// A search of accounts: the accounts matching all of the filters, in
+/// the order of [`SortBy`], a page at a time.
+///
+/// Text filters ignore case.
+#[derive(Debug, Clone, Default)]
+pub struct AccountQuery {
+    issuer: Option<String>,
+    tags: Vec<String>,
+    algorithm: Option<Algorithm>,
+    label: Option<String>,
+    sort: SortBy,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+/// A page of the accounts found by [`AccountQuery::run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPage<'a> {
+    /// The number of matching accounts over all pages.
+    pub total: usize,
+    pub accounts: Vec<&'a Account>,
+}
+
+impl AccountQuery {
+    /// A query of all the accounts.
+    pub fn new() -> AccountQuery {
+        AccountQuery::default()
+    }
+
+    /// Keep the accounts of `issuer`.
+    pub fn issuer(mut self, issuer: &str) -> Self {
+        self.issuer = Some(issuer.to_lowercase());
+        self
+    }
+
+    /// Keep the accounts with `tag`. Given more than once, keep the
+    /// accounts with all of the tags.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Keep the accounts whose codes use `algorithm`.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    /// Keep the accounts whose display label or account name contains
+    /// `text`.
+    pub fn label_contains(mut self, text: &str) -> Self {
+        self.label = Some(text.to_lowercase());
+        self
+    }
+
+    /// Set the order of the accounts.
+    pub fn sort_by(mut self, sort: SortBy) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Return at most `limit` accounts, after skipping the first `offset`.
+    pub fn page(mut self, offset: usize, limit: usize) -> Self {
+        self.offset = offset;
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Whether `account` passes the filters.
+    pub fn matches(&self, account: &Account) -> bool {
+        let issuer = self.issuer.as_ref().is_none_or(|issuer| account.issuer().is_some_and(|i| i.to_lowercase() == *issuer));
+        let label = self.label.as_ref().is_none_or(|text| {
+            account.display_label().to_lowercase().contains(text.as_str()) || account.uri.account.to_lowercase().contains(text.as_str())
+        });
+        issuer
+            && label
+            && self.tags.iter().all(|tag| account.has_tag(tag))
+            && self.algorithm.is_none_or(|algorithm| account.uri.algorithm == algorithm)
+    }
+
+    /// The page of `accounts` the query asks for.
+    pub fn run<'a>(&self, accounts: &'a [Account]) -> QueryPage<'a> {
+        let mut found: Vec<&Account> = accounts.iter().filter(|account| self.matches(account)).collect();
+        match self.sort {
+            SortBy::Position => {}
+            SortBy::Label => found.sort_by_cached_key(|account| account.display_label().to_lowercase()),
+            SortBy::LastUsed => found.sort_by_key(|account| core::cmp::Reverse(account.last_used)),
+        }
+        let total = found.len();
+        let accounts = found.into_iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect();
+        QueryPage { total, accounts }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::otp::Algorithm;
+    use crate::uri::OtpAuthUri;
+
+    use super::{Account, AccountQuery, SortBy};
+
+    #[test]
+    fn test_metadata() {
+        let mut account: Account = OtpAuthUri::parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").unwrap().into();
+        assert_eq!((account.issuer(), account.display_label().as_str()), (None, "alice"));
+        assert!(account.add_tag("Work"));
+        assert!(!account.add_tag("WORK"));
+        assert!(account.add_tag("Zoë"));
+        assert!(account.has_tag("zoË"));
+        assert_eq!(account.tags, ["Work", "Zoë"]);
+        assert!(account.remove_tag("work"));
+        assert!(!account.remove_tag("work"));
+        assert_eq!(account.tags, ["Zoë"]);
+    }
+
+    #[test]
+    fn test_query() {
+        let account = |uri: &str, tags: &[&str], last_used| {
+            let mut account = Account::new(OtpAuthUri::parse(uri).unwrap());
+            account.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            account.last_used = last_used;
+            account
+        };
+        let accounts = [
+            account("otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP", &["work"], Some(100)),
+            account("otpauth://totp/bank:alice?secret=GEZDGNBVGY3TQOJQ&algorithm=SHA256", &["money"], None),
+            account("otpauth://totp/GitHub:bob?secret=MFRGGZDFMZTWQ2LK", &["Work", "admin"], Some(300)),
+            account("otpauth://hotp/Acme:carol?secret=KRUGS4ZANFZSAYJA&counter=1", &[], Some(200)),
+        ];
+        let labels = |query: AccountQuery| {
+            let page = query.run(&accounts);
+            (page.total, page.accounts.iter().map(|account| account.display_label()).collect::<Vec<_>>())
+        };
+        assert_eq!(labels(AccountQuery::new().issuer("github")), (2, vec!["GitHub: alice".to_string(), "GitHub: bob".to_string()]));
+        assert_eq!(labels(AccountQuery::new().tag("work").tag("ADMIN")).1, ["GitHub: bob"]);
+        assert_eq!(labels(AccountQuery::new().algorithm(Algorithm::Sha256)).1, ["bank: alice"]);
+        assert_eq!(labels(AccountQuery::new().label_contains("ALI")).0, 2);
+        assert_eq!(labels(AccountQuery::new().issuer("GitHub").label_contains("carol")).0, 0);
+        assert_eq!(labels(AccountQuery::new().sort_by(SortBy::Label)).1, ["Acme: carol", "bank: alice", "GitHub: alice", "GitHub: bob"]);
+        assert_eq!(labels(AccountQuery::new().sort_by(SortBy::LastUsed)).1, ["GitHub: bob", "Acme: carol", "GitHub: alice", "bank: alice"]);
+        assert_eq!(labels(AccountQuery::new().sort_by(SortBy::Label).page(1, 2)), (4, vec!["bank: alice".to_string(), "GitHub: alice".to_string()]));
+        assert_eq!(labels(AccountQuery::new().page(8, 2)), (4, vec![]));
+    }
+}
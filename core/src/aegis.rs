@@ -0,0 +1,404 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The JSON backups of Aegis Authenticator.
+//!
+//! An encrypted backup carries the vault encrypted with AES-256-GCM under
+//! a random master key, and the master key encrypted once per password
+//! slot under a key derived from the password with scrypt. Biometric
+//! slots are not supported.
+//!
+//! ```
+//! use yotp_core::aegis;
+//!
+//! let accounts = aegis::import(r#"{"version": 1, "header": {"slots": null, "params": null}, "db": {
+//!     "version": 2,
+//!     "entries": [{"type": "totp", "name": "alice", "issuer": "Example",
+//!                  "info": {"secret": "JBSWY3DPEHPK3PXP", "algo": "SHA1", "digits": 6, "period": 30}}]
+//! }}"#, None).unwrap();
+//! assert_eq!(accounts[0].issuer.as_deref(), Some("Example"));
+//! ```
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zeroize::Zeroizing;
+
+use crate::base64;
+use crate::error::OtpError;
+use crate::hex;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{OtpAuthUri, OtpType};
+
+/// The scrypt cost of exported backups, the one Aegis uses.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// The largest scrypt cost of imported backups, 1 GiB of memory at most,
+/// as the slots are read before any of them is authenticated.
+const MAX_SCRYPT_LOG_N: u8 = 20;
+const MAX_SCRYPT_R: u32 = 8;
+const MAX_SCRYPT_P: u32 = 16;
+
+#[derive(Serialize, Deserialize)]
+struct Backup {
+    version: u32,
+    header: Header,
+    /// The vault, or the Base 64 cipher text of its JSON if encrypted.
+    db: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    slots: Option<Vec<Slot>>,
+    params: Option<KeyParams>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Slot {
+    #[serde(rename = "type")]
+    slot_type: u32,
+    #[serde(default)]
+    uuid: String,
+    /// The encrypted master key in hex.
+    key: String,
+    key_params: KeyParams,
+    #[serde(default)]
+    n: u64,
+    #[serde(default)]
+    r: u32,
+    #[serde(default)]
+    p: u32,
+    #[serde(default)]
+    salt: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyParams {
+    nonce: String,
+    tag: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Vault {
+    version: u32,
+    entries: Vec<Entry>,
+    #[serde(default)]
+    groups: Vec<Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    #[serde(rename = "type")]
+    entry_type: String,
+    #[serde(default)]
+    uuid: String,
+    name: String,
+    #[serde(default)]
+    issuer: String,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    favorite: bool,
+    info: Info,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Info {
+    secret: String,
+    algo: String,
+    digits: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    period: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    counter: Option<u64>,
+}
+
+/// Read the HOTP and TOTP accounts of an Aegis backup. `password` is
+/// required if the backup is encrypted.
+///
+/// Returns [`OtpError::ImportError`] if the backup is malformed, no
+/// password slot opens with `password`, or it has entries of other types
+/// such as Steam, which are not skipped silently.
+pub fn import(json: &str, password: Option<&str>) -> Result<Vec<OtpAuthUri>, OtpError> {
+    let backup: Backup = serde_json::from_str(json).map_err(|e| import_error(&e.to_string()))?;
+    if backup.version != 1 {
+        return Err(import_error("unsupported backup version"));
+    }
+    let vault: Vault = match (&backup.header.slots, &backup.db) {
+        (None, db) => Vault::deserialize(db).map_err(|e| import_error(&e.to_string()))?,
+        (Some(slots), Value::String(db)) => {
+            let password = password.ok_or_else(|| import_error("backup is encrypted"))?;
+            let params = backup.header.params.as_ref().ok_or_else(|| import_error("encrypted backup has no params"))?;
+            let master_key = open_slots(slots, password)?;
+            let db = base64::decode(db).ok_or_else(|| import_error("vault is not valid Base 64"))?;
+            let vault = Zeroizing::new(decrypt(&master_key, params, &db).ok_or_else(|| import_error("vault decryption failed"))?);
+            serde_json::from_slice(&vault).map_err(|e| import_error(&e.to_string()))?
+        }
+        _ => return Err(import_error("vault should be a string in encrypted backups"))
+    };
+    vault.entries.iter().map(import_entry).collect()
+}
+
+/// Write `accounts` as an unencrypted Aegis backup.
+pub fn export(accounts: &[OtpAuthUri]) -> Result<String, OtpError> {
+    let backup = Backup {
+        version: 1,
+        header: Header { slots: None, params: None },
+        db: serde_json::to_value(vault(accounts)?).expect("vault is valid JSON"),
+    };
+    Ok(serde_json::to_string_pretty(&backup).expect("backup is valid JSON"))
+}
+
+/// Write `accounts` as an Aegis backup encrypted with `password`.
+///
+/// Returns [`OtpError::RandomError`] if the random source fails.
+pub fn export_encrypted(accounts: &[OtpAuthUri], password: &str) -> Result<String, OtpError> {
+    let master_key = Zeroizing::new(random::<32>()?);
+    let salt = random::<32>()?;
+    let key = derive_key(password, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+    let (key_params, encrypted_key) = encrypt(&key[..], &master_key[..])?;
+    let slot = Slot {
+        slot_type: 1,
+        uuid: uuid()?,
+        key: hex::encode(&encrypted_key),
+        key_params,
+        n: 1 << SCRYPT_LOG_N,
+        r: SCRYPT_R,
+        p: SCRYPT_P,
+        salt: hex::encode(&salt),
+    };
+    let vault = Zeroizing::new(serde_json::to_vec(&vault(accounts)?).expect("vault is valid JSON"));
+    let (params, db) = encrypt(&master_key[..], &vault)?;
+    let backup = Backup {
+        version: 1,
+        header: Header { slots: Some(vec![slot]), params: Some(params) },
+        db: Value::String(base64::encode(&db)),
+    };
+    Ok(serde_json::to_string_pretty(&backup).expect("backup is valid JSON"))
+}
+
+fn import_entry(entry: &Entry) -> Result<OtpAuthUri, OtpError> {
+    let otp_type = match entry.entry_type.as_str() {
+        "totp" => OtpType::Totp,
+        "hotp" => OtpType::Hotp,
+        other => return Err(import_error(&format!("unsupported entry type {}", other)))
+    };
+    let info = &entry.info;
+    Ok(OtpAuthUri {
+        otp_type,
+        account: entry.name.clone(),
+        issuer: Some(entry.issuer.clone()).filter(|issuer| !issuer.is_empty()),
+        secret: Secret::from_base32(&info.secret).map_err(|_| import_error("secret is not valid Base 32"))?,
+        algorithm: Algorithm::from_name(&info.algo).ok_or_else(|| import_error("unsupported algorithm"))?,
+        digits: info.digits,
+        period: if otp_type == OtpType::Totp { info.period.unwrap_or(30) } else { 30 },
+        counter: if otp_type == OtpType::Hotp { info.counter.unwrap_or(0) } else { 0 },
+    })
+}
+
+fn vault(accounts: &[OtpAuthUri]) -> Result<Vault, OtpError> {
+    let entries = accounts
+        .iter()
+        .map(|account| {
+            Ok(Entry {
+                entry_type: match account.otp_type {
+                    OtpType::Totp => "totp".into(),
+                    OtpType::Hotp => "hotp".into(),
+                },
+                uuid: uuid()?,
+                name: account.account.clone(),
+                issuer: account.issuer.clone().unwrap_or_default(),
+                note: String::new(),
+                favorite: false,
+                info: Info {
+                    secret: account.secret.to_base32(),
+                    algo: account.algorithm.name().into(),
+                    digits: account.digits,
+                    period: Some(account.period).filter(|_| account.otp_type == OtpType::Totp),
+                    counter: Some(account.counter).filter(|_| account.otp_type == OtpType::Hotp),
+                },
+            })
+        })
+        .collect::<Result<_, OtpError>>()?;
+    Ok(Vault { version: 2, entries, groups: Vec::new() })
+}
+
+/// The master key from the first password slot `password` opens.
+fn open_slots(slots: &[Slot], password: &str) -> Result<Zeroizing<Vec<u8>>, OtpError> {
+    for slot in slots.iter().filter(|slot| slot.slot_type == 1) {
+        if !slot.n.is_power_of_two() {
+            return Err(import_error("invalid scrypt parameters"));
+        }
+        if slot.n.trailing_zeros() > MAX_SCRYPT_LOG_N as u32 || slot.r > MAX_SCRYPT_R || slot.p > MAX_SCRYPT_P {
+            return Err(import_error("scrypt parameters are too costly"));
+        }
+        let salt = hex::decode(&slot.salt).map_err(|_| import_error("invalid salt"))?;
+        let key = derive_key(password, &salt, slot.n.trailing_zeros() as u8, slot.r, slot.p)?;
+        let encrypted_key = hex::decode(&slot.key).map_err(|_| import_error("invalid slot key"))?;
+        if let Some(master_key) = decrypt(&key[..], &slot.key_params, &encrypted_key) {
+            return Ok(Zeroizing::new(master_key));
+        }
+    }
+    Err(import_error("no password slot opens with the password"))
+}
+
+fn derive_key(password: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<Zeroizing<[u8; 32]>, OtpError> {
+    let params = scrypt::Params::new(log_n, r, p, 32).map_err(|_| import_error("invalid scrypt parameters"))?;
+    let mut key = Zeroizing::new([0; 32]);
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut key[..]).map_err(|_| import_error("invalid scrypt parameters"))?;
+    Ok(key)
+}
+
+/// Decrypt `data` whose tag is kept apart in `params`, as Aegis does.
+fn decrypt(key: &[u8], params: &KeyParams, data: &[u8]) -> Option<Vec<u8>> {
+    let nonce = hex::decode(&params.nonce).ok().filter(|nonce| nonce.len() == 12)?;
+    let tag = hex::decode(&params.tag).ok()?;
+    let cipher = Aes256Gcm::new_from_slice(key).ok()?;
+    let msg = [data, &tag].concat();
+    cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: &msg, aad: &[] }).ok()
+}
+
+fn encrypt(key: &[u8], data: &[u8]) -> Result<(KeyParams, Vec<u8>), OtpError> {
+    let nonce = random::<12>()?;
+    let cipher = Aes256Gcm::new_from_slice(key).expect("key has 32 bytes");
+    let mut encrypted = cipher.encrypt(Nonce::from_slice(&nonce), data).expect("plain text is not too long");
+    let tag = encrypted.split_off(encrypted.len() - 16);
+    Ok((KeyParams { nonce: hex::encode(&nonce), tag: hex::encode(&tag) }, encrypted))
+}
+
+fn random<const N: usize>() -> Result<[u8; N], OtpError> {
+    let mut bytes = [0; N];
+    getrandom::fill(&mut bytes).map_err(|_| OtpError::RandomError)?;
+    Ok(bytes)
+}
+
+/// A random UUID of version 4.
+fn uuid() -> Result<String, OtpError> {
+    let mut bytes = random::<16>()?;
+    bytes[6] = bytes[6] & 0x0F | 0x40;
+    bytes[8] = bytes[8] & 0x3F | 0x80;
+    let hex = hex::encode(&bytes);
+    Ok(format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+}
+
+fn import_error(msg: &str) -> OtpError {
+    OtpError::ImportError(format!("Aegis: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::secret::Secret;
+    use crate::uri::{OtpAuthUri, OtpType};
+
+    use super::{export, export_encrypted, import, uuid};
+
+    // encrypted with the password "test" and a low scrypt cost
+    const ENCRYPTED: &str = r#"{
+      "version": 1,
+      "header": {
+        "slots": [{
+          "type": 1,
+          "uuid": "a1234567-89ab-cdef-0123-456789abcdef",
+          "key": "2d79642760c8782ef9d8efb0b5e1ae3468f5fdda83bd92aa6e0fe715a54e9c29",
+          "key_params": {"nonce": "000102030405060708090a0b", "tag": "b6bc456083c076460fc57363f11500ca"},
+          "n": 1024, "r": 8, "p": 1,
+          "salt": "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+          "repaired": true, "is_backup": false
+        }],
+        "params": {"nonce": "0c0d0e0f1011121314151617", "tag": "6ee5f40d9780193400684936bc97d0f7"}
+      },
+      "db": "DB"
+    }"#;
+
+    const DB: &str = concat!(
+        "733jEPqkjEB+q+9eymIuAyIGLe/0C3xWP6f3roa/E97PyM3MnogZbamKcmlcR+slLfFyM8jXL/in8XGsWGAPM4KYcX57qlKX",
+        "MJ+lYzhZU8jtz6zEwN3FZ0hAdHGWIm0ZZ6ty3Zz151I9gDAeUIQNm83rTvgCV+WxbSnBVcDO2bhO8MM3J4geMERcOVh8pZ1/",
+        "9HNs51PFq4pOX7+Czqr21FzIU6Sr6c54oJdsUNlK2/7tqWqLgWlR6n2C/LZO4p/IONa71lsWMy4GuIgqL1vC7kDhM8Rq2BIa",
+        "heL14/RT3bffKlkfWH1YPpBxdIQbxRuM1PkR1KNDDCYBOKLX0huO8MC9vym80Sg1xxnQfkexGahG+mDmd9FPg9BEeGZDZT4H",
+        "wrgHdowvDlNkENekHjmjRNsJkQvlvGG5uWuNuVS61q3EN7CJCOO1jLOTSKWSSPmb/jpthbN8he+LQp9pDlGKM/qLEs3z9XD3",
+        "rp9pH2yKwztTNQ85g7Kl3dy8krgtoqzTUPS6E6xLEdAEX6GHqm18W6HGVy2+aom+dPMEnTkZSfrUvFWVsJ5PHKGW566JNZwX",
+        "T2jamfee8LnZiLTDkcxlxdq4uWgmRhzHKUfnJdLZy/YRSVnM++T6c1keu8vkzMZQ4iyyh8D8p5kKMrlqlBJWuLFeuTQuBT0v",
+        "8037wKr75on0LmIgiGO0OpxfNieDnA==",
+    );
+
+    fn accounts() -> Vec<OtpAuthUri> {
+        vec![
+            OtpAuthUri {
+                otp_type: OtpType::Totp,
+                account: "alice@example.com".into(),
+                issuer: Some("Example".into()),
+                secret: Secret::from_base32("JBSWY3DPEHPK3PXP").unwrap(),
+                algorithm: Algorithm::Sha256,
+                digits: 8,
+                period: 60,
+                counter: 0,
+            },
+            OtpAuthUri {
+                otp_type: OtpType::Hotp,
+                account: "bob".into(),
+                issuer: None,
+                secret: Secret::new(b"12345678901234567890".to_vec()),
+                algorithm: Algorithm::Sha1,
+                digits: 6,
+                period: 30,
+                counter: 5,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_import_encrypted() {
+        let json = ENCRYPTED.replace("DB", DB);
+        assert_eq!(import(&json, Some("test")).unwrap(), accounts());
+        assert!(matches!(import(&json, Some("wrong")), Err(OtpError::ImportError(_))));
+        assert!(matches!(import(&json, None), Err(OtpError::ImportError(_))));
+        for costly in [r#""n": 1099511627776, "r": 8, "p": 1"#, r#""n": 1024, "r": 1024, "p": 1"#, r#""n": 1024, "r": 8, "p": 1024"#] {
+            let json = json.replace(r#""n": 1024, "r": 8, "p": 1"#, costly);
+            assert_eq!(import(&json, Some("test")), Err(OtpError::ImportError("Aegis: scrypt parameters are too costly".into())));
+        }
+    }
+
+    #[test]
+    fn test_import_unsupported() {
+        let json = r#"{"version": 1, "header": {"slots": null, "params": null}, "db": {"version": 2, "entries": [
+            {"type": "steam", "name": "a", "info": {"secret": "JBSWY3DP", "algo": "SHA1", "digits": 5, "period": 30}}]}}"#;
+        assert!(matches!(import(json, None), Err(OtpError::ImportError(_))));
+        assert!(matches!(import("{}", None), Err(OtpError::ImportError(_))));
+    }
+
+    #[test]
+    fn test_export() {
+        let json = export(&accounts()).unwrap();
+        assert!(json.contains("\"secret\": \"JBSWY3DPEHPK3PXP\""));
+        assert_eq!(import(&json, None).unwrap(), accounts());
+
+        let json = export_encrypted(&accounts(), "hunter2").unwrap();
+        assert!(!json.contains("JBSWY3DPEHPK3PXP"));
+        assert_eq!(import(&json, Some("hunter2")).unwrap(), accounts());
+    }
+
+    #[test]
+    fn test_uuid() {
+        let uuid = uuid().unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+    }
+}
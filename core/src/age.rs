@@ -0,0 +1,257 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Vaults encrypted with [age](https://age-encryption.org), to reuse
+//! existing age identities, including the hardware backed ones of age
+//! plugins, for the accounts.
+//!
+//! An age vault is an age file, encrypted either to recipients such as
+//! x25519 public keys or with a passphrase. Its plain text is the payload
+//! of a [`Vault`](crate::vault::Vault), and is upgraded the same way when
+//! opened. Like the `age` tool, `age -d -i key.txt accounts.age` decrypts
+//! it.
+//!
+//! Identities and recipients are the traits of the `age` crate, so those
+//! of plugins work once the `plugin` feature of `age` is enabled.
+//!
+//! ```no_run
+//! use yotp_core::age::AgeVault;
+//! use yotp_core::uri::OtpAuthUri;
+//!
+//! let identity = age::x25519::Identity::generate();
+//! let mut vault = AgeVault::create("accounts.age", vec![Box::new(identity.to_public())]).unwrap();
+//! vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+//! vault.save().unwrap();
+//!
+//! let vault = AgeVault::open("accounts.age", &[&identity], vec![Box::new(identity.to_public())]).unwrap();
+//! assert_eq!(vault.accounts()[0].uri.account, "alice");
+//! ```
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ::age::secrecy::SecretString;
+use ::age::{scrypt, DecryptError, Decryptor, Encryptor, Identity, Recipient};
+use zeroize::Zeroizing;
+
+use crate::account::Account;
+use crate::vault::{decode_payload, encode_payload, write_atomic, VaultError};
+
+/// The recipients an age vault is encrypted to.
+pub type Recipients = Vec<Box<dyn Recipient + Send>>;
+
+/// An opened age vault. Changes to the accounts are kept in memory until
+/// [`AgeVault::save`].
+pub struct AgeVault {
+    path: PathBuf,
+    recipients: Recipients,
+    accounts: Vec<Account>,
+}
+
+impl AgeVault {
+    /// Create an empty vault at `path` encrypted to `recipients`.
+    ///
+    /// Returns [`VaultError::Io`] if `path` exists, and
+    /// [`VaultError::Backend`] if `recipients` is empty or mixes a
+    /// passphrase with other recipients.
+    pub fn create(path: impl AsRef<Path>, recipients: Recipients) -> Result<AgeVault, VaultError> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists", path.display())).into());
+        }
+        let vault = AgeVault { path: path.to_owned(), recipients, accounts: Vec::new() };
+        vault.save()?;
+        Ok(vault)
+    }
+
+    /// Create an empty vault at `path` encrypted with `passphrase`.
+    pub fn create_with_passphrase(path: impl AsRef<Path>, passphrase: &str) -> Result<AgeVault, VaultError> {
+        AgeVault::create(path, passphrase_recipients(passphrase))
+    }
+
+    /// Open the vault at `path` with one of `identities`. The vault is
+    /// encrypted to `recipients` when saved, as an age file does not tell
+    /// whom it is encrypted to. A vault of an older schema is upgraded and
+    /// saved.
+    ///
+    /// Returns [`VaultError::WrongPassphrase`] if none of `identities`
+    /// decrypts the vault, and [`VaultError::Tampered`] if the vault is
+    /// truncated or modified.
+    pub fn open(path: impl AsRef<Path>, identities: &[&dyn Identity], recipients: Recipients) -> Result<AgeVault, VaultError> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        let decryptor = Decryptor::new_buffered(&data[..]).map_err(decrypt_error)?;
+        let mut reader = decryptor.decrypt(identities.iter().copied()).map_err(decrypt_error)?;
+        let mut payload = Zeroizing::new(Vec::new());
+        reader.read_to_end(&mut payload).map_err(|_| VaultError::Tampered)?;
+        let (accounts, migrated) = decode_payload(&payload)?;
+        let vault = AgeVault { path: path.to_owned(), recipients, accounts };
+        if migrated {
+            vault.save()?;
+        }
+        Ok(vault)
+    }
+
+    /// Open the vault at `path` encrypted with `passphrase`, and keep
+    /// encrypting it with `passphrase`.
+    pub fn open_with_passphrase(path: impl AsRef<Path>, passphrase: &str) -> Result<AgeVault, VaultError> {
+        let identity = scrypt::Identity::new(SecretString::from(passphrase.to_owned()));
+        AgeVault::open(path, &[&identity], passphrase_recipients(passphrase))
+    }
+
+    /// The path of the vault file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    pub fn accounts_mut(&mut self) -> &mut Vec<Account> {
+        &mut self.accounts
+    }
+
+    /// Encrypt the vault to `recipients` from now on, and save it along
+    /// with any unsaved changes of the accounts. If the save fails, the
+    /// file and the vault keep the old recipients.
+    pub fn set_recipients(&mut self, recipients: Recipients) -> Result<(), VaultError> {
+        let old_recipients = core::mem::replace(&mut self.recipients, recipients);
+        if let Err(e) = self.save() {
+            self.recipients = old_recipients;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Encrypt the accounts and replace the vault file atomically.
+    pub fn save(&self) -> Result<(), VaultError> {
+        let payload = encode_payload(&self.accounts);
+        let recipients = self.recipients.iter().map(|recipient| recipient.as_ref() as &dyn Recipient);
+        let encryptor = Encryptor::with_recipients(recipients).map_err(|e| VaultError::Backend(format!("age: {}", e)))?;
+        let mut data = Vec::with_capacity(payload.len() + 256);
+        let mut writer = encryptor.wrap_output(&mut data)?;
+        writer.write_all(&payload)?;
+        writer.finish()?;
+        write_atomic(&self.path, &data)?;
+        Ok(())
+    }
+}
+
+fn passphrase_recipients(passphrase: &str) -> Recipients {
+    vec![Box::new(scrypt::Recipient::new(SecretString::from(passphrase.to_owned())))]
+}
+
+/// The error of reading the header of an age file. Failures of the
+/// payload surface as I/O errors of the stream instead.
+fn decrypt_error(e: DecryptError) -> VaultError {
+    match e {
+        // a stanza fails to decrypt with a wrong passphrase
+        DecryptError::NoMatchingKeys | DecryptError::KeyDecryptionFailed | DecryptError::DecryptionFailed => {
+            VaultError::WrongPassphrase
+        }
+        DecryptError::InvalidMac => VaultError::Tampered,
+        DecryptError::InvalidHeader | DecryptError::UnknownFormat => VaultError::InvalidFormat,
+        DecryptError::Io(e) => VaultError::Io(e),
+        e => VaultError::Backend(format!("age: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use ::age::secrecy::SecretString;
+    use ::age::{scrypt, x25519};
+
+    use crate::uri::OtpAuthUri;
+    use crate::vault::VaultError;
+
+    use super::{AgeVault, Recipients};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("yotp-age-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("accounts.age")
+    }
+
+    fn to(identity: &x25519::Identity) -> Recipients {
+        vec![Box::new(identity.to_public())]
+    }
+
+    #[test]
+    fn test_recipients() {
+        let path = temp_path("recipients");
+        let (alice, bob) = (x25519::Identity::generate(), x25519::Identity::generate());
+        let mut vault = AgeVault::create(&path, vec![Box::new(alice.to_public()), Box::new(bob.to_public())]).unwrap();
+        vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+        vault.save().unwrap();
+        assert!(std::fs::read(&path).unwrap().starts_with(b"age-encryption.org/v1\n"));
+
+        assert_eq!(AgeVault::open(&path, &[&bob], to(&bob)).unwrap().accounts(), vault.accounts());
+        let eve = x25519::Identity::generate();
+        assert!(matches!(AgeVault::open(&path, &[&eve], to(&eve)), Err(VaultError::WrongPassphrase)));
+
+        vault.set_recipients(to(&eve)).unwrap();
+        assert!(matches!(AgeVault::open(&path, &[&alice], to(&alice)), Err(VaultError::WrongPassphrase)));
+        assert_eq!(AgeVault::open(&path, &[&alice, &eve], to(&eve)).unwrap().accounts(), vault.accounts());
+        assert!(matches!(vault.set_recipients(Vec::new()), Err(VaultError::Backend(_))));
+        assert!(matches!(AgeVault::create(&path, to(&alice)), Err(VaultError::Io(_))));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_passphrase() {
+        let path = temp_path("passphrase");
+        // a low work factor to keep the test fast
+        let mut recipient = scrypt::Recipient::new(SecretString::from("passphrase".to_owned()));
+        recipient.set_work_factor(2);
+        let mut vault = AgeVault::create(&path, vec![Box::new(recipient)]).unwrap();
+        vault.accounts_mut().push(OtpAuthUri::parse("otpauth://hotp/bob?secret=GEZDGNBVGY3TQOJQ&counter=5").unwrap().into());
+        vault.save().unwrap();
+
+        assert_eq!(AgeVault::open_with_passphrase(&path, "passphrase").unwrap().accounts(), vault.accounts());
+        assert!(matches!(AgeVault::open_with_passphrase(&path, "wrong"), Err(VaultError::WrongPassphrase)));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let path = temp_path("invalid");
+        let identity = x25519::Identity::generate();
+        let mut vault = AgeVault::create(&path, to(&identity)).unwrap();
+        vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+        vault.save().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let open = |data: &[u8]| {
+            std::fs::write(&path, data).unwrap();
+            AgeVault::open(&path, &[&identity], to(&identity))
+        };
+
+        let mut flipped = data.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(matches!(open(&flipped), Err(VaultError::Tampered)));
+        assert!(matches!(open(&data[..data.len() - 1]), Err(VaultError::Tampered)));
+        assert!(matches!(open(b"not an age file"), Err(VaultError::InvalidFormat)));
+        // schema 1 payloads are upgraded
+        let old = ::age::encrypt(&identity.to_public(), br#"{"accounts":["otpauth://totp/bob?secret=JBSWY3DP"]}"#).unwrap();
+        assert_eq!(open(&old).unwrap().accounts()[0].uri.account, "bob");
+        assert_ne!(std::fs::read(&path).unwrap(), old);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
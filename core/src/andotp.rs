@@ -0,0 +1,193 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The JSON backups of andOTP.
+//!
+//! A plain backup is a JSON array of entries. An encrypted backup
+//! (`.json.aes`) is the PBKDF2 iteration count as a big endian 32-bit
+//! integer, a 12-byte salt, a 12-byte nonce and the AES-256-GCM cipher
+//! text, with the key derived from the password by PBKDF2-HMAC-SHA1.
+//! The older backups encrypted with the SHA-256 hash of the password are
+//! not supported.
+//!
+//! ```
+//! use yotp_core::andotp;
+//!
+//! let accounts = andotp::import(r#"[{"secret": "JBSWY3DPEHPK3PXP", "issuer": "Example",
+//!     "label": "alice", "digits": 6, "type": "TOTP", "algorithm": "SHA1", "period": 30}]"#).unwrap();
+//! assert_eq!(accounts[0].account, "alice");
+//! ```
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use serde::Deserialize;
+use sha1::Sha1;
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{OtpAuthUri, OtpType};
+
+const SALT_LEN: usize = 12;
+const NONCE_LEN: usize = 12;
+/// The largest PBKDF2 cost of imported backups, whose header is read
+/// before it is authenticated. andOTP uses 140,000 to 160,000.
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+#[derive(Deserialize)]
+struct Entry {
+    secret: String,
+    #[serde(default)]
+    issuer: String,
+    label: String,
+    digits: usize,
+    #[serde(rename = "type")]
+    entry_type: String,
+    algorithm: String,
+    #[serde(default)]
+    period: Option<u64>,
+    #[serde(default)]
+    counter: Option<u64>,
+}
+
+/// Read the HOTP and TOTP accounts of a plain andOTP backup.
+///
+/// Returns [`OtpError::ImportError`] if the backup is malformed or has
+/// entries of other types such as Steam, which are not skipped silently.
+pub fn import(json: &str) -> Result<Vec<OtpAuthUri>, OtpError> {
+    let entries: Vec<Entry> = serde_json::from_str(json).map_err(|e| import_error(&e.to_string()))?;
+    entries.iter().map(import_entry).collect()
+}
+
+/// Read the accounts of an andOTP backup encrypted with `password`.
+///
+/// Returns [`OtpError::ImportError`] if the backup cannot be decrypted
+/// with `password`, or for the reasons of [`import`].
+pub fn import_encrypted(data: &[u8], password: &str) -> Result<Vec<OtpAuthUri>, OtpError> {
+    if data.len() < 4 + SALT_LEN + NONCE_LEN {
+        return Err(import_error("encrypted backup is truncated"));
+    }
+    let (iterations, rest) = data.split_at(4);
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, cipher_text) = rest.split_at(NONCE_LEN);
+    let iterations = u32::from_be_bytes(iterations.try_into().expect("4 bytes"));
+    if iterations == 0 || iterations > MAX_ITERATIONS {
+        return Err(import_error("invalid PBKDF2 iteration count"));
+    }
+    let mut key = Zeroizing::new([0; 32]);
+    pbkdf2::pbkdf2_hmac::<Sha1>(password.as_bytes(), salt, iterations, &mut key[..]);
+    let cipher = Aes256Gcm::new_from_slice(&key[..]).expect("key has 32 bytes");
+    let json = Zeroizing::new(
+        cipher
+            .decrypt(Nonce::from_slice(nonce), cipher_text)
+            .map_err(|_| import_error("decryption failed, the password may be wrong"))?,
+    );
+    let json = core::str::from_utf8(&json).map_err(|_| import_error("backup is not valid UTF-8"))?;
+    import(json)
+}
+
+fn import_entry(entry: &Entry) -> Result<OtpAuthUri, OtpError> {
+    let otp_type = match entry.entry_type.as_str() {
+        "TOTP" => OtpType::Totp,
+        "HOTP" => OtpType::Hotp,
+        other => return Err(import_error(&format!("unsupported entry type {}", other)))
+    };
+    Ok(OtpAuthUri {
+        otp_type,
+        account: entry.label.clone(),
+        issuer: Some(entry.issuer.clone()).filter(|issuer| !issuer.is_empty()),
+        secret: Secret::from_base32(&entry.secret).map_err(|_| import_error("secret is not valid Base 32"))?,
+        algorithm: Algorithm::from_name(&entry.algorithm).ok_or_else(|| import_error("unsupported algorithm"))?,
+        digits: entry.digits,
+        period: if otp_type == OtpType::Totp { entry.period.unwrap_or(30) } else { 30 },
+        counter: if otp_type == OtpType::Hotp { entry.counter.unwrap_or(0) } else { 0 },
+    })
+}
+
+fn import_error(msg: &str) -> OtpError {
+    OtpError::ImportError(format!("andOTP: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::base64;
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::secret::Secret;
+    use crate::uri::{OtpAuthUri, OtpType};
+
+    use super::{import, import_encrypted};
+
+    // encrypted with the password "test" and 1000 PBKDF2 iterations
+    const ENCRYPTED: &str = concat!(
+        "AAAD6AABAgMEBQYHCAkKCwwNDg8QERITFBUWF09X5O53dm84LrvGl1Gsf+dkiNmXWxK6cCWD4vnH2McI+ZmqU6Wzif/ghRsb",
+        "DBdOpGh+kJ5n5gVBPMGq23zDVL0O6WUx/SFYoxzcnarsLUFBs836VfrZm/iMTv8rqWTlr3nSNjpyAAUpTiSS9Ba138w6ML6d",
+        "83zsrH7JmspuBO0eU8LioN5TlMNH617n+gfTdjd6zdU6RSPgroeIXCHiroTprwoGe9RRE1fS/BdSS1RShUpyOnTz4VCsLrFp",
+        "Hx+Le626lV3WQH58fYUdO7I8ILONjYwyPw7jh35S0fyM+hBgpSLOHnrKEasQej+TQAxRtjSOqOuBZCtfeAyLLssMcIInMWCt",
+        "9/AUnOIubDyjYx3i4q4VStAZltTmocY7zLxSRKkZD5XQuvnRyfDeHYO39vuH0mq8UVdSaBhBMe3XdR0qnvhbcDgGO80XV5ZP",
+        "2xEL9OsybdSoaobskonIK2t4T9f4prOjZR67L/azgq528/iDk346LMobfgEzONQukCmlJXvWsapk+A+jR8nW31m5oK6ym9IQ",
+        "1WOzXlMV",
+    );
+
+    fn accounts() -> Vec<OtpAuthUri> {
+        vec![
+            OtpAuthUri {
+                otp_type: OtpType::Totp,
+                account: "alice@example.com".into(),
+                issuer: Some("Example".into()),
+                secret: Secret::from_base32("JBSWY3DPEHPK3PXP").unwrap(),
+                algorithm: Algorithm::Sha256,
+                digits: 8,
+                period: 60,
+                counter: 0,
+            },
+            OtpAuthUri {
+                otp_type: OtpType::Hotp,
+                account: "bob".into(),
+                issuer: None,
+                secret: Secret::new(b"12345678901234567890".to_vec()),
+                algorithm: Algorithm::Sha1,
+                digits: 6,
+                period: 30,
+                counter: 5,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_import_encrypted() {
+        let data = base64::decode(ENCRYPTED).unwrap();
+        assert_eq!(import_encrypted(&data, "test").unwrap(), accounts());
+        assert!(matches!(import_encrypted(&data, "wrong"), Err(OtpError::ImportError(_))));
+        assert!(matches!(import_encrypted(&data[..20], "test"), Err(OtpError::ImportError(_))));
+        let mut costly = data.clone();
+        costly[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(import_encrypted(&costly, "test"), Err(OtpError::ImportError(msg)) if msg.contains("iteration")));
+    }
+
+    #[test]
+    fn test_import() {
+        let json = r#"[{"secret": "JBSWY3DPEHPK3PXP", "label": "a", "digits": 6, "type": "TOTP", "algorithm": "SHA512",
+            "thumbnail": "Default", "last_used": 1, "used_frequency": 0, "tags": ["work"]}]"#;
+        let accounts = import(json).unwrap();
+        assert_eq!((accounts[0].algorithm, accounts[0].period, accounts[0].issuer.as_deref()), (Algorithm::Sha512, 30, None));
+
+        let json = r#"[{"secret": "JBSWY3DPEHPK3PXP", "label": "a", "digits": 5, "type": "STEAM", "algorithm": "SHA1", "period": 30}]"#;
+        assert!(matches!(import(json), Err(OtpError::ImportError(_))));
+        assert!(matches!(import("{}"), Err(OtpError::ImportError(_))));
+    }
+}
@@ -14,122 +14,596 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+//! Base 32 encoding of RFC 4648, as used for OTP secrets.
+//!
+//! The decoders return plain `Vec<u8>` and pull in no buffer crate;
+//! [`decode_into`] writes into a caller-provided slice for users who
+//! would rather not allocate at all.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::error::OtpError;
+
+/// Marks the bytes out of the alphabet in a decoding table.
+const INVALID: u8 = 0xFF;
+
+/// The 32 characters a Base 32 codec maps the 5-bit values to.
+///
+/// Decoding accepts the lower case forms of the letters as well.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Alphabet {
+    symbols: [u8; 32],
+    /// Value of every input byte, or [`INVALID`].
+    table: [u8; 256],
+    /// Whether encoding pads the output to a multiple of 8 characters.
+    padding: bool,
+}
+
+impl Alphabet {
+    /// The standard alphabet of RFC 4648 §6, `A` to `Z` and `2` to `7`.
+    pub const STANDARD: Alphabet = Alphabet::from_symbols(*b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567", true);
+
+    /// The "Extended Hex" alphabet of RFC 4648 §7, `0` to `9` and `A` to
+    /// `V`, which keeps the sort order of the encoded data.
+    pub const HEX: Alphabet = Alphabet::from_symbols(*b"0123456789ABCDEFGHIJKLMNOPQRSTUV", true);
+
+    /// Douglas Crockford's alphabet, `0` to `9` and the letters except
+    /// `I`, `L`, `O` and `U`. It is meant to be read and typed by humans:
+    /// decoding maps `O` to `0` and `I` and `L` to `1`, and encoding does
+    /// not pad the output.
+    pub const CROCKFORD: Alphabet = Alphabet::from_symbols(*b"0123456789ABCDEFGHJKMNPQRSTVWXYZ", false)
+        .with_alias(b'O', b'0')
+        .with_alias(b'I', b'1')
+        .with_alias(b'L', b'1');
+
+    /// A custom alphabet of the 32 characters of `symbols`, ordered by
+    /// their values, e.g. the shuffled alphabet of a proprietary token
+    /// format. Encoding pads the output with `=` if `padding` is true.
+    ///
+    /// Returns [`Base32Error::InvalidAlphabet`] unless `symbols` has 32
+    /// distinct printable ASCII characters other than `=`.
+    pub fn new(symbols: &str, padding: bool) -> Result<Alphabet, Base32Error> {
+        let symbols: [u8; 32] = symbols.as_bytes().try_into().map_err(|_| Base32Error::InvalidAlphabet)?;
+        for (i, c) in symbols.iter().enumerate() {
+            if !c.is_ascii_graphic() || *c == b'=' || symbols[..i].contains(c) {
+                return Err(Base32Error::InvalidAlphabet);
+            }
+        }
+        Ok(Alphabet::from_symbols(symbols, padding))
+    }
+
+    const fn from_symbols(symbols: [u8; 32], padding: bool) -> Alphabet {
+        let mut table = [INVALID; 256];
+        let mut i = 0;
+        while i < symbols.len() {
+            table[symbols[i].to_ascii_lowercase() as usize] = i as u8;
+            i += 1;
+        }
+        // a lower case letter of the alphabet takes precedence over the
+        // lower case form of another letter
+        i = 0;
+        while i < symbols.len() {
+            table[symbols[i] as usize] = i as u8;
+            i += 1;
+        }
+        Alphabet { symbols, table, padding }
+    }
+
+    /// Decode the letter `alias`, in either case, as the symbol `to`.
+    const fn with_alias(mut self, alias: u8, to: u8) -> Alphabet {
+        let value = self.table[to as usize];
+        self.table[alias as usize] = value;
+        self.table[alias.to_ascii_lowercase() as usize] = value;
+        self
+    }
+
+    /// The characters of the alphabet, ordered by their values.
+    pub fn symbols(&self) -> &str {
+        core::str::from_utf8(&self.symbols).expect("alphabets are ASCII")
+    }
+}
+
+impl fmt::Debug for Alphabet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Alphabet")
+            .field("symbols", &self.symbols())
+            .field("padding", &self.padding)
+            .finish()
+    }
+}
+
+/// Errors of Base 32 decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Base32Error {
+    /// The character at byte offset `index` is out of the alphabet.
+    InvalidCharacter { index: usize, character: char },
+    /// The padding is misplaced or has a wrong length, or the string
+    /// stops at a length no encoding produces.
+    InvalidPadding,
+    /// The bits after the last whole byte are not zero.
+    TrailingBits,
+    /// The output buffer is shorter than the given number of bytes.
+    BufferTooSmall(usize),
+    /// A custom alphabet does not have 32 distinct characters.
+    InvalidAlphabet,
+}
+
+impl fmt::Display for Base32Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Base32Error::InvalidCharacter { index, character } => {
+                write!(f, "invalid Base 32 character {:?} at offset {}", character, index)
+            }
+            Base32Error::InvalidPadding => write!(f, "invalid Base 32 padding"),
+            Base32Error::TrailingBits => write!(f, "non-zero trailing bits in Base 32 string"),
+            Base32Error::BufferTooSmall(len) => write!(f, "output buffer should have at least {} bytes", len),
+            Base32Error::InvalidAlphabet => write!(f, "Base 32 alphabet should have 32 distinct ASCII characters"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Base32Error {}
+
+impl From<Base32Error> for OtpError {
+    fn from(_: Base32Error) -> OtpError {
+        OtpError::DecodeError
+    }
+}
+
+/// Implementation of RFC 4648 Base 32 encoding, padded with `=` to a
+/// multiple of 8 characters.
+pub fn encode(data: &[u8]) -> String {
+    encode_with(data, &Alphabet::STANDARD)
+}
+
+/// Base 32 encoding with the characters of `alphabet`.
+pub fn encode_with(data: &[u8], alphabet: &Alphabet) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    for chunk in data.chunks(5) {
+        let mut block = [0u8; 8];
+        block[3..3 + chunk.len()].copy_from_slice(chunk);
+        let bits = u64::from_be_bytes(block);
+        // the characters needed for the bits of the chunk
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..8 {
+            if i < chars {
+                let v = (bits >> (35 - 5 * i)) & 0x1F;
+                out.push(alphabet.symbols[v as usize] as char);
+            } else if alphabet.padding {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
 /// Implementation of RFC 4648 Base 32 decoding
-pub fn decode(value: &str) -> Option<Vec<u8>> {
-    let values = value
-                      .chars()
-                      .map( |x| x.to_ascii_uppercase());
-    let mut buf = Vec::new();
-    buf.reserve(5 * value.len() / 8);
-    let mut next = 0u8;
-    let mut i = 0u8;
-    for ele in values {
-        if ele == '=' {
-            break;
-        }
-        match decode_char(ele) {
-            None => {
-                return None;
+///
+/// Returns [`Base32Error::InvalidCharacter`] with the first character
+/// out of the Base 32 alphabet, if any.
+pub fn decode(value: &str) -> Result<Vec<u8>, Base32Error> {
+    decode_with(value, &Alphabet::STANDARD)
+}
+
+/// Base 32 decoding with the characters of `alphabet`. Other behaviors
+/// are the same as [`decode`].
+pub fn decode_with(value: &str, alphabet: &Alphabet) -> Result<Vec<u8>, Base32Error> {
+    decode_symbols(value, |c| alphabet.table[c as usize])
+}
+
+/// Base 32 decoding that only accepts the exact output of [`encode`], up
+/// to the case of the letters, for validating stored data:
+///
+/// * the padding is required and nothing may follow it,
+/// * the data length is one an encoding produces, and
+/// * the unused bits of the last character are zero.
+///
+/// Returns [`Base32Error::InvalidPadding`] or [`Base32Error::TrailingBits`]
+/// if the input breaks a rule above, besides the errors of [`decode`].
+pub fn decode_strict(value: &str) -> Result<Vec<u8>, Base32Error> {
+    decode_strict_with(value, &Alphabet::STANDARD)
+}
+
+/// [`decode_strict`] with the characters of `alphabet`. The padding is
+/// rejected instead of required if the alphabet does not pad.
+pub fn decode_strict_with(value: &str, alphabet: &Alphabet) -> Result<Vec<u8>, Base32Error> {
+    let data_len = value.bytes().position(|c| c == b'=').unwrap_or(value.len());
+    let (data, padding) = value.split_at(data_len);
+    if padding.bytes().any(|c| c != b'=') {
+        return Err(Base32Error::InvalidPadding);
+    }
+    let decoded = decode_with(data, alphabet)?;
+    let rest = data_len % 8;
+    if !matches!(rest, 0 | 2 | 4 | 5 | 7) {
+        return Err(Base32Error::InvalidPadding);
+    }
+    let padding_len = if alphabet.padding && rest != 0 { 8 - rest } else { 0 };
+    if padding.len() != padding_len {
+        return Err(Base32Error::InvalidPadding);
+    }
+    if rest != 0 {
+        let unused_bits = 5 * rest % 8;
+        let last = alphabet.table[data.as_bytes()[data_len - 1] as usize];
+        if last & ((1 << unused_bits) - 1) != 0 {
+            return Err(Base32Error::TrailingBits);
+        }
+    }
+    Ok(decoded)
+}
+
+/// Base 32 decoding that also ignores whitespaces and hyphens, for the
+/// secrets copied from setup pages that show them in groups like
+/// `abcd efgh ijkl`. Lower case letters and missing padding are accepted
+/// as by [`decode`], and the index of an invalid character refers to
+/// `value` itself.
+pub fn decode_lenient(value: &str) -> Result<Vec<u8>, Base32Error> {
+    decode_lenient_with(value, &Alphabet::STANDARD)
+}
+
+/// [`decode_lenient`] with the characters of `alphabet`.
+pub fn decode_lenient_with(value: &str, alphabet: &Alphabet) -> Result<Vec<u8>, Base32Error> {
+    let is_separator = |c: char| c.is_whitespace() || c == '-';
+    let filtered: String = value.chars().filter(|&c| !is_separator(c)).collect();
+    decode_with(&filtered, alphabet).map_err(|e| match e {
+        Base32Error::InvalidCharacter { index, character } => {
+            // find the offset in `value` of the character at `index` of
+            // the filtered string
+            let mut filtered_len = 0;
+            let index = value
+                .char_indices()
+                .filter(|&(_, c)| !is_separator(c))
+                .find(|&(_, c)| {
+                    filtered_len += c.len_utf8();
+                    filtered_len > index
+                })
+                .map_or(index, |(i, _)| i);
+            Base32Error::InvalidCharacter { index, character }
+        }
+        e => e
+    })
+}
+
+/// Base 32 decoding of secrets whose time does not depend on the
+/// characters of `value`, only on its length and the position of the
+/// padding. Every character is mapped with branch-free arithmetic
+/// instead of a table lookup, so the decoding does not leak the secret
+/// through branches or cache accesses.
+///
+/// It accepts the same input as [`decode`].
+pub fn decode_ct(value: &str) -> Result<Vec<u8>, Base32Error> {
+    decode_symbols(value, decode_symbol_ct)
+}
+
+/// Incremental Base 32 decoder for input that arrives in chunks, such as
+/// a large backup payload read piece by piece. It accepts the same input
+/// as [`decode_with`], split anywhere.
+///
+/// ```
+/// use yotp_core::base32::Base32Decoder;
+///
+/// let mut decoder = Base32Decoder::new();
+/// let mut data = decoder.push("JBSWY3DPE").unwrap();
+/// data.extend(decoder.push("HPK3PXP").unwrap());
+/// data.extend(decoder.finish());
+/// assert_eq!(data, b"Hello!\xde\xad\xbe\xef");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Base32Decoder {
+    alphabet: Alphabet,
+    /// Characters of the incomplete block.
+    pending: [u8; 8],
+    pending_len: usize,
+    /// Bytes of input pushed so far, for the error offsets.
+    offset: usize,
+    /// Whether the padding has been reached.
+    padded: bool,
+}
+
+impl Base32Decoder {
+    /// Decoder of the standard alphabet.
+    pub fn new() -> Base32Decoder {
+        Base32Decoder::with_alphabet(Alphabet::STANDARD)
+    }
+
+    pub fn with_alphabet(alphabet: Alphabet) -> Base32Decoder {
+        Base32Decoder { alphabet, pending: [0; 8], pending_len: 0, offset: 0, padded: false }
+    }
+
+    /// Decode the next chunk of input and return the bytes completed by
+    /// it. The index of [`Base32Error::InvalidCharacter`] counts from the
+    /// start of the first chunk.
+    pub fn push(&mut self, chunk: &str) -> Result<Vec<u8>, Base32Error> {
+        let mut buf = Vec::with_capacity(5 * (self.pending_len + chunk.len()) / 8);
+        for (i, c) in chunk.bytes().enumerate() {
+            if self.padded {
+                break;
             }
-            Some(v) => {
-                i = match i {
-                    0 => {
-                        next = next | (v << 3);
-                        5
-                    }
-                    1 => {
-                        next = next | (v << 2);
-                        6
-                    }
-                    2 => {
-                        next = next | (v << 1);
-                        7
-                    }
-                    3 => {
-                        next = next | v;
-                        buf.push(next);
-                        next = 0;
-                        0
-                    }
-                    4 => {
-                        next = next | (v >> 1);
-                        buf.push(next);
-                        next = v << 7;
-                        1
-                    }
-                    5 => {
-                        next = next | (v >> 2);
-                        buf.push(next);
-                        next = v << 6;
-                        2
-                    }
-                    6 => {
-                        next = next | (v >> 3);
-                        buf.push(next);
-                        next = v << 5;
-                        3
-                    }
-                    7 => {
-                        next = next | (v >> 4);
-                        buf.push(next);
-                        next = v << 4;
-                        4
-                    }
-                    _ => {0}
-                }
+            if c == b'=' {
+                self.padded = true;
+                break;
+            }
+            if self.alphabet.table[c as usize] == INVALID {
+                let character = chunk[i..].chars().next().expect("a character at a valid position");
+                return Err(Base32Error::InvalidCharacter { index: self.offset + i, character });
+            }
+            self.pending[self.pending_len] = c;
+            self.pending_len += 1;
+            if self.pending_len == 8 {
+                let bits = decode_block(&self.pending, |c| self.alphabet.table[c as usize], &mut 0);
+                buf.extend_from_slice(&bits.to_be_bytes()[3..]);
+                self.pending_len = 0;
+            }
+        }
+        self.offset += chunk.len();
+        Ok(buf)
+    }
+
+    /// Count `len` bytes of input skipped by the caller.
+    #[cfg(feature = "std")]
+    fn skip(&mut self, len: usize) {
+        self.offset += len;
+    }
+
+    /// Return the bytes of the last incomplete block.
+    pub fn finish(self) -> Vec<u8> {
+        let rest = &self.pending[..self.pending_len];
+        let bits = decode_block(rest, |c| self.alphabet.table[c as usize], &mut 0) << (40 - 5 * rest.len());
+        bits.to_be_bytes()[3..3 + 5 * rest.len() / 8].to_vec()
+    }
+}
+
+impl Default for Base32Decoder {
+    fn default() -> Base32Decoder {
+        Base32Decoder::new()
+    }
+}
+
+/// Reader of the bytes decoded from the Base 32 text read from `inner`.
+/// Whitespaces such as line breaks are skipped, and the other errors of
+/// [`Base32Decoder`] are returned as [`std::io::ErrorKind::InvalidData`],
+/// with bytes out of ASCII reported as U+FFFD.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Base32Reader<R> {
+    inner: R,
+    decoder: Option<Base32Decoder>,
+    /// Decoded bytes not read yet.
+    decoded: Vec<u8>,
+    pos: usize,
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> Base32Reader<R> {
+    /// Reader of the standard alphabet.
+    pub fn new(inner: R) -> Base32Reader<R> {
+        Base32Reader::with_alphabet(inner, Alphabet::STANDARD)
+    }
 
+    pub fn with_alphabet(inner: R, alphabet: Alphabet) -> Base32Reader<R> {
+        Base32Reader { inner, decoder: Some(Base32Decoder::with_alphabet(alphabet)), decoded: Vec::new(), pos: 0 }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read and decode the next chunk of `inner`.
+    fn fill(&mut self) -> std::io::Result<()> {
+        let decoder = match self.decoder.as_mut() {
+            Some(decoder) => decoder,
+            None => return Ok(())
+        };
+        let mut chunk = [0u8; 1024];
+        let len = self.inner.read(&mut chunk)?;
+        self.pos = 0;
+        if len == 0 {
+            self.decoded = self.decoder.take().expect("the decoder is not finished").finish();
+            return Ok(());
+        }
+        self.decoded.clear();
+        let invalid_data = |e: Base32Error| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        for run in chunk[..len].split_inclusive(|c| c.is_ascii_whitespace()) {
+            let (text, skipped) = match run.split_last() {
+                Some((last, text)) if last.is_ascii_whitespace() => (text, 1),
+                _ => (run, 0)
+            };
+            let ascii_len = text.iter().position(|c| !c.is_ascii()).unwrap_or(text.len());
+            let ascii = core::str::from_utf8(&text[..ascii_len]).expect("ASCII is UTF-8");
+            self.decoded.extend(decoder.push(ascii).map_err(invalid_data)?);
+            if ascii_len < text.len() {
+                return Err(invalid_data(Base32Error::InvalidCharacter {
+                    index: decoder.offset,
+                    character: char::REPLACEMENT_CHARACTER,
+                }));
             }
+            decoder.skip(skipped);
         }
+        Ok(())
     }
-    Some(buf)
-}
-
-fn decode_char(v: char) -> Option<u8> {
-    match v {
-        'A' => Some(0u8),
-        'B' => Some(1u8),
-        'C' => Some(2u8),
-        'D' => Some(3u8),
-        'E' => Some(4u8),
-        'F' => Some(5u8),
-        'G' => Some(6u8),
-        'H' => Some(7u8),
-        'I' => Some(8u8),
-        'J' => Some(9u8),
-        'K' => Some(10u8),
-        'L' => Some(11u8),
-        'M' => Some(12u8),
-        'N' => Some(13u8),
-        'O' => Some(14u8),
-        'P' => Some(15u8),
-        'Q' => Some(16u8),
-        'R' => Some(17u8),
-        'S' => Some(18u8),
-        'T' => Some(19u8),
-        'U' => Some(20u8),
-        'V' => Some(21u8),
-        'W' => Some(22u8),
-        'X' => Some(23u8),
-        'Y' => Some(24u8),
-        'Z' => Some(25u8),
-        '2' => Some(26u8),
-        '3' => Some(27u8),
-        '4' => Some(28u8),
-        '5' => Some(29u8), 
-        '6' => Some(30u8),
-        '7' => Some(31u8),
-        '=' => None,
-        _ => None
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> std::io::Read for Base32Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.decoder.is_none() {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let len = buf.len().min(self.decoded.len() - self.pos);
+        buf[..len].copy_from_slice(&self.decoded[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
     }
 }
 
+/// Writer encoding the bytes written to it as Base 32 text to `inner`.
+///
+/// The last incomplete block is only written, with its padding, by
+/// [`Base32Writer::finish`], so it is lost if the writer is dropped.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Base32Writer<W> {
+    inner: W,
+    alphabet: Alphabet,
+    /// Bytes of the incomplete block.
+    pending: [u8; 5],
+    pending_len: usize,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Base32Writer<W> {
+    /// Writer of the standard alphabet.
+    pub fn new(inner: W) -> Base32Writer<W> {
+        Base32Writer::with_alphabet(inner, Alphabet::STANDARD)
+    }
+
+    pub fn with_alphabet(inner: W, alphabet: Alphabet) -> Base32Writer<W> {
+        Base32Writer { inner, alphabet, pending: [0; 5], pending_len: 0 }
+    }
+
+    /// Write the last block and return `inner`.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        let rest = encode_with(&self.pending[..self.pending_len], &self.alphabet);
+        self.inner.write_all(rest.as_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> std::io::Write for Base32Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut data = buf;
+        if self.pending_len > 0 {
+            let len = data.len().min(5 - self.pending_len);
+            self.pending[self.pending_len..self.pending_len + len].copy_from_slice(&data[..len]);
+            self.pending_len += len;
+            data = &data[len..];
+            if self.pending_len < 5 {
+                return Ok(buf.len());
+            }
+            let block = encode_with(&self.pending, &self.alphabet);
+            self.inner.write_all(block.as_bytes())?;
+            self.pending_len = 0;
+        }
+        let whole = data.len() / 5 * 5;
+        self.inner.write_all(encode_with(&data[..whole], &self.alphabet).as_bytes())?;
+        let rest = &data[whole..];
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.pending_len = rest.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decode `value` into the start of `out` without allocating, and return
+/// the number of bytes written. [`decoded_len`] tells how long `out`
+/// should be.
+///
+/// Besides the errors of [`decode`], it returns
+/// [`Base32Error::BufferTooSmall`] if `out` is too short.
+pub fn decode_into(value: &str, out: &mut [u8]) -> Result<usize, Base32Error> {
+    let table = &Alphabet::STANDARD.table;
+    decode_symbols_into(value, |c| table[c as usize], out)
+}
+
+/// The number of bytes [`decode`] returns for a valid `value`.
+pub fn decoded_len(value: &str) -> usize {
+    5 * data_part(value.as_bytes()).len() / 8
+}
+
+/// The input before the padding, as everything from the first padding
+/// character on is ignored.
+fn data_part(input: &[u8]) -> &[u8] {
+    match input.iter().position(|&c| c == b'=') {
+        Some(end) => &input[..end],
+        None => input
+    }
+}
+
+fn decode_symbols(value: &str, symbol: impl Fn(u8) -> u8) -> Result<Vec<u8>, Base32Error> {
+    let mut buf = vec![0u8; decoded_len(value)];
+    decode_symbols_into(value, symbol, &mut buf)?;
+    Ok(buf)
+}
+
+fn decode_symbols_into(value: &str, symbol: impl Fn(u8) -> u8, out: &mut [u8]) -> Result<usize, Base32Error> {
+    let input = data_part(value.as_bytes());
+    let len = 5 * input.len() / 8;
+    if out.len() < len {
+        return Err(Base32Error::BufferTooSmall(len));
+    }
+    // invalid characters are collected and reported after the whole
+    // input is decoded
+    let mut invalid = 0u8;
+    // 8 characters carry 40 bits, i.e. 5 bytes
+    let mut blocks = input.chunks_exact(8);
+    let mut pos = 0;
+    for block in &mut blocks {
+        let bits = decode_block(block, &symbol, &mut invalid);
+        out[pos..pos + 5].copy_from_slice(&bits.to_be_bytes()[3..]);
+        pos += 5;
+    }
+    let rest = blocks.remainder();
+    if !rest.is_empty() {
+        // the bits that do not fill a whole byte are dropped
+        let bits = decode_block(rest, &symbol, &mut invalid) << (40 - 5 * rest.len());
+        out[pos..len].copy_from_slice(&bits.to_be_bytes()[3..3 + 5 * rest.len() / 8]);
+    }
+    if invalid & 0xE0 != 0 {
+        let index = input.iter().position(|&c| symbol(c) == INVALID).expect("an invalid character");
+        let character = value[index..].chars().next().expect("a character at a valid position");
+        return Err(Base32Error::InvalidCharacter { index, character });
+    }
+    Ok(len)
+}
+
+/// Concatenate the 5-bit values of up to 8 characters.
+fn decode_block(block: &[u8], symbol: impl Fn(u8) -> u8, invalid: &mut u8) -> u64 {
+    let mut bits = 0u64;
+    for &c in block {
+        let v = symbol(c);
+        *invalid |= v;
+        bits = (bits << 5) | (v & 0x1F) as u64;
+    }
+    bits
+}
+
+/// The value of a Base 32 character, or [`INVALID`], without branches.
+fn decode_symbol_ct(c: u8) -> u8 {
+    let c = c as i16;
+    let mut v = -1i16;
+    // each term adds the value plus one if `c` is in its range
+    v += in_range(c, b'A', b'Z') & (c - b'A' as i16 + 1);
+    v += in_range(c, b'a', b'z') & (c - b'a' as i16 + 1);
+    v += in_range(c, b'2', b'7') & (c - b'2' as i16 + 27);
+    v as u8
+}
+
+/// All bits set if `lo <= c <= hi`, otherwise zero.
+fn in_range(c: i16, lo: u8, hi: u8) -> i16 {
+    ((lo as i16 - 1 - c) & (c - hi as i16 - 1)) >> 8
+}
+
+#[cfg(test)]
 mod test {
-    use crate::base32::decode;
+    use crate::base32::{decode, decode_ct, decode_into, decoded_len, decode_lenient, decode_lenient_with, decode_strict, decode_strict_with, Base32Decoder, decode_with, encode, encode_with, decode_symbol_ct, Alphabet, Base32Error};
+    use crate::error::OtpError;
     #[test]
     fn test_normal_decoding() {
         let value = decode("JBSWY3DPEHPK3PXP").unwrap();
         assert_eq!(value.len(), 10);
-        assert_eq!(value.get(0).unwrap().to_owned(), 0x48u8);        
+        assert_eq!(value.first().unwrap().to_owned(), 0x48u8);        
         assert_eq!(value.get(1).unwrap().to_owned(), 0x65u8);        
         assert_eq!(value.get(2).unwrap().to_owned(), 0x6cu8);        
         assert_eq!(value.get(3).unwrap().to_owned(), 0x6cu8);        
@@ -144,7 +618,7 @@ mod test {
     fn test_decode_padding() {
         let value = decode("32W353Y====").unwrap();
         assert_eq!(value.len(), 4);
-        assert_eq!(value.get(0).unwrap().to_owned(), 0xdeu8);        
+        assert_eq!(value.first().unwrap().to_owned(), 0xdeu8);        
         assert_eq!(value.get(1).unwrap().to_owned(), 0xadu8);        
         assert_eq!(value.get(2).unwrap().to_owned(), 0xbeu8);        
         assert_eq!(value.get(3).unwrap().to_owned(), 0xefu8);        
@@ -153,6 +627,197 @@ mod test {
     #[test]
     fn test_invalud_decode_input() {
         let value = decode ("32W39");
-        assert!(value.is_none());
+        assert_eq!(value, Err(Base32Error::InvalidCharacter { index: 4, character: '9' }));
+        assert_eq!(decode("JBSWY3DPEHPK3PX\u{e9}"), Err(Base32Error::InvalidCharacter { index: 15, character: '\u{e9}' }));
+        assert_eq!(decode("JB1").unwrap_err().to_string(), "invalid Base 32 character '1' at offset 2");
+        assert_eq!(OtpError::from(decode("JB1").unwrap_err()), OtpError::DecodeError);
+    }
+
+    #[test]
+    fn test_decode_lengths() {
+        // RFC 4648 test vectors, without the padding and in lower case
+        let vectors = [
+            ("", ""), ("MY", "f"), ("MZXQ", "fo"), ("MZXW6", "foo"), ("MZXW6YQ", "foob"),
+            ("MZXW6YTB", "fooba"), ("MZXW6YTBOI", "foobar"),
+        ];
+        for (encoded, decoded) in vectors {
+            assert_eq!(decode(encoded).unwrap(), decoded.as_bytes());
+            assert_eq!(decode(&encoded.to_lowercase()).unwrap(), decoded.as_bytes());
+        }
+        assert_eq!(decode("MZXW6YQ=").unwrap(), b"foob");
+    }
+
+    #[test]
+    fn test_encode() {
+        let vectors = [
+            ("", ""), ("f", "MY======"), ("fo", "MZXQ===="), ("foo", "MZXW6==="), ("foob", "MZXW6YQ="),
+            ("fooba", "MZXW6YTB"), ("foobar", "MZXW6YTBOI======"),
+        ];
+        for (decoded, encoded) in vectors {
+            assert_eq!(encode(decoded.as_bytes()), encoded);
+            assert_eq!(decode(encoded).unwrap(), decoded.as_bytes());
+        }
+        assert_eq!(encode(b"Hello!\xde\xad\xbe\xef"), "JBSWY3DPEHPK3PXP");
+        let data: Vec<u8> = (0..=255).collect();
+        for len in 0..data.len() {
+            assert_eq!(decode(&encode(&data[..len])).unwrap(), &data[..len]);
+        }
+    }
+
+    #[test]
+    fn test_hex_alphabet() {
+        // RFC 4648 test vectors of base32hex
+        let vectors = [
+            ("", ""), ("f", "CO======"), ("fo", "CPNG===="), ("foo", "CPNMU==="), ("foob", "CPNMUOG="),
+            ("fooba", "CPNMUOJ1"), ("foobar", "CPNMUOJ1E8======"),
+        ];
+        for (decoded, encoded) in vectors {
+            assert_eq!(encode_with(decoded.as_bytes(), &Alphabet::HEX), encoded);
+            assert_eq!(decode_with(encoded, &Alphabet::HEX).unwrap(), decoded.as_bytes());
+            assert_eq!(decode_with(&encoded.to_lowercase(), &Alphabet::HEX).unwrap(), decoded.as_bytes());
+        }
+        assert_eq!(decode_with("CPNW", &Alphabet::HEX), Err(Base32Error::InvalidCharacter { index: 3, character: 'W' }));
+        assert_eq!(Alphabet::HEX.symbols(), "0123456789ABCDEFGHIJKLMNOPQRSTUV");
+    }
+
+    #[test]
+    fn test_crockford_alphabet() {
+        let crockford = &Alphabet::CROCKFORD;
+        assert_eq!(encode_with(b"foobar", crockford), "CSQPYRK1E8");
+        assert_eq!(encode_with(b"f", crockford), "CR");
+        assert_eq!(decode_with("CSQPYRK1E8", crockford).unwrap(), b"foobar");
+        assert_eq!(decode_with("csqpyrkie8", crockford).unwrap(), b"foobar");
+        assert_eq!(decode_with("CSQPYRKLE8", crockford).unwrap(), b"foobar");
+        assert_eq!(decode_with("O1", crockford).unwrap(), decode_with("01", crockford).unwrap());
+        assert_eq!(decode_with("CU", crockford), Err(Base32Error::InvalidCharacter { index: 1, character: 'U' }));
+    }
+
+    #[test]
+    fn test_decode_lenient() {
+        assert_eq!(decode_lenient("jbsw y3dp ehpk 3pxp").unwrap(), decode("JBSWY3DPEHPK3PXP").unwrap());
+        assert_eq!(decode_lenient(" JBSW-Y3DP\tEHPK\n3PXP ").unwrap(), decode("JBSWY3DPEHPK3PXP").unwrap());
+        assert_eq!(decode_lenient("MZXW 6YQ").unwrap(), b"foob");
+        assert_eq!(decode_lenient("MZXW 6YQ=").unwrap(), b"foob");
+        assert_eq!(decode_lenient("JB SW 1"), Err(Base32Error::InvalidCharacter { index: 6, character: '1' }));
+        assert_eq!(decode_lenient("\u{3000}JB\u{e9}"), Err(Base32Error::InvalidCharacter { index: 5, character: '\u{e9}' }));
+        assert_eq!(decode_lenient_with("CSQP-YRK1-E8", &Alphabet::CROCKFORD).unwrap(), b"foobar");
+        assert!(decode("JBSW Y3DP").is_err());
+    }
+
+    #[test]
+    fn test_decode_strict() {
+        for len in 0..=10 {
+            let data: Vec<u8> = (0..len).map(|i| 0xC5 ^ i).collect();
+            assert_eq!(decode_strict(&encode(&data)).unwrap(), data);
+            assert_eq!(decode_strict(&encode(&data).to_lowercase()).unwrap(), data);
+            assert_eq!(decode_strict_with(&encode_with(&data, &Alphabet::CROCKFORD), &Alphabet::CROCKFORD).unwrap(), data);
+        }
+        assert_eq!(decode_strict("MZXW6YQ"), Err(Base32Error::InvalidPadding));
+        assert_eq!(decode_strict("MZXW6YQ=="), Err(Base32Error::InvalidPadding));
+        assert_eq!(decode_strict("MZXW6YTB========"), Err(Base32Error::InvalidPadding));
+        assert_eq!(decode_strict("MZXW6YQ=MZXW6YQ="), Err(Base32Error::InvalidPadding));
+        assert_eq!(decode_strict("MZX====="), Err(Base32Error::InvalidPadding));
+        assert_eq!(decode_strict("MY======").unwrap(), b"f");
+        assert_eq!(decode("MZ======").unwrap(), b"f");
+        assert_eq!(decode_strict("MZ======"), Err(Base32Error::TrailingBits));
+        assert_eq!(decode_strict("M1======"), Err(Base32Error::InvalidCharacter { index: 1, character: '1' }));
+        assert_eq!(decode_strict_with("CR==", &Alphabet::CROCKFORD), Err(Base32Error::InvalidPadding));
+    }
+
+    #[test]
+    fn test_streaming_decoder() {
+        let encoded = encode(&(0..=255).collect::<Vec<u8>>());
+        for chunk_len in [1, 3, 8, 13, 500] {
+            let mut decoder = Base32Decoder::new();
+            let mut data = Vec::new();
+            for chunk in encoded.as_bytes().chunks(chunk_len) {
+                data.extend(decoder.push(core::str::from_utf8(chunk).unwrap()).unwrap());
+            }
+            data.extend(decoder.finish());
+            assert_eq!(data, decode(&encoded).unwrap());
+        }
+        let mut decoder = Base32Decoder::with_alphabet(Alphabet::HEX);
+        assert_eq!(decoder.push("CPNMU").unwrap(), b"");
+        assert_eq!(decoder.push("OJ1E8===").unwrap(), b"fooba");
+        assert_eq!(decoder.push("ANYTHING").unwrap(), b"");
+        assert_eq!(decoder.finish(), b"r");
+        let mut decoder = Base32Decoder::new();
+        decoder.push("JBSW").unwrap();
+        assert_eq!(decoder.push("Y3D1"), Err(Base32Error::InvalidCharacter { index: 7, character: '1' }));
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let mut out = [0u8; 12];
+        assert_eq!(decoded_len("JBSWY3DPEHPK3PXP"), 10);
+        assert_eq!(decode_into("JBSWY3DPEHPK3PXP", &mut out).unwrap(), 10);
+        assert_eq!(&out[..10], b"Hello!\xde\xad\xbe\xef");
+        assert_eq!(decoded_len("MZXW6YQ="), 4);
+        assert_eq!(decode_into("MZXW6YQ=", &mut out[..4]).unwrap(), 4);
+        assert_eq!(&out[..4], b"foob");
+        assert_eq!(decode_into("JBSWY3DPEHPK3PXP", &mut out[..9]), Err(Base32Error::BufferTooSmall(10)));
+        assert_eq!(decode_into("JB1", &mut out), Err(Base32Error::InvalidCharacter { index: 2, character: '1' }));
+    }
+
+    #[test]
+    fn test_custom_alphabet() {
+        let shuffled = Alphabet::new("ZYXWVUTSRQPONMLKJIHGFEDCBA765432", false).unwrap();
+        assert_eq!(shuffled.symbols(), "ZYXWVUTSRQPONMLKJIHGFEDCBA765432");
+        let data: Vec<u8> = (0..=255).collect();
+        let encoded = encode_with(&data, &shuffled);
+        assert!(!encoded.contains('='));
+        assert_eq!(decode_with(&encoded, &shuffled).unwrap(), data);
+        assert_eq!(decode_with(&encoded.to_lowercase(), &shuffled).unwrap(), data);
+        let mixed = Alphabet::new("abcdefghijklmnopABCDEFGHIJKLMNOP", true).unwrap();
+        assert_eq!(decode_with(&encode_with(&data, &mixed), &mixed).unwrap(), data);
+        assert_eq!(Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ23456", true).unwrap_err(), Base32Error::InvalidAlphabet);
+        assert_eq!(Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ234566", true).unwrap_err(), Base32Error::InvalidAlphabet);
+        assert_eq!(Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ23456=", true).unwrap_err(), Base32Error::InvalidAlphabet);
+        assert_eq!(Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ23456 ", true).unwrap_err(), Base32Error::InvalidAlphabet);
+    }
+
+    #[test]
+    fn test_io_adapters() {
+        use std::io::{Read, Write};
+
+        use crate::base32::{Base32Reader, Base32Writer};
+
+        let data: Vec<u8> = (0..=255).cycle().take(3000).collect();
+        let mut writer = Base32Writer::new(Vec::new());
+        for chunk in data.chunks(7) {
+            writer.write_all(chunk).unwrap();
+        }
+        let encoded = writer.finish().unwrap();
+        assert_eq!(encoded, encode(&data).as_bytes());
+
+        let mut wrapped = Vec::new();
+        for line in encoded.chunks(64) {
+            wrapped.extend_from_slice(line);
+            wrapped.extend_from_slice(b"\r\n");
+        }
+        let mut decoded = Vec::new();
+        Base32Reader::new(wrapped.as_slice()).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+
+        let mut reader = Base32Reader::with_alphabet(&b"CPNM UOJ1\nE8======"[..], Alphabet::HEX);
+        let mut decoded = Vec::new();
+        reader.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, b"foobar");
+
+        let error = Base32Reader::new(&b"JBSW\nY3D1"[..]).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "invalid Base 32 character '1' at offset 8");
+        let error = Base32Reader::new("JB\u{e9}".as_bytes()).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid Base 32 character '\u{fffd}' at offset 2");
+    }
+
+    #[test]
+    fn test_decode_ct() {
+        for c in 0..=255u8 {
+            assert_eq!(decode_symbol_ct(c), Alphabet::STANDARD.table[c as usize]);
+        }
+        assert_eq!(decode_ct("JBSWY3DPEHPK3PXP").unwrap(), decode("JBSWY3DPEHPK3PXP").unwrap());
+        assert_eq!(decode_ct("32w353y====").unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(decode_ct("32W39"), Err(Base32Error::InvalidCharacter { index: 4, character: '9' }));
     }
 }
\ No newline at end of file
@@ -0,0 +1,99 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Base 64 of RFC 4648, as used by the backup formats of authenticators.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const SYMBOLS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode `data` with the standard alphabet and padding.
+pub(crate) fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let block = chunk.iter().enumerate().fold(0u32, |block, (i, &b)| block | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(SYMBOLS[(block >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode `value` in the standard or the URL-safe alphabet, with or
+/// without padding. Returns `None` if it is not valid Base 64.
+pub(crate) fn decode(value: &str) -> Option<Vec<u8>> {
+    let data = value.trim_end_matches('=').as_bytes();
+    if data.len() % 4 == 1 || value.len() - data.len() > 2 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        let mut block = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            block |= (symbol_value(c)? as u32) << (18 - 6 * i);
+        }
+        let bytes = block.to_be_bytes();
+        out.extend_from_slice(&bytes[1..chunk.len()]);
+        // the bits after the last whole byte must be zero
+        if block & (0xFFFFFF >> (8 * (chunk.len() - 1))) != 0 {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+fn symbol_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode};
+
+    #[test]
+    fn test_rfc4648_vectors() {
+        let vectors = [
+            ("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy"),
+        ];
+        for (data, encoded) in vectors {
+            assert_eq!(encode(data.as_bytes()), encoded);
+            assert_eq!(decode(encoded).unwrap(), data.as_bytes());
+            assert_eq!(decode(encoded.trim_end_matches('=')).unwrap(), data.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(decode("-_8="), Some(vec![0xfb, 0xff]));
+        assert_eq!(decode("Zm9v!"), None);
+        assert_eq!(decode("Z"), None);
+        assert_eq!(decode("Zh=="), None);
+        assert_eq!(decode("Zg==="), None);
+    }
+}
@@ -0,0 +1,328 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Secrets unlocked with the biometrics of the operating system as a
+//! [`SecretStore`]: Touch ID on macOS and Windows Hello on Windows.
+//!
+//! With the `vault` feature, `Vault::store_key` keeps the key of a vault
+//! in the store, and `Vault::open_from_store` opens it with a fingerprint
+//! or a face instead of the passphrase. The vault still opens with its
+//! passphrase, which remains the way to recover it.
+//!
+//! On macOS, the secrets are items of the data protection keychain whose
+//! access control requires Touch ID with the current set of fingers, as
+//! checked by the Secure Enclave. Adding a finger makes the items
+//! unreadable. The keychain only lets signed binaries with a keychain
+//! access group entitlement use such items.
+//!
+//! On Windows, the secrets are encrypted with AES-256-GCM under a key
+//! derived from a signature of the Windows Hello key of the service,
+//! which the TPM makes only after the user is verified, and the encrypted
+//! secrets are kept in the Credential Locker.
+//!
+//! On the other platforms, every operation fails.
+//!
+//! ```no_run
+//! use yotp_core::biometric::BiometricKeyProvider;
+//! use yotp_core::secret_store::SecretStore;
+//!
+//! let store = BiometricKeyProvider::new("yotp");
+//! store.set("accounts", b"Hello!\xde\xad\xbe\xef").unwrap();
+//! // asks for a fingerprint or a face
+//! assert!(store.get("accounts").unwrap().is_some());
+//! ```
+
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+use crate::secret_store::SecretStore;
+
+/// The secrets of a service unlocked with biometrics. The names of the
+/// secrets are the accounts of the keychain items on macOS and the user
+/// names of the Credential Locker on Windows.
+pub struct BiometricKeyProvider {
+    service: String,
+}
+
+impl BiometricKeyProvider {
+    /// The secrets of `service`, e.g. the name of the application.
+    pub fn new(service: &str) -> BiometricKeyProvider {
+        BiometricKeyProvider { service: service.to_owned() }
+    }
+}
+
+impl SecretStore for BiometricKeyProvider {
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, OtpError> {
+        platform::get(&self.service, name)
+    }
+
+    fn set(&self, name: &str, secret: &[u8]) -> Result<(), OtpError> {
+        platform::set(&self.service, name, secret)
+    }
+
+    fn delete(&self, name: &str) -> Result<bool, OtpError> {
+        platform::delete(&self.service, name)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use security_framework::passwords::{
+        delete_generic_password_options, generic_password, set_generic_password_options, AccessControlOptions, PasswordOptions,
+    };
+    use zeroize::Zeroizing;
+
+    use super::store_error;
+    use crate::error::OtpError;
+
+    /// `errSecItemNotFound`
+    const ITEM_NOT_FOUND: i32 = -25300;
+
+    fn options(service: &str, name: &str) -> PasswordOptions {
+        let mut options = PasswordOptions::new_generic_password(service, name);
+        options.use_protected_keychain();
+        options
+    }
+
+    pub(super) fn get(service: &str, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, OtpError> {
+        match generic_password(options(service, name)) {
+            Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+            Err(e) if e.code() == ITEM_NOT_FOUND => Ok(None),
+            Err(e) => Err(store_error(&e.to_string())),
+        }
+    }
+
+    pub(super) fn set(service: &str, name: &str, secret: &[u8]) -> Result<(), OtpError> {
+        // the access control of an item cannot be updated
+        delete(service, name)?;
+        let mut options = options(service, name);
+        options.set_access_control_options(AccessControlOptions::BIOMETRY_CURRENT_SET);
+        set_generic_password_options(secret, options).map_err(|e| store_error(&e.to_string()))
+    }
+
+    pub(super) fn delete(service: &str, name: &str) -> Result<bool, OtpError> {
+        match delete_generic_password_options(options(service, name)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.code() == ITEM_NOT_FOUND => Ok(false),
+            Err(e) => Err(store_error(&e.to_string())),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use sha2::{Digest, Sha256};
+    use windows::core::{Array, HRESULT, HSTRING};
+    use windows::Security::Credentials::{
+        KeyCredential, KeyCredentialCreationOption, KeyCredentialManager, KeyCredentialStatus, PasswordCredential, PasswordVault,
+    };
+    use windows::Security::Cryptography::CryptographicBuffer;
+    use zeroize::Zeroizing;
+
+    use super::{seal, store_error, unseal};
+    use crate::error::OtpError;
+
+    /// `HRESULT_FROM_WIN32(ERROR_NOT_FOUND)`, of a missing credential.
+    const NOT_FOUND: HRESULT = HRESULT(0x8007_0490_u32 as i32);
+
+    fn hello_error(e: windows::core::Error) -> OtpError {
+        store_error(&e.message())
+    }
+
+    fn status_error(status: KeyCredentialStatus) -> OtpError {
+        match status {
+            KeyCredentialStatus::UserCanceled => store_error("the user canceled Windows Hello"),
+            KeyCredentialStatus::SecurityDeviceLocked => store_error("the security device is locked"),
+            status => store_error(&format!("Windows Hello failed with status {}", status.0)),
+        }
+    }
+
+    /// The Windows Hello key of `service`, created on first use.
+    fn credential(service: &str) -> Result<KeyCredential, OtpError> {
+        let name = HSTRING::from(service);
+        let mut result = KeyCredentialManager::OpenAsync(&name).and_then(|op| op.get()).map_err(hello_error)?;
+        if result.Status().map_err(hello_error)? == KeyCredentialStatus::NotFound {
+            result = KeyCredentialManager::RequestCreateAsync(&name, KeyCredentialCreationOption::FailIfExists)
+                .and_then(|op| op.get())
+                .map_err(hello_error)?;
+        }
+        match result.Status().map_err(hello_error)? {
+            KeyCredentialStatus::Success => result.Credential().map_err(hello_error),
+            status => Err(status_error(status)),
+        }
+    }
+
+    /// The key of a secret: the hash of the signature of `salt` with the
+    /// Windows Hello key, which is deterministic for its RSA keys.
+    fn key(service: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, OtpError> {
+        let credential = credential(service)?;
+        let data = CryptographicBuffer::CreateFromByteArray(salt).map_err(hello_error)?;
+        let result = credential.RequestSignAsync(&data).and_then(|op| op.get()).map_err(hello_error)?;
+        let status = result.Status().map_err(hello_error)?;
+        if status != KeyCredentialStatus::Success {
+            return Err(status_error(status));
+        }
+        let mut signature = Array::<u8>::new();
+        CryptographicBuffer::CopyToByteArray(&result.Result().map_err(hello_error)?, &mut signature).map_err(hello_error)?;
+        Ok(Zeroizing::new(Sha256::digest(&signature[..]).into()))
+    }
+
+    fn retrieve(service: &str, name: &str) -> Result<Option<PasswordCredential>, OtpError> {
+        let vault = PasswordVault::new().map_err(hello_error)?;
+        match vault.Retrieve(&HSTRING::from(service), &HSTRING::from(name)) {
+            Ok(credential) => Ok(Some(credential)),
+            Err(e) if e.code() == NOT_FOUND => Ok(None),
+            Err(e) => Err(hello_error(e)),
+        }
+    }
+
+    pub(super) fn get(service: &str, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, OtpError> {
+        let Some(stored) = retrieve(service, name)? else {
+            return Ok(None);
+        };
+        stored.RetrievePassword().map_err(hello_error)?;
+        let sealed = crate::base64::decode(&stored.Password().map_err(hello_error)?.to_string())
+            .ok_or_else(|| store_error("invalid sealed secret"))?;
+        unseal(&sealed, |salt| key(service, salt)).map(Some)
+    }
+
+    pub(super) fn set(service: &str, name: &str, secret: &[u8]) -> Result<(), OtpError> {
+        let sealed = seal(secret, |salt| key(service, salt))?;
+        let stored = PasswordCredential::CreatePasswordCredential(
+            &HSTRING::from(service),
+            &HSTRING::from(name),
+            &HSTRING::from(crate::base64::encode(&sealed)),
+        )
+        .map_err(hello_error)?;
+        PasswordVault::new().and_then(|vault| vault.Add(&stored)).map_err(hello_error)
+    }
+
+    pub(super) fn delete(service: &str, name: &str) -> Result<bool, OtpError> {
+        match retrieve(service, name)? {
+            Some(stored) => PasswordVault::new().and_then(|vault| vault.Remove(&stored)).map(|()| true).map_err(hello_error),
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod platform {
+    use zeroize::Zeroizing;
+
+    use super::store_error;
+    use crate::error::OtpError;
+
+    fn unsupported() -> OtpError {
+        store_error("not supported on this platform")
+    }
+
+    pub(super) fn get(_service: &str, _name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, OtpError> {
+        Err(unsupported())
+    }
+
+    pub(super) fn set(_service: &str, _name: &str, _secret: &[u8]) -> Result<(), OtpError> {
+        Err(unsupported())
+    }
+
+    pub(super) fn delete(_service: &str, _name: &str) -> Result<bool, OtpError> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(any(windows, test))]
+const SALT_LEN: usize = 32;
+#[cfg(any(windows, test))]
+const NONCE_LEN: usize = 12;
+
+/// `secret` encrypted with the key of a random salt: the salt, the
+/// nonce and the AES-256-GCM cipher text with the salt as the associated
+/// data.
+#[cfg(any(windows, test))]
+fn seal<K>(secret: &[u8], key: K) -> Result<Vec<u8>, OtpError>
+where
+    K: FnOnce(&[u8]) -> Result<Zeroizing<[u8; 32]>, OtpError>,
+{
+    use aes_gcm::aead::{Aead, KeyInit, Payload};
+    use aes_gcm::{Aes256Gcm, Nonce};
+
+    let mut header = [0; SALT_LEN + NONCE_LEN];
+    getrandom::fill(&mut header).map_err(|_| OtpError::RandomError)?;
+    let (salt, nonce) = header.split_at(SALT_LEN);
+    let cipher = Aes256Gcm::new_from_slice(&key(salt)?[..]).expect("key has 32 bytes");
+    let cipher_text = cipher.encrypt(Nonce::from_slice(nonce), Payload { msg: secret, aad: salt }).expect("secret is not too long");
+    Ok([&header[..], &cipher_text].concat())
+}
+
+/// The secret of `sealed` of [`seal`].
+#[cfg(any(windows, test))]
+fn unseal<K>(sealed: &[u8], key: K) -> Result<Zeroizing<Vec<u8>>, OtpError>
+where
+    K: FnOnce(&[u8]) -> Result<Zeroizing<[u8; 32]>, OtpError>,
+{
+    use aes_gcm::aead::{Aead, KeyInit, Payload};
+    use aes_gcm::{Aes256Gcm, Nonce};
+
+    if sealed.len() < SALT_LEN + NONCE_LEN {
+        return Err(store_error("invalid sealed secret"));
+    }
+    let (salt, rest) = sealed.split_at(SALT_LEN);
+    let (nonce, cipher_text) = rest.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new_from_slice(&key(salt)?[..]).expect("key has 32 bytes");
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: cipher_text, aad: salt })
+        .map(Zeroizing::new)
+        .map_err(|_| store_error("the sealed secret does not decrypt with the key"))
+}
+
+fn store_error(msg: &str) -> OtpError {
+    OtpError::SecretStoreError(format!("biometric: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use sha2::{Digest, Sha256};
+    use zeroize::Zeroizing;
+
+    use crate::error::OtpError;
+
+    use super::{seal, unseal};
+
+    #[test]
+    fn test_seal() {
+        // a deterministic signature as of the RSA keys of Windows Hello
+        let key = |signer: &'static [u8]| {
+            move |salt: &[u8]| -> Result<Zeroizing<[u8; 32]>, OtpError> { Ok(Zeroizing::new(Sha256::digest([signer, salt].concat()).into())) }
+        };
+        let sealed = seal(b"Hello!\xde\xad\xbe\xef", key(b"alice")).unwrap();
+        assert_eq!(&unseal(&sealed, key(b"alice")).unwrap()[..], b"Hello!\xde\xad\xbe\xef");
+        assert!(unseal(&sealed, key(b"bob")).is_err());
+        assert_ne!(sealed, seal(b"Hello!\xde\xad\xbe\xef", key(b"alice")).unwrap());
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert!(unseal(&tampered, key(b"alice")).is_err());
+        assert!(unseal(&sealed[..40], key(b"alice")).is_err());
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    #[test]
+    fn test_unsupported() {
+        use super::BiometricKeyProvider;
+        use crate::secret_store::SecretStore;
+
+        let store = BiometricKeyProvider::new("yotp");
+        assert!(matches!(store.get("accounts"), Err(OtpError::SecretStoreError(_))));
+    }
+}
@@ -0,0 +1,163 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The TOTP field of Bitwarden and vaultwarden logins, the `login.totp`
+//! value of their JSON exports.
+//!
+//! The field holds an `otpauth://` URI, a `steam://` prefixed Base 32
+//! secret of a Steam Guard account, or a bare Base 32 secret which means
+//! TOTP with the default parameters.
+//!
+//! ```
+//! use yotp_core::bitwarden::TotpField;
+//!
+//! let TotpField::OtpAuth(uri) = TotpField::parse("JBSW Y3DP EHPK 3PXP").unwrap() else { panic!() };
+//! assert_eq!((uri.digits, uri.period), (6, 30));
+//! assert!(matches!(TotpField::parse("steam://JBSWY3DPEHPK3PXP"), Ok(TotpField::Steam(_))));
+//! ```
+
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{self, OtpAuthUri, OtpType};
+
+const STEAM_SCHEME: &str = "steam://";
+
+/// The value of a Bitwarden TOTP field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TotpField {
+    /// An HOTP or TOTP account. A bare secret is read as a TOTP account
+    /// without account name and issuer.
+    OtpAuth(OtpAuthUri),
+    /// The secret of a Steam Guard account, see [`crate::steam_totp`].
+    Steam(Secret),
+}
+
+impl TotpField {
+    /// Parse the value of a TOTP field. Surrounding whitespaces are
+    /// ignored, as are whitespaces, hyphens and the case of bare secrets.
+    ///
+    /// Returns the errors of [`OtpAuthUri::parse`] converted into
+    /// [`OtpError`] for `otpauth://` URIs, and [`OtpError::DecodeError`]
+    /// for secrets that are not valid Base 32.
+    pub fn parse(value: &str) -> Result<TotpField, OtpError> {
+        let value = value.trim();
+        if let Some(secret) = uri::strip_prefix_ignore_case(value, STEAM_SCHEME) {
+            return Ok(TotpField::Steam(decode_secret(secret)?));
+        }
+        if uri::strip_prefix_ignore_case(value, "otpauth://").is_some() {
+            return Ok(TotpField::OtpAuth(OtpAuthUri::parse(value)?));
+        }
+        Ok(TotpField::OtpAuth(OtpAuthUri {
+            otp_type: OtpType::Totp,
+            account: String::new(),
+            issuer: None,
+            secret: decode_secret(value)?,
+            algorithm: Algorithm::Sha1,
+            digits: 6,
+            period: 30,
+            counter: 0,
+        }))
+    }
+}
+
+fn decode_secret(value: &str) -> Result<Secret, OtpError> {
+    let value = zeroize::Zeroizing::new(value.to_ascii_uppercase());
+    Secret::from_base32(&value)
+}
+
+impl FromStr for TotpField {
+    type Err = OtpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TotpField::parse(s)
+    }
+}
+
+/// Formats the field as Bitwarden writes it: a bare secret for TOTP
+/// accounts with the default parameters and no account name or issuer,
+/// a `steam://` secret for Steam accounts and a URI otherwise.
+///
+/// The output contains the secret, so it must not be logged.
+impl fmt::Display for TotpField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TotpField::Steam(secret) => write!(f, "{}{}", STEAM_SCHEME, secret.to_base32()),
+            TotpField::OtpAuth(uri) if is_bare(uri) => f.write_str(&uri.secret.to_base32()),
+            TotpField::OtpAuth(uri) => write!(f, "{}", uri),
+        }
+    }
+}
+
+fn is_bare(uri: &OtpAuthUri) -> bool {
+    uri.otp_type == OtpType::Totp
+        && uri.account.is_empty()
+        && uri.issuer.is_none()
+        && uri.algorithm == Algorithm::Sha1
+        && uri.digits == 6
+        && uri.period == 30
+}
+
+impl From<OtpAuthUri> for TotpField {
+    fn from(uri: OtpAuthUri) -> Self {
+        TotpField::OtpAuth(uri)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::uri::OtpAuthUri;
+
+    use super::TotpField;
+
+    #[test]
+    fn test_parse() {
+        let TotpField::OtpAuth(uri) = TotpField::parse(" jbswy3dp-ehpk3pxp\n").unwrap() else { panic!() };
+        assert_eq!(uri.secret.to_base32(), "JBSWY3DPEHPK3PXP");
+        assert_eq!((uri.account.as_str(), uri.issuer.as_deref(), uri.algorithm), ("", None, Algorithm::Sha1));
+
+        let field = TotpField::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&digits=8").unwrap();
+        assert_eq!(field, TotpField::OtpAuth(OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&digits=8").unwrap()));
+
+        let TotpField::Steam(secret) = TotpField::parse("Steam://JBSWY3DPEHPK3PXP").unwrap() else { panic!() };
+        assert_eq!(secret.to_base32(), "JBSWY3DPEHPK3PXP");
+
+        assert_eq!(TotpField::parse("not a secret!"), Err(OtpError::DecodeError));
+        assert_eq!(TotpField::parse("otpauth://totp/a"), Err(OtpError::InvalidUri));
+    }
+
+    #[test]
+    fn test_display() {
+        for value in [
+            "JBSWY3DPEHPK3PXP",
+            "steam://JBSWY3DPEHPK3PXP",
+            "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example",
+            "otpauth://totp/?secret=JBSWY3DPEHPK3PXP&digits=8",
+        ] {
+            assert_eq!(TotpField::parse(value).unwrap().to_string(), value);
+        }
+        let uri = OtpAuthUri::parse("otpauth://totp/?secret=JBSWY3DPEHPK3PXP").unwrap();
+        assert_eq!(TotpField::from(uri).to_string(), "JBSWY3DPEHPK3PXP");
+    }
+}
@@ -0,0 +1,109 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+
+/// Source of the current time for TOTP.
+pub trait Clock {
+    /// The current time in seconds since UNIX epoch.
+    fn now_unix(&self) -> u64;
+}
+
+/// The wall clock of the system.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    /// Returns 0 if the system time is before UNIX epoch.
+    fn now_unix(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// A manually controlled clock for tests and platforms without a wall
+/// clock. Share it by reference or with an `Arc` to move time forward
+/// while it is used by a [`crate::Totp`].
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now: AtomicU64,
+}
+
+impl MockClock {
+    pub fn new(now: u64) -> MockClock {
+        MockClock { now: AtomicU64::new(now) }
+    }
+
+    /// Set the current time to `now`.
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    /// Move the current time forward by `secs` seconds.
+    pub fn advance(&self, secs: u64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_unix(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now_unix(&self) -> u64 {
+        (**self).now_unix()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now_unix(&self) -> u64 {
+        (**self).now_unix()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::sync::Arc;
+
+    use super::{Clock, MockClock};
+    #[cfg(feature = "std")]
+    use super::SystemClock;
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::new(59);
+        assert_eq!(clock.now_unix(), 59);
+        clock.advance(30);
+        assert_eq!(clock.now_unix(), 89);
+        clock.set(1111111109);
+        assert_eq!(Arc::new(clock).now_unix(), 1111111109);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_system_clock() {
+        assert!(SystemClock.now_unix() > 1_600_000_000);
+    }
+}
@@ -0,0 +1,288 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::error::OtpError;
+
+/// A generated one-time password.
+///
+/// Comparing a [`Code`] with another code or a string takes constant time
+/// with respect to the content, so that verifying user input with `==`
+/// does not leak how many leading characters match.
+#[derive(Debug, Clone)]
+pub struct Code(String);
+
+impl Code {
+    pub(crate) fn new(code: String) -> Code {
+        Code(code)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// Split the code into groups of `size` characters separated by a
+    /// space, e.g. `123 456` for a 6-digit code with `size` as 3.
+    pub fn grouped(&self, size: usize) -> String {
+        if size == 0 {
+            return self.0.clone();
+        }
+        let chars: Vec<char> = self.0.chars().collect();
+        let groups: Vec<String> = chars.chunks(size).map(|g| g.iter().collect()).collect();
+        groups.join(" ")
+    }
+
+    /// Group the code as authenticator apps usually display it: groups of
+    /// 3 for 6 and 9 characters, otherwise two halves with the shorter one
+    /// first, e.g. `1234 5678` or `123 4567`. Codes shorter than 5
+    /// characters are not grouped.
+    pub fn formatted(&self) -> String {
+        let len = self.0.chars().count();
+        if len < 5 {
+            return self.0.clone();
+        }
+        if len == 6 || len == 9 {
+            return self.grouped(3);
+        }
+        let first: String = self.0.chars().take(len / 2).collect();
+        let second: String = self.0.chars().skip(len / 2).collect();
+        format!("{} {}", first, second)
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Code {
+    type Err = OtpError;
+
+    /// Parse a code entered by a user. It should be a non-empty string of
+    /// ASCII letters and digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || !s.bytes().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(OtpError::InvalidCode);
+        }
+        Ok(Code(s.to_owned()))
+    }
+}
+
+impl AsRef<str> for Code {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Code {
+    fn eq(&self, other: &Code) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Code {}
+
+impl PartialEq<str> for Code {
+    fn eq(&self, other: &str) -> bool {
+        constant_time_eq(&self.0, other)
+    }
+}
+
+impl PartialEq<&str> for Code {
+    fn eq(&self, other: &&str) -> bool {
+        constant_time_eq(&self.0, other)
+    }
+}
+
+impl PartialEq<String> for Code {
+    fn eq(&self, other: &String) -> bool {
+        constant_time_eq(&self.0, other)
+    }
+}
+
+/// A decimal one-time password whose length `DIGITS` is part of its type,
+/// returned by [`hotp_const`](crate::hotp_const). The digits are stored
+/// inline, so it does not allocate.
+///
+/// Comparisons take constant time like the ones of [`Code`].
+#[derive(Debug, Clone, Copy)]
+pub struct FixedCode<const DIGITS: usize>([u8; DIGITS]);
+
+impl<const DIGITS: usize> FixedCode<DIGITS> {
+    pub(crate) fn new(digits: [u8; DIGITS]) -> FixedCode<DIGITS> {
+        FixedCode(digits)
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.0).expect("decimal digits are ASCII")
+    }
+
+    pub fn as_bytes(&self) -> &[u8; DIGITS] {
+        &self.0
+    }
+}
+
+impl<const DIGITS: usize> From<FixedCode<DIGITS>> for Code {
+    fn from(code: FixedCode<DIGITS>) -> Code {
+        Code(code.as_str().to_owned())
+    }
+}
+
+impl<const DIGITS: usize> fmt::Display for FixedCode<DIGITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const DIGITS: usize> AsRef<str> for FixedCode<DIGITS> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const DIGITS: usize> PartialEq for FixedCode<DIGITS> {
+    fn eq(&self, other: &FixedCode<DIGITS>) -> bool {
+        constant_time_eq(self.0, other.0)
+    }
+}
+
+impl<const DIGITS: usize> Eq for FixedCode<DIGITS> {}
+
+impl<const DIGITS: usize> PartialEq<str> for FixedCode<DIGITS> {
+    fn eq(&self, other: &str) -> bool {
+        constant_time_eq(self.0, other)
+    }
+}
+
+impl<const DIGITS: usize> PartialEq<&str> for FixedCode<DIGITS> {
+    fn eq(&self, other: &&str) -> bool {
+        constant_time_eq(self.0, other)
+    }
+}
+
+/// Normalize a code typed or pasted by a user before comparing it.
+///
+/// Whitespaces, hyphens and dashes are removed, and full-width characters
+/// are mapped to their ASCII forms, so that `123 456`, `123-456` and
+/// `１２３４５６` are all read as `123456`.
+pub fn normalize_code(input: &str) -> String {
+    input
+        .chars()
+        .filter(|&c| !c.is_whitespace() && !is_separator(c))
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c
+        })
+        .collect()
+}
+
+fn is_separator(c: char) -> bool {
+    matches!(c,
+        '-' | '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{FE63}' | '\u{FF0D}'
+        | '\u{200B}' | '\u{FEFF}')
+}
+
+/// Compare two codes in time independent of their content, so that the
+/// comparison does not leak how many leading characters match. The length
+/// is not considered secret, so codes of different lengths are rejected
+/// immediately.
+pub fn constant_time_eq<A: AsRef<[u8]>, B: AsRef<[u8]>>(a: A, b: B) -> bool {
+    let a = a.as_ref();
+    let b = b.as_ref();
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+
+    use super::{constant_time_eq, normalize_code, Code};
+
+    #[test]
+    fn test_eq() {
+        let code = Code::new("123456".to_owned());
+        assert_eq!(code, "123456");
+        assert_eq!(code, "123456".to_owned());
+        assert_ne!(code, "123457");
+        assert_ne!(code, "12345");
+        assert_eq!(code, "123456".parse::<Code>().unwrap());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("123456", "123456"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq("123456", "123457"));
+        assert!(!constant_time_eq("123456", "1234567"));
+        assert!(constant_time_eq(String::from("PV9M4"), b"PV9M4"));
+    }
+
+    #[test]
+    fn test_normalize_code() {
+        assert_eq!(normalize_code("123 456"), "123456");
+        assert_eq!(normalize_code(" 123-456\n"), "123456");
+        assert_eq!(normalize_code("1234\u{2013}5678"), "12345678");
+        assert_eq!(normalize_code("\u{FF11}\u{FF12}\u{FF13}\u{3000}\u{FF14}\u{FF15}\u{FF16}"), "123456");
+        assert_eq!(normalize_code("\u{FF30}V9M4"), "PV9M4");
+        assert_eq!(normalize_code("12\u{00A0}34\u{200B}56"), "123456");
+    }
+
+    #[test]
+    fn test_display_and_parse() {
+        let code: Code = "PV9M4".parse().unwrap();
+        assert_eq!(code.to_string(), "PV9M4");
+        assert_eq!("".parse::<Code>().unwrap_err(), OtpError::InvalidCode);
+        assert_eq!("123 456".parse::<Code>().unwrap_err(), OtpError::InvalidCode);
+    }
+
+    #[test]
+    fn test_grouped() {
+        let code = Code::new("12345678".to_owned());
+        assert_eq!(code.grouped(3), "123 456 78");
+        assert_eq!(code.grouped(4), "1234 5678");
+        assert_eq!(code.grouped(0), "12345678");
+    }
+
+    #[test]
+    fn test_formatted() {
+        let formatted = |s: &str| Code::new(s.to_owned()).formatted();
+        assert_eq!(formatted("1234"), "1234");
+        assert_eq!(formatted("PV9M4"), "PV 9M4");
+        assert_eq!(formatted("123456"), "123 456");
+        assert_eq!(formatted("1234567"), "123 4567");
+        assert_eq!(formatted("12345678"), "1234 5678");
+        assert_eq!(formatted("123456789"), "123 456 789");
+        assert_eq!(formatted("1234567890"), "12345 67890");
+    }
+}
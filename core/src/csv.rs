@@ -0,0 +1,339 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Accounts as CSV of RFC 4180, for spreadsheets and bulk enrollment.
+//!
+//! The first record is the header naming the columns, in any order and
+//! case:
+//!
+//! | Column      | Value                                         | Default |
+//! |-------------|-----------------------------------------------|---------|
+//! | `issuer`    | the issuer, empty for none                    | empty   |
+//! | `label`     | the account name                              | empty   |
+//! | `secret`    | the Base 32 secret                            | required|
+//! | `algorithm` | `SHA1`, `SHA256` or `SHA512`                  | `SHA1`  |
+//! | `digits`    | the number of digits                          | 6       |
+//! | `period`    | the TOTP period in seconds, empty for HOTP    | 30      |
+//! | `counter`   | the HOTP counter, empty for TOTP              | empty   |
+//!
+//! An account is HOTP if its counter is set and TOTP otherwise. Other
+//! columns are ignored. Fields with commas, quotes or line breaks are
+//! quoted, with quotes doubled, and the whitespaces around unquoted
+//! fields are ignored.
+//!
+//! Fields starting with `=`, `+`, `-`, `@`, a tab or a carriage return
+//! are exported with a `'` prefix, so that spreadsheets show them as text
+//! instead of running them as formulas. The prefix is removed on import.
+//!
+//! ```
+//! use yotp_core::csv;
+//!
+//! let accounts = csv::import("issuer,label,secret\n\"ACME, Inc.\",alice,JBSWY3DPEHPK3PXP\n").unwrap();
+//! assert_eq!(accounts[0].issuer.as_deref(), Some("ACME, Inc."));
+//! assert!(csv::export(&accounts).starts_with("issuer,label,secret,algorithm,digits,period,counter\r\n"));
+//! ```
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{OtpAuthUri, OtpType};
+
+const COLUMNS: [&str; 7] = ["issuer", "label", "secret", "algorithm", "digits", "period", "counter"];
+
+/// The first characters that make spreadsheets read a field as a formula.
+const FORMULA_CHARS: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// The fields of a record, which may hold secrets.
+type Record = Zeroizing<Vec<String>>;
+
+/// Read the accounts of `data`. Empty lines are skipped.
+///
+/// Returns [`OtpError::ImportError`] with the line of the first record that
+/// is malformed, e.g. with an unterminated quote or an invalid secret, or
+/// if the header has no `secret` column.
+pub fn import(data: &str) -> Result<Vec<OtpAuthUri>, OtpError> {
+    let mut records = parse(data)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Ok(Vec::new());
+    };
+    let column = |name: &str| header.iter().position(|column| column.trim().eq_ignore_ascii_case(name));
+    let indices = COLUMNS.map(column);
+    if indices[2].is_none() {
+        return Err(import_error(1, "the header has no secret column"));
+    }
+    records
+        .map(|(line, record)| {
+            let field = |i: usize| indices[i].and_then(|index| record.get(index)).map(|field| unguard(field)).unwrap_or_default();
+            import_record(field).map_err(|msg| import_error(line, msg))
+        })
+        .collect()
+}
+
+fn import_record<'a>(field: impl Fn(usize) -> &'a str) -> Result<OtpAuthUri, &'static str> {
+    let parse_number = |i, msg| if field(i).is_empty() { Ok(None) } else { field(i).parse().map(Some).map_err(|_| msg) };
+    let counter = parse_number(6, "invalid counter")?;
+    let otp_type = if counter.is_some() { OtpType::Hotp } else { OtpType::Totp };
+    let algorithm = match field(3) {
+        "" => Algorithm::Sha1,
+        name => Algorithm::from_name(name).ok_or("unsupported algorithm")?,
+    };
+    let digits = match field(4) {
+        "" => 6,
+        digits => digits.parse().ok().filter(|digits| crate::otp::DIGITS_RANGE.contains(digits)).ok_or("invalid digits")?,
+    };
+    let period = match (otp_type, parse_number(5, "invalid period")?) {
+        (OtpType::Totp, Some(0)) => return Err("invalid period"),
+        (OtpType::Totp, Some(period)) => period,
+        _ => 30,
+    };
+    Ok(OtpAuthUri {
+        otp_type,
+        account: field(1).to_string(),
+        issuer: Some(field(0).to_string()).filter(|issuer| !issuer.is_empty()),
+        secret: Secret::from_base32(field(2)).map_err(|_| "secret is not valid Base 32")?,
+        algorithm,
+        digits,
+        period,
+        counter: counter.unwrap_or(0),
+    })
+}
+
+/// Write `accounts` with all columns, in the order of the table above and
+/// with CRLF line breaks as RFC 4180 asks.
+///
+/// The output contains the secrets, so it must be stored as carefully as
+/// they are.
+pub fn export(accounts: &[OtpAuthUri]) -> Zeroizing<String> {
+    let mut out = Zeroizing::new(COLUMNS.join(",") + "\r\n");
+    for account in accounts {
+        let (period, counter) = match account.otp_type {
+            OtpType::Totp => (account.period.to_string(), String::new()),
+            OtpType::Hotp => (String::new(), account.counter.to_string()),
+        };
+        let secret = Zeroizing::new(account.secret.to_base32());
+        let fields = [
+            account.issuer.as_deref().unwrap_or_default(),
+            &account.account,
+            &secret,
+            account.algorithm.name(),
+            &account.digits.to_string(),
+            &period,
+            &counter,
+        ];
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            push_field(&mut out, field);
+        }
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn push_field(out: &mut String, field: &str) {
+    let guarded;
+    let field = if is_guarded(field) {
+        guarded = Zeroizing::new(format!("'{}", field));
+        &guarded
+    } else {
+        field
+    };
+    let quoted = field.contains([',', '"', '\r', '\n']) || field.trim() != field;
+    if quoted {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+/// Whether `field` is exported with a `'` prefix: it starts with one of
+/// [`FORMULA_CHARS`], possibly after the `'` of earlier prefixes, so that
+/// a field starting with `'=` also comes back unchanged.
+fn is_guarded(field: &str) -> bool {
+    field.trim_start_matches('\'').starts_with(FORMULA_CHARS)
+}
+
+/// `field` without the `'` prefix that [`push_field`] adds.
+fn unguard(field: &str) -> &str {
+    match field.strip_prefix('\'') {
+        Some(rest) if is_guarded(rest) => rest,
+        _ => field,
+    }
+}
+
+/// The records of `data` with the lines they start at, counting from 1.
+fn parse(data: &str) -> Result<Vec<(usize, Record)>, OtpError> {
+    let mut records = Vec::new();
+    let mut chars = data.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut record = Record::new(Vec::new());
+        let mut field = String::new();
+        let mut quoted = false;
+        // the length of `field` at its closing quote
+        let mut quote_end = None;
+        loop {
+            match chars.next() {
+                Some('"') if quoted => {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        quoted = false;
+                        quote_end = Some(field.len());
+                    }
+                }
+                Some('"') if field.trim().is_empty() => {
+                    field.clear();
+                    quoted = true;
+                }
+                Some('\n') if quoted => {
+                    line += 1;
+                    field.push('\n');
+                }
+                Some(c) if quoted => field.push(c),
+                None if quoted => return Err(import_error(start, "unterminated quote")),
+                Some(',') => {
+                    trim_field(&mut field, quote_end.take());
+                    record.push(core::mem::take(&mut field));
+                }
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    line += 1;
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        if !record.is_empty() || !field.trim().is_empty() {
+            trim_field(&mut field, quote_end);
+            record.push(field);
+            records.push((start, record));
+        }
+    }
+    Ok(records)
+}
+
+/// Remove the whitespaces around `field`, or only those after the closing
+/// quote at `quote_end` if it was quoted, in place as it may be a secret.
+fn trim_field(field: &mut String, quote_end: Option<usize>) {
+    let start = match quote_end {
+        Some(_) => 0,
+        None => field.len() - field.trim_start().len(),
+    };
+    let end = quote_end.unwrap_or(start);
+    let len = end + field[end..].trim_end().len();
+    field.truncate(len);
+    field.drain(..start);
+}
+
+fn import_error(line: usize, msg: &str) -> OtpError {
+    OtpError::ImportError(format!("CSV: line {}: {}", line, msg))
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::uri::{OtpAuthUri, OtpType};
+
+    use super::{export, import, parse};
+
+    #[test]
+    fn test_parse() {
+        let records = parse("a,\"b,\"\"c\"\"\"\r\n\n\"multi\nline\",x\nlast , \" kept \" ").unwrap();
+        let records: Vec<(usize, Vec<String>)> = records.into_iter().map(|(line, record)| (line, record.to_vec())).collect();
+        assert_eq!(records, [
+            (1, vec!["a".into(), "b,\"c\"".into()]),
+            (3, vec!["multi\nline".into(), "x".into()]),
+            (5, vec!["last".into(), " kept ".into()]),
+        ]);
+        assert_eq!(parse("a,b\n\"open,c\n").unwrap_err(), OtpError::ImportError("CSV: line 2: unterminated quote".into()));
+    }
+
+    #[test]
+    fn test_import() {
+        let data = "Secret,Label,Issuer,Digits,Period,Counter,Notes\n\
+                    JBSWY3DPEHPK3PXP,alice,Example,8,60,,work\n\
+                    GEZDGNBVGY3TQOJQ,bob,,,,7,\n";
+        let accounts = import(data).unwrap();
+        assert_eq!((accounts[0].otp_type, accounts[0].digits, accounts[0].period), (OtpType::Totp, 8, 60));
+        assert_eq!(accounts[0].issuer.as_deref(), Some("Example"));
+        assert_eq!((accounts[1].otp_type, accounts[1].counter, accounts[1].issuer.as_deref()), (OtpType::Hotp, 7, None));
+        assert_eq!(accounts[1].algorithm, Algorithm::Sha1);
+        assert_eq!(import("").unwrap(), []);
+
+        assert_eq!(import("label\nalice\n").unwrap_err(), OtpError::ImportError("CSV: line 1: the header has no secret column".into()));
+        let errors = [
+            ("secret,digits\nJBSWY3DP,6\nJBSWY3DP,4\n", "CSV: line 3: invalid digits"),
+            ("secret,algorithm\nJBSWY3DP,MD5\n", "CSV: line 2: unsupported algorithm"),
+            ("secret,period\nJBSWY3DP,0\n", "CSV: line 2: invalid period"),
+            ("secret,counter\nJBSWY3DP,-1\n", "CSV: line 2: invalid counter"),
+            ("secret\n\"JBSW\n!\"\n", "CSV: line 2: secret is not valid Base 32"),
+        ];
+        for (data, msg) in errors {
+            assert_eq!(import(data).unwrap_err(), OtpError::ImportError(msg.into()));
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let accounts = [
+            OtpAuthUri::parse("otpauth://totp/ACME%2C%20%22Inc%22:alice?secret=JBSWY3DPEHPK3PXP&algorithm=SHA256&period=60").unwrap(),
+            OtpAuthUri::parse("otpauth://hotp/%20bob?secret=GEZDGNBVGY3TQOJQ&counter=3").unwrap(),
+        ];
+        let data = export(&accounts);
+        assert_eq!(
+            data.lines().collect::<Vec<_>>(),
+            [
+                "issuer,label,secret,algorithm,digits,period,counter",
+                "\"ACME, \"\"Inc\"\"\",alice,JBSWY3DPEHPK3PXP,SHA256,6,60,",
+                ",bob,GEZDGNBVGY3TQOJQ,SHA1,6,,3",
+            ]
+        );
+        assert_eq!(import(&data).unwrap(), accounts);
+    }
+
+    #[test]
+    fn test_formula_guard() {
+        let uri = |issuer: &str, account: &str| {
+            let mut uri = OtpAuthUri::parse("otpauth://totp/a?secret=JBSWY3DPEHPK3PXP").unwrap();
+            uri.issuer = Some(issuer.into());
+            uri.account = account.into();
+            uri
+        };
+        let accounts = [uri("=HYPERLINK(\"x\")", "@alice"), uri("'=quoted", "-1+1"), uri("'plain", "\tbob")];
+        let data = export(&accounts);
+        assert_eq!(
+            data.lines().skip(1).map(|line| line.split(",JBSW").next().unwrap()).collect::<Vec<_>>(),
+            ["\"'=HYPERLINK(\"\"x\"\")\",'@alice", "''=quoted,'-1+1", "'plain,'\tbob"]
+        );
+        assert_eq!(import(&data).unwrap(), accounts);
+    }
+}
@@ -0,0 +1,94 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::clock::Clock;
+use crate::totp::Totp;
+use crate::verify::StepMatch;
+
+/// Per-account clock drift tracking as RFC 6238 §6 recommends.
+///
+/// When a code matches `k` steps away from the current step, `k` is
+/// recorded as the drift of the account, and the next verification window
+/// is centered around it.
+#[derive(Debug, Clone)]
+pub struct DriftTracker<K = String> {
+    drifts: HashMap<K, i64>,
+}
+
+impl<K: Eq + Hash> Default for DriftTracker<K> {
+    fn default() -> Self {
+        DriftTracker { drifts: HashMap::new() }
+    }
+}
+
+impl<K: Eq + Hash> DriftTracker<K> {
+    pub fn new() -> DriftTracker<K> {
+        DriftTracker::default()
+    }
+
+    /// Verify `code` of `id` with a window of `window` steps around the
+    /// recorded drift, and update the drift on success.
+    pub fn verify<C: Clock>(&mut self, id: K, totp: &Totp<C>, code: &str, window: u64) -> Option<StepMatch> {
+        let matched = totp.verify_with_drift(code, window, self.drift(&id))?;
+        self.drifts.insert(id, matched.offset);
+        Some(matched)
+    }
+
+    /// The recorded drift of `id` in steps, to be persisted with the
+    /// account. It is 0 for unknown accounts.
+    pub fn drift(&self, id: &K) -> i64 {
+        self.drifts.get(id).copied().unwrap_or(0)
+    }
+
+    /// Restore the drift of `id`, e.g. from persisted state.
+    pub fn set_drift(&mut self, id: K, drift: i64) {
+        self.drifts.insert(id, drift);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::MockClock;
+    use crate::totp::Totp;
+
+    use super::DriftTracker;
+
+    #[test]
+    fn test_drift_learning() {
+        let clock = MockClock::new(1111111109 + 60);
+        let totp = Totp::builder()
+            .secret(b"12345678901234567890")
+            .digits(8)
+            .clock(&clock)
+            .build()
+            .unwrap();
+        let mut tracker = DriftTracker::new();
+        // the token is 2 steps behind
+        assert_eq!(tracker.verify("alice", &totp, "07081804", 2).unwrap().offset, -2);
+        assert_eq!(tracker.drift(&"alice"), -2);
+        // 3 steps behind is out of a window of 1 step unless the drift is
+        // compensated
+        clock.advance(90);
+        let code = totp.generate_at(1111111109 + 60).unwrap();
+        assert!(totp.verify_window(code.as_str(), 1).is_none());
+        assert_eq!(tracker.verify("alice", &totp, code.as_str(), 1).unwrap().offset, -3);
+        assert_eq!(tracker.drift(&"alice"), -3);
+        assert_eq!(tracker.drift(&"bob"), 0);
+    }
+}
@@ -0,0 +1,164 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::code::Code;
+use crate::error::OtpError;
+use crate::otp::{self, Algorithm};
+
+const DECIMAL: &str = "0123456789";
+const LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const ALPHANUMERIC: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// Alphabet of Steam Guard codes, which leaves out characters that are
+/// easily confused with each other.
+const STEAM: &str = "23456789BCDFGHJKMNPQRTVWXY";
+
+/// How the truncated 31-bit value of HOTP is mapped onto the characters
+/// of a code.
+///
+/// The value is written in base `n` with an alphabet of `n` characters.
+/// The decimal encoding puts the most significant digit first as RFC 4226
+/// does, while the other encodings put the least significant character
+/// first, which is the convention of Steam Guard codes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeEncoding {
+    alphabet: Vec<char>,
+    most_significant_first: bool,
+}
+
+impl Default for CodeEncoding {
+    fn default() -> Self {
+        CodeEncoding::decimal()
+    }
+}
+
+impl CodeEncoding {
+    /// Decimal digits, as used by HOTP and TOTP.
+    pub fn decimal() -> CodeEncoding {
+        CodeEncoding {
+            alphabet: DECIMAL.chars().collect(),
+            most_significant_first: true,
+        }
+    }
+
+    /// Upper case letters `A` to `Z`.
+    pub fn letters() -> CodeEncoding {
+        CodeEncoding::from_valid(LETTERS)
+    }
+
+    /// Decimal digits followed by upper case letters.
+    pub fn alphanumeric() -> CodeEncoding {
+        CodeEncoding::from_valid(ALPHANUMERIC)
+    }
+
+    /// The alphabet of Steam Guard codes.
+    pub fn steam() -> CodeEncoding {
+        CodeEncoding::from_valid(STEAM)
+    }
+
+    /// A custom alphabet. Returns [`OtpError::InvalidAlphabet`] if it has
+    /// less than 2 characters or duplicated characters.
+    pub fn custom(alphabet: &str) -> Result<CodeEncoding, OtpError> {
+        let chars: Vec<char> = alphabet.chars().collect();
+        if chars.len() < 2 {
+            return Err(OtpError::InvalidAlphabet);
+        }
+        for (i, c) in chars.iter().enumerate() {
+            if chars[i + 1..].contains(c) {
+                return Err(OtpError::InvalidAlphabet);
+            }
+        }
+        Ok(CodeEncoding::from_valid(alphabet))
+    }
+
+    fn from_valid(alphabet: &str) -> CodeEncoding {
+        CodeEncoding {
+            alphabet: alphabet.chars().collect(),
+            most_significant_first: false,
+        }
+    }
+
+    pub fn alphabet(&self) -> &[char] {
+        &self.alphabet
+    }
+
+    /// Encode `value` as a code of `len` characters.
+    pub fn encode(&self, value: u32, len: usize) -> String {
+        let base = self.alphabet.len() as u32;
+        let mut value = value;
+        let mut code: Vec<char> = Vec::with_capacity(len);
+        for _i in 0..len {
+            code.push(self.alphabet[(value % base) as usize]);
+            value /= base;
+        }
+        if self.most_significant_first {
+            code.reverse();
+        }
+        code.into_iter().collect()
+    }
+}
+
+/// HOTP with the truncated value encoded by `encoding` instead of decimal
+/// digits. `len` is the number of characters of the code, which should
+/// be 1 to 10. Other parameters are the same as [`otp::hotp_with_algorithm`].
+pub fn hotp_encoded(key: impl AsRef<[u8]>, c: u64, len: usize, algorithm: Algorithm, encoding: &CodeEncoding) -> Result<Code, OtpError> {
+    if !(1..=10).contains(&len) {
+        return Err(OtpError::InvalidDigits(len));
+    }
+    let value = otp::hotp_raw_with_algorithm(key, c, algorithm)?;
+    Ok(Code::new(encoding.encode(value, len)))
+}
+
+/// TOTP for time `t` with the truncated value encoded by `encoding`.
+/// Other parameters are the same as [`otp::totp_at_with_algorithm`] and
+/// [`hotp_encoded`].
+pub fn totp_at_encoded(key: impl AsRef<[u8]>, t: u64, t0: u64, interval: u64, len: usize, algorithm: Algorithm, encoding: &CodeEncoding) -> Result<Code, OtpError> {
+    hotp_encoded(key, otp::time_counter(t, t0, interval)?, len, algorithm, encoding)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::{hotp, Algorithm};
+
+    use super::{hotp_encoded, CodeEncoding};
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(CodeEncoding::decimal().encode(1234567, 6), "234567");
+        assert_eq!(CodeEncoding::letters().encode(27, 3), "BBA");
+        assert_eq!(CodeEncoding::alphanumeric().encode(35, 2), "Z0");
+        let binary = CodeEncoding::custom("01").unwrap();
+        assert_eq!(binary.encode(6, 4), "0110");
+    }
+
+    #[test]
+    fn test_custom_invalid() {
+        assert_eq!(CodeEncoding::custom("0"), Err(OtpError::InvalidAlphabet));
+        assert_eq!(CodeEncoding::custom("ABCA"), Err(OtpError::InvalidAlphabet));
+    }
+
+    #[test]
+    fn test_hotp_encoded() {
+        let key = b"12345678901234567890";
+        let decimal = hotp_encoded(key, 1, 6, Algorithm::Sha1, &CodeEncoding::decimal()).unwrap();
+        assert_eq!(decimal, hotp(key, 1, 6).unwrap());
+        assert_eq!(hotp_encoded(key, 1, 5, Algorithm::Sha1, &CodeEncoding::steam()).unwrap(), "PV9M4");
+        assert_eq!(hotp_encoded(key, 1, 0, Algorithm::Sha1, &CodeEncoding::steam()), Err(OtpError::InvalidDigits(0)));
+    }
+}
@@ -0,0 +1,134 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::string::String;
+use core::fmt;
+
+/// Errors returned by the fallible APIs of yOTP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OtpError {
+    /// The requested OTP length is not supported.
+    InvalidDigits(usize),
+    /// The TOTP interval is zero.
+    InvalidInterval,
+    /// The time is before the UNIX epoch or before `t0`.
+    TimeError,
+    /// The HOTP counter would go past `u64::MAX`.
+    CounterOverflow,
+    /// The key is too short to generate an OTP.
+    KeyTooShort,
+    /// The input is not a valid Base 32 string.
+    DecodeError,
+    /// The input is not a valid hexadecimal string.
+    InvalidHex,
+    /// The OCRA suite is malformed or not supported.
+    InvalidOcraSuite,
+    /// The OCRA data input does not match the suite.
+    InvalidOcraInput,
+    /// The alphabet of a code encoding is too short or has duplicates.
+    InvalidAlphabet,
+    /// The truncation offset is out of the HMAC output.
+    InvalidOffset,
+    /// The code is empty or contains characters other than letters and
+    /// digits.
+    InvalidCode,
+    /// Too many failed verifications; retry after the given seconds.
+    Throttled(u64),
+    /// Too many failed verifications; the account is locked out.
+    LockedOut,
+    /// The verifier state store failed.
+    StoreError(String),
+    /// The output buffer is shorter than the given number of bytes.
+    BufferTooSmall(usize),
+    /// The random source of the system failed.
+    RandomError,
+    /// The secret is shorter than the given minimum number of bits.
+    WeakSecret(usize),
+    /// The secret is longer than the given maximum number of bytes.
+    SecretTooLong(usize),
+    /// The input is not a valid `otpauth://` URI.
+    InvalidUri,
+    /// The exported accounts of an authenticator are malformed.
+    ImportError(String),
+    /// The accounts cannot be represented in the export format.
+    ExportError(String),
+    /// The data cannot be encoded or rendered as a QR code.
+    QrError(String),
+    /// The account vault cannot be opened or saved.
+    VaultError(String),
+    /// The storage behind a secret store failed.
+    SecretStoreError(String),
+    /// The shares of a split secret are malformed or do not recover it.
+    ShareError(String),
+    /// The remote copy of a vault cannot be pulled or pushed.
+    SyncError(String),
+    /// The backend holding a key failed to compute an HMAC.
+    KeyBackendError(String),
+    /// The OATH applet of a YubiKey cannot be reached or refused a command.
+    YubiKeyError(String),
+}
+
+impl fmt::Display for OtpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OtpError::InvalidDigits(d) => write!(f, "unsupported OTP length {}", d),
+            OtpError::InvalidInterval => write!(f, "TOTP interval should be greater than 0"),
+            OtpError::TimeError => write!(f, "time is before the start time of TOTP"),
+            OtpError::CounterOverflow => write!(f, "HOTP counter overflowed"),
+            OtpError::KeyTooShort => write!(f, "key is too short"),
+            OtpError::DecodeError => write!(f, "invalid Base 32 string"),
+            OtpError::InvalidHex => write!(f, "invalid hex string"),
+            OtpError::InvalidOcraSuite => write!(f, "invalid OCRA suite"),
+            OtpError::InvalidOcraInput => write!(f, "OCRA data input does not match the suite"),
+            OtpError::InvalidAlphabet => write!(f, "alphabet should have at least 2 distinct characters"),
+            OtpError::InvalidOffset => write!(f, "truncation offset is out of the HMAC output"),
+            OtpError::InvalidCode => write!(f, "invalid OTP code"),
+            OtpError::Throttled(secs) => write!(f, "too many failed attempts, retry after {} seconds", secs),
+            OtpError::LockedOut => write!(f, "too many failed attempts, account is locked out"),
+            OtpError::StoreError(msg) => write!(f, "verifier store error: {}", msg),
+            OtpError::BufferTooSmall(len) => write!(f, "output buffer should have at least {} bytes", len),
+            OtpError::RandomError => write!(f, "failed to get random bytes from the system"),
+            OtpError::WeakSecret(bits) => write!(f, "secret should have at least {} bits", bits),
+            OtpError::SecretTooLong(len) => write!(f, "secret should have at most {} bytes", len),
+            OtpError::InvalidUri => write!(f, "invalid otpauth URI"),
+            OtpError::ImportError(msg) => write!(f, "import error: {}", msg),
+            OtpError::ExportError(msg) => write!(f, "export error: {}", msg),
+            OtpError::QrError(msg) => write!(f, "QR code error: {}", msg),
+            OtpError::VaultError(msg) => write!(f, "vault error: {}", msg),
+            OtpError::SecretStoreError(msg) => write!(f, "secret store error: {}", msg),
+            OtpError::ShareError(msg) => write!(f, "secret sharing error: {}", msg),
+            OtpError::SyncError(msg) => write!(f, "sync error: {}", msg),
+            OtpError::KeyBackendError(msg) => write!(f, "key backend error: {}", msg),
+            OtpError::YubiKeyError(msg) => write!(f, "YubiKey error: {}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OtpError {}
+
+
+#[cfg(test)]
+mod test {
+    use super::OtpError;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(OtpError::InvalidDigits(5).to_string(), "unsupported OTP length 5");
+        assert_eq!(OtpError::DecodeError.to_string(), "invalid Base 32 string");
+    }
+}
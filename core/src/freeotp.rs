@@ -0,0 +1,185 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The backups of FreeOTP+ and the legacy FreeOTP.
+//!
+//! FreeOTP+ exports a JSON object with the `tokens` and their order. The
+//! legacy FreeOTP keeps the same tokens in the key-value file of its
+//! Android preferences, `tokens.xml`, each token a JSON string under its
+//! key next to the `tokenOrder` key. Both store secrets as arrays of
+//! signed bytes.
+//!
+//! ```
+//! use yotp_core::freeotp;
+//!
+//! let accounts = freeotp::import(r#"{"tokenOrder": ["Example:alice"], "tokens": [{
+//!     "algo": "SHA1", "counter": 0, "digits": 6, "issuerExt": "Example", "label": "alice",
+//!     "period": 30, "secret": [72, 101, 108, 108, 111, 33, -34, -83, -66, -17], "type": "TOTP"}]}"#).unwrap();
+//! assert_eq!(accounts[0].secret.to_base32(), "JBSWY3DPEHPK3PXP");
+//! ```
+
+use roxmltree::Document;
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{OtpAuthUri, OtpType};
+
+#[derive(Deserialize)]
+struct Backup {
+    tokens: Vec<Token>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Token {
+    #[serde(default = "default_algorithm")]
+    algo: String,
+    #[serde(default)]
+    counter: u64,
+    #[serde(default = "default_digits")]
+    digits: usize,
+    #[serde(default)]
+    issuer_ext: String,
+    #[serde(default)]
+    issuer_int: String,
+    #[serde(default)]
+    issuer_alt: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    label_alt: String,
+    #[serde(default = "default_period")]
+    period: u64,
+    secret: Vec<i8>,
+    #[serde(rename = "type")]
+    token_type: String,
+}
+
+fn default_algorithm() -> String {
+    "SHA1".into()
+}
+
+fn default_digits() -> usize {
+    6
+}
+
+fn default_period() -> u64 {
+    30
+}
+
+/// Read the accounts of a FreeOTP+ JSON backup.
+///
+/// Returns [`OtpError::ImportError`] if the backup is malformed or has
+/// tokens of unsupported types or algorithms.
+pub fn import(json: &str) -> Result<Vec<OtpAuthUri>, OtpError> {
+    let backup: Backup = serde_json::from_str(json).map_err(|e| import_error(&e.to_string()))?;
+    backup.tokens.iter().map(import_token).collect()
+}
+
+/// Read the accounts of the `tokens.xml` preferences file of the legacy
+/// FreeOTP, in the order of its `tokenOrder` key if present. Tokens not in
+/// the order come last, in the order of the file.
+///
+/// Returns [`OtpError::ImportError`] for the reasons of [`import`].
+pub fn import_legacy(xml: &str) -> Result<Vec<OtpAuthUri>, OtpError> {
+    let document = Document::parse(xml).map_err(|e| import_error(&e.to_string()))?;
+    let mut order: Vec<String> = Vec::new();
+    let mut tokens = Vec::new();
+    for node in document.root_element().children().filter(|node| node.has_tag_name("string")) {
+        let name = node.attribute("name").unwrap_or_default();
+        let value = Zeroizing::new(node.text().unwrap_or_default().to_string());
+        if name == "tokenOrder" {
+            order = serde_json::from_str(&value).map_err(|e| import_error(&e.to_string()))?;
+        } else {
+            let token: Token = serde_json::from_str(&value).map_err(|e| import_error(&e.to_string()))?;
+            tokens.push((name.to_string(), token));
+        }
+    }
+    tokens.sort_by_key(|(name, _)| order.iter().position(|key| key == name).unwrap_or(order.len()));
+    tokens.iter().map(|(_, token)| import_token(token)).collect()
+}
+
+fn import_token(token: &Token) -> Result<OtpAuthUri, OtpError> {
+    let otp_type = match token.token_type.as_str() {
+        "TOTP" => OtpType::Totp,
+        "HOTP" => OtpType::Hotp,
+        other => return Err(import_error(&format!("unsupported token type {}", other)))
+    };
+    let issuer = [&token.issuer_alt, &token.issuer_ext, &token.issuer_int].into_iter().find(|issuer| !issuer.is_empty());
+    let account = [&token.label_alt, &token.label].into_iter().find(|label| !label.is_empty());
+    let secret = Zeroizing::new(token.secret.iter().map(|&b| b as u8).collect::<Vec<u8>>());
+    Ok(OtpAuthUri {
+        otp_type,
+        account: account.cloned().unwrap_or_default(),
+        issuer: issuer.cloned(),
+        secret: Secret::from_raw(&secret),
+        algorithm: Algorithm::from_name(&token.algo).ok_or_else(|| import_error("unsupported algorithm"))?,
+        digits: token.digits,
+        period: if otp_type == OtpType::Totp { token.period } else { 30 },
+        counter: if otp_type == OtpType::Hotp { token.counter } else { 0 },
+    })
+}
+
+fn import_error(msg: &str) -> OtpError {
+    OtpError::ImportError(format!("FreeOTP: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::uri::OtpType;
+
+    use super::{import, import_legacy};
+
+    #[test]
+    fn test_import() {
+        let json = r#"{"tokenOrder": ["Example:alice", "bob"], "tokens": [
+            {"algo": "SHA256", "counter": 0, "digits": 8, "issuerExt": "Example", "issuerInt": "Example", "label": "alice",
+             "period": 60, "secret": [72, 101, 108, 108, 111, 33, -34, -83, -66, -17], "type": "TOTP"},
+            {"algo": "SHA1", "counter": 5, "digits": 6, "issuerExt": "", "label": "bob", "labelAlt": "Bob",
+             "period": 30, "secret": [49, 50, 51, 52, 53, 54, 55, 56, 57, 48], "type": "HOTP"}]}"#;
+        let accounts = import(json).unwrap();
+        assert_eq!(accounts[0].issuer.as_deref(), Some("Example"));
+        assert_eq!((accounts[0].algorithm, accounts[0].digits, accounts[0].period), (Algorithm::Sha256, 8, 60));
+        assert_eq!(accounts[0].secret.to_base32(), "JBSWY3DPEHPK3PXP");
+        assert_eq!((accounts[1].otp_type, accounts[1].counter), (OtpType::Hotp, 5));
+        assert_eq!((accounts[1].account.as_str(), accounts[1].issuer.as_deref()), ("Bob", None));
+        assert_eq!(accounts[1].secret.as_bytes(), b"1234567890");
+
+        let json = r#"{"tokens": [{"secret": [1], "type": "STEAM", "label": "a"}]}"#;
+        assert!(matches!(import(json), Err(OtpError::ImportError(_))));
+        assert!(matches!(import("[]"), Err(OtpError::ImportError(_))));
+    }
+
+    #[test]
+    fn test_import_legacy() {
+        let xml = r#"<?xml version='1.0' encoding='utf-8' standalone='yes' ?>
+<map>
+    <string name="bob">{&quot;algo&quot;:&quot;SHA1&quot;,&quot;counter&quot;:5,&quot;digits&quot;:6,&quot;label&quot;:&quot;bob&quot;,&quot;period&quot;:30,&quot;secret&quot;:[49,50,51],&quot;type&quot;:&quot;HOTP&quot;}</string>
+    <string name="tokenOrder">[&quot;Example:alice&quot;,&quot;bob&quot;]</string>
+    <string name="Example:alice">{&quot;algo&quot;:&quot;SHA1&quot;,&quot;counter&quot;:0,&quot;digits&quot;:6,&quot;issuerExt&quot;:&quot;Example&quot;,&quot;label&quot;:&quot;alice&quot;,&quot;period&quot;:30,&quot;secret&quot;:[-1,0],&quot;type&quot;:&quot;TOTP&quot;}</string>
+</map>"#;
+        let accounts = import_legacy(xml).unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!((accounts[0].account.as_str(), accounts[0].secret.as_bytes()), ("alice", &[0xFF, 0][..]));
+        assert_eq!((accounts[1].account.as_str(), accounts[1].counter), ("bob", 5));
+        assert!(matches!(import_legacy("<map><string name=\"a\">{}</string></map>"), Err(OtpError::ImportError(_))));
+    }
+}
@@ -0,0 +1,285 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Vaults encrypted to GPG keys, with the trust model of pass: the vault
+//! opens with whichever secret key of the recipients gpg-agent holds, on
+//! a smartcard or not.
+//!
+//! A GPG vault is an OpenPGP message whose plain text is the payload of a
+//! [`Vault`](crate::vault::Vault), and is upgraded the same way when
+//! opened. The vault runs `gpg` like pass does, so `gpg --decrypt
+//! accounts.gpg` shows the accounts.
+//!
+//! ```no_run
+//! use yotp_core::gpg::GpgVault;
+//! use yotp_core::uri::OtpAuthUri;
+//!
+//! let mut vault = GpgVault::create("accounts.gpg", &["alice@example.com"]).unwrap();
+//! vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+//! vault.save().unwrap();
+//!
+//! let vault = GpgVault::open("accounts.gpg", &["alice@example.com"]).unwrap();
+//! assert_eq!(vault.accounts()[0].uri.account, "alice");
+//! ```
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use zeroize::Zeroizing;
+
+use crate::account::Account;
+use crate::vault::{decode_payload, encode_payload, write_atomic, VaultError};
+
+/// An opened GPG vault. Changes to the accounts are kept in memory until
+/// [`GpgVault::save`].
+pub struct GpgVault {
+    path: PathBuf,
+    recipients: Vec<String>,
+    accounts: Vec<Account>,
+    /// The `--homedir` of gpg, or the one of the environment if `None`.
+    homedir: Option<PathBuf>,
+}
+
+impl GpgVault {
+    /// Create an empty vault at `path` encrypted to `recipients`, the key
+    /// IDs, fingerprints or user IDs of GPG keys.
+    ///
+    /// Returns [`VaultError::Io`] if `path` exists, and
+    /// [`VaultError::Backend`] if `recipients` is empty or `gpg` fails to
+    /// encrypt to them.
+    pub fn create(path: impl AsRef<Path>, recipients: &[&str]) -> Result<GpgVault, VaultError> {
+        GpgVault::create_with_homedir(path.as_ref(), recipients, None)
+    }
+
+    fn create_with_homedir(path: &Path, recipients: &[&str], homedir: Option<&Path>) -> Result<GpgVault, VaultError> {
+        if path.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists", path.display())).into());
+        }
+        let vault =
+            GpgVault { path: path.to_owned(), recipients: to_owned(recipients), accounts: Vec::new(), homedir: homedir.map(Path::to_owned) };
+        vault.save()?;
+        Ok(vault)
+    }
+
+    /// Open the vault at `path` with the secret keys of gpg. The vault is
+    /// encrypted to `recipients` when saved. A vault of an older schema is
+    /// upgraded and saved.
+    ///
+    /// Returns [`VaultError::WrongPassphrase`] if gpg has none of the
+    /// secret keys the vault is encrypted to, and [`VaultError::Tampered`]
+    /// if the vault is truncated or modified.
+    pub fn open(path: impl AsRef<Path>, recipients: &[&str]) -> Result<GpgVault, VaultError> {
+        GpgVault::open_with_homedir(path.as_ref(), recipients, None)
+    }
+
+    fn open_with_homedir(path: &Path, recipients: &[&str], homedir: Option<&Path>) -> Result<GpgVault, VaultError> {
+        let output = gpg_command(homedir)
+            .args(["--quiet", "--batch", "--yes", "--status-fd", "2", "--decrypt"])
+            .arg(path)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| backend_error(&format!("cannot run gpg: {}", e)))?;
+        let payload = Zeroizing::new(output.stdout);
+        if !output.status.success() {
+            if !path.exists() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display())).into());
+            }
+            return Err(decrypt_error(&String::from_utf8_lossy(&output.stderr)));
+        }
+        let (accounts, migrated) = decode_payload(&payload)?;
+        let vault = GpgVault { path: path.to_owned(), recipients: to_owned(recipients), accounts, homedir: homedir.map(Path::to_owned) };
+        if migrated {
+            vault.save()?;
+        }
+        Ok(vault)
+    }
+
+    /// The path of the vault file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The keys the vault is encrypted to when saved.
+    pub fn recipients(&self) -> &[String] {
+        &self.recipients
+    }
+
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    pub fn accounts_mut(&mut self) -> &mut Vec<Account> {
+        &mut self.accounts
+    }
+
+    /// Encrypt the vault to `recipients` from now on, and save it along
+    /// with any unsaved changes of the accounts. If the save fails, the
+    /// file and the vault keep the old recipients.
+    pub fn set_recipients(&mut self, recipients: &[&str]) -> Result<(), VaultError> {
+        let old_recipients = core::mem::replace(&mut self.recipients, to_owned(recipients));
+        if let Err(e) = self.save() {
+            self.recipients = old_recipients;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Encrypt the accounts and replace the vault file atomically.
+    pub fn save(&self) -> Result<(), VaultError> {
+        if self.recipients.is_empty() {
+            return Err(backend_error("no recipients"));
+        }
+        let payload = encode_payload(&self.accounts);
+        let mut command = gpg_command(self.homedir.as_deref());
+        command.args(["--quiet", "--batch", "--yes", "--encrypt"]);
+        for recipient in &self.recipients {
+            command.arg("--recipient").arg(recipient);
+        }
+        let mut child = command
+            .args(["--output", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| backend_error(&format!("cannot run gpg: {}", e)))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // write in another thread, as gpg may fill the pipe of its output
+        // before reading all of its input
+        let (written, output) = std::thread::scope(|scope| {
+            let writer = scope.spawn(move || stdin.write_all(&payload));
+            let output = child.wait_with_output();
+            (writer.join().expect("writer does not panic"), output)
+        });
+        let output = output?;
+        if written.is_err() || !output.status.success() {
+            return Err(backend_error(&format!("encryption to {} failed", self.recipients.join(", "))));
+        }
+        write_atomic(&self.path, &output.stdout)?;
+        Ok(())
+    }
+}
+
+fn gpg_command(homedir: Option<&Path>) -> Command {
+    let mut command = Command::new("gpg");
+    if let Some(homedir) = homedir {
+        command.arg("--homedir").arg(homedir);
+    }
+    command
+}
+
+fn to_owned(recipients: &[&str]) -> Vec<String> {
+    recipients.iter().map(|recipient| recipient.to_string()).collect()
+}
+
+/// The error of a failed decryption from the status lines of gpg.
+fn decrypt_error(stderr: &str) -> VaultError {
+    let status: Vec<&str> = stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] "))
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+    let has = |keyword| status.contains(&keyword);
+    if has("NODATA") {
+        VaultError::InvalidFormat
+    } else if has("BADMDC") || has("DECRYPTION_FAILED") {
+        // gpg names the key once it decrypts the session key
+        if has("DECRYPTION_KEY") { VaultError::Tampered } else { VaultError::WrongPassphrase }
+    } else {
+        let messages: Vec<&str> = stderr.lines().filter(|line| !line.starts_with("[GNUPG:] ")).collect();
+        backend_error(&format!("decryption failed: {}", messages.join("; ")))
+    }
+}
+
+fn backend_error(msg: &str) -> VaultError {
+    VaultError::Backend(format!("gpg: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use std::process::Command;
+
+    use crate::uri::OtpAuthUri;
+    use crate::vault::VaultError;
+
+    use super::{decrypt_error, GpgVault};
+
+    #[test]
+    fn test_decrypt_error() {
+        let status = |lines: &[&str]| lines.iter().map(|line| format!("[GNUPG:] {}\n", line)).collect::<String>();
+        let error = |lines: &[&str]| decrypt_error(&status(lines));
+        assert!(matches!(error(&["ENC_TO 09E0A2667035D23F 18 0", "BEGIN_DECRYPTION", "DECRYPTION_FAILED"]), VaultError::WrongPassphrase));
+        assert!(matches!(error(&["DECRYPTION_KEY 48E8 8DCB u", "BADMDC", "DECRYPTION_FAILED"]), VaultError::Tampered));
+        assert!(matches!(error(&["NODATA 1", "NODATA 2", "FAILURE decrypt 4294967295"]), VaultError::InvalidFormat));
+        let VaultError::Backend(msg) = decrypt_error("gpg: can't open 'x'\n[GNUPG:] FAILURE decrypt 1\n") else {
+            panic!("not a backend error");
+        };
+        assert_eq!(msg, "gpg: decryption failed: gpg: can't open 'x'");
+    }
+
+    fn gpg(home: &Path, args: &[&str]) -> bool {
+        Command::new("gpg").arg("--homedir").arg(home).args(["--quiet", "--batch"]).args(args).status().is_ok_and(|s| s.success())
+    }
+
+    #[test]
+    fn test_vault() {
+        let dir = std::env::temp_dir().join(format!("yotp-gpg-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (alice, bob) = (dir.join("alice"), dir.join("bob"));
+        for home in [&alice, &bob] {
+            std::fs::create_dir_all(home).unwrap();
+            #[cfg(unix)]
+            std::fs::set_permissions(home, std::os::unix::fs::PermissionsExt::from_mode(0o700)).unwrap();
+        }
+        let user_id = |home: &Path| format!("{}@example.com", home.file_name().unwrap().to_str().unwrap());
+        let generated = [&alice, &bob]
+            .iter()
+            .all(|home| gpg(home, &["--passphrase", "", "--quick-gen-key", &user_id(home), "future-default", "default", "never"]));
+        if !generated {
+            // gpg is not installed
+            std::fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+        // the home directory is passed to gpg instead of setting GNUPGHOME,
+        // which other tests running in parallel would see
+        let open = |path: &Path, homedir: &Path| GpgVault::open_with_homedir(path, &[], Some(homedir));
+
+        let path = dir.join("accounts.gpg");
+        let mut vault = GpgVault::create_with_homedir(&path, &["alice@example.com"], Some(&alice)).unwrap();
+        vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+        vault.save().unwrap();
+        assert_eq!(open(&path, &alice).unwrap().accounts(), vault.accounts());
+        assert!(matches!(vault.set_recipients(&["0xDEADBEEF"]), Err(VaultError::Backend(_))));
+        assert!(matches!(GpgVault::create_with_homedir(&path, &["alice@example.com"], Some(&alice)), Err(VaultError::Io(_))));
+
+        let data = std::fs::read(&path).unwrap();
+        let mut flipped = data.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        let tampered = dir.join("tampered.gpg");
+        std::fs::write(&tampered, &flipped).unwrap();
+        assert!(matches!(open(&tampered, &alice), Err(VaultError::Tampered)));
+        std::fs::write(&tampered, b"not a message").unwrap();
+        assert!(matches!(open(&tampered, &alice), Err(VaultError::InvalidFormat)));
+        assert!(matches!(open(&dir.join("missing.gpg"), &alice), Err(VaultError::Io(_))));
+
+        assert!(matches!(open(&path, &bob), Err(VaultError::WrongPassphrase)));
+        for home in [&alice, &bob] {
+            let _ = Command::new("gpgconf").arg("--homedir").arg(home).args(["--kill", "gpg-agent"]).status();
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Hexadecimal encoding of secrets, as some providers hand them out.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::error::OtpError;
+
+const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Encode `data` as lower case hexadecimal digits.
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(2 * data.len());
+    for byte in data {
+        out.push(DIGITS[(byte >> 4) as usize] as char);
+        out.push(DIGITS[(byte & 0xF) as usize] as char);
+    }
+    out
+}
+
+/// Decode a string of hexadecimal digits in either case.
+///
+/// Returns [`OtpError::InvalidHex`] if `value` has an odd length or
+/// characters other than hexadecimal digits.
+pub fn decode(value: &str) -> Result<Vec<u8>, OtpError> {
+    let value = value.as_bytes();
+    if !value.len().is_multiple_of(2) {
+        return Err(OtpError::InvalidHex);
+    }
+    value
+        .chunks_exact(2)
+        .map(|pair| Ok(decode_digit(pair[0])? << 4 | decode_digit(pair[1])?))
+        .collect()
+}
+
+fn decode_digit(c: u8) -> Result<u8, OtpError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(OtpError::InvalidHex)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+
+    use super::{decode, encode};
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(decode("3132333435363738393031323334353637383930").unwrap(), b"12345678901234567890");
+        assert_eq!(decode("DEADbeef").unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(decode("").unwrap(), b"");
+        assert_eq!(encode(&[0xde, 0xad, 0xbe, 0xef, 0x01]), "deadbeef01");
+        assert_eq!(decode("abc"), Err(OtpError::InvalidHex));
+        assert_eq!(decode("0g"), Err(OtpError::InvalidHex));
+    }
+}
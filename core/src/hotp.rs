@@ -0,0 +1,263 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::code::{constant_time_eq, normalize_code, Code};
+use crate::error::OtpError;
+use crate::otp::{self, Algorithm, PreparedKey};
+use crate::secret::Secret;
+
+/// A stateful HOTP generator owning the key and the current counter.
+///
+/// ```
+/// use yotp_core::Hotp;
+///
+/// let mut hotp = Hotp::builder()
+///     .secret(b"12345678901234567890")
+///     .build()
+///     .unwrap();
+/// assert_eq!(hotp.next().unwrap(), "755224");
+/// assert_eq!(hotp.next().unwrap(), "287082");
+/// assert_eq!(hotp.counter(), 2);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotp {
+    secret: Secret,
+    counter: u64,
+    digits: usize,
+    algorithm: Algorithm,
+}
+
+impl Hotp {
+    /// Start building a [`Hotp`] with the default parameters: counter 0,
+    /// 6 digits and SHA-1.
+    pub fn builder() -> HotpBuilder {
+        HotpBuilder::default()
+    }
+
+    /// Generate the code for the current counter and advance the counter.
+    ///
+    /// Returns [`OtpError::CounterOverflow`] without a code if the counter
+    /// is `u64::MAX` and cannot be advanced.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Code, OtpError> {
+        let next = self.counter.checked_add(1).ok_or(OtpError::CounterOverflow)?;
+        let code = self.generate(self.counter)?;
+        self.counter = next;
+        Ok(code)
+    }
+
+    /// Look ahead the code `n` counters after the current one without
+    /// changing the counter. `peek(0)` is the code `next()` would return.
+    ///
+    /// Returns [`OtpError::CounterOverflow`] if that counter is past
+    /// `u64::MAX`.
+    pub fn peek(&self, n: u64) -> Result<Code, OtpError> {
+        self.generate(self.counter.checked_add(n).ok_or(OtpError::CounterOverflow)?)
+    }
+
+    /// Check `code` against the counters from the current one to
+    /// `look_ahead` counters after it. On success the counter is moved
+    /// past the matched one, following the resynchronization of RFC 4226.
+    /// `code` is normalized by [`normalize_code`] first.
+    ///
+    /// All counters in the range are compared in constant time without
+    /// returning early. The range ends at `u64::MAX`, and a code matching
+    /// that counter returns [`OtpError::CounterOverflow`], as the counter
+    /// cannot be moved past it.
+    pub fn verify(&mut self, code: &str, look_ahead: u64) -> Result<bool, OtpError> {
+        let code = normalize_code(code);
+        let last = self.counter.saturating_add(look_ahead);
+        let key = PreparedKey::new(self.secret.as_bytes(), self.algorithm)?;
+        let mut matched = None;
+        for c in self.counter..=last {
+            let expected = key.hotp(c, self.digits)?;
+            if constant_time_eq(expected.as_str(), &code) && matched.is_none() {
+                matched = Some(c);
+            }
+        }
+        match matched {
+            Some(c) => {
+                self.counter = c.checked_add(1).ok_or(OtpError::CounterOverflow)?;
+                Ok(true)
+            }
+            None => Ok(false)
+        }
+    }
+
+    /// Generate the code for an arbitrary counter `c`.
+    pub fn generate(&self, c: u64) -> Result<Code, OtpError> {
+        otp::hotp_with_algorithm(self.secret.as_bytes(), c, self.digits, self.algorithm)
+    }
+
+    pub fn secret(&self) -> &[u8] {
+        self.secret.as_bytes()
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn secret_ref(&self) -> &Secret {
+        &self.secret
+    }
+
+    /// The counter that will be used by the next call to `next()`.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    /// Overwrite the counter, e.g. after a resynchronization.
+    pub fn set_counter(&mut self, counter: u64) {
+        self.counter = counter;
+    }
+
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+}
+
+/// Builder of [`Hotp`]. See [`Hotp::builder`].
+#[derive(Debug, Clone)]
+pub struct HotpBuilder {
+    secret: Secret,
+    counter: u64,
+    digits: usize,
+    algorithm: Algorithm,
+}
+
+impl Default for HotpBuilder {
+    fn default() -> Self {
+        HotpBuilder {
+            secret: Secret::default(),
+            counter: 0,
+            digits: 6,
+            algorithm: Algorithm::Sha1,
+        }
+    }
+}
+
+impl HotpBuilder {
+    /// Set the shared secret as raw bytes, e.g. a [`Secret`].
+    pub fn secret(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.secret = Secret::new(secret.as_ref().to_vec());
+        self
+    }
+
+    /// Set the initial counter.
+    pub fn counter(mut self, counter: u64) -> Self {
+        self.counter = counter;
+        self
+    }
+
+    /// Set the length of generated codes.
+    pub fn digits(mut self, digits: usize) -> Self {
+        self.digits = digits;
+        self
+    }
+
+    /// Set the hash function used by the HMAC.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Validate the parameters and build the [`Hotp`].
+    pub fn build(self) -> Result<Hotp, OtpError> {
+        if !otp::DIGITS_RANGE.contains(&self.digits) {
+            return Err(OtpError::InvalidDigits(self.digits));
+        }
+        if self.secret.is_empty() {
+            return Err(OtpError::KeyTooShort);
+        }
+        Ok(Hotp {
+            secret: self.secret,
+            counter: self.counter,
+            digits: self.digits,
+            algorithm: self.algorithm,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+
+    use super::Hotp;
+
+    // Test vectors from RFC 4226 Appendix D.
+    const RFC4226_CODES: [&str; 10] = [
+        "755224", "287082", "359152", "969429", "338314",
+        "254676", "287922", "162583", "399871", "520489",
+    ];
+
+    #[test]
+    fn test_next() {
+        let mut hotp = Hotp::builder().secret(b"12345678901234567890").build().unwrap();
+        for (i, code) in RFC4226_CODES.iter().enumerate() {
+            assert_eq!(hotp.counter(), i as u64);
+            assert_eq!(&hotp.next().unwrap(), code);
+        }
+        assert_eq!(hotp.counter(), 10);
+    }
+
+    #[test]
+    fn test_peek() {
+        let hotp = Hotp::builder().secret(b"12345678901234567890").counter(3).build().unwrap();
+        assert_eq!(hotp.peek(0).unwrap(), RFC4226_CODES[3]);
+        assert_eq!(hotp.peek(4).unwrap(), RFC4226_CODES[7]);
+        assert_eq!(hotp.counter(), 3);
+    }
+
+    #[test]
+    fn test_counter_overflow() {
+        let mut hotp = Hotp::builder().secret(b"12345678901234567890").counter(u64::MAX).build().unwrap();
+        assert_eq!(hotp.peek(0), hotp.generate(u64::MAX));
+        assert_eq!(hotp.peek(1), Err(OtpError::CounterOverflow));
+        assert_eq!(hotp.next(), Err(OtpError::CounterOverflow));
+        assert_eq!(hotp.counter(), u64::MAX);
+    }
+
+    #[test]
+    fn test_builder_invalid() {
+        assert_eq!(Hotp::builder().build(), Err(OtpError::KeyTooShort));
+        assert_eq!(Hotp::builder().secret(b"key").digits(5).build(), Err(OtpError::InvalidDigits(5)));
+    }
+
+    #[test]
+    fn test_verify_resync() {
+        let mut hotp = Hotp::builder().secret(b"12345678901234567890").counter(2).build().unwrap();
+        assert!(!hotp.verify(RFC4226_CODES[6], 3).unwrap());
+        assert_eq!(hotp.counter(), 2);
+        assert!(hotp.verify(RFC4226_CODES[5], 3).unwrap());
+        assert_eq!(hotp.counter(), 6);
+        // a used code is never accepted again
+        assert!(!hotp.verify(RFC4226_CODES[5], 3).unwrap());
+        assert!(hotp.verify("287 922", 0).unwrap());
+        assert_eq!(hotp.counter(), 7);
+    }
+
+    #[test]
+    fn test_verify_overflow() {
+        let mut hotp = Hotp::builder().secret(b"12345678901234567890").counter(u64::MAX - 1).build().unwrap();
+        let last = hotp.peek(1).unwrap();
+        assert_eq!(hotp.verify(last.as_str(), 5), Err(OtpError::CounterOverflow));
+        assert_eq!(hotp.counter(), u64::MAX - 1);
+        let first = hotp.peek(0).unwrap();
+        assert!(hotp.verify(first.as_str(), 5).unwrap());
+        assert_eq!(hotp.counter(), u64::MAX);
+    }
+}
@@ -0,0 +1,169 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The TOTP fields of KeePass and KeePassXC entries.
+//!
+//! KeePassXC keeps an `otpauth://` URI in the `otp` custom field. Older
+//! versions and KeePass plugins such as KeeTrayTOTP use the `TOTP Seed`
+//! field with the Base 32 secret and the `TOTP Settings` field with the
+//! period and the number of digits, e.g. `30;6`. Those can express
+//! HMAC-SHA-1 TOTP accounts only.
+//!
+//! ```
+//! use yotp_core::keepass;
+//!
+//! let uri = keepass::read_fields([("TOTP Seed", "JBSWY3DPEHPK3PXP"), ("TOTP Settings", "60;8")]).unwrap().unwrap();
+//! assert_eq!((uri.period, uri.digits), (60, 8));
+//! ```
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{OtpAuthUri, OtpType};
+
+/// The custom field of KeePassXC holding an `otpauth://` URI.
+pub const OTP_FIELD: &str = "otp";
+/// The legacy field holding the Base 32 secret.
+pub const SEED_FIELD: &str = "TOTP Seed";
+/// The legacy field holding the period and the number of digits.
+pub const SETTINGS_FIELD: &str = "TOTP Settings";
+
+/// Read the account of the fields of an entry, given as pairs of field
+/// names and values. The `otp` field is preferred over the legacy fields,
+/// and the settings default to `30;6` if only the seed is set.
+///
+/// Returns `Ok(None)` if the entry has no TOTP fields, and
+/// [`OtpError::ImportError`] if they do not parse. Steam accounts, whose
+/// settings have `S` as the number of digits, are not supported.
+pub fn read_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Option<OtpAuthUri>, OtpError> {
+    let (mut otp, mut seed, mut settings) = (None, None, None);
+    for (name, value) in fields {
+        match name {
+            OTP_FIELD => otp = Some(value),
+            SEED_FIELD => seed = Some(value),
+            SETTINGS_FIELD => settings = Some(value),
+            _ => {}
+        }
+    }
+    if let Some(otp) = otp {
+        return OtpAuthUri::parse(otp.trim()).map(Some).map_err(|e| import_error(&e.to_string()));
+    }
+    match seed {
+        Some(seed) => parse_legacy(seed, settings.unwrap_or("30;6")).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Parse the legacy `TOTP Seed` and `TOTP Settings` values. A third part
+/// of the settings, the time server of KeeTrayTOTP, is ignored.
+///
+/// Returns [`OtpError::ImportError`] if they do not parse.
+pub fn parse_legacy(seed: &str, settings: &str) -> Result<OtpAuthUri, OtpError> {
+    let mut parts = settings.trim().split(';');
+    let period = parts
+        .next()
+        .and_then(|period| period.trim().parse().ok())
+        .filter(|&period| period > 0)
+        .ok_or_else(|| import_error("invalid period in TOTP Settings"))?;
+    let digits = match parts.next().map(str::trim) {
+        Some("S") => return Err(import_error("Steam accounts are not supported")),
+        Some(digits) => digits
+            .parse()
+            .ok()
+            .filter(|digits| crate::otp::DIGITS_RANGE.contains(digits))
+            .ok_or_else(|| import_error("invalid digits in TOTP Settings"))?,
+        None => 6,
+    };
+    Ok(OtpAuthUri {
+        otp_type: OtpType::Totp,
+        account: String::new(),
+        issuer: None,
+        secret: Secret::from_base32(&seed.to_ascii_uppercase()).map_err(|_| import_error("TOTP Seed is not valid Base 32"))?,
+        algorithm: Algorithm::Sha1,
+        digits,
+        period,
+        counter: 0,
+    })
+}
+
+/// The fields to store `uri` in an entry: the `otp` field, and the legacy
+/// fields too if they can express the account, so that older clients can
+/// read it as well.
+///
+/// The values contain the secret, so they must not be logged.
+pub fn write_fields(uri: &OtpAuthUri) -> Vec<(&'static str, String)> {
+    let mut fields = vec![(OTP_FIELD, uri.to_string())];
+    if uri.otp_type == OtpType::Totp && uri.algorithm == Algorithm::Sha1 {
+        fields.push((SEED_FIELD, uri.secret.to_base32()));
+        fields.push((SETTINGS_FIELD, format!("{};{}", uri.period, uri.digits)));
+    }
+    fields
+}
+
+fn import_error(msg: &str) -> OtpError {
+    OtpError::ImportError(format!("KeePass: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::uri::OtpAuthUri;
+
+    use super::{parse_legacy, read_fields, write_fields, OTP_FIELD, SEED_FIELD, SETTINGS_FIELD};
+
+    #[test]
+    fn test_read_fields() {
+        let uri = "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example&algorithm=SHA256";
+        let fields = [("UserName", "alice"), (OTP_FIELD, uri), (SEED_FIELD, "GEZDGNBV")];
+        assert_eq!(read_fields(fields).unwrap(), Some(OtpAuthUri::parse(uri).unwrap()));
+
+        let read = read_fields([(SEED_FIELD, "jbswy3dpehpk3pxp")]).unwrap().unwrap();
+        assert_eq!((read.period, read.digits, read.algorithm), (30, 6, Algorithm::Sha1));
+        assert_eq!(read_fields([("Password", "hunter2")]).unwrap(), None);
+        assert!(matches!(read_fields([(OTP_FIELD, "otpauth://totp/a")]), Err(OtpError::ImportError(_))));
+    }
+
+    #[test]
+    fn test_parse_legacy() {
+        let uri = parse_legacy("JBSWY3DPEHPK3PXP", "30;8;https://time.example.com").unwrap();
+        assert_eq!((uri.period, uri.digits), (30, 8));
+        assert_eq!(parse_legacy("JBSWY3DPEHPK3PXP", "45").unwrap().period, 45);
+        for settings in ["30;S", "0;6", "x;6", "30;4", "30;x"] {
+            assert!(matches!(parse_legacy("JBSWY3DPEHPK3PXP", settings), Err(OtpError::ImportError(_))), "{}", settings);
+        }
+        assert!(matches!(parse_legacy("!", "30;6"), Err(OtpError::ImportError(_))));
+    }
+
+    #[test]
+    fn test_write_fields() {
+        let uri = OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example&digits=8&period=60").unwrap();
+        let fields = write_fields(&uri);
+        assert_eq!(fields[1..], [(SEED_FIELD, "JBSWY3DPEHPK3PXP".into()), (SETTINGS_FIELD, "60;8".into())]);
+        let read = read_fields(fields.iter().map(|(name, value)| (*name, value.as_str()))).unwrap();
+        assert_eq!(read, Some(uri));
+        let legacy = read_fields(fields[1..].iter().map(|(name, value)| (*name, value.as_str()))).unwrap().unwrap();
+        assert_eq!((legacy.secret.to_base32(), legacy.period, legacy.digits), ("JBSWY3DPEHPK3PXP".into(), 60, 8));
+
+        let uri = OtpAuthUri::parse("otpauth://hotp/a?secret=JBSWY3DPEHPK3PXP&counter=3").unwrap();
+        assert_eq!(write_fields(&uri).len(), 1);
+    }
+}
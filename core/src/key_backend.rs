@@ -0,0 +1,122 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::vec::Vec;
+
+use crate::code::Code;
+use crate::error::OtpError;
+use crate::otp::{big_endian_u64, format_decimal, time_counter, truncate, Algorithm, PreparedKey, DIGITS_RANGE};
+
+/// A key that computes HMACs without handing out the secret, e.g. one
+/// kept in a PKCS#11 token or an HSM, so that the secret is never in the
+/// memory of the process. See [`hotp_with_backend`].
+///
+/// Unlike [`hotp_with_mac`](crate::hotp_with_mac), which takes a MAC of
+/// the process keyed with the secret, the HMAC may fail, e.g. when the
+/// token is removed.
+pub trait KeyBackend {
+    /// The hash function of the HMAC.
+    fn algorithm(&self) -> Algorithm;
+
+    /// The HMAC of `message` with the key.
+    ///
+    /// Implementations report failures of the backend as
+    /// [`OtpError::KeyBackendError`].
+    fn hmac(&self, message: &[u8]) -> Result<Vec<u8>, OtpError>;
+}
+
+/// A key in memory, for tests and for callers that switch between
+/// software and hardware keys.
+impl KeyBackend for PreparedKey {
+    fn algorithm(&self) -> Algorithm {
+        PreparedKey::algorithm(self)
+    }
+
+    fn hmac(&self, message: &[u8]) -> Result<Vec<u8>, OtpError> {
+        Ok(PreparedKey::hmac(self, message))
+    }
+}
+
+/// HOTP of counter `c` with the HMAC computed by `backend`. Only the
+/// counter is sent to the backend.
+///
+/// ```
+/// use yotp_core::{hotp_with_backend, Algorithm, PreparedKey};
+///
+/// let key = PreparedKey::new(b"12345678901234567890", Algorithm::Sha1).unwrap();
+/// assert_eq!(hotp_with_backend(&key, 1, 6).unwrap(), "287082");
+/// ```
+///
+/// Besides the error of `backend`, it returns [`OtpError::InvalidDigits`]
+/// if `digit_len` is out of range and [`OtpError::InvalidOffset`] if the
+/// HMAC is too short for the dynamic truncation.
+pub fn hotp_with_backend<B: KeyBackend + ?Sized>(backend: &B, c: u64, digit_len: usize) -> Result<Code, OtpError> {
+    if !DIGITS_RANGE.contains(&digit_len) {
+        return Err(OtpError::InvalidDigits(digit_len));
+    }
+    let hash = backend.hmac(&big_endian_u64(c))?;
+    Ok(format_decimal(truncate(&hash, None)?, digit_len))
+}
+
+/// TOTP for an explicit time `t` with the HMAC computed by `backend`.
+/// Other parameters are the same as [`totp_at`](crate::totp_at).
+pub fn totp_at_with_backend<B: KeyBackend + ?Sized>(backend: &B, t: u64, t0: u64, interval: u64, digit_len: usize) -> Result<Code, OtpError> {
+    hotp_with_backend(backend, time_counter(t, t0, interval)?, digit_len)
+}
+
+/// TOTP of the current time with the HMAC computed by `backend`. Other
+/// parameters are the same as [`totp`](crate::totp).
+#[cfg(feature = "std")]
+pub fn totp_with_backend<B: KeyBackend + ?Sized>(backend: &B, t0: u64, interval: u64, digit_len: usize) -> Result<Code, OtpError> {
+    totp_at_with_backend(backend, crate::otp::now()?, t0, interval, digit_len)
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use crate::error::OtpError;
+    use crate::otp::{Algorithm, PreparedKey};
+
+    use super::{hotp_with_backend, totp_at_with_backend, KeyBackend};
+
+    struct RemovedToken;
+
+    impl KeyBackend for RemovedToken {
+        fn algorithm(&self) -> Algorithm {
+            Algorithm::Sha1
+        }
+
+        fn hmac(&self, _message: &[u8]) -> Result<Vec<u8>, OtpError> {
+            Err(OtpError::KeyBackendError("token removed".into()))
+        }
+    }
+
+    #[test]
+    fn test_backend() {
+        // RFC 4226 Appendix D and RFC 6238 Appendix B
+        let key = PreparedKey::new(b"12345678901234567890", Algorithm::Sha1).unwrap();
+        let backend: &dyn KeyBackend = &key;
+        let codes: Vec<_> = (0..3).map(|c| hotp_with_backend(backend, c, 6).unwrap()).collect();
+        assert_eq!(codes, ["755224", "287082", "359152"]);
+        assert_eq!(totp_at_with_backend(backend, 59, 0, 30, 8).unwrap(), "94287082");
+        let key = PreparedKey::new(b"12345678901234567890123456789012", Algorithm::Sha256).unwrap();
+        assert_eq!(totp_at_with_backend(&key, 59, 0, 30, 8).unwrap(), "46119246");
+
+        assert!(matches!(hotp_with_backend(backend, 0, 5), Err(OtpError::InvalidDigits(5))));
+        assert_eq!(hotp_with_backend(&RemovedToken, 0, 6), Err(OtpError::KeyBackendError("token removed".into())));
+    }
+}
@@ -0,0 +1,80 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The keyring of the operating system as a [`SecretStore`]: the Secret
+//! Service on Linux and the BSDs, the Keychain on macOS and the
+//! Credential Manager on Windows.
+//!
+//! With the `vault` feature, `Vault::store_key` keeps the key of a vault
+//! in the keyring, so that it opens without the passphrase.
+//!
+//! ```no_run
+//! use yotp_core::keyring::KeyringBackend;
+//! use yotp_core::secret_store::SecretStore;
+//!
+//! let keyring = KeyringBackend::new("yotp");
+//! keyring.set("github", b"Hello!\xde\xad\xbe\xef").unwrap();
+//! assert!(keyring.get("github").unwrap().is_some());
+//! ```
+
+use ::keyring::Entry;
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+use crate::secret_store::SecretStore;
+
+/// The secrets of a service in the keyring of the operating system. The
+/// names of the secrets are the user names of the keyring entries.
+pub struct KeyringBackend {
+    service: String,
+}
+
+impl KeyringBackend {
+    /// The secrets of `service`, e.g. the name of the application.
+    pub fn new(service: &str) -> KeyringBackend {
+        KeyringBackend { service: service.to_owned() }
+    }
+
+    fn entry(&self, name: &str) -> Result<Entry, OtpError> {
+        Entry::new(&self.service, name).map_err(store_error)
+    }
+}
+
+impl SecretStore for KeyringBackend {
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, OtpError> {
+        match self.entry(name)?.get_secret() {
+            Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+            Err(::keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(store_error(e)),
+        }
+    }
+
+    fn set(&self, name: &str, secret: &[u8]) -> Result<(), OtpError> {
+        self.entry(name)?.set_secret(secret).map_err(store_error)
+    }
+
+    fn delete(&self, name: &str) -> Result<bool, OtpError> {
+        match self.entry(name)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(::keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(store_error(e)),
+        }
+    }
+}
+
+fn store_error(e: ::keyring::Error) -> OtpError {
+    OtpError::SecretStoreError(format!("keyring: {}", e))
+}
@@ -0,0 +1,133 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The JSON export of LastPass Authenticator.
+//!
+//! LastPass Authenticator only has TOTP accounts. The names the user
+//! edited, `issuerName` and `userName`, are preferred over the original
+//! ones from the provisioning URI.
+//!
+//! ```
+//! use yotp_core::lastpass;
+//!
+//! let accounts = lastpass::import(r#"{"version": 3, "accounts": [{"issuerName": "Example",
+//!     "userName": "alice", "secret": "JBSWY3DPEHPK3PXP", "timeStep": 30, "digits": 6,
+//!     "algorithm": "SHA1"}]}"#).unwrap();
+//! assert_eq!(accounts[0].account, "alice");
+//! ```
+
+use serde::Deserialize;
+
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{OtpAuthUri, OtpType};
+
+#[derive(Deserialize)]
+struct Export {
+    accounts: Vec<Account>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Account {
+    #[serde(default)]
+    issuer_name: String,
+    #[serde(default)]
+    original_issuer_name: String,
+    #[serde(default)]
+    user_name: String,
+    #[serde(default)]
+    original_user_name: String,
+    #[serde(default)]
+    secret: String,
+    #[serde(default = "default_time_step")]
+    time_step: u64,
+    #[serde(default = "default_digits")]
+    digits: usize,
+    #[serde(default = "default_algorithm")]
+    algorithm: String,
+}
+
+fn default_time_step() -> u64 {
+    30
+}
+
+fn default_digits() -> usize {
+    6
+}
+
+fn default_algorithm() -> String {
+    "SHA1".into()
+}
+
+/// Read the accounts of a LastPass Authenticator export.
+///
+/// Returns [`OtpError::ImportError`] if the export is malformed or has
+/// accounts without a secret, such as the push notification accounts of
+/// LastPass itself, which are not skipped silently.
+pub fn import(json: &str) -> Result<Vec<OtpAuthUri>, OtpError> {
+    let export: Export = serde_json::from_str(json).map_err(|e| import_error(&e.to_string()))?;
+    export.accounts.iter().map(import_account).collect()
+}
+
+fn import_account(account: &Account) -> Result<OtpAuthUri, OtpError> {
+    let name = [&account.user_name, &account.original_user_name].into_iter().find(|name| !name.is_empty());
+    let issuer = [&account.issuer_name, &account.original_issuer_name].into_iter().find(|issuer| !issuer.is_empty());
+    if account.secret.is_empty() {
+        return Err(import_error(&format!("account {} has no secret", name.map_or("", String::as_str))));
+    }
+    Ok(OtpAuthUri {
+        otp_type: OtpType::Totp,
+        account: name.cloned().unwrap_or_default(),
+        issuer: issuer.cloned(),
+        secret: Secret::from_base32(&account.secret).map_err(|_| import_error("secret is not valid Base 32"))?,
+        algorithm: Algorithm::from_name(&account.algorithm).ok_or_else(|| import_error("unsupported algorithm"))?,
+        digits: account.digits,
+        period: account.time_step,
+        counter: 0,
+    })
+}
+
+fn import_error(msg: &str) -> OtpError {
+    OtpError::ImportError(format!("LastPass: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+
+    use super::import;
+
+    #[test]
+    fn test_import() {
+        let json = r#"{"deviceName": "phone", "version": 3, "localDeviceId": "x", "accounts": [
+            {"accountID": "", "issuerName": "", "originalIssuerName": "Example", "userName": "Alice",
+             "originalUserName": "alice@example.com", "pushNotification": false, "secret": "JBSWY3DPEHPK3PXP",
+             "timeStep": 60, "digits": 8, "creationTimestamp": 1700000000000, "isFavorite": false,
+             "algorithm": "SHA256", "folderData": {"folderId": 0, "position": 0}}],
+            "folders": [{"id": 0, "name": "Favorites", "isOpened": true}]}"#;
+        let accounts = import(json).unwrap();
+        assert_eq!((accounts[0].account.as_str(), accounts[0].issuer.as_deref()), ("Alice", Some("Example")));
+        assert_eq!((accounts[0].algorithm, accounts[0].digits, accounts[0].period), (Algorithm::Sha256, 8, 60));
+        assert_eq!(accounts[0].secret.to_base32(), "JBSWY3DPEHPK3PXP");
+
+        let json = r#"{"accounts": [{"issuerName": "LastPass", "userName": "a", "pushNotification": true, "secret": ""}]}"#;
+        assert!(matches!(import(json), Err(OtpError::ImportError(_))));
+        assert!(matches!(import("[]"), Err(OtpError::ImportError(_))));
+    }
+}
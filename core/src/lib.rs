@@ -14,9 +14,201 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-extern crate crypto;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod account;
+#[cfg(feature = "aegis")]
+pub mod aegis;
+#[cfg(feature = "age")]
+pub mod age;
+#[cfg(feature = "andotp")]
+pub mod andotp;
 pub mod base32;
+mod base64;
+#[cfg(feature = "biometric")]
+pub mod biometric;
+pub mod bitwarden;
+mod clock;
+mod code;
+pub mod csv;
+#[cfg(feature = "std")]
+mod drift;
+mod encoding;
+mod error;
+#[cfg(feature = "freeotp")]
+pub mod freeotp;
+#[cfg(feature = "gpg")]
+pub mod gpg;
+pub mod hex;
+mod hotp;
+pub mod keepass;
+mod key_backend;
+#[cfg(feature = "keyring")]
+pub mod keyring;
+#[cfg(feature = "lastpass")]
+pub mod lastpass;
+pub mod migration;
+mod motp;
+pub mod ocra;
 mod otp;
+#[cfg(feature = "paper")]
+pub mod paper;
+#[cfg(feature = "pass")]
+pub mod pass;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+#[cfg(feature = "pskc")]
+pub mod pskc;
+#[cfg(feature = "qr")]
+pub mod qr;
+#[cfg(feature = "std")]
+mod replay;
+#[cfg(feature = "s3")]
+pub mod s3;
+mod secret;
+#[cfg(feature = "std")]
+pub mod secret_store;
+pub mod seed;
+#[cfg(feature = "serde")]
+mod serialization;
+pub mod shamir;
+mod steam;
+#[cfg(feature = "std")]
+pub mod sync;
+#[cfg(feature = "std")]
+mod throttle;
+mod totp;
+#[cfg(feature = "tpm")]
+pub mod tpm;
+#[cfg(feature = "twofas")]
+pub mod twofas;
+pub mod uri;
+#[cfg(feature = "vault")]
+pub mod vault;
+#[cfg(feature = "std")]
+mod verifier;
+mod verify;
+#[cfg(feature = "webdav")]
+pub mod webdav;
+#[cfg(feature = "yubikey")]
+pub mod yubikey;
+
+/// Decode a Base 32 secret literal at compile time into a `&'static [u8]`.
+///
+/// ```
+/// const SECRET: &[u8] = yotp_core::totp_secret!("JBSW Y3DP EHPK 3PXP");
+/// assert_eq!(SECRET, b"Hello!\xde\xad\xbe\xef");
+/// ```
+///
+/// ```compile_fail
+/// const SECRET: &[u8] = yotp_core::totp_secret!("JBSWY3DP1");
+/// ```
+#[cfg(feature = "macros")]
+pub use yotp_macros::totp_secret;
+
+/// The MAC traits accepted by [`hotp_with_mac`].
+pub use hmac::digest;
+
+pub use clock::Clock;
+pub use clock::MockClock;
+#[cfg(feature = "std")]
+pub use clock::SystemClock;
+
+pub use code::Code;
+pub use code::FixedCode;
+pub use code::constant_time_eq;
+pub use code::normalize_code;
+
+#[cfg(feature = "std")]
+pub use drift::DriftTracker;
+
+pub use encoding::CodeEncoding;
+pub use encoding::hotp_encoded;
+pub use encoding::totp_at_encoded;
+
+pub use error::OtpError;
+
+pub use hotp::Hotp;
+pub use hotp::HotpBuilder;
 
+pub use key_backend::KeyBackend;
+pub use key_backend::hotp_with_backend;
+pub use key_backend::totp_at_with_backend;
+#[cfg(feature = "std")]
+pub use key_backend::totp_with_backend;
+
+pub use motp::motp;
+
+pub use otp::Algorithm;
+pub use otp::HotpRange;
+pub use otp::PreparedKey;
 pub use otp::hotp;
-pub use otp::totp;
\ No newline at end of file
+pub use otp::hotp_raw;
+pub use otp::hotp_raw_with_algorithm;
+pub use otp::hotp_sha256;
+pub use otp::hotp_sha512;
+pub use otp::hotp_const;
+pub use otp::hotp_into;
+pub use otp::hotp_range;
+pub use otp::hotp_with_algorithm;
+pub use otp::hotp_with_mac;
+pub use otp::hotp_with_offset;
+#[cfg(feature = "std")]
+pub use otp::totp;
+pub use otp::totp_at;
+pub use otp::totp_at_with_algorithm;
+#[cfg(feature = "std")]
+pub use otp::totp_sha256;
+#[cfg(feature = "std")]
+pub use otp::totp_sha512;
+#[cfg(feature = "std")]
+pub use otp::totp_with_algorithm;
+pub use otp::truncate;
+
+#[cfg(feature = "std")]
+pub use replay::ReplayGuard;
+
+pub use secret::Secret;
+pub use secret::SecretEncoding;
+pub use secret::SecretStrength;
+
+#[cfg(feature = "serde")]
+pub use serialization::ExposeSecret;
+#[cfg(feature = "serde")]
+pub use serialization::expose_secret;
+
+#[cfg(feature = "std")]
+pub use steam::steam_totp;
+pub use steam::steam_totp_at;
+
+#[cfg(feature = "std")]
+pub use throttle::Throttle;
+#[cfg(feature = "std")]
+pub use throttle::ThrottlePolicy;
+
+pub use totp::Totp;
+pub use totp::TotpBuilder;
+
+#[cfg(feature = "std")]
+pub use verifier::AccountState;
+#[cfg(feature = "std")]
+pub use verifier::HotpVerifier;
+#[cfg(feature = "std")]
+pub use verifier::MemoryStore;
+#[cfg(feature = "std")]
+pub use verifier::RotatedSecret;
+#[cfg(feature = "std")]
+pub use verifier::TotpRotation;
+#[cfg(feature = "std")]
+pub use verifier::TotpVerifier;
+#[cfg(feature = "std")]
+pub use verifier::VerifierStore;
+
+pub use verify::StepMatch;
+pub use verify::verify_hotp;
+#[cfg(feature = "rayon")]
+pub use verify::verify_hotp_parallel;
+#[cfg(feature = "std")]
+pub use verify::verify_totp;
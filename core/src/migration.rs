@@ -0,0 +1,490 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The `otpauth-migration://offline?data=...` URIs of the export QR codes
+//! of Google Authenticator.
+//!
+//! The data is a Base 64 `MigrationPayload` protocol buffer:
+//!
+//! ```text
+//! message MigrationPayload {
+//!   repeated OtpParameters otp_parameters = 1;
+//!   int32 version = 2;
+//!   int32 batch_size = 3;
+//!   int32 batch_index = 4;
+//!   int32 batch_id = 5;
+//! }
+//! message OtpParameters {
+//!   bytes secret = 1;
+//!   string name = 2;
+//!   string issuer = 3;
+//!   Algorithm algorithm = 4;  // 1 SHA1, 2 SHA256, 3 SHA512, 4 MD5
+//!   DigitCount digits = 5;    // 1 six, 2 eight
+//!   OtpType type = 6;         // 1 HOTP, 2 TOTP
+//!   int64 counter = 7;
+//! }
+//! ```
+//!
+//! ```
+//! use yotp_core::migration;
+//!
+//! let batch = migration::decode(
+//!     "otpauth-migration://offline?data=CjEKCkhlbGxvId6tvu8SGEV4YW1wbGU6YWxpY2VAZ29vZ2xlLmNvbRoHRXhhbXBsZSABKAEwAhABGAEgACgA"
+//! ).unwrap();
+//! assert_eq!(batch.accounts[0].issuer.as_deref(), Some("Example"));
+//! assert_eq!(batch.accounts[0].account, "alice@google.com");
+//!
+//! let uris = migration::encode(&batch.accounts, 0, migration::DEFAULT_MAX_LEN).unwrap();
+//! assert_eq!(migration::decode(&uris[0]).unwrap().accounts, batch.accounts);
+//! ```
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use zeroize::Zeroizing;
+
+use crate::base64;
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{self, OtpAuthUri, OtpType};
+
+/// A URI length that fits in a QR code still easy to scan from a screen.
+pub const DEFAULT_MAX_LEN: usize = 1024;
+
+/// Upper bound of the length change of a URI when the batch fields at the
+/// end of its payload change, see [`encode`].
+const BATCH_FIELDS_SLACK: usize = 36;
+
+/// The accounts of one export QR code. Google Authenticator splits large
+/// exports into `batch_size` codes sharing the same `batch_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationBatch {
+    pub accounts: Vec<OtpAuthUri>,
+    /// The version of the payload format.
+    pub version: i32,
+    /// The number of QR codes of the export.
+    pub batch_size: usize,
+    /// The position of this QR code in the export, from 0.
+    pub batch_index: usize,
+    /// The identifier shared by the QR codes of one export.
+    pub batch_id: i32,
+}
+
+/// Decode an `otpauth-migration://offline?data=...` URI.
+///
+/// Returns [`OtpError::ImportError`] if the URI or its payload is
+/// malformed, or an account uses an algorithm other than SHA-1, SHA-256
+/// and SHA-512.
+pub fn decode(uri: &str) -> Result<MigrationBatch, OtpError> {
+    let query = uri::strip_prefix_ignore_case(uri, "otpauth-migration://offline?")
+        .ok_or_else(|| import_error("URI should start with otpauth-migration://offline"))?;
+    let data = query
+        .split('&')
+        .find_map(|param| param.strip_prefix("data="))
+        .ok_or_else(|| import_error("URI has no data"))?;
+    let data = Zeroizing::new(uri::percent_decode(data, false).map_err(|_| import_error("invalid percent escape in data"))?);
+    let payload = Zeroizing::new(base64::decode(&data).ok_or_else(|| import_error("data is not valid Base 64"))?);
+    decode_payload(&payload)
+}
+
+/// Encode `accounts` into migration URIs of at most `max_len` bytes each,
+/// in as few batches as possible. The URIs share `batch_id` and carry
+/// their batch index and the batch size, so Google Authenticator imports
+/// them as one export.
+///
+/// Returns [`OtpError::ExportError`] if an account is not supported by
+/// Google Authenticator, i.e. has a period other than 30 seconds or a
+/// length other than 6 and 8 digits, or does not fit in `max_len` alone.
+pub fn encode(accounts: &[OtpAuthUri], batch_id: i32, max_len: usize) -> Result<Vec<String>, OtpError> {
+    let parameters = accounts.iter().map(encode_parameters).collect::<Result<Vec<_>, _>>()?;
+    // Group with the batch fields of the largest possible values; the
+    // final ones are no longer in the payload and, being at its end, only
+    // change the last Base 64 characters of the URI.
+    let mut groups: Vec<Vec<&[u8]>> = Vec::new();
+    let mut group: Vec<&[u8]> = Vec::new();
+    for account in &parameters {
+        group.push(account);
+        if uri_len(&group, accounts.len(), batch_id) + BATCH_FIELDS_SLACK > max_len {
+            group.pop();
+            if group.is_empty() {
+                return Err(export_error("account does not fit in the URI length"));
+            }
+            groups.push(core::mem::replace(&mut group, alloc::vec![&account[..]]));
+            if uri_len(&group, accounts.len(), batch_id) + BATCH_FIELDS_SLACK > max_len {
+                return Err(export_error("account does not fit in the URI length"));
+            }
+        }
+    }
+    if !group.is_empty() || groups.is_empty() {
+        groups.push(group);
+    }
+    let batch_size = groups.len();
+    Ok(groups
+        .iter()
+        .enumerate()
+        .map(|(index, group)| encode_uri(&encode_payload(group, batch_size, index, batch_id)))
+        .collect())
+}
+
+fn uri_len(group: &[&[u8]], batch: usize, batch_id: i32) -> usize {
+    encode_uri(&encode_payload(group, batch, batch, batch_id)).len()
+}
+
+fn encode_uri(payload: &[u8]) -> String {
+    let data = Zeroizing::new(base64::encode(payload));
+    let mut uri = String::from("otpauth-migration://offline?data=");
+    for c in data.chars() {
+        match c {
+            '+' => uri.push_str("%2B"),
+            '/' => uri.push_str("%2F"),
+            '=' => uri.push_str("%3D"),
+            c => uri.push(c),
+        }
+    }
+    uri
+}
+
+/// The `MigrationPayload` of encoded `OtpParameters`, with the batch
+/// fields at the end.
+fn encode_payload(group: &[&[u8]], batch_size: usize, batch_index: usize, batch_id: i32) -> Zeroizing<Vec<u8>> {
+    let mut payload = Zeroizing::new(Vec::new());
+    for parameters in group {
+        write_bytes(&mut payload, 1, parameters);
+    }
+    write_varint_field(&mut payload, 2, 1);
+    write_varint_field(&mut payload, 3, batch_size as u64);
+    write_varint_field(&mut payload, 4, batch_index as u64);
+    write_varint_field(&mut payload, 5, batch_id as i64 as u64);
+    payload
+}
+
+fn encode_parameters(account: &OtpAuthUri) -> Result<Zeroizing<Vec<u8>>, OtpError> {
+    let digits = match account.digits {
+        6 => 1,
+        8 => 2,
+        _ => return Err(export_error("only 6 and 8 digits are supported"))
+    };
+    if account.otp_type == OtpType::Totp && account.period != 30 {
+        return Err(export_error("only a period of 30 seconds is supported"));
+    }
+    let algorithm = match account.algorithm {
+        Algorithm::Sha1 => 1,
+        Algorithm::Sha256 => 2,
+        Algorithm::Sha512 => 3,
+    };
+    let mut parameters = Zeroizing::new(Vec::new());
+    write_bytes(&mut parameters, 1, account.secret.as_bytes());
+    write_bytes(&mut parameters, 2, account.account.as_bytes());
+    if let Some(issuer) = &account.issuer {
+        write_bytes(&mut parameters, 3, issuer.as_bytes());
+    }
+    write_varint_field(&mut parameters, 4, algorithm);
+    write_varint_field(&mut parameters, 5, digits);
+    match account.otp_type {
+        OtpType::Hotp => {
+            write_varint_field(&mut parameters, 6, 1);
+            write_varint_field(&mut parameters, 7, account.counter);
+        }
+        OtpType::Totp => write_varint_field(&mut parameters, 6, 2),
+    }
+    Ok(parameters)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_varint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(out, field << 3);
+    write_varint(out, value);
+}
+
+fn write_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(out, field << 3 | 2);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Decode the `MigrationPayload` protocol buffer, i.e. the data of a
+/// migration URI after Base 64 decoding.
+pub fn decode_payload(payload: &[u8]) -> Result<MigrationBatch, OtpError> {
+    let mut batch = MigrationBatch { accounts: Vec::new(), version: 0, batch_size: 1, batch_index: 0, batch_id: 0 };
+    let mut reader = Reader::new(payload);
+    while let Some((field, value)) = reader.field()? {
+        match (field, value) {
+            (1, Value::Bytes(parameters)) => batch.accounts.push(decode_parameters(parameters)?),
+            (2, Value::Varint(version)) => batch.version = version as i32,
+            (3, Value::Varint(size)) => batch.batch_size = size as usize,
+            (4, Value::Varint(index)) => batch.batch_index = index as usize,
+            (5, Value::Varint(id)) => batch.batch_id = id as i32,
+            _ => {}
+        }
+    }
+    Ok(batch)
+}
+
+fn decode_parameters(parameters: &[u8]) -> Result<OtpAuthUri, OtpError> {
+    let mut secret = Secret::default();
+    let mut name = String::new();
+    let mut issuer = String::new();
+    let mut account = OtpAuthUri {
+        otp_type: OtpType::Totp,
+        account: String::new(),
+        issuer: None,
+        secret: Secret::default(),
+        algorithm: Algorithm::Sha1,
+        digits: 6,
+        period: 30,
+        counter: 0,
+    };
+    let mut reader = Reader::new(parameters);
+    while let Some((field, value)) = reader.field()? {
+        match (field, value) {
+            (1, Value::Bytes(bytes)) => secret = Secret::new(bytes.to_vec()),
+            (2, Value::Bytes(bytes)) => name = utf8(bytes)?,
+            (3, Value::Bytes(bytes)) => issuer = utf8(bytes)?,
+            (4, Value::Varint(algorithm)) => {
+                account.algorithm = match algorithm {
+                    0 | 1 => Algorithm::Sha1,
+                    2 => Algorithm::Sha256,
+                    3 => Algorithm::Sha512,
+                    _ => return Err(import_error("unsupported algorithm"))
+                }
+            }
+            (5, Value::Varint(digits)) => {
+                account.digits = match digits {
+                    0 | 1 => 6,
+                    2 => 8,
+                    _ => return Err(import_error("unsupported digit count"))
+                }
+            }
+            (6, Value::Varint(otp_type)) => {
+                account.otp_type = match otp_type {
+                    1 => OtpType::Hotp,
+                    _ => OtpType::Totp,
+                }
+            }
+            (7, Value::Varint(counter)) => account.counter = counter,
+            _ => {}
+        }
+    }
+    if secret.is_empty() {
+        return Err(import_error("account has no secret"));
+    }
+    if account.otp_type == OtpType::Totp {
+        account.counter = 0;
+    }
+    account.secret = secret;
+    // the name usually repeats the issuer as a prefix, like a label
+    account.account = match name.split_once(':') {
+        Some((prefix, rest)) if issuer.is_empty() || prefix.trim() == issuer.trim() => {
+            if issuer.is_empty() {
+                issuer = prefix.into();
+            }
+            rest.trim().into()
+        }
+        _ => name.trim().into()
+    };
+    account.issuer = Some(String::from(issuer.trim())).filter(|issuer| !issuer.is_empty());
+    Ok(account)
+}
+
+fn utf8(bytes: &[u8]) -> Result<String, OtpError> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| import_error("string is not valid UTF-8"))
+}
+
+fn import_error(msg: &str) -> OtpError {
+    OtpError::ImportError(format!("Google Authenticator migration: {}", msg))
+}
+
+fn export_error(msg: &str) -> OtpError {
+    OtpError::ExportError(format!("Google Authenticator migration: {}", msg))
+}
+
+/// A value of the protocol buffer wire format.
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Minimal reader of the protocol buffer wire format.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    fn varint(&mut self) -> Result<u64, OtpError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or_else(|| import_error("truncated payload"))?;
+            self.pos += 1;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(import_error("varint is too long"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], OtpError> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len());
+        let end = end.ok_or_else(|| import_error("truncated payload"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// The next field number and its value, or `None` at the end.
+    fn field(&mut self) -> Result<Option<(u64, Value<'a>)>, OtpError> {
+        if self.pos == self.data.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let len = self.varint()?;
+                Value::Bytes(self.take(usize::try_from(len).unwrap_or(usize::MAX))?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            _ => return Err(import_error("unsupported wire type"))
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::uri::OtpType;
+
+    use crate::secret::Secret;
+    use crate::uri::OtpAuthUri;
+
+    use super::{decode, decode_payload, encode, DEFAULT_MAX_LEN};
+
+    #[test]
+    fn test_decode() {
+        let batch = decode(
+            "otpauth-migration://offline?data=CjEKCkhlbGxvId6tvu8SGEV4YW1wbGU6YWxpY2VAZ29vZ2xlLmNvbRoHRXhhbXBsZSABKAEwAhABGAEgACgA"
+        ).unwrap();
+        assert_eq!((batch.version, batch.batch_size, batch.batch_index, batch.batch_id), (1, 1, 0, 0));
+        assert_eq!(batch.accounts.len(), 1);
+        let account = &batch.accounts[0];
+        assert_eq!(account.otp_type, OtpType::Totp);
+        assert_eq!(account.secret.as_bytes(), b"Hello!\xde\xad\xbe\xef");
+        assert_eq!(account.account, "alice@google.com");
+        assert_eq!(account.issuer.as_deref(), Some("Example"));
+        assert_eq!(account.algorithm, Algorithm::Sha1);
+        assert_eq!(account.digits, 6);
+    }
+
+    #[test]
+    fn test_decode_hotp() {
+        // secret "12345678901234567890", name "bob", SHA256, 8 digits, HOTP,
+        // counter 300, and an unknown fixed32 field 9
+        let mut parameters = vec![0x0a, 20];
+        parameters.extend_from_slice(b"12345678901234567890");
+        parameters.extend_from_slice(&[0x12, 3, b'b', b'o', b'b', 0x20, 2, 0x28, 2, 0x30, 1, 0x38, 0xac, 0x02]);
+        parameters.extend_from_slice(&[0x4d, 0, 0, 0, 0]);
+        let mut payload = vec![0x0a, parameters.len() as u8];
+        payload.extend_from_slice(&parameters);
+        let batch = decode_payload(&payload).unwrap();
+        let account = &batch.accounts[0];
+        assert_eq!(account.otp_type, OtpType::Hotp);
+        assert_eq!(account.account, "bob");
+        assert_eq!(account.issuer, None);
+        assert_eq!(account.algorithm, Algorithm::Sha256);
+        assert_eq!(account.digits, 8);
+        assert_eq!(account.counter, 300);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(matches!(decode("otpauth://totp/a?secret=JBSWY3DP"), Err(OtpError::ImportError(_))));
+        assert!(matches!(decode("otpauth-migration://offline?data=!!"), Err(OtpError::ImportError(_))));
+        assert!(matches!(decode("otpauth-migration://offline?version=1"), Err(OtpError::ImportError(_))));
+        // truncated account
+        assert!(matches!(decode_payload(&[0x0a, 5, 0x0a]), Err(OtpError::ImportError(_))));
+        // account without secret
+        assert!(matches!(decode_payload(&[0x0a, 2, 0x20, 1]), Err(OtpError::ImportError(_))));
+        // MD5
+        assert!(matches!(decode_payload(&[0x0a, 5, 0x0a, 1, 1, 0x20, 4]), Err(OtpError::ImportError(_))));
+    }
+
+    fn account(n: usize) -> OtpAuthUri {
+        OtpAuthUri {
+            otp_type: if n.is_multiple_of(2) { OtpType::Totp } else { OtpType::Hotp },
+            account: alloc::format!("user{}@example.com", n),
+            issuer: Some("Example".into()).filter(|_| !n.is_multiple_of(3)),
+            secret: Secret::new((0..20).map(|i| (i * n) as u8).collect()),
+            algorithm: Algorithm::Sha1,
+            digits: if n % 4 == 1 { 8 } else { 6 },
+            period: 30,
+            counter: if n.is_multiple_of(2) { 0 } else { n as u64 * 1000 },
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let accounts: Vec<_> = (0..40).map(account).collect();
+        let uris = encode(&accounts, -7, 300).unwrap();
+        assert!(uris.len() > 1);
+        let mut decoded = Vec::new();
+        for (index, uri) in uris.iter().enumerate() {
+            assert!(uri.len() <= 300, "{}", uri);
+            let batch = decode(uri).unwrap();
+            assert_eq!((batch.version, batch.batch_size, batch.batch_index, batch.batch_id), (1, uris.len(), index, -7));
+            decoded.extend(batch.accounts);
+        }
+        assert_eq!(decoded, accounts);
+
+        let uris = encode(&accounts[..2], 0, DEFAULT_MAX_LEN).unwrap();
+        assert_eq!(uris.len(), 1);
+        assert_eq!(encode(&[], 0, DEFAULT_MAX_LEN).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_encode_unsupported() {
+        let mut unsupported = account(0);
+        unsupported.period = 60;
+        assert!(matches!(encode(&[unsupported], 0, DEFAULT_MAX_LEN), Err(OtpError::ExportError(_))));
+        let mut unsupported = account(0);
+        unsupported.digits = 7;
+        assert!(matches!(encode(&[unsupported], 0, DEFAULT_MAX_LEN), Err(OtpError::ExportError(_))));
+        assert!(matches!(encode(&[account(0)], 0, 60), Err(OtpError::ExportError(_))));
+    }
+}
@@ -0,0 +1,58 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use md5::{Digest, Md5};
+
+use crate::code::Code;
+use crate::error::OtpError;
+
+/// Implementation of Mobile-OTP (mOTP).
+///
+/// The code is the first 6 hex digits of the MD5 of the concatenation of
+/// `t / 10`, the secret and the PIN, all as strings.
+///
+/// * `secret`: the init-secret of the token, usually 16 hex characters.
+/// * `pin`: the PIN chosen by the user.
+/// * `t`: the time in seconds since UNIX epoch.
+pub fn motp(secret: &str, pin: &str, t: u64) -> Result<Code, OtpError> {
+    if secret.is_empty() {
+        return Err(OtpError::KeyTooShort);
+    }
+    let mut md5 = Md5::new();
+    md5.update((t / 10).to_string());
+    md5.update(secret);
+    md5.update(pin);
+    let digest = md5.finalize();
+    let hex: String = digest[0..3].iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(Code::new(hex))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+
+    use super::motp;
+
+    #[test]
+    fn test_motp() {
+        assert_eq!(motp("0123456789abcdef", "1234", 1111111109).unwrap(), "063dcf");
+        assert_eq!(motp("0123456789abcdef", "1234", 1111111100).unwrap(), "063dcf");
+        assert_eq!(motp("0123456789abcdef", "1234", 1234567890).unwrap(), "f41e13");
+        assert_eq!(motp("", "1234", 1234567890), Err(OtpError::KeyTooShort));
+    }
+}
@@ -0,0 +1,458 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Implementation of OCRA, the OATH Challenge-Response Algorithm
+//! described in RFC 6287.
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use sha1::{Digest, Sha1};
+use sha2::{Sha256, Sha512};
+
+use crate::code::Code;
+use crate::error::OtpError;
+use crate::otp::{self, Algorithm};
+
+/// Length of the challenge field in the OCRA data input.
+const CHALLENGE_LEN: usize = 128;
+
+/// Format of the challenge question of an OCRA suite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeFormat {
+    /// `QA`: alphanumeric characters, encoded as ASCII.
+    Alphanumeric,
+    /// `QN`: a decimal number.
+    Numeric,
+    /// `QH`: a hex string.
+    Hex,
+}
+
+/// A parsed OCRA suite, e.g. `OCRA-1:HOTP-SHA1-6:QN08`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OcraSuite {
+    suite: String,
+    algorithm: Algorithm,
+    digits: usize,
+    counter: bool,
+    challenge_format: ChallengeFormat,
+    challenge_max_len: usize,
+    password: Option<Algorithm>,
+    session_len: Option<usize>,
+    time_step: Option<u64>,
+}
+
+impl OcraSuite {
+    /// Parse an OCRA suite string. Returns [`OtpError::InvalidOcraSuite`]
+    /// if the suite is malformed or uses unsupported options.
+    pub fn parse(suite: &str) -> Result<OcraSuite, OtpError> {
+        let mut parts = suite.split(':');
+        let version = parts.next().ok_or(OtpError::InvalidOcraSuite)?;
+        let function = parts.next().ok_or(OtpError::InvalidOcraSuite)?;
+        let data_input = parts.next().ok_or(OtpError::InvalidOcraSuite)?;
+        if parts.next().is_some() || version != "OCRA-1" {
+            return Err(OtpError::InvalidOcraSuite);
+        }
+
+        // the crypto function looks like HOTP-SHA1-6
+        let mut function = function.split('-');
+        if function.next() != Some("HOTP") {
+            return Err(OtpError::InvalidOcraSuite);
+        }
+        let algorithm = function
+            .next()
+            .and_then(Algorithm::from_name)
+            .ok_or(OtpError::InvalidOcraSuite)?;
+        let digits: usize = function
+            .next()
+            .and_then(|d| d.parse().ok())
+            .ok_or(OtpError::InvalidOcraSuite)?;
+        if function.next().is_some() || !(4..=10).contains(&digits) {
+            return Err(OtpError::InvalidOcraSuite);
+        }
+
+        let mut result = OcraSuite {
+            suite: suite.to_owned(),
+            algorithm,
+            digits,
+            counter: false,
+            challenge_format: ChallengeFormat::Numeric,
+            challenge_max_len: 0,
+            password: None,
+            session_len: None,
+            time_step: None,
+        };
+        let mut has_challenge = false;
+        for (i, field) in data_input.split('-').enumerate() {
+            // the fields are sliced by byte below
+            if !field.is_ascii() {
+                return Err(OtpError::InvalidOcraSuite);
+            }
+            if field == "C" && i == 0 {
+                result.counter = true;
+            } else if let Some(q) = field.strip_prefix('Q') {
+                if has_challenge || q.len() != 3 {
+                    return Err(OtpError::InvalidOcraSuite);
+                }
+                result.challenge_format = match &q[0..1] {
+                    "A" => ChallengeFormat::Alphanumeric,
+                    "N" => ChallengeFormat::Numeric,
+                    "H" => ChallengeFormat::Hex,
+                    _ => return Err(OtpError::InvalidOcraSuite)
+                };
+                result.challenge_max_len = q[1..].parse().map_err(|_| OtpError::InvalidOcraSuite)?;
+                if !(4..=64).contains(&result.challenge_max_len) {
+                    return Err(OtpError::InvalidOcraSuite);
+                }
+                has_challenge = true;
+            } else if let Some(p) = field.strip_prefix('P') {
+                result.password = Some(Algorithm::from_name(p).ok_or(OtpError::InvalidOcraSuite)?);
+            } else if let Some(s) = field.strip_prefix('S') {
+                let len = s.parse().map_err(|_| OtpError::InvalidOcraSuite)?;
+                if s.len() != 3 || len > 512 {
+                    return Err(OtpError::InvalidOcraSuite);
+                }
+                result.session_len = Some(len);
+            } else if let Some(t) = field.strip_prefix('T') {
+                result.time_step = Some(parse_time_step(t)?);
+            } else {
+                return Err(OtpError::InvalidOcraSuite);
+            }
+        }
+        if !has_challenge {
+            return Err(OtpError::InvalidOcraSuite);
+        }
+        Ok(result)
+    }
+
+    /// The suite string as it was parsed.
+    pub fn as_str(&self) -> &str {
+        &self.suite
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    /// Whether the suite takes a counter `C`.
+    pub fn uses_counter(&self) -> bool {
+        self.counter
+    }
+
+    pub fn challenge_format(&self) -> ChallengeFormat {
+        self.challenge_format
+    }
+
+    /// The maximal length of the challenge question.
+    pub fn challenge_max_len(&self) -> usize {
+        self.challenge_max_len
+    }
+
+    /// The hash function of the password, if the suite takes a password.
+    pub fn password(&self) -> Option<Algorithm> {
+        self.password
+    }
+
+    /// The length of the session information in bytes, if the suite takes
+    /// session information.
+    pub fn session_len(&self) -> Option<usize> {
+        self.session_len
+    }
+
+    /// The time step in seconds, if the suite takes a timestamp.
+    pub fn time_step(&self) -> Option<u64> {
+        self.time_step
+    }
+}
+
+/// Parse the time step `G` of `TG`, e.g. `30S`, `1M` or `1H`.
+fn parse_time_step(value: &str) -> Result<u64, OtpError> {
+    if value.len() < 2 || !value.is_ascii() {
+        return Err(OtpError::InvalidOcraSuite);
+    }
+    let (num, unit) = value.split_at(value.len() - 1);
+    let num: u64 = num.parse().map_err(|_| OtpError::InvalidOcraSuite)?;
+    let (max, scale) = match unit {
+        "S" => (59, 1),
+        "M" => (59, 60),
+        "H" => (48, 3600),
+        _ => return Err(OtpError::InvalidOcraSuite)
+    };
+    if num == 0 || num > max {
+        return Err(OtpError::InvalidOcraSuite);
+    }
+    Ok(num * scale)
+}
+
+/// The data input of an OCRA computation. Fields not used by the suite
+/// are ignored, and fields used by the suite are required.
+#[derive(Debug, Clone, Default)]
+pub struct OcraInput<'a> {
+    /// The counter `C`.
+    pub counter: Option<u64>,
+    /// The challenge question `Q`, in the format defined by the suite.
+    pub challenge: &'a str,
+    /// The PIN or password, which is hashed as the suite defines.
+    pub password: Option<&'a [u8]>,
+    /// The session information `S`.
+    pub session: Option<&'a [u8]>,
+    /// The time in seconds since UNIX epoch, which is converted to the
+    /// number of time steps of the suite.
+    pub time: Option<u64>,
+}
+
+/// Compute the OCRA response of `suite` with `key` and `input`.
+///
+/// Returns [`OtpError::InvalidOcraInput`] if a field required by the suite
+/// is missing or malformed.
+pub fn ocra(suite: &OcraSuite, key: impl AsRef<[u8]>, input: &OcraInput) -> Result<Code, OtpError> {
+    let key = key.as_ref();
+    if key.is_empty() {
+        return Err(OtpError::KeyTooShort);
+    }
+    let mut message = suite.suite.as_bytes().to_vec();
+    message.push(0);
+    if suite.counter {
+        let c = input.counter.ok_or(OtpError::InvalidOcraInput)?;
+        message.extend_from_slice(&otp::big_endian_u64(c));
+    }
+    message.extend_from_slice(&encode_challenge(suite, input.challenge)?);
+    if let Some(algorithm) = suite.password {
+        let password = input.password.ok_or(OtpError::InvalidOcraInput)?;
+        message.extend_from_slice(&hash(password, algorithm));
+    }
+    if let Some(len) = suite.session_len {
+        let session = input.session.ok_or(OtpError::InvalidOcraInput)?;
+        if session.len() > len {
+            return Err(OtpError::InvalidOcraInput);
+        }
+        // session information is padded with leading zeros
+        message.resize(message.len() + len - session.len(), 0);
+        message.extend_from_slice(session);
+    }
+    if let Some(step) = suite.time_step {
+        let t = input.time.ok_or(OtpError::InvalidOcraInput)?;
+        message.extend_from_slice(&otp::big_endian_u64(t / step));
+    }
+
+    let hash = otp::hmac(key, &message, suite.algorithm);
+    Ok(otp::format_decimal(otp::dynamic_truncate(&hash), suite.digits))
+}
+
+/// Encode the challenge question to the 128 bytes challenge field.
+///
+/// The length in the suite is not enforced: in mutual challenge-response
+/// mode, the question is the concatenation of the client and the server
+/// challenges, which is longer than the suite defines.
+fn encode_challenge(suite: &OcraSuite, challenge: &str) -> Result<Vec<u8>, OtpError> {
+    if challenge.is_empty() || challenge.len() > CHALLENGE_LEN {
+        return Err(OtpError::InvalidOcraInput);
+    }
+    let hex = match suite.challenge_format {
+        ChallengeFormat::Alphanumeric => {
+            if !challenge.bytes().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(OtpError::InvalidOcraInput);
+            }
+            return Ok(pad_challenge(challenge.as_bytes().to_vec()));
+        }
+        ChallengeFormat::Numeric => decimal_to_hex(challenge)?,
+        ChallengeFormat::Hex => {
+            if !challenge.bytes().all(|c| c.is_ascii_hexdigit()) {
+                return Err(OtpError::InvalidOcraInput);
+            }
+            challenge.to_ascii_uppercase()
+        }
+    };
+    // the hex string is left aligned in the challenge field, so an odd
+    // number of hex digits fills the high half of the last byte
+    let mut bytes = Vec::with_capacity(CHALLENGE_LEN);
+    for pair in hex.as_bytes().chunks(2) {
+        let high = hex_value(pair[0]);
+        let low = pair.get(1).map(|&c| hex_value(c)).unwrap_or(0);
+        bytes.push((high << 4) | low);
+    }
+    Ok(pad_challenge(bytes))
+}
+
+fn pad_challenge(mut bytes: Vec<u8>) -> Vec<u8> {
+    bytes.resize(CHALLENGE_LEN, 0);
+    bytes
+}
+
+fn hex_value(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'A'..=b'F' => c - b'A' + 10,
+        b'a'..=b'f' => c - b'a' + 10,
+        _ => 0
+    }
+}
+
+/// Convert a decimal string to the upper case hex string of the same
+/// number, without leading zeros.
+fn decimal_to_hex(value: &str) -> Result<String, OtpError> {
+    // big-endian base 256 digits of the number
+    let mut num: Vec<u8> = Vec::new();
+    for c in value.bytes() {
+        if !c.is_ascii_digit() {
+            return Err(OtpError::InvalidOcraInput);
+        }
+        let mut carry = (c - b'0') as u32;
+        for byte in num.iter_mut().rev() {
+            let v = (*byte as u32) * 10 + carry;
+            *byte = (v & 0xFF) as u8;
+            carry = v >> 8;
+        }
+        if carry > 0 {
+            num.insert(0, carry as u8);
+        }
+    }
+    let hex: String = num.iter().map(|b| format!("{:02X}", b)).collect();
+    let hex = hex.trim_start_matches('0');
+    if hex.is_empty() {
+        Ok("0".to_owned())
+    } else {
+        Ok(hex.to_owned())
+    }
+}
+
+fn hash(data: &[u8], algorithm: Algorithm) -> Vec<u8> {
+    match algorithm {
+        Algorithm::Sha1 => Sha1::digest(data).to_vec(),
+        Algorithm::Sha256 => Sha256::digest(data).to_vec(),
+        Algorithm::Sha512 => Sha512::digest(data).to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+
+    use super::{decimal_to_hex, ocra, ChallengeFormat, OcraInput, OcraSuite};
+
+    // Test vectors in this module are from RFC 6287 Appendix C.
+    const SEED: &[u8] = b"12345678901234567890";
+    const SEED32: &[u8] = b"12345678901234567890123456789012";
+    const SEED64: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+    const QUESTIONS: [&str; 10] = [
+        "00000000", "11111111", "22222222", "33333333", "44444444",
+        "55555555", "66666666", "77777777", "88888888", "99999999",
+    ];
+
+    #[test]
+    fn test_parse_suite() {
+        let suite = OcraSuite::parse("OCRA-1:HOTP-SHA512-8:C-QN08-PSHA1-S064-T1M").unwrap();
+        assert_eq!(suite.algorithm(), Algorithm::Sha512);
+        assert_eq!(suite.digits(), 8);
+        assert!(suite.uses_counter());
+        assert_eq!(suite.challenge_format(), ChallengeFormat::Numeric);
+        assert_eq!(suite.challenge_max_len(), 8);
+        assert_eq!(suite.password(), Some(Algorithm::Sha1));
+        assert_eq!(suite.session_len(), Some(64));
+        assert_eq!(suite.time_step(), Some(60));
+
+        for invalid in ["OCRA-2:HOTP-SHA1-6:QN08", "OCRA-1:HOTP-MD5-6:QN08", "OCRA-1:HOTP-SHA1-6:C",
+                        "OCRA-1:HOTP-SHA1-6:QX08", "OCRA-1:HOTP-SHA1-6:QN08-T0M", "OCRA-1:HOTP-SHA1-11:QN08",
+                        "OCRA-1:HOTP-SHA1-6:Q\u{e9}8", "OCRA-1:HOTP-SHA1-6:QN08-T1\u{e9}"] {
+            assert_eq!(OcraSuite::parse(invalid), Err(OtpError::InvalidOcraSuite));
+        }
+    }
+
+    #[test]
+    fn test_decimal_to_hex() {
+        assert_eq!(decimal_to_hex("00000000").unwrap(), "0");
+        assert_eq!(decimal_to_hex("12345678").unwrap(), "BC614E");
+        assert_eq!(decimal_to_hex("22222222").unwrap(), "153158E");
+    }
+
+    #[test]
+    fn test_one_way_sha1() {
+        let suite = OcraSuite::parse("OCRA-1:HOTP-SHA1-6:QN08").unwrap();
+        let expected = ["237653", "243178", "653583", "740991", "608993",
+                        "388898", "816933", "224598", "750600", "294470"];
+        for (q, code) in QUESTIONS.iter().zip(expected) {
+            let input = OcraInput { challenge: q, ..Default::default() };
+            assert_eq!(ocra(&suite, SEED, &input).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_one_way_counter_pin() {
+        let suite = OcraSuite::parse("OCRA-1:HOTP-SHA256-8:C-QN08-PSHA1").unwrap();
+        let expected = ["65347737", "86775851", "78192410", "71565254", "10104329",
+                        "65983500", "70069104", "91771096", "75011558", "08522129"];
+        for (c, code) in expected.iter().enumerate() {
+            let input = OcraInput {
+                counter: Some(c as u64),
+                challenge: "12345678",
+                password: Some(b"1234"),
+                ..Default::default()
+            };
+            assert_eq!(&ocra(&suite, SEED32, &input).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_one_way_sha512_counter() {
+        let suite = OcraSuite::parse("OCRA-1:HOTP-SHA512-8:C-QN08").unwrap();
+        let expected = ["07016083", "63947962", "70123924", "25341727", "33203315",
+                        "34205738", "44343969", "51946085", "20403879", "31409299"];
+        for (c, (q, code)) in QUESTIONS.iter().zip(expected).enumerate() {
+            let input = OcraInput { counter: Some(c as u64), challenge: q, ..Default::default() };
+            assert_eq!(ocra(&suite, SEED64, &input).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_one_way_time() {
+        let suite = OcraSuite::parse("OCRA-1:HOTP-SHA512-8:QN08-T1M").unwrap();
+        let expected = ["95209754", "55907591", "22048402", "24218844", "36209546"];
+        for (q, code) in QUESTIONS.iter().zip(expected) {
+            let input = OcraInput { challenge: q, time: Some(0x132d0b6 * 60), ..Default::default() };
+            assert_eq!(ocra(&suite, SEED64, &input).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_mutual_alphanumeric() {
+        let suite = OcraSuite::parse("OCRA-1:HOTP-SHA256-8:QA08").unwrap();
+        let vectors = [
+            ("CLI22220SRV11110", "28247970"),
+            ("CLI22221SRV11111", "01984843"),
+            ("SRV11110CLI22220", "15510767"),
+            ("SRV11111CLI22221", "90175646"),
+        ];
+        for (q, code) in vectors {
+            let input = OcraInput { challenge: q, ..Default::default() };
+            assert_eq!(ocra(&suite, SEED32, &input).unwrap(), code);
+        }
+        let input = OcraInput { challenge: "SRV-1111", ..Default::default() };
+        assert_eq!(ocra(&suite, SEED32, &input), Err(OtpError::InvalidOcraInput));
+    }
+
+    #[test]
+    fn test_missing_input() {
+        let suite = OcraSuite::parse("OCRA-1:HOTP-SHA1-6:C-QN08").unwrap();
+        let input = OcraInput { challenge: "12345678", ..Default::default() };
+        assert_eq!(ocra(&suite, SEED, &input), Err(OtpError::InvalidOcraInput));
+    }
+}
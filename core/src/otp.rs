@@ -14,50 +14,401 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::time::{Duration, SystemTime};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::time::SystemTime;
 
-use crypto::{hmac::Hmac, sha1::Sha1, mac::Mac};
+use crate::code::{Code, FixedCode};
+use crate::error::OtpError;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+/// Supported lengths of decimal OTPs. The 31-bit truncated value has at
+/// most 10 decimal digits.
+pub(crate) const DIGITS_RANGE: RangeInclusive<usize> = 6..=10;
+
+/// Hash function used by the HMAC in HOTP/TOTP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "UPPERCASE"))]
+pub enum Algorithm {
+    /// HMAC-SHA-1, the default of RFC 4226 and most authenticators.
+    #[default]
+    Sha1,
+    /// HMAC-SHA-256, allowed by RFC 6238.
+    Sha256,
+    /// HMAC-SHA-512, allowed by RFC 6238.
+    Sha512,
+}
+
+impl Algorithm {
+    /// Parse the algorithm name as it appears in account configurations,
+    /// e.g. `SHA1`, `SHA256` or `SHA512`. The name is case-insensitive
+    /// and an optional hyphen (`SHA-256`) is accepted.
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        match name.to_ascii_uppercase().replace('-', "").as_str() {
+            "SHA1" => Some(Algorithm::Sha1),
+            "SHA256" => Some(Algorithm::Sha256),
+            "SHA512" => Some(Algorithm::Sha512),
+            _ => None
+        }
+    }
+
+    /// The canonical name of the algorithm, e.g. `SHA256`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        }
+    }
+
+    /// The length of the hash output in bytes.
+    pub fn output_len(&self) -> usize {
+        match self {
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha256 => 32,
+            Algorithm::Sha512 => 64,
+        }
+    }
+}
 
 /// Implementation of HMAC-based One-Time Password as it is described
-/// in RFC 4226. It utilizes rust-crypto crate.
+/// in RFC 4226. It utilizes the hmac, sha1 and sha2 crates of RustCrypto.
 ///
 /// Parameters:
 /// * `key`: the "key" for generating the OTP.
 /// * `c`: the "counter" for generating the OTP.
-/// * `digit_len`: the length of generated OTP. It should be 6, 7 or 8.
-pub fn hotp(key: &[u8], c: u64, digit_len: usize) -> String {
-    if digit_len < 6 || digit_len > 8 {
-        panic!("HMAC-based OTP length should be 6 to 8 digits, but got {}.", digit_len);
+/// * `digit_len`: the length of generated OTP. It should be 6 to 10.
+///
+/// Returns [`OtpError::InvalidDigits`] if `digit_len` is out of range and
+/// [`OtpError::KeyTooShort`] if `key` is empty.
+pub fn hotp(key: impl AsRef<[u8]>, c: u64, digit_len: usize) -> Result<Code, OtpError> {
+    hotp_with_algorithm(key, c, digit_len, Algorithm::Sha1)
+}
+
+/// HOTP with HMAC-SHA-256 as the underlying hash function, which is
+/// allowed by RFC 6238. Parameters are the same as [`hotp`].
+pub fn hotp_sha256(key: impl AsRef<[u8]>, c: u64, digit_len: usize) -> Result<Code, OtpError> {
+    hotp_with_algorithm(key, c, digit_len, Algorithm::Sha256)
+}
+
+/// HOTP with HMAC-SHA-512 as the underlying hash function, which is
+/// allowed by RFC 6238. Parameters are the same as [`hotp`].
+pub fn hotp_sha512(key: impl AsRef<[u8]>, c: u64, digit_len: usize) -> Result<Code, OtpError> {
+    hotp_with_algorithm(key, c, digit_len, Algorithm::Sha512)
+}
+
+/// HOTP with the hash function selected at runtime by `algorithm`.
+/// Other parameters are the same as [`hotp`].
+pub fn hotp_with_algorithm(key: impl AsRef<[u8]>, c: u64, digit_len: usize, algorithm: Algorithm) -> Result<Code, OtpError> {
+    if !DIGITS_RANGE.contains(&digit_len) {
+        return Err(OtpError::InvalidDigits(digit_len));
     }
-    let digest = Sha1::new();
-    
-    // start the HMAC digest with the key
-    let mut hmac = Hmac::new(digest, key);
-    // and then feed the counter to the HMAC digest
-    hmac.input(&big_endian_u64(c));
+    let hotp_num = hotp_raw_with_algorithm(key, c, algorithm)?;
+    Ok(format_decimal(hotp_num, digit_len))
+}
+
+/// HMAC-SHA-1 HOTP codes of the `count` consecutive counters from
+/// `start`, e.g. to search for a resynchronization or to print a sheet of
+/// codes. The HMAC key state is computed once and reused for every code.
+///
+/// It returns the same errors as [`hotp`], checked before any code is
+/// generated. The range stops early at `u64::MAX`.
+pub fn hotp_range(key: impl AsRef<[u8]>, start: u64, count: u64, digit_len: usize) -> Result<HotpRange, OtpError> {
+    if !DIGITS_RANGE.contains(&digit_len) {
+        return Err(OtpError::InvalidDigits(digit_len));
+    }
+    let key = PreparedKey::new(key, Algorithm::Sha1)?;
+    Ok(HotpRange { key, counter: start, end: start.saturating_add(count), digit_len })
+}
+
+/// Iterator over the HOTP codes of consecutive counters, created by
+/// [`hotp_range`].
+#[derive(Debug, Clone)]
+pub struct HotpRange {
+    key: PreparedKey,
+    counter: u64,
+    end: u64,
+    digit_len: usize,
+}
+
+impl HotpRange {
+    /// The counter of the next code to be returned.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+}
+
+impl Iterator for HotpRange {
+    type Item = Code;
+
+    fn next(&mut self) -> Option<Code> {
+        if self.counter >= self.end {
+            return None;
+        }
+        let hotp_num = self.key.hotp_raw(self.counter);
+        self.counter += 1;
+        Some(format_decimal(hotp_num, self.digit_len))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.end - self.counter) as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for HotpRange {}
+
+/// HOTP with HMAC-SHA-1 whose length is checked at compile time: a
+/// `DIGITS` outside of 6 to 10 fails to build instead of returning
+/// [`OtpError::InvalidDigits`].
+///
+/// ```
+/// let code = yotp_core::hotp_const::<6>(b"12345678901234567890", 1).unwrap();
+/// assert_eq!(code, "287082");
+/// ```
+///
+/// ```compile_fail
+/// let code = yotp_core::hotp_const::<4>(b"12345678901234567890", 1);
+/// ```
+///
+/// Returns [`OtpError::KeyTooShort`] if `key` is empty.
+pub fn hotp_const<const DIGITS: usize>(key: impl AsRef<[u8]>, c: u64) -> Result<FixedCode<DIGITS>, OtpError> {
+    let () = ValidDigits::<DIGITS>::CHECK;
+    let mut digits = [0u8; DIGITS];
+    write_decimal(hotp_raw(key, c)?, &mut digits);
+    Ok(FixedCode::new(digits))
+}
+
+struct ValidDigits<const DIGITS: usize>;
+
+impl<const DIGITS: usize> ValidDigits<DIGITS> {
+    const CHECK: () = assert!(
+        *DIGITS_RANGE.start() <= DIGITS && DIGITS <= *DIGITS_RANGE.end(),
+        "unsupported OTP length"
+    );
+}
+
+/// HOTP computed with a caller supplied MAC instance, already keyed with
+/// the shared secret. This lets hardware-backed or certified HMAC
+/// providers reuse the truncation logic: only the counter is fed to `mac`.
+///
+/// Returns [`OtpError::InvalidOffset`] if the MAC output is too short for
+/// the dynamic truncation.
+pub fn hotp_with_mac<M: Mac>(mut mac: M, c: u64, digit_len: usize) -> Result<Code, OtpError> {
+    if !DIGITS_RANGE.contains(&digit_len) {
+        return Err(OtpError::InvalidDigits(digit_len));
+    }
+    mac.update(&big_endian_u64(c));
+    let hash = mac.finalize().into_bytes();
+    Ok(format_decimal(truncate(&hash, None)?, digit_len))
+}
+
+/// Keep the lowest `digit_len` decimal digits of `value`, padded with
+/// leading zeros.
+pub(crate) fn format_decimal(value: u32, digit_len: usize) -> Code {
+    let mut hotp = vec![0u8; digit_len];
+    write_decimal(value, &mut hotp);
+    Code::new(String::from_utf8(hotp).unwrap())
+}
+
+/// Fill `out` with the lowest `out.len()` decimal digits of `value` as
+/// ASCII, padded with leading zeros.
+fn write_decimal(value: u32, out: &mut [u8]) {
+    let mut hotp_num = value;
+    for byte in out.iter_mut().rev() {
+        *byte = b'0' + (hotp_num % 10) as u8;
+        hotp_num /= 10;
+    }
+}
+
+/// HOTP with HMAC-SHA-1 written as ASCII digits into the first
+/// `digit_len` bytes of `out`, without any heap allocation. It returns
+/// the number of bytes written, which is `digit_len`.
+///
+/// Besides the errors of [`hotp`], it returns [`OtpError::BufferTooSmall`]
+/// if `out` is shorter than `digit_len`.
+pub fn hotp_into(key: impl AsRef<[u8]>, c: u64, digit_len: usize, out: &mut [u8]) -> Result<usize, OtpError> {
+    if !DIGITS_RANGE.contains(&digit_len) {
+        return Err(OtpError::InvalidDigits(digit_len));
+    }
+    if out.len() < digit_len {
+        return Err(OtpError::BufferTooSmall(digit_len));
+    }
+    let hotp_num = hotp_raw(key, c)?;
+    write_decimal(hotp_num, &mut out[..digit_len]);
+    Ok(digit_len)
+}
+
+/// The 31-bit value after the dynamic truncation of HOTP with HMAC-SHA-1,
+/// before it is reduced to decimal digits. Custom code formats can be
+/// built on top of it.
+///
+/// Returns [`OtpError::KeyTooShort`] if `key` is empty.
+pub fn hotp_raw(key: impl AsRef<[u8]>, c: u64) -> Result<u32, OtpError> {
+    hotp_raw_with_algorithm(key, c, Algorithm::Sha1)
+}
+
+/// [`hotp_raw`] with the hash function selected at runtime by `algorithm`.
+pub fn hotp_raw_with_algorithm(key: impl AsRef<[u8]>, c: u64, algorithm: Algorithm) -> Result<u32, OtpError> {
+    Ok(PreparedKey::new(key, algorithm)?.hotp_raw(c))
+}
+
+/// The HMAC key state of a secret, computed once and reused by every
+/// HOTP value generated with it. Verifying over a wide window or
+/// generating many codes of the same key then skips hashing the key
+/// blocks again for every counter.
+#[derive(Clone)]
+pub struct PreparedKey {
+    mac: PreparedMac,
+}
+
+#[derive(Clone)]
+enum PreparedMac {
+    Sha1(Hmac<Sha1>),
+    Sha256(Hmac<Sha256>),
+    Sha512(Hmac<Sha512>),
+}
+
+impl PreparedKey {
+    /// Prepare `key` for HMAC with the hash function `algorithm`.
+    ///
+    /// Returns [`OtpError::KeyTooShort`] if `key` is empty.
+    pub fn new(key: impl AsRef<[u8]>, algorithm: Algorithm) -> Result<PreparedKey, OtpError> {
+        let key = key.as_ref();
+        if key.is_empty() {
+            return Err(OtpError::KeyTooShort);
+        }
+        let mac = match algorithm {
+            Algorithm::Sha1 => PreparedMac::Sha1(new_mac(key)),
+            Algorithm::Sha256 => PreparedMac::Sha256(new_mac(key)),
+            Algorithm::Sha512 => PreparedMac::Sha512(new_mac(key)),
+        };
+        Ok(PreparedKey { mac })
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self.mac {
+            PreparedMac::Sha1(_) => Algorithm::Sha1,
+            PreparedMac::Sha256(_) => Algorithm::Sha256,
+            PreparedMac::Sha512(_) => Algorithm::Sha512,
+        }
+    }
+
+    /// The 31-bit value of counter `c`, same as [`hotp_raw_with_algorithm`].
+    pub fn hotp_raw(&self, c: u64) -> u32 {
+        let message = big_endian_u64(c);
+        match &self.mac {
+            PreparedMac::Sha1(mac) => mac_truncated(mac.clone(), &message),
+            PreparedMac::Sha256(mac) => mac_truncated(mac.clone(), &message),
+            PreparedMac::Sha512(mac) => mac_truncated(mac.clone(), &message),
+        }
+    }
+
+    /// The HMAC of `message` with the key.
+    pub(crate) fn hmac(&self, message: &[u8]) -> Vec<u8> {
+        match &self.mac {
+            PreparedMac::Sha1(mac) => mac.clone().chain_update(message).finalize().into_bytes().to_vec(),
+            PreparedMac::Sha256(mac) => mac.clone().chain_update(message).finalize().into_bytes().to_vec(),
+            PreparedMac::Sha512(mac) => mac.clone().chain_update(message).finalize().into_bytes().to_vec(),
+        }
+    }
+
+    /// The HOTP code of counter `c`, same as [`hotp_with_algorithm`].
+    pub fn hotp(&self, c: u64, digit_len: usize) -> Result<Code, OtpError> {
+        if !DIGITS_RANGE.contains(&digit_len) {
+            return Err(OtpError::InvalidDigits(digit_len));
+        }
+        Ok(format_decimal(self.hotp_raw(c), digit_len))
+    }
+}
+
+impl fmt::Debug for PreparedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreparedKey").field("algorithm", &self.algorithm()).finish_non_exhaustive()
+    }
+}
 
-    // get the HMAC digest result and truncate it to a 31-bit string
-    let hash = hmac.result();
-    let length = hash.code().len();
-    let offset = hash.code()[length-1] & 0xF;   
-    let mut hotp_num= extract31(hash.code(), offset as usize);
+fn new_mac<M: Mac + hmac::digest::KeyInit>(key: &[u8]) -> M {
+    <M as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length")
+}
+
+/// The dynamic truncation of the MAC of `message`, with the digest kept
+/// on the stack.
+fn mac_truncated<M: Mac>(mut mac: M, message: &[u8]) -> u32 {
+    mac.update(message);
+    dynamic_truncate(&mac.finalize().into_bytes())
+}
+
+/// HOTP with a fixed truncation offset instead of the dynamic one, which
+/// RFC 4226 permits for testing and some hardware tokens use. `offset`
+/// should be at most the digest length minus 4. Other parameters are the
+/// same as [`hotp_with_algorithm`].
+pub fn hotp_with_offset(key: impl AsRef<[u8]>, c: u64, digit_len: usize, algorithm: Algorithm, offset: usize) -> Result<Code, OtpError> {
+    let key = key.as_ref();
+    if !DIGITS_RANGE.contains(&digit_len) {
+        return Err(OtpError::InvalidDigits(digit_len));
+    }
+    if key.is_empty() {
+        return Err(OtpError::KeyTooShort);
+    }
+    let hash = hmac(key, &big_endian_u64(c), algorithm);
+    Ok(format_decimal(truncate(&hash, Some(offset))?, digit_len))
+}
+
+/// Truncate an HMAC output to a 31-bit value as RFC 4226 describes.
+///
+/// With `offset` as `None` the dynamic offset from the low 4 bits of the
+/// last byte is used, otherwise the 4 bytes at `offset` are extracted.
+/// Returns [`OtpError::InvalidOffset`] if the 4 bytes are out of `hash`.
+pub fn truncate(hash: &[u8], offset: Option<usize>) -> Result<u32, OtpError> {
+    let offset = match offset {
+        Some(offset) => offset,
+        None => match hash.last() {
+            Some(last) => (last & 0xF) as usize,
+            None => return Err(OtpError::InvalidOffset)
+        }
+    };
+    if offset.checked_add(4).is_none_or(|end| end > hash.len()) {
+        return Err(OtpError::InvalidOffset);
+    }
+    Ok(extract31(hash, offset))
+}
 
-    // keep 6 digits to get the HOTP value
-    let mut hotp: Vec<u8> = Vec::new();
-    for _i in 0..digit_len {
-        let c = '0' as u8 + (hotp_num % 10) as u8;
-        hotp_num = hotp_num / 10;
-        hotp.push(c);
+/// Compute the HMAC of `message` with the hash function `algorithm`.
+pub(crate) fn hmac(key: &[u8], message: &[u8], algorithm: Algorithm) -> Vec<u8> {
+    match algorithm {
+        Algorithm::Sha1 => hmac_digest::<Hmac<Sha1>>(key, message),
+        Algorithm::Sha256 => hmac_digest::<Hmac<Sha256>>(key, message),
+        Algorithm::Sha512 => hmac_digest::<Hmac<Sha512>>(key, message),
     }
-    hotp.reverse();
-    String::from_utf8(hotp).unwrap()
+}
+
+fn hmac_digest<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = new_mac::<M>(key);
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Truncate the HMAC digest to a 31-bit value. The offset always comes
+/// from the low 4 bits of the last byte, so it works for the 20, 32 and
+/// 64 bytes long digests alike.
+pub(crate) fn dynamic_truncate(hash: &[u8]) -> u32 {
+    let length = hash.len();
+    let offset = hash[length-1] & 0xF;
+    extract31(hash, offset as usize)
 }
 
 fn extract31(hash: &[u8], offset: usize) -> u32 {
     let mut value = 0u32;
     for i in 0..4 {
         let pos_shift = (3-i) * 8;
-        value = value | ((hash[offset + i] as u32) << pos_shift);
+        value |= (hash[offset + i] as u32) << pos_shift;
     }
     value  & 0x7FFFFFFF
 }
@@ -66,27 +417,82 @@ fn extract31(hash: &[u8], offset: usize) -> u32 {
 /// 
 /// * `t0` is the start time in seconds since UNIX epoch (default as 0).
 /// * `interval` is the interval time in seconds (default is 30).
-pub fn totp(key: &[u8], t0:u64, interval: u64) -> String {
-    let t = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
-    let c = (t - t0) / interval;
-    hotp(key, c, 6)
+/// * `digit_len` is the length of generated OTP. It should be 6 to 10.
+///
+/// Besides the errors of [`hotp`], it returns [`OtpError::InvalidInterval`]
+/// if `interval` is zero and [`OtpError::TimeError`] if the current time
+/// is before `t0`.
+#[cfg(feature = "std")]
+pub fn totp(key: impl AsRef<[u8]>, t0:u64, interval: u64, digit_len: usize) -> Result<Code, OtpError> {
+    totp_at(key, now()?, t0, interval, digit_len)
+}
+
+/// TOTP for an explicit time `t` in seconds since UNIX epoch instead
+/// of the current system time. Other parameters are the same as [`totp`].
+pub fn totp_at(key: impl AsRef<[u8]>, t: u64, t0:u64, interval: u64, digit_len: usize) -> Result<Code, OtpError> {
+    hotp(key, time_counter(t, t0, interval)?, digit_len)
+}
+
+/// TOTP with HMAC-SHA-256. Parameters are the same as [`totp`].
+#[cfg(feature = "std")]
+pub fn totp_sha256(key: impl AsRef<[u8]>, t0:u64, interval: u64, digit_len: usize) -> Result<Code, OtpError> {
+    hotp_sha256(key, time_counter(now()?, t0, interval)?, digit_len)
 }
 
-fn big_endian_u64(v: u64)-> [u8;8] {
+/// TOTP with HMAC-SHA-512. Parameters are the same as [`totp`].
+#[cfg(feature = "std")]
+pub fn totp_sha512(key: impl AsRef<[u8]>, t0:u64, interval: u64, digit_len: usize) -> Result<Code, OtpError> {
+    hotp_sha512(key, time_counter(now()?, t0, interval)?, digit_len)
+}
+
+/// TOTP with the hash function selected at runtime by `algorithm`.
+/// Other parameters are the same as [`totp`].
+#[cfg(feature = "std")]
+pub fn totp_with_algorithm(key: impl AsRef<[u8]>, t0:u64, interval: u64, digit_len: usize, algorithm: Algorithm) -> Result<Code, OtpError> {
+    totp_at_with_algorithm(key, now()?, t0, interval, digit_len, algorithm)
+}
+
+/// TOTP for an explicit time `t` with the hash function selected at
+/// runtime by `algorithm`. Other parameters are the same as [`totp_at`].
+pub fn totp_at_with_algorithm(key: impl AsRef<[u8]>, t: u64, t0:u64, interval: u64, digit_len: usize, algorithm: Algorithm) -> Result<Code, OtpError> {
+    hotp_with_algorithm(key, time_counter(t, t0, interval)?, digit_len, algorithm)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn now() -> Result<u64, OtpError> {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .map_err(|_| OtpError::TimeError)
+}
+
+pub(crate) fn time_counter(t: u64, t0: u64, interval: u64) -> Result<u64, OtpError> {
+    if interval == 0 {
+        return Err(OtpError::InvalidInterval);
+    }
+    match t.checked_sub(t0) {
+        Some(elapsed) => Ok(elapsed / interval),
+        None => Err(OtpError::TimeError)
+    }
+}
+
+pub(crate) fn big_endian_u64(v: u64)-> [u8;8] {
     let mut r = [0u8;8];
-    for i in 0..8 {
+    for (i, byte) in r.iter_mut().enumerate() {
         let offset = (7 - i) * 8;
         let mask = 0xFFu64 << offset;
-        r[i] = ((v & mask)>> offset) as u8;
+        *byte = ((v & mask)>> offset) as u8;
     }
-    return r;
+    r
 }
 
 
+#[cfg(test)]
 mod test {
     use crate::base32;
+    use crate::error::OtpError;
 
-    use super::{big_endian_u64, extract31, hotp};
+    use super::{big_endian_u64, extract31, hotp, hotp_raw, hotp_raw_with_algorithm, hotp_const, hotp_into, hotp_range, hotp_with_mac, hotp_with_offset, truncate, hotp_sha256, hotp_sha512, hotp_with_algorithm, totp, totp_at, totp_at_with_algorithm, Algorithm, PreparedKey};
 
     #[test]
     fn test_big_endian() {
@@ -114,20 +520,31 @@ mod test {
     fn test_hotp() {
         let key = big_endian_u64(0xdeadbeef12345678);
         let c = 19260817;
-        let code = hotp(&key, c, 6);
+        let code = hotp(key, c, 6).unwrap();
         assert_eq!(code, "649433");
-        let code = hotp(&key, c, 7);
+        let code = hotp(key, c, 7).unwrap();
         assert_eq!(code, "6649433");
-        let code = hotp(&key, c, 8);
+        let code = hotp(key, c, 8).unwrap();
         assert_eq!(code, "66649433");
     }
 
     #[test]
-    #[should_panic]
     fn test_hotp_wrong_digit_len() {
         let key = big_endian_u64(0xdeadbeef12345678);
         let c = 19260817;
-        let code = hotp(&key, c, 5);
+        assert_eq!(hotp(key, c, 5), Err(OtpError::InvalidDigits(5)));
+    }
+
+    #[test]
+    fn test_hotp_empty_key() {
+        assert_eq!(hotp([], 0, 6), Err(OtpError::KeyTooShort));
+    }
+
+    #[test]
+    fn test_totp_invalid_time() {
+        let key = b"12345678901234567890";
+        assert_eq!(totp_at(key, 59, 0, 0, 6), Err(OtpError::InvalidInterval));
+        assert_eq!(totp_at(key, 59, 60, 30, 6), Err(OtpError::TimeError));
     }
 
     #[test]
@@ -135,7 +552,180 @@ mod test {
         // This test case is from Google Authenticator Android unit test.
         // See more in https://github.com/google/google-authenticator-android/blob/master/javatests/com/google/android/apps/authenticator/otp/PasscodeGeneratorTest.java
         let key = base32::decode("7777777777777777").unwrap();
-        assert_eq!(hotp(&key, 0, 6), "724477");
-        assert_eq!(hotp(&key, 123456789123456789, 6), "815107");
+        assert_eq!(hotp(&key, 0, 6).unwrap(), "724477");
+        assert_eq!(hotp(&key, 123456789123456789, 6).unwrap(), "815107");
+    }
+
+    #[test]
+    fn test_hotp_sha2_rfc6238() {
+        // Test vectors from RFC 6238 Appendix B, with T = 59 and
+        // T = 1111111109 seconds (counters 1 and 0x23523EC).
+        let key_sha1 = b"12345678901234567890";
+        let key_sha256 = b"12345678901234567890123456789012";
+        let key_sha512 = b"1234567890123456789012345678901234567890123456789012345678901234";
+        assert_eq!(hotp(key_sha1, 1, 8).unwrap(), "94287082");
+        assert_eq!(hotp_sha256(key_sha256, 1, 8).unwrap(), "46119246");
+        assert_eq!(hotp_sha512(key_sha512, 1, 8).unwrap(), "90693936");
+        assert_eq!(hotp(key_sha1, 0x23523EC, 8).unwrap(), "07081804");
+        assert_eq!(hotp_sha256(key_sha256, 0x23523EC, 8).unwrap(), "68084774");
+        assert_eq!(hotp_sha512(key_sha512, 0x23523EC, 8).unwrap(), "25091201");
+    }
+
+    #[test]
+    fn test_hotp_with_algorithm() {
+        let key = b"12345678901234567890123456789012";
+        assert_eq!(hotp_with_algorithm(key, 1, 8, Algorithm::Sha256).unwrap(), hotp_sha256(key, 1, 8).unwrap());
+        assert_eq!(hotp_with_algorithm(key, 1, 8, Algorithm::Sha512).unwrap(), hotp_sha512(key, 1, 8).unwrap());
+        assert_eq!(hotp_with_algorithm(key, 1, 8, Algorithm::Sha1).unwrap(), hotp(key, 1, 8).unwrap());
+    }
+
+    #[test]
+    fn test_algorithm_from_name() {
+        assert_eq!(Algorithm::from_name("SHA1"), Some(Algorithm::Sha1));
+        assert_eq!(Algorithm::from_name("sha256"), Some(Algorithm::Sha256));
+        assert_eq!(Algorithm::from_name("SHA-512"), Some(Algorithm::Sha512));
+        assert_eq!(Algorithm::from_name("MD5"), None);
+        assert_eq!(Algorithm::from_name(Algorithm::Sha256.name()), Some(Algorithm::Sha256));
+    }
+
+    #[test]
+    fn test_totp_digit_len() {
+        let key = b"12345678901234567890";
+        assert_eq!(totp(key, 0, 30, 6).unwrap().as_str().len(), 6);
+        assert_eq!(totp(key, 0, 30, 8).unwrap().as_str().len(), 8);
+    }
+
+    #[test]
+    fn test_totp_at_rfc6238() {
+        // Test vectors from RFC 6238 Appendix B.
+        let key_sha1 = b"12345678901234567890";
+        let key_sha256 = b"12345678901234567890123456789012";
+        let key_sha512 = b"1234567890123456789012345678901234567890123456789012345678901234";
+        let vectors = [
+            (59u64, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (t, sha1, sha256, sha512) in vectors {
+            assert_eq!(totp_at(key_sha1, t, 0, 30, 8).unwrap(), sha1);
+            assert_eq!(totp_at_with_algorithm(key_sha256, t, 0, 30, 8, Algorithm::Sha256).unwrap(), sha256);
+            assert_eq!(totp_at_with_algorithm(key_sha512, t, 0, 30, 8, Algorithm::Sha512).unwrap(), sha512);
+        }
+    }
+
+    #[test]
+    fn test_hotp_long_digits() {
+        // The truncated values of the RFC 4226 test key are 1284755224 and
+        // 1094287082 for counters 0 and 1.
+        let key = b"12345678901234567890";
+        assert_eq!(hotp(key, 0, 9).unwrap(), "284755224");
+        assert_eq!(hotp(key, 0, 10).unwrap(), "1284755224");
+        assert_eq!(hotp(key, 1, 9).unwrap(), "094287082");
+        assert_eq!(hotp(key, 1, 10).unwrap(), "1094287082");
+        assert_eq!(hotp(key, 1, 11), Err(OtpError::InvalidDigits(11)));
+    }
+
+    #[test]
+    fn test_hotp_raw() {
+        // Truncated values from RFC 4226 Appendix D.
+        let key = b"12345678901234567890";
+        assert_eq!(hotp_raw(key, 0).unwrap(), 1284755224);
+        assert_eq!(hotp_raw(key, 1).unwrap(), 1094287082);
+        assert_eq!(hotp_raw(key, 9).unwrap(), 645520489);
+        assert_eq!(hotp_raw_with_algorithm(key, 9, Algorithm::Sha1), hotp_raw(key, 9));
+        assert_eq!(hotp_raw([], 0), Err(OtpError::KeyTooShort));
+    }
+
+    #[test]
+    fn test_truncate() {
+        // HMAC-SHA-1 of counter 1 with the RFC 4226 test key.
+        let hash = [
+            0x75, 0xa4, 0x8a, 0x19, 0xd4, 0xcb, 0xe1, 0x00, 0x64, 0x4e,
+            0x8a, 0xc1, 0x39, 0x7e, 0xea, 0x74, 0x7a, 0x2d, 0x33, 0xab,
+        ];
+        assert_eq!(truncate(&hash, None).unwrap(), 1094287082);
+        assert_eq!(truncate(&hash, Some(0)).unwrap(), 1973717529);
+        assert_eq!(truncate(&hash, Some(16)).unwrap(), 2049782699);
+        assert_eq!(truncate(&hash, Some(17)), Err(OtpError::InvalidOffset));
+        assert_eq!(truncate(&[], None), Err(OtpError::InvalidOffset));
+        assert_eq!(truncate(&hash, Some(usize::MAX)), Err(OtpError::InvalidOffset));
+    }
+
+    #[test]
+    fn test_hotp_with_offset() {
+        let key = b"12345678901234567890";
+        assert_eq!(hotp_with_offset(key, 1, 6, Algorithm::Sha1, 4).unwrap(), "647552");
+        assert_eq!(hotp_with_offset(key, 1, 6, Algorithm::Sha1, 20), Err(OtpError::InvalidOffset));
+        assert_eq!(hotp_with_offset(key, 1, 6, Algorithm::Sha1, usize::MAX), Err(OtpError::InvalidOffset));
+    }
+
+    #[test]
+    fn test_prepared_key() {
+        let key = b"12345678901234567890";
+        for algorithm in [Algorithm::Sha1, Algorithm::Sha256, Algorithm::Sha512] {
+            let prepared = PreparedKey::new(key, algorithm).unwrap();
+            assert_eq!(prepared.algorithm(), algorithm);
+            for c in 0..10 {
+                assert_eq!(prepared.hotp(c, 8).unwrap(), hotp_with_algorithm(key, c, 8, algorithm).unwrap());
+            }
+        }
+        let prepared = PreparedKey::new(key, Algorithm::Sha1).unwrap();
+        assert_eq!(prepared.hotp(0, 5), Err(OtpError::InvalidDigits(5)));
+        assert_eq!(format!("{:?}", prepared), "PreparedKey { algorithm: Sha1, .. }");
+        assert!(matches!(PreparedKey::new([], Algorithm::Sha1), Err(OtpError::KeyTooShort)));
+    }
+
+    #[test]
+    fn test_hotp_const() {
+        let key = b"12345678901234567890";
+        assert_eq!(hotp_const::<6>(key, 7).unwrap(), "162583");
+        assert_eq!(hotp_const::<8>(key, 7).unwrap(), hotp(key, 7, 8).unwrap().as_str());
+        assert_eq!(hotp_const::<10>(key, 0).unwrap().to_string(), hotp(key, 0, 10).unwrap().to_string());
+        assert!(matches!(hotp_const::<6>(&[], 0), Err(OtpError::KeyTooShort)));
+    }
+
+    #[test]
+    fn test_hotp_range() {
+        let key = b"12345678901234567890";
+        let codes: Vec<_> = hotp_range(key, 0, 10, 6).unwrap().collect();
+        assert_eq!(codes.len(), 10);
+        for (c, code) in codes.iter().enumerate() {
+            assert_eq!(code, &hotp(key, c as u64, 6).unwrap());
+        }
+        let mut range = hotp_range(key, 7, 2, 6).unwrap();
+        assert_eq!(range.len(), 2);
+        assert_eq!(range.next().unwrap(), "162583");
+        assert_eq!(range.counter(), 8);
+        assert_eq!(range.next().unwrap(), "399871");
+        assert!(range.next().is_none());
+        assert_eq!(hotp_range(key, u64::MAX - 1, 5, 6).unwrap().count(), 1);
+        assert!(matches!(hotp_range([], 0, 1, 6), Err(OtpError::KeyTooShort)));
+    }
+
+    #[test]
+    fn test_hotp_into() {
+        let key = b"12345678901234567890";
+        let mut out = [b'x'; 10];
+        assert_eq!(hotp_into(key, 1, 6, &mut out).unwrap(), 6);
+        assert_eq!(&out, b"287082xxxx");
+        assert_eq!(hotp_into(key, 1, 8, &mut out[..6]), Err(OtpError::BufferTooSmall(8)));
+        assert_eq!(hotp_into(key, 1, 11, &mut out), Err(OtpError::InvalidDigits(11)));
+        assert_eq!(hotp_into([], 1, 6, &mut out), Err(OtpError::KeyTooShort));
+    }
+
+    #[test]
+    fn test_hotp_with_mac() {
+        use hmac::{Hmac, Mac};
+        use sha1::Sha1;
+        let key = b"12345678901234567890";
+        for c in 0..10 {
+            let mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+            assert_eq!(hotp_with_mac(mac, c, 6).unwrap(), hotp(key, c, 6).unwrap());
+        }
+        let mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+        assert_eq!(hotp_with_mac(mac, 0, 5), Err(OtpError::InvalidDigits(5)));
     }
 }
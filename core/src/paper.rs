@@ -0,0 +1,255 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Printable paper backups of accounts, as A4 pages in SVG.
+//!
+//! Each account gets its name, the parameters of its codes, a QR code of
+//! its `otpauth://` URI and its Base 32 secret, so that it can be restored
+//! by scanning the code or by typing the secret. Browsers print the pages
+//! at their actual size.
+//!
+//! ```
+//! use yotp_core::account::Account;
+//! use yotp_core::paper::PaperBackup;
+//! use yotp_core::uri::OtpAuthUri;
+//!
+//! let accounts: Vec<Account> = vec![OtpAuthUri::parse("otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP").unwrap().into()];
+//! let pages = PaperBackup::new(&accounts).title("Alice's accounts").to_svg_pages().unwrap();
+//! assert_eq!(pages.len(), 1);
+//! assert!(pages[0].contains("JBSW Y3DP EHPK 3PXP"));
+//! ```
+//!
+//! With the `vault` feature, [`PaperBackup::encrypted`] prints QR codes of
+//! the blocks of [`Account::export_encrypted`] instead, and leaves out
+//! the secrets, so that the sheet is useless without the passphrase.
+
+use std::fmt::Write;
+
+use zeroize::Zeroizing;
+
+use crate::account::Account;
+use crate::error::OtpError;
+use crate::qr::QrCode;
+use crate::uri::OtpType;
+#[cfg(feature = "vault")]
+use crate::vault::KdfParams;
+
+const PAGE_WIDTH: usize = 210;
+const PAGE_HEIGHT: usize = 297;
+const MARGIN: usize = 15;
+const COLUMNS: usize = 2;
+const ROWS: usize = 4;
+const CELL_WIDTH: usize = (PAGE_WIDTH - 2 * MARGIN) / COLUMNS;
+const CELL_HEIGHT: usize = 60;
+/// The top of the first row, below the title.
+const GRID_TOP: usize = 32;
+const QR_SIZE: usize = 40;
+/// The quiet zone around the QR codes in modules.
+const QUIET_ZONE: usize = 4;
+/// The characters of a label that fit next to a QR code.
+const LABEL_LEN: usize = 22;
+
+/// A paper backup of accounts, a page at a time.
+///
+/// Unless encrypted, the pages carry the secrets of the accounts. They
+/// should be printed, not stored, and the sheets kept like the recovery
+/// codes of the accounts.
+pub struct PaperBackup<'a> {
+    accounts: &'a [Account],
+    title: String,
+    #[cfg(feature = "vault")]
+    encryption: Option<(Zeroizing<String>, KdfParams)>,
+}
+
+impl<'a> PaperBackup<'a> {
+    /// The number of accounts on each page.
+    pub const ACCOUNTS_PER_PAGE: usize = COLUMNS * ROWS;
+
+    /// A backup of `accounts`, in their order.
+    pub fn new(accounts: &'a [Account]) -> PaperBackup<'a> {
+        PaperBackup {
+            accounts,
+            title: "yOTP paper backup".into(),
+            #[cfg(feature = "vault")]
+            encryption: None,
+        }
+    }
+
+    /// Set the title printed at the top of each page.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Print QR codes of the accounts encrypted with `passphrase` instead
+    /// of their secrets, see [`Account::export_encrypted`]. The names of
+    /// the accounts are still printed.
+    #[cfg(feature = "vault")]
+    pub fn encrypted(self, passphrase: &str) -> Self {
+        self.encrypted_with_params(passphrase, KdfParams::default())
+    }
+
+    /// [`PaperBackup::encrypted`] with a key derived with `params`.
+    #[cfg(feature = "vault")]
+    pub fn encrypted_with_params(mut self, passphrase: &str, params: KdfParams) -> Self {
+        self.encryption = Some((Zeroizing::new(passphrase.into()), params));
+        self
+    }
+
+    /// The pages as SVG documents, at least one.
+    ///
+    /// Returns [`OtpError::QrError`] if the URI of an account is too long
+    /// for a QR code.
+    pub fn to_svg_pages(&self) -> Result<Vec<String>, OtpError> {
+        let mut chunks: Vec<&[Account]> = self.accounts.chunks(PaperBackup::ACCOUNTS_PER_PAGE).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        let count = chunks.len();
+        let mut pages = Vec::with_capacity(count);
+        for (index, accounts) in chunks.into_iter().enumerate() {
+            let mut svg = format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\">\n\
+                 <rect width=\"{w}\" height=\"{h}\" fill=\"#fff\"/>\n",
+                w = PAGE_WIDTH,
+                h = PAGE_HEIGHT
+            );
+            text(&mut svg, MARGIN, 22, "8", "bold", &self.title);
+            for (i, account) in accounts.iter().enumerate() {
+                let x = MARGIN + i % COLUMNS * CELL_WIDTH;
+                let y = GRID_TOP + i / COLUMNS * CELL_HEIGHT;
+                self.account(&mut svg, x, y, account)?;
+            }
+            let footer = format!("Page {} of {}. {}", index + 1, count, self.warning());
+            text(&mut svg, MARGIN, PAGE_HEIGHT - MARGIN + 5, "3", "normal", &footer);
+            svg.push_str("</svg>\n");
+            pages.push(svg);
+        }
+        Ok(pages)
+    }
+
+    fn account(&self, svg: &mut String, x: usize, y: usize, account: &Account) -> Result<(), OtpError> {
+        let text_x = x + QR_SIZE + 4;
+        text(svg, text_x, y + 6, "4.5", "bold", &shorten(&account.display_label()));
+        if account.label.is_some() || account.uri.issuer.is_some() {
+            text(svg, text_x, y + 12, "3.5", "normal", &shorten(&account.uri.account));
+        }
+        #[cfg(feature = "vault")]
+        if let Some((passphrase, params)) = &self.encryption {
+            let armored = Zeroizing::new(account.export_encrypted_with_params(passphrase, *params)?);
+            qr(svg, x, y, &QrCode::new(&armored)?);
+            text(svg, text_x, y + 20, "3.5", "normal", "Encrypted, import the");
+            text(svg, text_x, y + 25, "3.5", "normal", "scanned text with the");
+            text(svg, text_x, y + 30, "3.5", "normal", "passphrase.");
+            return Ok(());
+        }
+        qr(svg, x, y, &QrCode::from_uri(&account.uri)?);
+        let uri = &account.uri;
+        let parameters = match uri.otp_type {
+            OtpType::Totp => format!("TOTP, {}, {} digits, {} s", uri.algorithm.name(), uri.digits, uri.period),
+            OtpType::Hotp => format!("HOTP, {}, {} digits, counter {}", uri.algorithm.name(), uri.digits, uri.counter),
+        };
+        text(svg, text_x, y + 18, "3", "normal", &parameters);
+        let secret = Zeroizing::new(uri.secret.to_base32());
+        let groups: Vec<&str> = secret.as_bytes().chunks(4).map(|group| std::str::from_utf8(group).expect("Base 32 is ASCII")).collect();
+        for (row, line) in groups.chunks(4).enumerate() {
+            let line = Zeroizing::new(line.join(" "));
+            let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" font-size=\"3.5\" font-family=\"monospace\">{}</text>", text_x, y + 25 + row * 5, line.as_str());
+        }
+        Ok(())
+    }
+
+    fn warning(&self) -> &'static str {
+        #[cfg(feature = "vault")]
+        if self.encryption.is_some() {
+            return "Keep the passphrase apart from this sheet.";
+        }
+        "This sheet holds the secrets of the accounts, keep it safe."
+    }
+}
+
+/// Draw `code` as a square of [`QR_SIZE`] at `x` and `y`.
+fn qr(svg: &mut String, x: usize, y: usize, code: &QrCode) {
+    let side = code.width() + 2 * QUIET_ZONE;
+    let _ = write!(svg, "<svg x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {s} {s}\" shape-rendering=\"crispEdges\"><path fill=\"#000\" d=\"", x, y, QR_SIZE, QR_SIZE, s = side);
+    for row in 0..code.width() {
+        for column in (0..code.width()).filter(|&column| code.is_dark(column, row)) {
+            let _ = write!(svg, "M{},{}h1v1h-1z", column + QUIET_ZONE, row + QUIET_ZONE);
+        }
+    }
+    svg.push_str("\"/></svg>\n");
+}
+
+fn text(svg: &mut String, x: usize, y: usize, size: &str, weight: &str, content: &str) {
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" font-size=\"{}\" font-weight=\"{}\">{}</text>", x, y, size, weight, escape(content));
+}
+
+/// `label` cut to the width next to a QR code.
+fn shorten(label: &str) -> String {
+    if label.chars().count() <= LABEL_LEN {
+        return label.into();
+    }
+    let mut short: String = label.chars().take(LABEL_LEN - 1).collect();
+    short.push('…');
+    short
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::account::Account;
+    use crate::uri::OtpAuthUri;
+
+    use super::{shorten, PaperBackup};
+
+    fn accounts(count: usize) -> Vec<Account> {
+        (0..count)
+            .map(|i| OtpAuthUri::parse(&format!("otpauth://totp/A%26B:user{}?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&digits=8", i)).unwrap().into())
+            .collect()
+    }
+
+    #[test]
+    fn test_pages() {
+        assert_eq!(PaperBackup::new(&[]).to_svg_pages().unwrap().len(), 1);
+        let accounts = accounts(PaperBackup::ACCOUNTS_PER_PAGE + 1);
+        let pages = PaperBackup::new(&accounts).title("<Backup>").to_svg_pages().unwrap();
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].starts_with("<?xml") && pages[0].contains("width=\"210mm\" height=\"297mm\""));
+        assert!(pages[0].contains("&lt;Backup&gt;") && pages[1].contains("Page 2 of 2."));
+        assert_eq!(pages[0].matches("shape-rendering").count(), PaperBackup::ACCOUNTS_PER_PAGE);
+        assert_eq!(pages[1].matches("shape-rendering").count(), 1);
+        assert!(pages[1].contains("A&amp;B: user8"));
+        assert!(pages[1].contains("TOTP, SHA1, 8 digits, 30 s"));
+        assert_eq!(pages[1].matches(">JBSW Y3DP EHPK 3PXP</text>").count(), 2);
+        assert_eq!(shorten("An account with a long label"), "An account with a lon…");
+    }
+
+    #[cfg(feature = "vault")]
+    #[test]
+    fn test_encrypted() {
+        use crate::vault::KdfParams;
+
+        let accounts = accounts(1);
+        let params = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+        let page = PaperBackup::new(&accounts).encrypted_with_params("passphrase", params).to_svg_pages().unwrap().remove(0);
+        assert!(page.contains("A&amp;B: user0") && page.contains("Keep the passphrase apart"));
+        assert!(!page.contains("JBSW"));
+    }
+}
@@ -0,0 +1,256 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The `otpauth://` URIs of pass entries, as the pass-otp extension of
+//! the standard Unix password manager keeps them.
+//!
+//! An entry is a GPG encrypted file `<name>.gpg` in the password store,
+//! `$PASSWORD_STORE_DIR` or `~/.password-store`. pass-otp reads the first
+//! line of the entry starting with `otpauth://`. Like pass, the store
+//! runs `gpg` for encryption, to the keys listed in the `.gpg-id` file of
+//! the nearest directory.
+//!
+//! ```
+//! use yotp_core::pass;
+//!
+//! let entry = "hunter2\nlogin: alice\notpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP\n";
+//! assert_eq!(pass::find_uri(entry).unwrap().unwrap().account, "alice");
+//! ```
+
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+use crate::uri::OtpAuthUri;
+
+const URI_PREFIX: &str = "otpauth://";
+
+/// The account of the first `otpauth://` line of the decrypted `entry`,
+/// or `None` if it has none.
+pub fn find_uri(entry: &str) -> Option<Result<OtpAuthUri, OtpError>> {
+    let line = entry.lines().map(str::trim).find(|line| line.starts_with(URI_PREFIX))?;
+    Some(OtpAuthUri::parse(line).map_err(OtpError::from))
+}
+
+/// `entry` with its first `otpauth://` line replaced by the URI of `uri`,
+/// or with the URI appended as a new line if it has none, as
+/// `pass otp append` does. The other lines are kept.
+pub fn set_uri(entry: &str, uri: &OtpAuthUri) -> Zeroizing<String> {
+    let uri = Zeroizing::new(uri.to_string());
+    let mut out = Zeroizing::new(String::with_capacity(entry.len() + uri.len() + 1));
+    let mut replaced = false;
+    for line in entry.lines() {
+        if !replaced && line.trim().starts_with(URI_PREFIX) {
+            out.push_str(&uri);
+            replaced = true;
+        } else {
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    if !replaced {
+        out.push_str(&uri);
+        out.push('\n');
+    }
+    out
+}
+
+/// A password store on disk.
+pub struct PasswordStore {
+    dir: PathBuf,
+}
+
+impl PasswordStore {
+    /// The store at `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> PasswordStore {
+        PasswordStore { dir: dir.into() }
+    }
+
+    /// The store pass uses: `$PASSWORD_STORE_DIR`, or `.password-store`
+    /// in the home directory.
+    ///
+    /// Returns [`OtpError::ImportError`] if neither is set.
+    pub fn open_default() -> Result<PasswordStore, OtpError> {
+        if let Some(dir) = std::env::var_os("PASSWORD_STORE_DIR") {
+            return Ok(PasswordStore::new(dir));
+        }
+        let home = std::env::var_os("HOME").ok_or_else(|| import_error("neither PASSWORD_STORE_DIR nor HOME is set"))?;
+        Ok(PasswordStore::new(Path::new(&home).join(".password-store")))
+    }
+
+    /// The directory of the store.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Decrypt the entry `name`, e.g. `email/example.com`.
+    ///
+    /// Returns [`OtpError::ImportError`] if `name` is not a relative path
+    /// inside the store or `gpg` fails.
+    pub fn read(&self, name: &str) -> Result<Zeroizing<String>, OtpError> {
+        let path = self.entry_path(name).map_err(|msg| import_error(&msg))?;
+        let output = Command::new("gpg")
+            .args(["--quiet", "--batch", "--yes", "--decrypt"])
+            .arg(&path)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| import_error(&format!("cannot run gpg: {}", e)))?;
+        let stdout = Zeroizing::new(output.stdout);
+        if !output.status.success() {
+            return Err(import_error(&format!("gpg fails to decrypt {}", path.display())));
+        }
+        let entry = std::str::from_utf8(&stdout).map_err(|_| import_error("entry is not valid UTF-8"))?;
+        Ok(Zeroizing::new(entry.to_owned()))
+    }
+
+    /// The account of the entry `name`.
+    ///
+    /// Returns [`OtpError::ImportError`] if the entry has no `otpauth://`
+    /// line, or for the reasons of [`PasswordStore::read`].
+    pub fn read_uri(&self, name: &str) -> Result<OtpAuthUri, OtpError> {
+        find_uri(&self.read(name)?).unwrap_or_else(|| Err(import_error(&format!("{} has no otpauth:// URI", name))))
+    }
+
+    /// Encrypt `contents` as the entry `name` for the keys of the nearest
+    /// `.gpg-id`, replacing the entry if it exists.
+    ///
+    /// Returns [`OtpError::ExportError`] if `name` is not a relative path
+    /// inside the store, no `.gpg-id` is found or `gpg` fails.
+    pub fn write(&self, name: &str, contents: &str) -> Result<(), OtpError> {
+        let path = self.entry_path(name).map_err(|msg| export_error(&msg))?;
+        let recipients = self.recipients(&path)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| export_error(&e.to_string()))?;
+        }
+        let mut command = Command::new("gpg");
+        command.args(["--quiet", "--batch", "--yes", "--encrypt"]);
+        for recipient in &recipients {
+            command.arg("--recipient").arg(recipient);
+        }
+        let mut child = command
+            .arg("--output")
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| export_error(&format!("cannot run gpg: {}", e)))?;
+        let written = child.stdin.take().expect("stdin is piped").write_all(contents.as_bytes());
+        let status = child.wait().map_err(|e| export_error(&e.to_string()))?;
+        if written.is_err() || !status.success() {
+            return Err(export_error(&format!("gpg fails to encrypt {}", path.display())));
+        }
+        Ok(())
+    }
+
+    /// Store `uri` in the entry `name` with [`set_uri`], creating the
+    /// entry if it does not exist.
+    pub fn write_uri(&self, name: &str, uri: &OtpAuthUri) -> Result<(), OtpError> {
+        let path = self.entry_path(name).map_err(|msg| export_error(&msg))?;
+        let entry = if path.exists() { self.read(name)? } else { Zeroizing::new(String::new()) };
+        self.write(name, &set_uri(&entry, uri))
+    }
+
+    fn entry_path(&self, name: &str) -> Result<PathBuf, String> {
+        let name = Path::new(name);
+        if name.as_os_str().is_empty() || !name.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(format!("invalid entry name {}", name.display()));
+        }
+        let mut path = self.dir.join(name).into_os_string();
+        path.push(".gpg");
+        Ok(path.into())
+    }
+
+    /// The key IDs of the `.gpg-id` nearest to `path`, one per line.
+    fn recipients(&self, path: &Path) -> Result<Vec<String>, OtpError> {
+        let mut dir = path.parent();
+        while let Some(current) = dir.filter(|dir| dir.starts_with(&self.dir)) {
+            if let Ok(ids) = std::fs::read_to_string(current.join(".gpg-id")) {
+                let ids: Vec<String> = ids
+                    .lines()
+                    .map(|line| line.split('#').next().unwrap_or_default().trim())
+                    .filter(|id| !id.is_empty())
+                    .map(str::to_owned)
+                    .collect();
+                if !ids.is_empty() {
+                    return Ok(ids);
+                }
+            }
+            dir = current.parent();
+        }
+        Err(export_error("no .gpg-id in the password store"))
+    }
+}
+
+fn import_error(msg: &str) -> OtpError {
+    OtpError::ImportError(format!("pass: {}", msg))
+}
+
+fn export_error(msg: &str) -> OtpError {
+    OtpError::ExportError(format!("pass: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::uri::OtpAuthUri;
+
+    use super::{find_uri, set_uri, PasswordStore};
+
+    #[test]
+    fn test_find_uri() {
+        let entry = "hunter2\n  otpauth://hotp/a?secret=JBSWY3DPEHPK3PXP&counter=3\notpauth://totp/b?secret=JBSWY3DP\n";
+        assert_eq!(find_uri(entry).unwrap().unwrap().account, "a");
+        assert!(find_uri("hunter2\nurl: https://otpauth.example.com\n").is_none());
+        assert_eq!(find_uri("otpauth://totp/a").unwrap(), Err(OtpError::InvalidUri));
+    }
+
+    #[test]
+    fn test_set_uri() {
+        let uri = OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example").unwrap();
+        let line = uri.to_string();
+        assert_eq!(*set_uri("hunter2", &uri), format!("hunter2\n{}\n", line));
+        assert_eq!(*set_uri("", &uri), format!("{}\n", line));
+        let entry = "hunter2\notpauth://totp/old?secret=GEZDGNBV\nlogin: alice\n";
+        assert_eq!(*set_uri(entry, &uri), format!("hunter2\n{}\nlogin: alice\n", line));
+    }
+
+    #[test]
+    fn test_entry_path() {
+        let store = PasswordStore::new("/store");
+        assert_eq!(store.entry_path("email/example.com").unwrap(), std::path::Path::new("/store/email/example.com.gpg"));
+        for name in ["", "../secret", "/etc/passwd", "a/../../b"] {
+            assert!(store.entry_path(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_recipients() {
+        let dir = std::env::temp_dir().join(format!("yotp-pass-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("work/team")).unwrap();
+        std::fs::write(dir.join(".gpg-id"), "alice@example.com\n").unwrap();
+        std::fs::write(dir.join("work/.gpg-id"), "# team keys\nAAAA1111\n\nBBBB2222 # bob\n").unwrap();
+        let store = PasswordStore::new(&dir);
+        let recipients = |name| store.recipients(&store.entry_path(name).unwrap());
+        assert_eq!(recipients("github").unwrap(), ["alice@example.com"]);
+        assert_eq!(recipients("work/team/vpn").unwrap(), ["AAAA1111", "BBBB2222"]);
+        std::fs::remove_file(dir.join(".gpg-id")).unwrap();
+        assert!(matches!(recipients("github"), Err(OtpError::ExportError(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Secrets kept in PKCS#11 tokens and HSMs as [`KeyBackend`]s, e.g.
+//! SoftHSM, a YubiHSM or a Nitrokey HSM through the PKCS#11 module of its
+//! vendor. The token computes the HMACs, so the secret never leaves it.
+//!
+//! [`Pkcs11Key::create`] imports the secret of an account as a sensitive
+//! key that cannot be extracted, and [`Pkcs11Key::open`] finds it again
+//! by its label.
+//!
+//! ```no_run
+//! use yotp_core::pkcs11::Pkcs11Key;
+//! use yotp_core::{totp_with_backend, Algorithm};
+//!
+//! let key = Pkcs11Key::open("/usr/lib/softhsm/libsofthsm2.so", "yotp", "1234", "GitHub:alice", Algorithm::Sha1).unwrap();
+//! println!("{}", totp_with_backend(&key, 0, 30, 6).unwrap());
+//! ```
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use cryptoki::context::{CInitializeArgs, CInitializeFlags, Pkcs11};
+use cryptoki::error::{Error, RvError};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, KeyType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use zeroize::Zeroize;
+
+use crate::error::OtpError;
+use crate::key_backend::KeyBackend;
+use crate::otp::Algorithm;
+
+/// An HMAC key of a PKCS#11 token, with a logged in session.
+pub struct Pkcs11Key {
+    session: Mutex<Session>,
+    key: ObjectHandle,
+    algorithm: Algorithm,
+    label: String,
+}
+
+impl Pkcs11Key {
+    /// The secret key labelled `label` on the token labelled `token` of
+    /// the PKCS#11 module at `module`, logged in as the user with `pin`.
+    /// The HMACs are computed with the hash function `algorithm`.
+    ///
+    /// Returns [`OtpError::KeyBackendError`] if the module fails to load,
+    /// the token is not found, the PIN is wrong, or the token has no key
+    /// or more than one key labelled `label`.
+    pub fn open(module: impl AsRef<Path>, token: &str, pin: &str, label: &str, algorithm: Algorithm) -> Result<Pkcs11Key, OtpError> {
+        let session = login(module.as_ref(), token, pin, false)?;
+        let key = find_key(&session, label)?.ok_or_else(|| backend_error(&format!("no key labelled {}", label)))?;
+        Ok(Pkcs11Key { session: Mutex::new(session), key, algorithm, label: label.to_owned() })
+    }
+
+    /// Import `secret` into the token as a key labelled `label`, which is
+    /// sensitive and cannot be extracted, and open it as [`Pkcs11Key::open`]
+    /// does. The secret can be erased from the memory of the process
+    /// afterwards.
+    ///
+    /// Returns [`OtpError::KeyBackendError`] if the token has a key
+    /// labelled `label` already, or for the reasons of [`Pkcs11Key::open`].
+    pub fn create(
+        module: impl AsRef<Path>,
+        token: &str,
+        pin: &str,
+        label: &str,
+        secret: &[u8],
+        algorithm: Algorithm,
+    ) -> Result<Pkcs11Key, OtpError> {
+        let session = login(module.as_ref(), token, pin, true)?;
+        if find_key(&session, label)?.is_some() {
+            return Err(backend_error(&format!("a key labelled {} exists", label)));
+        }
+        let mut template = vec![
+            Attribute::Class(ObjectClass::SECRET_KEY),
+            Attribute::KeyType(KeyType::GENERIC_SECRET),
+            Attribute::Token(true),
+            Attribute::Private(true),
+            Attribute::Sensitive(true),
+            Attribute::Extractable(false),
+            Attribute::Sign(true),
+            Attribute::Label(label.as_bytes().to_vec()),
+            Attribute::Value(secret.to_vec()),
+        ];
+        let key = session.create_object(&template);
+        for attribute in &mut template {
+            if let Attribute::Value(value) = attribute {
+                value.zeroize();
+            }
+        }
+        let key = key.map_err(pkcs11_error)?;
+        Ok(Pkcs11Key { session: Mutex::new(session), key, algorithm, label: label.to_owned() })
+    }
+
+    /// The label of the key on the token.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+impl KeyBackend for Pkcs11Key {
+    fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn hmac(&self, message: &[u8]) -> Result<Vec<u8>, OtpError> {
+        let mechanism = match self.algorithm {
+            Algorithm::Sha1 => Mechanism::Sha1Hmac,
+            Algorithm::Sha256 => Mechanism::Sha256Hmac,
+            Algorithm::Sha512 => Mechanism::Sha512Hmac,
+        };
+        let session = self.session.lock().map_err(|_| backend_error("session lock poisoned"))?;
+        session.sign(&mechanism, self.key, message).map_err(pkcs11_error)
+    }
+}
+
+/// A session with the token labelled `token`, logged in as the user.
+fn login(module: &Path, token: &str, pin: &str, read_write: bool) -> Result<Session, OtpError> {
+    let pkcs11 = Pkcs11::new(module).map_err(pkcs11_error)?;
+    // the module is loaded once per process, and may be initialized by
+    // another key already
+    match pkcs11.initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK)) {
+        Ok(()) | Err(Error::Pkcs11(RvError::CryptokiAlreadyInitialized, _)) => {}
+        Err(e) => return Err(pkcs11_error(e)),
+    }
+    let mut slot = None;
+    for candidate in pkcs11.get_slots_with_token().map_err(pkcs11_error)? {
+        if pkcs11.get_token_info(candidate).map_err(pkcs11_error)?.label() == token {
+            slot = Some(candidate);
+            break;
+        }
+    }
+    let slot = slot.ok_or_else(|| backend_error(&format!("no token labelled {}", token)))?;
+    let session = if read_write { pkcs11.open_rw_session(slot) } else { pkcs11.open_ro_session(slot) }.map_err(pkcs11_error)?;
+    // the login is shared by the sessions of the token
+    match session.login(UserType::User, Some(&AuthPin::from(pin.to_owned()))) {
+        Ok(()) | Err(Error::Pkcs11(RvError::UserAlreadyLoggedIn, _)) => Ok(session),
+        Err(e) => Err(pkcs11_error(e)),
+    }
+}
+
+/// The secret key labelled `label`, if there is exactly one.
+fn find_key(session: &Session, label: &str) -> Result<Option<ObjectHandle>, OtpError> {
+    let template = [Attribute::Class(ObjectClass::SECRET_KEY), Attribute::Label(label.as_bytes().to_vec())];
+    let keys = session.find_objects(&template).map_err(pkcs11_error)?;
+    match keys[..] {
+        [] => Ok(None),
+        [key] => Ok(Some(key)),
+        _ => Err(backend_error(&format!("more than one key labelled {}", label))),
+    }
+}
+
+fn pkcs11_error(e: Error) -> OtpError {
+    backend_error(&e.to_string())
+}
+
+fn backend_error(msg: &str) -> OtpError {
+    OtpError::KeyBackendError(format!("pkcs11: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::{hotp_with_backend, totp_at_with_backend};
+
+    use super::Pkcs11Key;
+
+    #[test]
+    fn test_missing_module() {
+        let key = Pkcs11Key::open("/nonexistent/libpkcs11.so", "yotp", "1234", "alice", Algorithm::Sha1);
+        assert!(matches!(key, Err(OtpError::KeyBackendError(msg)) if msg.starts_with("pkcs11: ")));
+    }
+
+    #[test]
+    fn test_softhsm() {
+        // needs a SoftHSM token labelled yotp with the user PIN 1234, e.g.
+        // softhsm2-util --init-token --free --label yotp --pin 1234 --so-pin 1234
+        let Some(module) = std::env::var_os("YOTP_TEST_PKCS11_MODULE") else {
+            return;
+        };
+        let label = format!("test {}", std::process::id());
+        let key = Pkcs11Key::create(&module, "yotp", "1234", &label, b"12345678901234567890", Algorithm::Sha1).unwrap();
+        assert_eq!(hotp_with_backend(&key, 1, 6).unwrap(), "287082");
+        let key = Pkcs11Key::open(&module, "yotp", "1234", &label, Algorithm::Sha1).unwrap();
+        assert_eq!(totp_at_with_backend(&key, 59, 0, 30, 8).unwrap(), "94287082");
+        assert!(Pkcs11Key::create(&module, "yotp", "1234", &label, b"secret", Algorithm::Sha1).is_err());
+        assert!(Pkcs11Key::open(&module, "yotp", "1234", "missing", Algorithm::Sha1).is_err());
+        key.session.lock().unwrap().destroy_object(key.key).unwrap();
+    }
+}
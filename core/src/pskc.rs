@@ -0,0 +1,638 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The Portable Symmetric Key Container (PSKC) of RFC 6030, the XML
+//! format the seeds of hardware OATH tokens are shipped in.
+//!
+//! HOTP and TOTP keys are supported, with secrets either in plain text or
+//! encrypted with AES-CBC under a pre-shared key or a key derived from a
+//! password with PBKDF2, and authenticated with HMAC-SHA1 or
+//! HMAC-SHA256.
+//!
+//! ```
+//! use yotp_core::pskc;
+//!
+//! let accounts = pskc::import(r#"<?xml version="1.0" encoding="UTF-8"?>
+//! <KeyContainer Version="1.0" xmlns="urn:ietf:params:xml:ns:keyprov:pskc">
+//!   <KeyPackage>
+//!     <DeviceInfo><Manufacturer>Manufacturer</Manufacturer><SerialNo>987654321</SerialNo></DeviceInfo>
+//!     <Key Id="12345678" Algorithm="urn:ietf:params:xml:ns:keyprov:pskc:hotp">
+//!       <Issuer>Issuer-A</Issuer>
+//!       <AlgorithmParameters><ResponseFormat Length="8" Encoding="DECIMAL"/></AlgorithmParameters>
+//!       <Data>
+//!         <Secret><PlainValue>MTIzNDU2Nzg5MDEyMzQ1Njc4OTA=</PlainValue></Secret>
+//!         <Counter><PlainValue>0</PlainValue></Counter>
+//!       </Data>
+//!     </Key>
+//!   </KeyPackage>
+//! </KeyContainer>"#, None).unwrap();
+//! assert_eq!(accounts[0].account, "987654321");
+//! assert_eq!(accounts[0].to_hotp().unwrap().next().unwrap(), "84755224");
+//! ```
+
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockCipher, BlockDecryptMut, BlockEncryptMut, KeyInit, KeyIvInit};
+use hmac::{Hmac, Mac};
+use roxmltree::{Document, Node};
+use sha1::Sha1;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::base64;
+use crate::error::OtpError;
+use crate::otp::{Algorithm, DIGITS_RANGE};
+use crate::secret::Secret;
+use crate::uri::{OtpAuthUri, OtpType};
+
+const AES128_CBC: &str = "http://www.w3.org/2001/04/xmlenc#aes128-cbc";
+const AES256_CBC: &str = "http://www.w3.org/2001/04/xmlenc#aes256-cbc";
+const HMAC_SHA1: &str = "http://www.w3.org/2000/09/xmldsig#hmac-sha1";
+const PBKDF2: &str = "http://www.rsasecurity.com/rsalabs/pkcs/schemas/pkcs-5v2-0#pbkdf2";
+
+/// The iteration count of PBKDF2 in exported containers.
+const PBKDF2_ITERATIONS: u32 = 100_000;
+/// The largest PBKDF2 cost of imported containers, whose parameters are
+/// read before any MAC is checked.
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+/// The longest derived key, the one of AES-256.
+const MAX_KEY_LEN: usize = 32;
+
+/// The key protecting the secrets of an encrypted container.
+#[derive(Debug, Clone, Copy)]
+pub enum PskcKey<'a> {
+    /// A pre-shared AES key of 16, 24 or 32 bytes.
+    PreShared(&'a [u8]),
+    /// A password the AES-128 key is derived from with PBKDF2.
+    Password(&'a str),
+}
+
+/// Read the HOTP and TOTP keys of a PSKC container. `key` is required if
+/// the secrets are encrypted.
+///
+/// The account name is the `UserId` of the key, else the serial number of
+/// the device, else the `Id` of the key. `Time`, `TimeDrift` and the
+/// policy of the keys are not kept.
+///
+/// Returns [`OtpError::ImportError`] if the container is malformed, uses
+/// an unsupported algorithm, or its MAC does not match, e.g. because the
+/// key is wrong.
+pub fn import(xml: &str, key: Option<PskcKey>) -> Result<Vec<OtpAuthUri>, OtpError> {
+    let document = Document::parse(xml).map_err(|e| import_error(&e.to_string()))?;
+    let container = document.root_element();
+    if container.tag_name().name() != "KeyContainer" {
+        return Err(import_error("root element should be KeyContainer"));
+    }
+    let encryption_key = match child(container, "EncryptionKey") {
+        Some(encryption_key) => Some(derive_key(encryption_key, key.ok_or_else(|| import_error("container is encrypted"))?)?),
+        None => None
+    };
+    let mac = match (child(container, "MACMethod"), &encryption_key) {
+        (Some(method), Some(encryption_key)) => Some(mac_key(method, encryption_key)?),
+        // encrypted values must be authenticated
+        (None, Some(_)) => return Err(import_error("encrypted container has no MACMethod")),
+        (_, None) => None
+    };
+    children(container, "KeyPackage")
+        .filter_map(|package| child(package, "Key").map(|key| (package, key)))
+        .map(|(package, key)| import_key(package, key, encryption_key.as_ref().map(|key| &key[..]), mac.as_ref()))
+        .collect()
+}
+
+/// Write `accounts` as a PSKC container with plain text secrets.
+pub fn export(accounts: &[OtpAuthUri]) -> String {
+    write_container(accounts, None, |secret| Ok(format!("<PlainValue>{}</PlainValue>", base64::encode(secret))))
+        .expect("plain export does not fail")
+}
+
+/// Write `accounts` as a PSKC container protected by `key`: secrets are
+/// encrypted with AES-CBC and authenticated with HMAC-SHA1 under a random
+/// MAC key. Passwords are stretched with PBKDF2-HMAC-SHA1 into an AES-128
+/// key.
+///
+/// Returns [`OtpError::ExportError`] if a pre-shared key is neither 16 nor
+/// 32 bytes long, and [`OtpError::RandomError`] if the random source
+/// fails.
+pub fn export_encrypted(accounts: &[OtpAuthUri], key: PskcKey) -> Result<String, OtpError> {
+    let (encryption_key, key_xml) = match key {
+        PskcKey::PreShared(key) => {
+            if key.len() != 16 && key.len() != 32 {
+                return Err(OtpError::ExportError("PSKC: pre-shared key should have 16 or 32 bytes".into()));
+            }
+            (Zeroizing::new(key.to_vec()), "<ds:KeyName>Pre-shared-key</ds:KeyName>".into())
+        }
+        PskcKey::Password(password) => {
+            let salt = random::<16>()?;
+            let mut key = Zeroizing::new(vec![0; 16]);
+            pbkdf2::pbkdf2_hmac::<Sha1>(password.as_bytes(), &salt, PBKDF2_ITERATIONS, &mut key);
+            let key_xml = format!(
+                "<DerivedKey><KeyDerivationMethod Algorithm=\"{}\"><pkcs5:PBKDF2-params><Salt><Specified>{}</Specified></Salt>\
+                 <IterationCount>{}</IterationCount><KeyLength>16</KeyLength></pkcs5:PBKDF2-params></KeyDerivationMethod>\
+                 <MasterKeyName>Password</MasterKeyName></DerivedKey>",
+                PBKDF2,
+                base64::encode(&salt),
+                PBKDF2_ITERATIONS
+            );
+            (key, key_xml)
+        }
+    };
+    let algorithm = if encryption_key.len() == 16 { AES128_CBC } else { AES256_CBC };
+    let mac_key = Zeroizing::new(random::<20>()?);
+    let header = format!(
+        "  <EncryptionKey>{}</EncryptionKey>\n  <MACMethod Algorithm=\"{}\"><MACKey>{}</MACKey></MACMethod>\n",
+        key_xml,
+        HMAC_SHA1,
+        encrypted_value(algorithm, &encrypt(&encryption_key, &mac_key[..])?)
+    );
+    write_container(accounts, Some(header), |secret| {
+        let encrypted = encrypt(&encryption_key, secret)?;
+        let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(&mac_key[..]).expect("HMAC accepts keys of any length");
+        mac.update(&encrypted);
+        Ok(format!(
+            "<EncryptedValue>{}</EncryptedValue><ValueMAC>{}</ValueMAC>",
+            encrypted_value(algorithm, &encrypted),
+            base64::encode(&mac.finalize().into_bytes())
+        ))
+    })
+}
+
+/// The MAC algorithm of a container and its key.
+struct MacKey {
+    sha256: bool,
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl MacKey {
+    fn verify(&self, data: &[u8], value_mac: &[u8]) -> bool {
+        if self.sha256 {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+            mac.update(data);
+            mac.verify_slice(value_mac).is_ok()
+        } else {
+            let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+            mac.update(data);
+            mac.verify_slice(value_mac).is_ok()
+        }
+    }
+}
+
+fn import_key(package: Node, key: Node, encryption_key: Option<&[u8]>, mac: Option<&MacKey>) -> Result<OtpAuthUri, OtpError> {
+    let otp_type = match key.attribute("Algorithm").map(|algorithm| algorithm.rsplit([':', '#']).next().unwrap_or_default()) {
+        Some("hotp") => OtpType::Hotp,
+        Some("totp") => OtpType::Totp,
+        _ => return Err(import_error("only HOTP and TOTP keys are supported"))
+    };
+    let parameters = child(key, "AlgorithmParameters");
+    let algorithm = match parameters.and_then(|parameters| child_text(parameters, "Suite")) {
+        Some(suite) => Algorithm::from_name(suite.trim().trim_start_matches("HMAC-")).ok_or_else(|| import_error("unsupported suite"))?,
+        None => Algorithm::Sha1
+    };
+    let digits = match parameters.and_then(|parameters| child(parameters, "ResponseFormat")) {
+        Some(format) => {
+            if format.attribute("Encoding").is_some_and(|encoding| encoding != "DECIMAL") {
+                return Err(import_error("only decimal responses are supported"));
+            }
+            format.attribute("Length").and_then(|len| len.parse().ok()).ok_or_else(|| import_error("invalid response length"))?
+        }
+        None => 6
+    };
+    if !DIGITS_RANGE.contains(&digits) {
+        return Err(import_error("invalid response length"));
+    }
+    let data = child(key, "Data").ok_or_else(|| import_error("key has no Data"))?;
+    let secret = child(data, "Secret").ok_or_else(|| import_error("key has no secret"))?;
+    let secret = import_secret(secret, encryption_key, mac)?;
+    let number = |name: &str| -> Result<Option<u64>, OtpError> {
+        match child(data, name).and_then(|value| child_text(value, "PlainValue")) {
+            Some(value) => value.trim().parse().map(Some).map_err(|_| import_error("invalid number")),
+            None => Ok(None)
+        }
+    };
+    let counter = number("Counter")?.unwrap_or(0);
+    let period = number("TimeInterval")?.unwrap_or(30);
+    if otp_type == OtpType::Totp && period == 0 {
+        return Err(import_error("invalid time interval"));
+    }
+    let device = child(package, "DeviceInfo");
+    let account = child_text(key, "UserId")
+        .or_else(|| device.and_then(|device| child_text(device, "UserId")))
+        .or_else(|| device.and_then(|device| child_text(device, "SerialNo")))
+        .or_else(|| key.attribute("Id"))
+        .unwrap_or_default();
+    Ok(OtpAuthUri {
+        otp_type,
+        account: account.trim().into(),
+        issuer: child_text(key, "Issuer").map(|issuer| issuer.trim().into()).filter(|issuer: &String| !issuer.is_empty()),
+        secret,
+        algorithm,
+        digits,
+        period: if otp_type == OtpType::Totp { period } else { 30 },
+        counter: if otp_type == OtpType::Hotp { counter } else { 0 },
+    })
+}
+
+fn import_secret(secret: Node, encryption_key: Option<&[u8]>, mac: Option<&MacKey>) -> Result<Secret, OtpError> {
+    if let Some(value) = child_text(secret, "PlainValue") {
+        return Ok(Secret::new(decode_base64(value)?));
+    }
+    let encrypted = child(secret, "EncryptedValue").ok_or_else(|| import_error("secret has no value"))?;
+    let encryption_key = encryption_key.ok_or_else(|| import_error("secret is encrypted without EncryptionKey"))?;
+    let (algorithm, data) = cipher_value(encrypted)?;
+    if let Some(mac) = mac {
+        let value_mac = child_text(secret, "ValueMAC").ok_or_else(|| import_error("secret has no ValueMAC"))?;
+        if !mac.verify(&data, &decode_base64(value_mac)?) {
+            return Err(import_error("MAC mismatch, the key may be wrong"));
+        }
+    }
+    Ok(Secret::new(decrypt(algorithm, encryption_key, &data)?.to_vec()))
+}
+
+/// The AES key of the container from its `EncryptionKey` element.
+fn derive_key(element: Node, key: PskcKey) -> Result<Zeroizing<Vec<u8>>, OtpError> {
+    match (child(element, "DerivedKey"), key) {
+        (None, PskcKey::PreShared(key)) => Ok(Zeroizing::new(key.to_vec())),
+        (Some(derived), PskcKey::Password(password)) => {
+            let method = child(derived, "KeyDerivationMethod").ok_or_else(|| import_error("DerivedKey has no method"))?;
+            if method.attribute("Algorithm") != Some(PBKDF2) {
+                return Err(import_error("only PBKDF2 key derivation is supported"));
+            }
+            let params = child(method, "PBKDF2-params").ok_or_else(|| import_error("PBKDF2 has no parameters"))?;
+            let salt = child(params, "Salt").and_then(|salt| child_text(salt, "Specified"));
+            let salt = decode_base64(salt.ok_or_else(|| import_error("PBKDF2 has no salt"))?)?;
+            let number = |name: &str| child_text(params, name).and_then(|value| value.trim().parse().ok());
+            let iterations = number("IterationCount")
+                .filter(|iterations| (1..=MAX_PBKDF2_ITERATIONS).contains(iterations))
+                .ok_or_else(|| import_error("invalid PBKDF2 iteration count"))?;
+            let key_len = number("KeyLength").unwrap_or(16) as usize;
+            if !(1..=MAX_KEY_LEN).contains(&key_len) {
+                return Err(import_error("invalid PBKDF2 key length"));
+            }
+            let mut key = Zeroizing::new(vec![0; key_len]);
+            match child(params, "PRF").and_then(|prf| prf.attribute("Algorithm")) {
+                Some(prf) if prf.ends_with("hmac-sha256") => pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, iterations, &mut key),
+                Some(prf) if !prf.ends_with("hmac-sha1") => return Err(import_error("unsupported PBKDF2 PRF")),
+                _ => pbkdf2::pbkdf2_hmac::<Sha1>(password.as_bytes(), &salt, iterations, &mut key),
+            }
+            Ok(key)
+        }
+        (None, PskcKey::Password(_)) => Err(import_error("container is encrypted with a pre-shared key, not a password")),
+        (Some(_), PskcKey::PreShared(_)) => Err(import_error("container is encrypted with a password, not a pre-shared key")),
+    }
+}
+
+/// The MAC of `MACMethod`, whose key is either encrypted in `MACKey` or,
+/// in the drafts of RFC 6030, the encryption key itself.
+fn mac_key(method: Node, encryption_key: &[u8]) -> Result<MacKey, OtpError> {
+    let sha256 = match method.attribute("Algorithm") {
+        Some(HMAC_SHA1) => false,
+        Some("http://www.w3.org/2001/04/xmldsig-more#hmac-sha256") => true,
+        _ => return Err(import_error("unsupported MAC algorithm"))
+    };
+    let key = match child(method, "MACKey") {
+        Some(mac_key) => {
+            let (algorithm, data) = cipher_value(mac_key)?;
+            decrypt(algorithm, encryption_key, &data)?
+        }
+        None => Zeroizing::new(encryption_key.to_vec())
+    };
+    Ok(MacKey { sha256, key })
+}
+
+/// The encryption algorithm and the IV and cipher text of an
+/// `xenc:EncryptedDataType` element.
+fn cipher_value<'a>(element: Node<'a, '_>) -> Result<(&'a str, Vec<u8>), OtpError> {
+    let algorithm = child(element, "EncryptionMethod")
+        .and_then(|method| method.attribute("Algorithm"))
+        .ok_or_else(|| import_error("encrypted value has no EncryptionMethod"))?;
+    let value = child(element, "CipherData").and_then(|data| child_text(data, "CipherValue"));
+    Ok((algorithm, decode_base64(value.ok_or_else(|| import_error("encrypted value has no CipherValue"))?)?))
+}
+
+fn decrypt(algorithm: &str, key: &[u8], data: &[u8]) -> Result<Zeroizing<Vec<u8>>, OtpError> {
+    let plain = match (algorithm, key.len()) {
+        (AES128_CBC, 16) => decrypt_cbc::<aes::Aes128>(key, data),
+        ("http://www.w3.org/2001/04/xmlenc#aes192-cbc", 24) => decrypt_cbc::<aes::Aes192>(key, data),
+        (AES256_CBC, 32) => decrypt_cbc::<aes::Aes256>(key, data),
+        (AES128_CBC | AES256_CBC | "http://www.w3.org/2001/04/xmlenc#aes192-cbc", _) => {
+            return Err(import_error("key length does not match the encryption algorithm"));
+        }
+        _ => return Err(import_error("unsupported encryption algorithm"))
+    };
+    plain.map(Zeroizing::new).ok_or_else(|| import_error("decryption failed, the key may be wrong"))
+}
+
+fn decrypt_cbc<C: BlockCipher + BlockDecryptMut + KeyInit>(key: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < 16 {
+        return None;
+    }
+    let (iv, cipher_text) = data.split_at(16);
+    cbc::Decryptor::<C>::new_from_slices(key, iv).ok()?.decrypt_padded_vec_mut::<Pkcs7>(cipher_text).ok()
+}
+
+/// Encrypt `data` with AES-CBC under a random IV, returning the IV and the
+/// cipher text.
+fn encrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, OtpError> {
+    let iv = random::<16>()?;
+    let cipher_text = match key.len() {
+        16 => encrypt_cbc::<aes::Aes128>(key, &iv, data),
+        _ => encrypt_cbc::<aes::Aes256>(key, &iv, data),
+    };
+    Ok([&iv[..], &cipher_text].concat())
+}
+
+fn encrypt_cbc<C: BlockCipher + BlockEncryptMut + KeyInit>(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    cbc::Encryptor::<C>::new_from_slices(key, iv)
+        .expect("key and IV lengths are checked")
+        .encrypt_padded_vec_mut::<Pkcs7>(data)
+}
+
+fn random<const N: usize>() -> Result<[u8; N], OtpError> {
+    let mut bytes = [0; N];
+    getrandom::fill(&mut bytes).map_err(|_| OtpError::RandomError)?;
+    Ok(bytes)
+}
+
+fn encrypted_value(algorithm: &str, data: &[u8]) -> String {
+    format!(
+        "<xenc:EncryptionMethod Algorithm=\"{}\"/><xenc:CipherData><xenc:CipherValue>{}</xenc:CipherValue></xenc:CipherData>",
+        algorithm,
+        base64::encode(data)
+    )
+}
+
+/// Write the container with `header` after the version and each secret
+/// as written by `secret`.
+fn write_container(
+    accounts: &[OtpAuthUri],
+    header: Option<String>,
+    secret: impl Fn(&[u8]) -> Result<String, OtpError>,
+) -> Result<String, OtpError> {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<KeyContainer Version=\"1.0\" xmlns=\"urn:ietf:params:xml:ns:keyprov:pskc\" \
+         xmlns:ds=\"http://www.w3.org/2000/09/xmldsig#\" xmlns:xenc=\"http://www.w3.org/2001/04/xmlenc#\" \
+         xmlns:pkcs5=\"http://www.rsasecurity.com/rsalabs/pkcs/schemas/pkcs-5v2-0#\">\n",
+    );
+    xml.push_str(&header.unwrap_or_default());
+    for (i, account) in accounts.iter().enumerate() {
+        let (algorithm, data) = match account.otp_type {
+            OtpType::Hotp => ("hotp", format!("<Counter><PlainValue>{}</PlainValue></Counter>", account.counter)),
+            OtpType::Totp => ("totp", format!("<TimeInterval><PlainValue>{}</PlainValue></TimeInterval>", account.period)),
+        };
+        xml.push_str(&format!(
+            "  <KeyPackage>\n    <Key Id=\"{}\" Algorithm=\"urn:ietf:params:xml:ns:keyprov:pskc:{}\">\n",
+            i + 1,
+            algorithm
+        ));
+        if let Some(issuer) = &account.issuer {
+            xml.push_str(&format!("      <Issuer>{}</Issuer>\n", escape(issuer)));
+        }
+        xml.push_str(&format!(
+            "      <AlgorithmParameters><Suite>HMAC-{}</Suite><ResponseFormat Length=\"{}\" Encoding=\"DECIMAL\"/></AlgorithmParameters>\n",
+            account.algorithm.name(),
+            account.digits
+        ));
+        let value = Zeroizing::new(secret(account.secret.as_bytes())?);
+        xml.push_str(&format!("      <Data><Secret>{}</Secret>{}</Data>\n", value.as_str(), data));
+        xml.push_str(&format!("      <UserId>{}</UserId>\n    </Key>\n  </KeyPackage>\n", escape(&account.account)));
+    }
+    xml.push_str("</KeyContainer>\n");
+    Ok(xml)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>, OtpError> {
+    let value: String = value.split_whitespace().collect();
+    base64::decode(&value).ok_or_else(|| import_error("invalid Base 64 value"))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |child| child.tag_name().name() == name)
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|child| child.text())
+}
+
+fn import_error(msg: &str) -> OtpError {
+    OtpError::ImportError(format!("PSKC: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::secret::Secret;
+    use crate::uri::{OtpAuthUri, OtpType};
+
+    use super::{export, export_encrypted, import, PskcKey};
+
+    // RFC 6030 Figure 6, with a MAC key and MAC of our own
+    const PRE_SHARED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<KeyContainer Version="1.0" xmlns="urn:ietf:params:xml:ns:keyprov:pskc"
+    xmlns:ds="http://www.w3.org/2000/09/xmldsig#" xmlns:xenc="http://www.w3.org/2001/04/xmlenc#">
+  <EncryptionKey><ds:KeyName>Pre-shared-key</ds:KeyName></EncryptionKey>
+  <MACMethod Algorithm="http://www.w3.org/2000/09/xmldsig#hmac-sha1">
+    <MACKey>
+      <xenc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes128-cbc"/>
+      <xenc:CipherData>
+        <xenc:CipherValue>EBESExQVFhcYGRobHB0eH2p04Tj/pT9YG/mSJ0SZHsHHSHGTtkEkLf50U55Bo24R</xenc:CipherValue>
+      </xenc:CipherData>
+    </MACKey>
+  </MACMethod>
+  <KeyPackage>
+    <DeviceInfo><Manufacturer>Manufacturer</Manufacturer><SerialNo>987654321</SerialNo></DeviceInfo>
+    <CryptoModuleInfo><Id>CM_ID_001</Id></CryptoModuleInfo>
+    <Key Id="12345678" Algorithm="urn:ietf:params:xml:ns:keyprov:pskc:hotp">
+      <Issuer>Issuer</Issuer>
+      <AlgorithmParameters><ResponseFormat Length="8" Encoding="DECIMAL"/></AlgorithmParameters>
+      <Data>
+        <Secret>
+          <EncryptedValue>
+            <xenc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes128-cbc"/>
+            <xenc:CipherData>
+              <xenc:CipherValue>AAECAwQFBgcICQoLDA0OD+cIHItlB3Wra1DUpxVvOx2lef1VmNPCMl8jwZqIUqGv</xenc:CipherValue>
+            </xenc:CipherData>
+          </EncryptedValue>
+          <ValueMAC>CfINp47UzVXiFcDNKYVcy7sQw3w=</ValueMAC>
+        </Secret>
+        <Counter><PlainValue>0</PlainValue></Counter>
+      </Data>
+    </Key>
+  </KeyPackage>
+</KeyContainer>"#;
+
+    const PASSWORD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<pskc:KeyContainer xmlns:pskc="urn:ietf:params:xml:ns:keyprov:pskc"
+    xmlns:pkcs5="http://www.rsasecurity.com/rsalabs/pkcs/schemas/pkcs-5v2-0#"
+    xmlns:xenc="http://www.w3.org/2001/04/xmlenc#" Version="1.0">
+  <pskc:EncryptionKey>
+    <pskc:DerivedKey>
+      <pskc:KeyDerivationMethod Algorithm="http://www.rsasecurity.com/rsalabs/pkcs/schemas/pkcs-5v2-0#pbkdf2">
+        <pkcs5:PBKDF2-params>
+          <Salt><Specified>Ej7/PEpyEpw=</Specified></Salt>
+          <IterationCount>1000</IterationCount>
+          <KeyLength>16</KeyLength>
+        </pkcs5:PBKDF2-params>
+      </pskc:KeyDerivationMethod>
+      <pskc:MasterKeyName>My Password 1</pskc:MasterKeyName>
+    </pskc:DerivedKey>
+  </pskc:EncryptionKey>
+  <pskc:MACMethod Algorithm="http://www.w3.org/2000/09/xmldsig#hmac-sha1">
+    <pskc:MACKey>
+      <xenc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes128-cbc"/>
+      <xenc:CipherData>
+        <xenc:CipherValue>MDEyMzQ1Njc4OTo7PD0+PweO0PFcrhlVyLEjZpFoeLVt8Gq2MVgi23SVplxCpYlP</xenc:CipherValue>
+      </xenc:CipherData>
+    </pskc:MACKey>
+  </pskc:MACMethod>
+  <pskc:KeyPackage>
+    <pskc:Key Id="123456" Algorithm="urn:ietf:params:xml:ns:keyprov:pskc:totp">
+      <pskc:AlgorithmParameters><pskc:Suite>HMAC-SHA256</pskc:Suite></pskc:AlgorithmParameters>
+      <pskc:Data>
+        <pskc:Secret>
+          <pskc:EncryptedValue>
+            <xenc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes128-cbc"/>
+            <xenc:CipherData>
+              <xenc:CipherValue>ICEiIyQlJicoKSorLC0uLyYqpXHQmCL5o94o8nwCIpZq3t3zMdUVQynDDEy9MWq2</xenc:CipherValue>
+            </xenc:CipherData>
+          </pskc:EncryptedValue>
+          <pskc:ValueMAC>R/B2NNxsBHrKhPRzr5UH4kGPC3g=</pskc:ValueMAC>
+        </pskc:Secret>
+        <pskc:TimeInterval><pskc:PlainValue>60</pskc:PlainValue></pskc:TimeInterval>
+      </pskc:Data>
+      <pskc:UserId>alice</pskc:UserId>
+    </pskc:Key>
+  </pskc:KeyPackage>
+</pskc:KeyContainer>"#;
+
+    const PRE_SHARED_KEY: [u8; 16] = [0x12, 0x34, 0x56, 0x78, 0x90, 0x12, 0x34, 0x56, 0x78, 0x90, 0x12, 0x34, 0x56, 0x78, 0x90, 0x12];
+
+    #[test]
+    fn test_import_pre_shared() {
+        let accounts = import(PRE_SHARED, Some(PskcKey::PreShared(&PRE_SHARED_KEY))).unwrap();
+        let account = &accounts[0];
+        assert_eq!(account.otp_type, OtpType::Hotp);
+        assert_eq!(account.secret.as_bytes(), b"12345678901234567890");
+        assert_eq!(account.account, "987654321");
+        assert_eq!(account.issuer.as_deref(), Some("Issuer"));
+        assert_eq!(account.digits, 8);
+
+        let mut wrong = PRE_SHARED_KEY;
+        wrong[0] ^= 1;
+        assert!(matches!(import(PRE_SHARED, Some(PskcKey::PreShared(&wrong))), Err(OtpError::ImportError(_))));
+        assert!(matches!(import(PRE_SHARED, None), Err(OtpError::ImportError(_))));
+        assert!(matches!(import(PRE_SHARED, Some(PskcKey::Password("qwerty"))), Err(OtpError::ImportError(_))));
+    }
+
+    #[test]
+    fn test_import_password() {
+        let accounts = import(PASSWORD, Some(PskcKey::Password("qwerty"))).unwrap();
+        let account = &accounts[0];
+        assert_eq!(account.otp_type, OtpType::Totp);
+        assert_eq!(account.secret.as_bytes(), b"12345678901234567890");
+        assert_eq!(account.account, "alice");
+        assert_eq!(account.issuer, None);
+        assert_eq!(account.algorithm, Algorithm::Sha256);
+        assert_eq!(account.period, 60);
+        assert_eq!(account.digits, 6);
+        assert!(matches!(import(PASSWORD, Some(PskcKey::Password("wrong"))), Err(OtpError::ImportError(_))));
+    }
+
+    #[test]
+    fn test_import_invalid() {
+        assert!(matches!(import("<KeyContainer", None), Err(OtpError::ImportError(_))));
+        assert!(matches!(import("<Other/>", None), Err(OtpError::ImportError(_))));
+        let ocra = r#"<KeyContainer><KeyPackage><Key Algorithm="urn:ietf:params:xml:ns:keyprov:pskc#OCRA-1">
+            <Data><Secret><PlainValue>MTIz</PlainValue></Secret></Data></Key></KeyPackage></KeyContainer>"#;
+        assert!(matches!(import(ocra, None), Err(OtpError::ImportError(_))));
+
+        let plain = r#"<KeyContainer><KeyPackage><Key Algorithm="urn:ietf:params:xml:ns:keyprov:pskc:totp">
+            <AlgorithmParameters><ResponseFormat Length="6" Encoding="DECIMAL"/></AlgorithmParameters>
+            <Data><Secret><PlainValue>MTIz</PlainValue></Secret><TimeInterval><PlainValue>30</PlainValue></TimeInterval></Data>
+            </Key></KeyPackage></KeyContainer>"#;
+        assert_eq!(import(plain, None).unwrap()[0].period, 30);
+        for invalid in [plain.replace("Length=\"6\"", "Length=\"4\""), plain.replace("<PlainValue>30<", "<PlainValue>0<")] {
+            assert!(matches!(import(&invalid, None), Err(OtpError::ImportError(_))));
+        }
+    }
+
+    #[test]
+    fn test_import_untrusted_params() {
+        let password = Some(PskcKey::Password("qwerty"));
+        let costly = [
+            PASSWORD.replace("<IterationCount>1000<", "<IterationCount>4294967295<"),
+            PASSWORD.replace("<KeyLength>16<", "<KeyLength>4294967295<"),
+        ];
+        for costly in costly {
+            assert!(matches!(import(&costly, password), Err(OtpError::ImportError(msg)) if msg.contains("PBKDF2")));
+        }
+        // the MAC check cannot be skipped by leaving out the MACMethod
+        let start = PASSWORD.find("<pskc:MACMethod").unwrap();
+        let end = PASSWORD.find("</pskc:MACMethod>").unwrap() + "</pskc:MACMethod>".len();
+        let unauthenticated = [&PASSWORD[..start], &PASSWORD[end..]].concat();
+        assert!(matches!(import(&unauthenticated, password), Err(OtpError::ImportError(msg)) if msg.contains("MACMethod")));
+    }
+
+    fn accounts() -> Vec<OtpAuthUri> {
+        vec![
+            OtpAuthUri {
+                otp_type: OtpType::Totp,
+                account: "alice & <bob>".into(),
+                issuer: Some("\"ACME\"".into()),
+                secret: Secret::new(b"12345678901234567890".to_vec()),
+                algorithm: Algorithm::Sha512,
+                digits: 8,
+                period: 60,
+                counter: 0,
+            },
+            OtpAuthUri {
+                otp_type: OtpType::Hotp,
+                account: "carol".into(),
+                issuer: None,
+                secret: Secret::new(b"secret".to_vec()),
+                algorithm: Algorithm::Sha1,
+                digits: 6,
+                period: 30,
+                counter: 42,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_export() {
+        let xml = export(&accounts());
+        assert!(xml.contains("<PlainValue>MTIzNDU2Nzg5MDEyMzQ1Njc4OTA=</PlainValue>"));
+        assert_eq!(import(&xml, None).unwrap(), accounts());
+    }
+
+    #[test]
+    fn test_export_encrypted() {
+        let xml = export_encrypted(&accounts(), PskcKey::PreShared(&[7; 32])).unwrap();
+        assert!(!xml.contains("MTIzNDU2Nzg5MDEyMzQ1Njc4OTA="));
+        assert_eq!(import(&xml, Some(PskcKey::PreShared(&[7; 32]))).unwrap(), accounts());
+        assert!(import(&xml, Some(PskcKey::PreShared(&[8; 32]))).is_err());
+
+        let xml = export_encrypted(&accounts(), PskcKey::Password("hunter2")).unwrap();
+        assert_eq!(import(&xml, Some(PskcKey::Password("hunter2"))).unwrap(), accounts());
+        assert!(matches!(export_encrypted(&accounts(), PskcKey::PreShared(&[7; 8])), Err(OtpError::ExportError(_))));
+    }
+}
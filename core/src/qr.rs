@@ -0,0 +1,220 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! QR codes of `otpauth://` URIs for provisioning authenticators.
+//!
+//! ```
+//! use yotp_core::qr::QrCode;
+//! use yotp_core::uri::OtpAuthUri;
+//!
+//! let uri = OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example").unwrap();
+//! let qr = QrCode::from_uri(&uri).unwrap();
+//! assert!(qr.to_svg().starts_with("<?xml"));
+//! assert!(qr.to_png(4).unwrap().starts_with(b"\x89PNG"));
+//! ```
+//!
+//! With the `qr-decode` feature, [`decode_uri`] reads the URI back from a
+//! PNG or JPEG image such as a screenshot of the setup page of a provider.
+
+use qrcode::render::{svg, unicode};
+use qrcode::types::Color;
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+use crate::uri::OtpAuthUri;
+#[cfg(feature = "qr-decode")]
+use crate::uri::UriParseError;
+
+/// The width of the quiet zone around the QR code in modules, as the QR
+/// code standard requires.
+const QUIET_ZONE: usize = 4;
+
+/// A QR code encoding a provisioning URI.
+///
+/// The code carries the secret of the account, so its images should be
+/// shown to the user only and not be stored.
+pub struct QrCode {
+    code: qrcode::QrCode,
+}
+
+impl QrCode {
+    /// Encode `data`, e.g. an `otpauth://` or `otpauth-migration://` URI.
+    ///
+    /// Returns [`OtpError::QrError`] if `data` is too long for a QR code.
+    pub fn new(data: &str) -> Result<QrCode, OtpError> {
+        let code = qrcode::QrCode::new(data).map_err(|e| OtpError::QrError(e.to_string()))?;
+        Ok(QrCode { code })
+    }
+
+    /// Encode the URI of an account.
+    pub fn from_uri(uri: &OtpAuthUri) -> Result<QrCode, OtpError> {
+        QrCode::new(&Zeroizing::new(uri.to_string()))
+    }
+
+    /// The number of modules on each side, without the quiet zone.
+    pub fn width(&self) -> usize {
+        self.code.width()
+    }
+
+    /// Whether the module at column `x` and row `y` is dark.
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.code[(x, y)] == Color::Dark
+    }
+
+    /// Render as an SVG image.
+    pub fn to_svg(&self) -> String {
+        self.code.render::<svg::Color>().min_dimensions(200, 200).build()
+    }
+
+    /// Render as a grayscale PNG image with `scale` pixels per module.
+    ///
+    /// Returns [`OtpError::QrError`] if the image would have more than
+    /// `u32::MAX` pixels.
+    pub fn to_png(&self, scale: usize) -> Result<Vec<u8>, OtpError> {
+        let too_large = || OtpError::QrError("image is too large".into());
+        let scale = scale.max(1);
+        let side = (self.width() + 2 * QUIET_ZONE).checked_mul(scale).ok_or_else(too_large)?;
+        let side_u32 = u32::try_from(side).map_err(|_| too_large())?;
+        let len = side_u32.checked_mul(side_u32).ok_or_else(too_large)?;
+        let mut pixels = vec![0xFF; len as usize];
+        for y in 0..self.width() {
+            for x in (0..self.width()).filter(|&x| self.is_dark(x, y)) {
+                for row in 0..scale {
+                    let start = ((y + QUIET_ZONE) * scale + row) * side + (x + QUIET_ZONE) * scale;
+                    pixels[start..start + scale].fill(0);
+                }
+            }
+        }
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, side_u32, side_u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&pixels))
+            .map_err(|e| OtpError::QrError(e.to_string()))?;
+        Ok(png)
+    }
+
+    /// Render as text for terminals, two modules per character with the
+    /// half block characters. The light modules are drawn, so that the
+    /// code reads correctly on the usual light-on-dark terminals.
+    pub fn to_unicode(&self) -> String {
+        self.code
+            .render::<unicode::Dense1x2>()
+            .dark_color(unicode::Dense1x2::Light)
+            .light_color(unicode::Dense1x2::Dark)
+            .build()
+    }
+}
+
+/// The contents of all QR codes found in a PNG or JPEG `image`, in the
+/// order they are detected. Codes that fail to decode are skipped.
+///
+/// Returns [`OtpError::QrError`] if the image cannot be decoded.
+#[cfg(feature = "qr-decode")]
+pub fn decode_image(image: &[u8]) -> Result<Vec<String>, OtpError> {
+    let image = image::load_from_memory(image).map_err(|e| OtpError::QrError(e.to_string()))?.to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(image.width() as usize, image.height() as usize, |x, y| {
+        image.get_pixel(x as u32, y as u32).0[0]
+    });
+    Ok(prepared
+        .detect_grids()
+        .iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_, content)| content)
+        .collect())
+}
+
+/// The account of the first QR code of `image` that holds an
+/// `otpauth://` URI. Use [`decode_image`] and
+/// [`crate::migration::decode`] for the export codes of Google
+/// Authenticator.
+///
+/// Returns [`OtpError::QrError`] if the image has no QR code, or the
+/// error of [`OtpAuthUri::parse`] for the first code if none parses.
+#[cfg(feature = "qr-decode")]
+pub fn decode_uri(image: &[u8]) -> Result<OtpAuthUri, OtpError> {
+    let contents = decode_image(image)?;
+    let mut error = None;
+    for content in contents.iter().map(|content| Zeroizing::new(content.clone())) {
+        match OtpAuthUri::parse(&content) {
+            Ok(uri) => return Ok(uri),
+            Err(e) => {
+                error.get_or_insert(e);
+            }
+        }
+    }
+    Err(error.map_or_else(|| OtpError::QrError("no QR code found".into()), UriParseError::into))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::uri::OtpAuthUri;
+
+    use super::{QrCode, QUIET_ZONE};
+
+    #[test]
+    fn test_render() {
+        let uri = OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example").unwrap();
+        let qr = QrCode::from_uri(&uri).unwrap();
+        let width = qr.width();
+        // the finder pattern at the top left corner
+        assert!(qr.is_dark(0, 0) && qr.is_dark(6, 6) && !qr.is_dark(1, 1) && qr.is_dark(2, 2));
+
+        let png = qr.to_png(3).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        let side = ((width + 2 * QUIET_ZONE) * 3) as u32;
+        assert_eq!(&png[16..24], &[side.to_be_bytes(), side.to_be_bytes()].concat()[..]);
+        assert!(matches!(qr.to_png(usize::MAX), Err(OtpError::QrError(_))));
+        // 65536 pixels per side exceed u32::MAX pixels
+        assert!(matches!(qr.to_png(65536), Err(OtpError::QrError(_))));
+
+        let text = qr.to_unicode();
+        assert_eq!(text.lines().count(), (width + 2 * QUIET_ZONE).div_ceil(2));
+        assert!(qr.to_svg().contains("<svg"));
+    }
+
+    #[test]
+    fn test_too_long() {
+        assert!(QrCode::new(&"A".repeat(8000)).is_err());
+    }
+
+    #[cfg(feature = "qr-decode")]
+    #[test]
+    fn test_decode() {
+        use crate::error::OtpError;
+
+        use super::{decode_image, decode_uri};
+
+        let uri = OtpAuthUri::parse("otpauth://hotp/ACME%20Co:bob?secret=JBSWY3DPEHPK3PXP&counter=7").unwrap();
+        let png = QrCode::from_uri(&uri).unwrap().to_png(4).unwrap();
+        assert_eq!(decode_uri(&png).unwrap(), uri);
+
+        let png = QrCode::new("https://example.com").unwrap().to_png(4).unwrap();
+        assert_eq!(decode_image(&png).unwrap(), ["https://example.com"]);
+        assert_eq!(decode_uri(&png), Err(OtpError::InvalidUri));
+
+        let mut blank = Vec::new();
+        let mut encoder = png::Encoder::new(&mut blank, 64, 64);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.write_header().unwrap().write_image_data(&[0xFF; 64 * 64]).unwrap();
+        assert_eq!(decode_image(&blank).unwrap(), Vec::<String>::new());
+        assert!(matches!(decode_uri(&blank), Err(OtpError::QrError(_))));
+        assert!(matches!(decode_image(b"not an image"), Err(OtpError::QrError(_))));
+    }
+}
@@ -0,0 +1,119 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::clock::Clock;
+use crate::totp::Totp;
+use crate::verify::StepMatch;
+
+/// Replay protection of TOTP codes.
+///
+/// It remembers the highest accepted time step of each identity and
+/// rejects any code of that step or an earlier one, so an accepted code
+/// cannot be used again within its validity window.
+#[derive(Debug, Clone)]
+pub struct ReplayGuard<K = String> {
+    last_steps: HashMap<K, u64>,
+}
+
+impl<K: Eq + Hash> Default for ReplayGuard<K> {
+    fn default() -> Self {
+        ReplayGuard { last_steps: HashMap::new() }
+    }
+}
+
+impl<K: Eq + Hash> ReplayGuard<K> {
+    pub fn new() -> ReplayGuard<K> {
+        ReplayGuard::default()
+    }
+
+    /// Verify `code` of `id` with `totp` and a window of `window` steps.
+    /// The code is accepted only if it matches a step later than the last
+    /// accepted one, and that step is recorded on success.
+    pub fn verify<C: Clock>(&mut self, id: K, totp: &Totp<C>, code: &str, window: u64) -> Option<StepMatch> {
+        let matched = totp.verify_window(code, window)?;
+        if self.accept(id, matched.step) {
+            Some(matched)
+        } else {
+            None
+        }
+    }
+
+    /// Record `step` as accepted for `id` if it is later than the last
+    /// accepted step. Returns false if `step` would be a replay.
+    pub fn accept(&mut self, id: K, step: u64) -> bool {
+        match self.last_steps.get(&id) {
+            Some(&last) if step <= last => false,
+            _ => {
+                self.last_steps.insert(id, step);
+                true
+            }
+        }
+    }
+
+    /// The last accepted step of `id`, to be persisted with the account.
+    pub fn last_step(&self, id: &K) -> Option<u64> {
+        self.last_steps.get(id).copied()
+    }
+
+    /// Restore the last accepted step of `id`, e.g. from persisted state.
+    pub fn set_last_step(&mut self, id: K, step: u64) {
+        self.last_steps.insert(id, step);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::MockClock;
+    use crate::totp::Totp;
+
+    use super::ReplayGuard;
+
+    #[test]
+    fn test_accept() {
+        let mut guard = ReplayGuard::new();
+        assert!(guard.accept("alice", 10));
+        assert!(!guard.accept("alice", 10));
+        assert!(!guard.accept("alice", 9));
+        assert!(guard.accept("bob", 9));
+        assert!(guard.accept("alice", 11));
+        assert_eq!(guard.last_step(&"alice"), Some(11));
+        assert_eq!(guard.last_step(&"carol"), None);
+    }
+
+    #[test]
+    fn test_verify_replay() {
+        let clock = MockClock::new(1111111109);
+        let totp = Totp::builder()
+            .secret(b"12345678901234567890")
+            .digits(8)
+            .clock(&clock)
+            .build()
+            .unwrap();
+        let mut guard = ReplayGuard::new();
+        assert_eq!(guard.verify("alice".to_owned(), &totp, "07081804", 1).unwrap().step, 37037036);
+        assert!(guard.verify("alice".to_owned(), &totp, "07081804", 1).is_none());
+        // the previous code is still in the window, but older than the
+        // accepted one
+        clock.advance(30);
+        let previous = totp.generate_at(1111111109 - 30).unwrap();
+        assert!(guard.verify("alice".to_owned(), &totp, previous.as_str(), 2).is_none());
+        let current = totp.generate().unwrap();
+        assert!(guard.verify("alice".to_owned(), &totp, current.as_str(), 1).is_some());
+    }
+}
@@ -0,0 +1,334 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::base32;
+use crate::code::constant_time_eq;
+use crate::error::OtpError;
+use crate::hex;
+use crate::otp::Algorithm;
+
+/// How a secret of acceptable length compares with the recommendation,
+/// returned by [`Secret::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretStrength {
+    /// At least as long as the hash output of the algorithm.
+    Recommended,
+    /// At least 128 bits as RFC 4226 requires, but shorter than the hash
+    /// output, e.g. a 20-byte secret used with HMAC-SHA-256.
+    ShorterThanHash,
+}
+
+/// The textual forms a secret is handed out in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretEncoding {
+    /// RFC 4648 Base 32, as in `otpauth://` URIs.
+    Base32,
+    /// Hexadecimal digits, optionally prefixed with `0x`.
+    Hex,
+    /// The bytes of the string itself.
+    Raw,
+}
+
+/// The shared secret of an OTP generator, decoded from the form a
+/// provider hands it out in.
+///
+/// The bytes are zeroed when the secret is dropped, `Debug` and `Display`
+/// print `[REDACTED]` instead of them, and comparisons take constant
+/// time, so that the key does not leak into logs or memory dumps.
+///
+/// ```
+/// use yotp_core::{Secret, Totp};
+///
+/// let secret = Secret::from_hex("3132333435363738393031323334353637383930").unwrap();
+/// let totp = Totp::builder().secret(&secret).build().unwrap();
+/// assert_eq!(totp.generate_at(59).unwrap(), "287082");
+/// ```
+#[derive(Clone, Default)]
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    /// The secret length RFC 4226 recommends for HMAC-SHA-1, in bits.
+    pub const DEFAULT_BITS: usize = 160;
+
+    pub(crate) fn new(bytes: Vec<u8>) -> Secret {
+        Secret(bytes)
+    }
+
+    /// Decode a secret whose encoding is detected by
+    /// [`Secret::detect_encoding`]. Whitespaces and hyphens between the
+    /// characters of Base 32 and hex secrets are ignored.
+    ///
+    /// Use [`Secret::parse_as`] when the encoding is known, so that a
+    /// secret is never decoded with a wrong one.
+    pub fn parse(value: &str) -> Result<Secret, OtpError> {
+        Secret::parse_as(value, Secret::detect_encoding(value))
+    }
+
+    /// Decode a secret given in `encoding`.
+    pub fn parse_as(value: &str, encoding: SecretEncoding) -> Result<Secret, OtpError> {
+        match encoding {
+            SecretEncoding::Base32 => Secret::from_base32(value),
+            SecretEncoding::Hex => Secret::from_hex(&strip_hex(value)),
+            SecretEncoding::Raw => Ok(Secret(value.as_bytes().to_vec())),
+        }
+    }
+
+    /// Guess the encoding of `value`: Base 32 if it is valid Base 32,
+    /// otherwise hex if it is valid hex, otherwise raw. Base 32 is
+    /// checked with [`base32::decode_ct`] and hex by the character class
+    /// only, so the time taken does not depend on the secret.
+    ///
+    /// Base 32 wins when both are valid, e.g. for `ABCDEF23`, since it is
+    /// what authenticators use. Hex secrets usually contain one of `0`,
+    /// `1`, `8` and `9`, which are out of the Base 32 alphabet.
+    pub fn detect_encoding(value: &str) -> SecretEncoding {
+        let trimmed = value.trim();
+        if trimmed.is_empty() {
+            SecretEncoding::Raw
+        } else if Secret::from_base32(trimmed).is_ok() {
+            SecretEncoding::Base32
+        } else if is_hex(&strip_hex(trimmed)) {
+            SecretEncoding::Hex
+        } else {
+            SecretEncoding::Raw
+        }
+    }
+
+    /// A new random secret of `bits` bits, rounded up to whole bytes, from
+    /// the cryptographically secure random source of the system. Use
+    /// [`Secret::DEFAULT_BITS`] unless the algorithm asks for more, and
+    /// [`Secret::to_base32`] for the form shown to users.
+    ///
+    /// Returns [`OtpError::KeyTooShort`] if `bits` is zero and
+    /// [`OtpError::RandomError`] if the random source fails.
+    #[cfg(feature = "getrandom")]
+    pub fn generate(bits: usize) -> Result<Secret, OtpError> {
+        if bits == 0 {
+            return Err(OtpError::KeyTooShort);
+        }
+        let mut bytes = alloc::vec![0u8; bits.div_ceil(8)];
+        getrandom::fill(&mut bytes).map_err(|_| OtpError::RandomError)?;
+        Ok(Secret(bytes))
+    }
+
+    /// A secret of the raw bytes `bytes`.
+    pub fn from_raw(bytes: &[u8]) -> Secret {
+        Secret(bytes.to_vec())
+    }
+
+    /// Decode a secret given in Base 32, with whitespaces and hyphens
+    /// ignored. It uses [`base32::decode_ct`], so the decoding time does
+    /// not depend on the secret.
+    ///
+    /// Returns [`OtpError::DecodeError`] if `value` is not valid Base 32.
+    pub fn from_base32(value: &str) -> Result<Secret, OtpError> {
+        let value: Zeroizing<String> =
+            Zeroizing::new(value.chars().filter(|&c| !(c.is_whitespace() || c == '-')).collect());
+        Ok(Secret(base32::decode_ct(&value)?))
+    }
+
+    /// Decode a secret given as hexadecimal digits.
+    ///
+    /// Returns [`OtpError::InvalidHex`] if `value` is not valid hex.
+    pub fn from_hex(value: &str) -> Result<Secret, OtpError> {
+        Ok(Secret(hex::decode(value)?))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Check the length of the secret for use with `algorithm`, e.g. before
+    /// accepting a seed imported by a user.
+    ///
+    /// Returns [`OtpError::WeakSecret`] if it is shorter than the 128 bits
+    /// RFC 4226 requires. A secret shorter than the hash output of
+    /// `algorithm`, which RFC 4226 and RFC 6238 recommend as the length,
+    /// is accepted as [`SecretStrength::ShorterThanHash`].
+    pub fn validate(&self, algorithm: Algorithm) -> Result<SecretStrength, OtpError> {
+        const MIN_BITS: usize = 128;
+        if self.0.len() * 8 < MIN_BITS {
+            return Err(OtpError::WeakSecret(MIN_BITS));
+        }
+        if self.0.len() < algorithm.output_len() {
+            Ok(SecretStrength::ShorterThanHash)
+        } else {
+            Ok(SecretStrength::Recommended)
+        }
+    }
+
+    /// The Base 32 form of the secret without padding, as authenticators
+    /// and `otpauth://` URIs expect it.
+    pub fn to_base32(&self) -> String {
+        let mut encoded = base32::encode(&self.0);
+        encoded.truncate(encoded.trim_end_matches('=').len());
+        encoded
+    }
+
+    /// The length of the secret in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl AsRef<[u8]> for Secret {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Secret {
+    fn from(bytes: Vec<u8>) -> Secret {
+        Secret(bytes)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl PartialEq for Secret {
+    fn eq(&self, other: &Secret) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Secret {}
+
+/// Remove the separators and the `0x` prefix of a hex secret.
+fn strip_hex(value: &str) -> Zeroizing<String> {
+    let value = value.trim();
+    let value = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+    Zeroizing::new(value.chars().filter(|&c| !(c.is_whitespace() || c == '-')).collect())
+}
+
+/// Whether [`hex::decode`] accepts `value`, by the character class of
+/// every digit and without stopping at the first invalid one.
+fn is_hex(value: &str) -> bool {
+    let invalid = value.bytes().fold(0u8, |acc, c| acc | !c.is_ascii_hexdigit() as u8);
+    value.len().is_multiple_of(2) && invalid == 0
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+
+    use crate::otp::Algorithm;
+
+    use super::{Secret, SecretEncoding, SecretStrength};
+
+    #[test]
+    fn test_from_hex() {
+        let secret = Secret::from_hex("3132333435363738393031323334353637383930").unwrap();
+        assert_eq!(secret.as_bytes(), b"12345678901234567890");
+        assert_eq!(Secret::from_hex("313"), Err(OtpError::InvalidHex));
+    }
+
+    #[test]
+    fn test_constructors() {
+        let key = b"12345678901234567890";
+        assert_eq!(Secret::from_raw(key).as_bytes(), key);
+        assert_eq!(Secret::from(key.to_vec()).as_bytes(), key);
+        assert_eq!(Secret::from_base32("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap().as_bytes(), key);
+        assert_eq!(Secret::from_base32("gezd-gnbv-gy3t-qojq").unwrap().as_bytes(), b"1234567890");
+        assert_eq!(Secret::from_base32("GEZD1"), Err(OtpError::DecodeError));
+        let secret = Secret::from_raw(key);
+        assert_eq!(crate::otp::hotp(&secret, 1, 6).unwrap(), crate::otp::hotp(key, 1, 6).unwrap());
+    }
+
+    #[test]
+    fn test_redacted() {
+        let secret = Secret::from_hex("3132333435363738393031323334353637383930").unwrap();
+        assert_eq!(format!("{:?}", secret), "[REDACTED]");
+        assert_eq!(secret.to_string(), "[REDACTED]");
+        assert_eq!(secret, Secret::new(b"12345678901234567890".to_vec()));
+        assert_ne!(secret, Secret::new(b"1234567890123456789".to_vec()));
+        assert_eq!(secret.len(), 20);
+        let totp = crate::Totp::builder().secret(secret.as_bytes()).build().unwrap();
+        assert!(!format!("{:?}", totp).contains("49, 50"));
+    }
+
+    #[cfg(feature = "getrandom")]
+    #[test]
+    fn test_generate() {
+        let secret = Secret::generate(Secret::DEFAULT_BITS).unwrap();
+        assert_eq!(secret.len(), 20);
+        assert_eq!(secret.to_base32().len(), 32);
+        assert_eq!(Secret::parse(&secret.to_base32()).unwrap(), secret);
+        assert_ne!(Secret::generate(Secret::DEFAULT_BITS).unwrap(), secret);
+        assert_eq!(Secret::generate(129).unwrap().len(), 17);
+        assert!(matches!(Secret::generate(0), Err(OtpError::KeyTooShort)));
+    }
+
+    #[test]
+    fn test_validate() {
+        let secret = |len: usize| Secret::new(alloc::vec![0x5a; len]);
+        assert_eq!(secret(20).validate(Algorithm::Sha1), Ok(SecretStrength::Recommended));
+        assert_eq!(secret(20).validate(Algorithm::Sha256), Ok(SecretStrength::ShorterThanHash));
+        assert_eq!(secret(64).validate(Algorithm::Sha512), Ok(SecretStrength::Recommended));
+        assert_eq!(secret(16).validate(Algorithm::Sha1), Ok(SecretStrength::ShorterThanHash));
+        assert_eq!(secret(15).validate(Algorithm::Sha1), Err(OtpError::WeakSecret(128)));
+    }
+
+    #[test]
+    fn test_to_base32() {
+        assert_eq!(Secret::new(b"12345678901234567890".to_vec()).to_base32(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(Secret::new(b"foob".to_vec()).to_base32(), "MZXW6YQ");
+    }
+
+    #[test]
+    fn test_parse() {
+        let key = b"12345678901234567890";
+        let parse = |value: &str| Secret::parse(value).unwrap();
+        assert_eq!(parse("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").as_bytes(), key);
+        assert_eq!(parse("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").as_bytes(), key);
+        assert_eq!(parse("3132333435363738393031323334353637383930").as_bytes(), key);
+        assert_eq!(parse("0x31323334 35363738 39303132 33343536 37383930").as_bytes(), key);
+        assert_eq!(Secret::detect_encoding("JBSWY3DPEHPK3PXP"), SecretEncoding::Base32);
+        assert_eq!(Secret::detect_encoding("ABCDEF23"), SecretEncoding::Base32);
+        assert_eq!(Secret::detect_encoding("deadbeef01"), SecretEncoding::Hex);
+        assert_eq!(Secret::detect_encoding("12345678901234567890"), SecretEncoding::Hex);
+        assert_eq!(Secret::detect_encoding("p@ssw0rd!"), SecretEncoding::Raw);
+        assert_eq!(Secret::detect_encoding("0x313"), SecretEncoding::Raw);
+        assert_eq!(Secret::detect_encoding("0x31 32-33"), SecretEncoding::Hex);
+        assert_eq!(Secret::parse_as("12345678901234567890", SecretEncoding::Raw).unwrap().as_bytes(), key);
+        assert_eq!(Secret::parse_as("JBSW1", SecretEncoding::Base32), Err(OtpError::DecodeError));
+    }
+}
@@ -0,0 +1,95 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Storage of secret bytes under names, such as the key of a vault or
+//! the secrets of single accounts, outside of the files of yOTP.
+//!
+//! ```
+//! use yotp_core::secret_store::{MemorySecretStore, SecretStore};
+//!
+//! let store = MemorySecretStore::new();
+//! store.set("github", b"Hello!\xde\xad\xbe\xef").unwrap();
+//! assert_eq!(store.get("github").unwrap().as_deref().map(|s| &s[..]), Some(&b"Hello!\xde\xad\xbe\xef"[..]));
+//! assert!(store.delete("github").unwrap());
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+
+/// A place to keep secrets under names, e.g. the keyring of the
+/// operating system.
+///
+/// Implementations report failures of the underlying storage as
+/// [`OtpError::SecretStoreError`].
+pub trait SecretStore {
+    /// The secret stored under `name`, or `None` if there is none.
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, OtpError>;
+
+    /// Store `secret` under `name`, replacing the secret stored under it.
+    fn set(&self, name: &str, secret: &[u8]) -> Result<(), OtpError>;
+
+    /// Remove the secret stored under `name`. Returns whether there was
+    /// one.
+    fn delete(&self, name: &str) -> Result<bool, OtpError>;
+}
+
+/// A [`SecretStore`] in memory, for tests and for callers that keep the
+/// secrets elsewhere.
+#[derive(Default)]
+pub struct MemorySecretStore {
+    secrets: Mutex<HashMap<String, Zeroizing<Vec<u8>>>>,
+}
+
+impl MemorySecretStore {
+    pub fn new() -> MemorySecretStore {
+        MemorySecretStore::default()
+    }
+}
+
+impl SecretStore for MemorySecretStore {
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, OtpError> {
+        Ok(self.secrets.lock().unwrap_or_else(|e| e.into_inner()).get(name).cloned())
+    }
+
+    fn set(&self, name: &str, secret: &[u8]) -> Result<(), OtpError> {
+        self.secrets.lock().unwrap_or_else(|e| e.into_inner()).insert(name.to_owned(), Zeroizing::new(secret.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<bool, OtpError> {
+        Ok(self.secrets.lock().unwrap_or_else(|e| e.into_inner()).remove(name).is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MemorySecretStore, SecretStore};
+
+    #[test]
+    fn test_memory_store() {
+        let store = MemorySecretStore::new();
+        assert_eq!(store.get("a").unwrap(), None);
+        store.set("a", b"1").unwrap();
+        store.set("a", b"2").unwrap();
+        assert_eq!(store.get("a").unwrap().unwrap().as_slice(), b"2");
+        assert!(store.delete("a").unwrap());
+        assert!(!store.delete("a").unwrap());
+    }
+}
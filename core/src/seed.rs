@@ -0,0 +1,201 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Secrets of accounts derived from one master seed, for deployments
+//! that back up a single secret instead of one per account.
+//!
+//! The secret of an account is HKDF-SHA-256 (RFC 5869) of the master seed,
+//! with the salt `yOTP master seed` and the label of the account,
+//! `issuer:account` or `account` as in `otpauth://` URIs, as the info, with
+//! `%` and `:` in the issuer and the account escaped as `%25` and `%3A` so
+//! that different accounts never share a label. The
+//! same seed and label always give the same secret, so the accounts can be
+//! derived again from the seed alone. Whoever has the seed has the
+//! secrets of all the accounts, including those added later.
+//!
+//! ```
+//! use yotp_core::seed::MasterSeed;
+//! use yotp_core::uri::OtpType;
+//!
+//! let seed = MasterSeed::new(b"an example seed of 32 bytes.....").unwrap();
+//! let uri = seed.derive_uri(OtpType::Totp, Some("VPN"), "alice");
+//! assert_eq!(uri.secret.len(), 20);
+//! assert_eq!(uri.to_string(), seed.derive_uri(OtpType::Totp, Some("VPN"), "alice").to_string());
+//! assert_ne!(uri.secret, seed.derive_uri(OtpType::Totp, Some("VPN"), "bob").secret);
+//! ```
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{OtpAuthUri, OtpType};
+
+const SALT: &[u8] = b"yOTP master seed";
+const HASH_LEN: usize = 32;
+/// The most bytes HKDF-SHA-256 derives.
+const MAX_LEN: usize = 255 * HASH_LEN;
+
+/// The seed the secrets of accounts are derived from.
+///
+/// Like [`Secret`], the seed is zeroed when dropped and redacted from
+/// `Debug`.
+#[derive(Clone)]
+pub struct MasterSeed(Secret);
+
+impl MasterSeed {
+    /// The minimum length of a seed in bytes.
+    pub const MIN_LEN: usize = 16;
+
+    /// The seed of the bytes `seed`, e.g. restored from a backup.
+    ///
+    /// Returns [`OtpError::WeakSecret`] if `seed` is shorter than
+    /// [`MasterSeed::MIN_LEN`].
+    pub fn new(seed: &[u8]) -> Result<MasterSeed, OtpError> {
+        if seed.len() < MasterSeed::MIN_LEN {
+            return Err(OtpError::WeakSecret(MasterSeed::MIN_LEN * 8));
+        }
+        Ok(MasterSeed(Secret::from_raw(seed)))
+    }
+
+    /// A new random seed of 32 bytes.
+    ///
+    /// Returns [`OtpError::RandomError`] if the random source fails.
+    #[cfg(feature = "getrandom")]
+    pub fn generate() -> Result<MasterSeed, OtpError> {
+        Ok(MasterSeed(Secret::generate(HASH_LEN * 8)?))
+    }
+
+    /// The bytes of the seed, e.g. to back it up.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    /// The secret of `len` bytes of the account of `issuer` and `account`.
+    /// An empty issuer is the same as none, as in `otpauth://` URIs.
+    ///
+    /// Returns [`OtpError::SecretTooLong`] if `len` is more than the 8160
+    /// bytes HKDF-SHA-256 derives.
+    pub fn derive_secret(&self, issuer: Option<&str>, account: &str, len: usize) -> Result<Secret, OtpError> {
+        if len > MAX_LEN {
+            return Err(OtpError::SecretTooLong(MAX_LEN));
+        }
+        let label = match issuer.filter(|issuer| !issuer.is_empty()) {
+            Some(issuer) => format!("{}:{}", escape(issuer), escape(account)),
+            None => escape(account),
+        };
+        Ok(Secret::new(hkdf_sha256(SALT, self.as_bytes(), label.as_bytes(), len)))
+    }
+
+    /// The URI of the account of `issuer` and `account` with a derived
+    /// secret of 20 bytes and the default parameters: SHA-1, 6 digits, 30
+    /// seconds period and counter 0.
+    pub fn derive_uri(&self, otp_type: OtpType, issuer: Option<&str>, account: &str) -> OtpAuthUri {
+        OtpAuthUri {
+            otp_type,
+            account: account.into(),
+            issuer: issuer.filter(|issuer| !issuer.is_empty()).map(String::from),
+            secret: self.derive_secret(issuer, account, Secret::DEFAULT_BITS / 8).expect("20 bytes are derived"),
+            algorithm: Algorithm::Sha1,
+            digits: 6,
+            period: 30,
+            counter: 0,
+        }
+    }
+}
+
+/// `value` with `%` and `:` percent-encoded, so that a `:` of the label
+/// only ever separates the issuer.
+fn escape(value: &str) -> String {
+    value.replace('%', "%25").replace(':', "%3A")
+}
+
+impl core::fmt::Debug for MasterSeed {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("MasterSeed([REDACTED])")
+    }
+}
+
+/// HKDF-SHA-256 of RFC 5869: `len` bytes of keying material from `ikm`.
+fn hkdf_sha256(salt: &[u8], ikm: &[u8], info: &[u8], len: usize) -> Vec<u8> {
+    assert!(len <= MAX_LEN, "HKDF-SHA-256 derives at most 8160 bytes");
+    let hmac = |key: &[u8], parts: &[&[u8]]| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+        for part in parts {
+            mac.update(part);
+        }
+        Zeroizing::new(<[u8; HASH_LEN]>::from(mac.finalize().into_bytes()))
+    };
+    let prk = hmac(salt, &[ikm]);
+    let mut okm = Vec::with_capacity(len + HASH_LEN);
+    let mut block = Zeroizing::new([0u8; HASH_LEN]);
+    for i in 1..=len.div_ceil(HASH_LEN) {
+        let previous: &[u8] = if i == 1 { &[] } else { &block[..] };
+        block = hmac(&prk[..], &[previous, info, &[i as u8]]);
+        okm.extend_from_slice(&block[..]);
+    }
+    okm.truncate(len);
+    okm
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::hex;
+    use crate::uri::{OtpAuthUri, OtpType};
+
+    use super::{hkdf_sha256, MasterSeed};
+
+    #[test]
+    fn test_hkdf() {
+        // RFC 5869 Appendix A.1 and A.3
+        let okm = hkdf_sha256(&hex::decode("000102030405060708090a0b0c").unwrap(), &[0x0b; 22], &hex::decode("f0f1f2f3f4f5f6f7f8f9").unwrap(), 42);
+        assert_eq!(hex::encode(&okm), "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865");
+        let okm = hkdf_sha256(b"", &[0x0b; 22], b"", 42);
+        assert_eq!(hex::encode(&okm), "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8");
+    }
+
+    #[test]
+    fn test_derive() {
+        let seed = MasterSeed::new(&[7; 32]).unwrap();
+        let alice = seed.derive_uri(OtpType::Totp, Some("VPN"), "alice");
+        assert_eq!(alice.secret, seed.derive_secret(Some("VPN"), "alice", 20).unwrap());
+        assert_eq!(seed.derive_secret(Some("VPN"), "alice", 32).unwrap().as_bytes()[..20], alice.secret.as_bytes()[..]);
+        assert_ne!(alice.secret, seed.derive_secret(None, "alice", 20).unwrap());
+        assert_ne!(alice.secret, MasterSeed::new(&[8; 32]).unwrap().derive_secret(Some("VPN"), "alice", 20).unwrap());
+        // the labels a:b:c are of different accounts
+        let secrets = [seed.derive_secret(Some("a:b"), "c", 20).unwrap(), seed.derive_secret(Some("a"), "b:c", 20).unwrap(), seed.derive_secret(None, "a:b:c", 20).unwrap()];
+        assert!(secrets[0] != secrets[1] && secrets[1] != secrets[2] && secrets[0] != secrets[2]);
+        assert_ne!(seed.derive_secret(None, "a%3Ab", 20).unwrap(), seed.derive_secret(Some("a"), "b", 20).unwrap());
+        // an empty issuer is no issuer, and not the label ":alice"
+        assert_eq!(seed.derive_secret(Some(""), "alice", 20).unwrap(), seed.derive_secret(None, "alice", 20).unwrap());
+        assert_eq!(seed.derive_uri(OtpType::Totp, Some(""), "alice"), seed.derive_uri(OtpType::Totp, None, "alice"));
+        assert_eq!(seed.derive_secret(None, "alice", 8160).unwrap().len(), 8160);
+        assert_eq!(seed.derive_secret(None, "alice", 8161), Err(OtpError::SecretTooLong(8160)));
+        // the exported URI gives the same account back
+        assert_eq!(OtpAuthUri::parse(&alice.to_string()).unwrap(), alice);
+        assert_eq!(seed.derive_uri(OtpType::Hotp, None, "bob").to_string().split('?').next(), Some("otpauth://hotp/bob"));
+
+        assert!(matches!(MasterSeed::new(&[0; 15]), Err(OtpError::WeakSecret(128))));
+        assert_eq!(format!("{:?}", seed), "MasterSeed([REDACTED])");
+    }
+}
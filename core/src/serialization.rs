@@ -0,0 +1,259 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Serde support of the configuration types.
+//!
+//! Secrets are never serialized by default: [`Hotp`] and [`Totp`] are
+//! serialized without their secrets, and [`Secret`] and [`OtpAuthUri`]
+//! are not `Serialize` at all. Wrap a value in [`ExposeSecret`], or use [`expose_secret`] as the
+//! `serialize_with` function of a field, to include the secret in Base 32.
+//! Deserialization accepts the secret in Base 32 as well, and an
+//! [`OtpAuthUri`] is (de)serialized as the URI string.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroizing;
+
+use crate::clock::Clock;
+use crate::hotp::Hotp;
+use crate::otp::Algorithm;
+use crate::secret::{Secret, SecretEncoding};
+use crate::totp::{Totp, TotpBuilder};
+use crate::uri::OtpAuthUri;
+
+/// Serialize the wrapped value including its secret.
+///
+/// ```
+/// use yotp_core::{ExposeSecret, Hotp};
+///
+/// let hotp = Hotp::builder().secret(b"12345678901234567890").build().unwrap();
+/// let json = serde_json::to_string(&ExposeSecret(&hotp)).unwrap();
+/// assert_eq!(json, r#"{"secret":"GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ","counter":0,"digits":6,"algorithm":"SHA1"}"#);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ExposeSecret<'a, T>(pub &'a T);
+
+/// Serialize a [`Secret`] in Base 32, e.g. with
+/// `#[serde(serialize_with = "yotp_core::expose_secret")]`.
+pub fn expose_secret<S: Serializer>(secret: &Secret, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&Zeroizing::new(secret.to_base32()))
+}
+
+impl Serialize for ExposeSecret<'_, Secret> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        expose_secret(self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Secret, D::Error> {
+        let encoded = Zeroizing::new(alloc::string::String::deserialize(deserializer)?);
+        Secret::parse_as(&encoded, SecretEncoding::Base32).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize)]
+struct HotpFields<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<ExposeSecret<'a, Secret>>,
+    counter: u64,
+    digits: usize,
+    algorithm: Algorithm,
+}
+
+impl HotpFields<'_> {
+    fn new<'a>(hotp: &'a Hotp, secret: Option<ExposeSecret<'a, Secret>>) -> HotpFields<'a> {
+        HotpFields { secret, counter: hotp.counter(), digits: hotp.digits(), algorithm: hotp.algorithm() }
+    }
+}
+
+impl Serialize for Hotp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        HotpFields::new(self, None).serialize(serializer)
+    }
+}
+
+impl Serialize for ExposeSecret<'_, Hotp> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        HotpFields::new(self.0, Some(ExposeSecret(self.0.secret_ref()))).serialize(serializer)
+    }
+}
+
+#[derive(Deserialize)]
+struct HotpConfig {
+    secret: Secret,
+    #[serde(default)]
+    counter: u64,
+    #[serde(default = "default_digits")]
+    digits: usize,
+    #[serde(default)]
+    algorithm: Algorithm,
+}
+
+impl<'de> Deserialize<'de> for Hotp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Hotp, D::Error> {
+        let config = HotpConfig::deserialize(deserializer)?;
+        Hotp::builder()
+            .secret(config.secret.as_bytes())
+            .counter(config.counter)
+            .digits(config.digits)
+            .algorithm(config.algorithm)
+            .build()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize)]
+struct TotpFields<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<ExposeSecret<'a, Secret>>,
+    t0: u64,
+    period: u64,
+    digits: usize,
+    algorithm: Algorithm,
+}
+
+impl TotpFields<'_> {
+    fn new<'a, C: Clock>(totp: &'a Totp<C>, secret: Option<ExposeSecret<'a, Secret>>) -> TotpFields<'a> {
+        TotpFields { secret, t0: totp.t0(), period: totp.period(), digits: totp.digits(), algorithm: totp.algorithm() }
+    }
+}
+
+/// The clock is not serialized.
+impl<C: Clock> Serialize for Totp<C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TotpFields::new(self, None).serialize(serializer)
+    }
+}
+
+impl<C: Clock> Serialize for ExposeSecret<'_, Totp<C>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TotpFields::new(self.0, Some(ExposeSecret(self.0.secret_ref()))).serialize(serializer)
+    }
+}
+
+#[derive(Deserialize)]
+struct TotpConfig {
+    secret: Secret,
+    #[serde(default)]
+    t0: u64,
+    #[serde(default = "default_period")]
+    period: u64,
+    #[serde(default = "default_digits")]
+    digits: usize,
+    #[serde(default)]
+    algorithm: Algorithm,
+}
+
+/// The clock is the default one of `C`.
+impl<'de, C: Clock + Default> Deserialize<'de> for Totp<C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Totp<C>, D::Error> {
+        let config = TotpConfig::deserialize(deserializer)?;
+        TotpBuilder::with_clock(C::default())
+            .secret(config.secret.as_bytes())
+            .t0(config.t0)
+            .period(config.period)
+            .digits(config.digits)
+            .algorithm(config.algorithm)
+            .build()
+            .map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for ExposeSecret<'_, OtpAuthUri> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for OtpAuthUri {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OtpAuthUri, D::Error> {
+        let uri = Zeroizing::new(alloc::string::String::deserialize(deserializer)?);
+        OtpAuthUri::parse(&uri).map_err(D::Error::custom)
+    }
+}
+
+fn default_digits() -> usize {
+    6
+}
+
+fn default_period() -> u64 {
+    30
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::hotp::Hotp;
+    use crate::secret::Secret;
+    use crate::totp::Totp;
+    use crate::uri::OtpAuthUri;
+
+    use super::ExposeSecret;
+
+    #[test]
+    fn test_totp_serde() {
+        let totp = Totp::builder().secret(b"12345678901234567890").digits(8).build().unwrap();
+        let json = serde_json::to_string(&totp).unwrap();
+        assert_eq!(json, r#"{"t0":0,"period":30,"digits":8,"algorithm":"SHA1"}"#);
+        assert!(serde_json::from_str::<Totp>(&json).is_err());
+
+        let json = serde_json::to_string(&ExposeSecret(&totp)).unwrap();
+        assert_eq!(json, r#"{"secret":"GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ","t0":0,"period":30,"digits":8,"algorithm":"SHA1"}"#);
+        assert_eq!(serde_json::from_str::<Totp>(&json).unwrap(), totp);
+
+        let minimal: Totp = serde_json::from_str(r#"{"secret":"gezd gnbv gy3t qojq","algorithm":"SHA256"}"#).unwrap();
+        assert_eq!(minimal.period(), 30);
+        assert_eq!(minimal.digits(), 6);
+        assert_eq!(minimal.secret(), b"1234567890");
+        let error = serde_json::from_str::<Totp>(r#"{"secret":"GEZDGNBV","digits":11}"#).unwrap_err();
+        assert!(error.to_string().contains(&OtpError::InvalidDigits(11).to_string()));
+    }
+
+    #[test]
+    fn test_hotp_serde() {
+        let hotp = Hotp::builder().secret(b"12345678901234567890").counter(7).build().unwrap();
+        assert_eq!(serde_json::to_string(&hotp).unwrap(), r#"{"counter":7,"digits":6,"algorithm":"SHA1"}"#);
+        let json = serde_json::to_string(&ExposeSecret(&hotp)).unwrap();
+        assert_eq!(serde_json::from_str::<Hotp>(&json).unwrap(), hotp);
+    }
+
+    #[test]
+    fn test_secret_serde() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Account {
+            #[serde(serialize_with = "crate::expose_secret")]
+            secret: Secret,
+        }
+        let account = Account { secret: Secret::parse("JBSWY3DPEHPK3PXP").unwrap() };
+        let json = serde_json::to_string(&account).unwrap();
+        assert_eq!(json, r#"{"secret":"JBSWY3DPEHPK3PXP"}"#);
+        assert_eq!(serde_json::from_str::<Account>(&json).unwrap().secret, account.secret);
+        assert!(serde_json::from_str::<Account>(r#"{"secret":"JBSW1"}"#).is_err());
+    }
+
+    #[test]
+    fn test_uri_serde() {
+        let json = r#""otpauth://totp/Example:alice@google.com?secret=JBSWY3DPEHPK3PXP&issuer=Example&digits=6""#;
+        let uri: OtpAuthUri = serde_json::from_str(json).unwrap();
+        assert_eq!(uri.account, "alice@google.com");
+        assert_eq!(
+            serde_json::to_string(&ExposeSecret(&uri)).unwrap(),
+            r#""otpauth://totp/Example:alice@google.com?secret=JBSWY3DPEHPK3PXP&issuer=Example""#
+        );
+        assert!(serde_json::from_str::<OtpAuthUri>(r#""otpauth://totp/a""#).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Shamir's secret sharing, to split a secret such as the key of a vault
+//! into `n` shares of which any `k` recover it, while fewer than `k` tell
+//! nothing about it.
+//!
+//! # Scheme
+//!
+//! The secret is extended with the first 4 bytes of its SHA-256 digest,
+//! so that combining shares of different secrets is detected. Each byte
+//! of the extended secret is the constant term of a random polynomial of
+//! degree `k - 1` over GF(2^8) with the AES polynomial `x^8 + x^4 + x^3 +
+//! x + 1`. Share `x`, for `x` from 1 to `n`, holds the values of the
+//! polynomials at `x`, and Lagrange interpolation at 0 of `k` shares
+//! recovers the extended secret.
+//!
+//! A share is written as
+//!
+//! ```text
+//! yotp-share-1-<id>-<k>-<x>-<data>-<check>
+//! ```
+//!
+//! where `1` is the version of the scheme, `<id>` is 4 hexadecimal digits
+//! chosen at random for each split, `<k>` and `<x>` are decimal, `<data>`
+//! is the values in hexadecimal, and `<check>` is the first 2 bytes of the
+//! SHA-256 digest of the text before it in hexadecimal, to catch typos.
+//!
+//! ```
+//! use yotp_core::shamir::{self, Share};
+//!
+//! let shares = shamir::split(b"Hello!\xde\xad\xbe\xef", 2, 3).unwrap();
+//! let text = shares[2].to_string();
+//! let recovered = shamir::combine(&[shares[0].clone(), text.parse::<Share>().unwrap()]).unwrap();
+//! assert_eq!(&recovered[..], b"Hello!\xde\xad\xbe\xef");
+//! ```
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::code::constant_time_eq;
+use crate::error::OtpError;
+
+const PREFIX: &str = "yotp-share-1";
+const CHECKSUM_LEN: usize = 4;
+
+/// One share of a split secret.
+///
+/// The share is as sensitive as the secret once `k - 1` other shares are
+/// known, so it must be kept apart from them.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Share {
+    id: u16,
+    threshold: u8,
+    index: u8,
+    data: Zeroizing<Vec<u8>>,
+}
+
+impl Share {
+    /// The random identifier of the split the share is from.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// The number of shares needed to recover the secret.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// The number of the share, from 1.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    fn body(&self) -> String {
+        format!("{}-{:04x}-{}-{}-{}", PREFIX, self.id, self.threshold, self.index, crate::hex::encode(&self.data))
+    }
+}
+
+/// The text form of the scheme above. It holds the share, so it must be
+/// kept as carefully.
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = Zeroizing::new(self.body());
+        write!(f, "{}-{}", *body, crate::hex::encode(&check(&body)))
+    }
+}
+
+impl fmt::Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Share").field("id", &self.id).field("threshold", &self.threshold).field("index", &self.index).finish()
+    }
+}
+
+/// Parse the text form of a share in either case.
+///
+/// Returns [`OtpError::ShareError`] if the text is malformed or has a
+/// typo.
+impl FromStr for Share {
+    type Err = OtpError;
+
+    fn from_str(s: &str) -> Result<Share, OtpError> {
+        let s = Zeroizing::new(s.trim().to_ascii_lowercase());
+        let (body, checksum) = s.rsplit_once('-').ok_or_else(|| share_error("malformed share"))?;
+        let fields = body.strip_prefix(PREFIX).and_then(|rest| rest.strip_prefix('-')).ok_or_else(|| share_error("malformed share"))?;
+        let fields: Vec<&str> = fields.split('-').collect();
+        let [id, threshold, index, data] = fields[..] else {
+            return Err(share_error("malformed share"));
+        };
+        if checksum.len() != 4 || crate::hex::decode(checksum).ok().as_deref() != Some(&check(body)[..]) {
+            return Err(share_error("the share has a typo"));
+        }
+        let id = u16::from_str_radix(id, 16).ok().filter(|_| id.len() == 4);
+        let threshold = threshold.parse().ok().filter(|&threshold: &u8| threshold >= 2);
+        let index = index.parse().ok().filter(|&index: &u8| index >= 1);
+        let data = crate::hex::decode(data).ok().map(Zeroizing::new).filter(|data| data.len() > CHECKSUM_LEN);
+        match (id, threshold, index, data) {
+            (Some(id), Some(threshold), Some(index), Some(data)) => Ok(Share { id, threshold, index, data }),
+            _ => Err(share_error("malformed share")),
+        }
+    }
+}
+
+/// Split `secret` into `count` shares, any `threshold` of which recover
+/// it with [`combine`].
+///
+/// Returns [`OtpError::ShareError`] if `secret` is empty or `threshold` is
+/// not between 2 and `count`, and [`OtpError::RandomError`] if the random
+/// source fails.
+#[cfg(feature = "getrandom")]
+pub fn split(secret: &[u8], threshold: u8, count: u8) -> Result<Vec<Share>, OtpError> {
+    if secret.is_empty() {
+        return Err(share_error("the secret is empty"));
+    }
+    if threshold < 2 || threshold > count {
+        return Err(share_error("the threshold should be between 2 and the number of shares"));
+    }
+    let mut value = Zeroizing::new(secret.to_vec());
+    value.extend_from_slice(&checksum(secret));
+    let degree = threshold as usize - 1;
+    let mut coefficients = Zeroizing::new(alloc::vec![0u8; value.len() * degree]);
+    getrandom::fill(&mut coefficients).map_err(|_| OtpError::RandomError)?;
+    let mut id = [0; 2];
+    getrandom::fill(&mut id).map_err(|_| OtpError::RandomError)?;
+
+    let shares = (1..=count)
+        .map(|x| {
+            let data = value
+                .iter()
+                .zip(coefficients.chunks(degree))
+                .map(|(&constant, coefficients)| {
+                    // Horner's method, from the highest degree
+                    let high = coefficients.iter().rev().fold(0, |acc, &c| mul(acc, x) ^ c);
+                    mul(high, x) ^ constant
+                })
+                .collect();
+            Share { id: u16::from_be_bytes(id), threshold, index: x, data: Zeroizing::new(data) }
+        })
+        .collect();
+    Ok(shares)
+}
+
+/// Recover the secret from `shares` of the same split. Shares beyond the
+/// threshold are ignored, as are repeated ones.
+///
+/// Returns [`OtpError::ShareError`] if there are fewer shares than the
+/// threshold or the shares are of different splits.
+pub fn combine(shares: &[Share]) -> Result<Zeroizing<Vec<u8>>, OtpError> {
+    let first = shares.first().ok_or_else(|| share_error("no shares"))?;
+    let mut chosen: Vec<&Share> = Vec::new();
+    for share in shares {
+        if share.id != first.id || share.threshold != first.threshold || share.data.len() != first.data.len() {
+            return Err(share_error("the shares are from different splits"));
+        }
+        if !chosen.iter().any(|other| other.index == share.index) {
+            chosen.push(share);
+        }
+    }
+    let threshold = first.threshold as usize;
+    if chosen.len() < threshold {
+        return Err(share_error(&format!("{} shares are needed, only {} are given", threshold, chosen.len())));
+    }
+    chosen.truncate(threshold);
+
+    // Lagrange interpolation at 0, where subtraction is XOR
+    let mut value = Zeroizing::new(alloc::vec![0u8; first.data.len()]);
+    for share in &chosen {
+        let basis = chosen
+            .iter()
+            .filter(|other| other.index != share.index)
+            .fold(1, |basis, other| mul(basis, mul(other.index, inverse(other.index ^ share.index))));
+        for (byte, &y) in value.iter_mut().zip(share.data.iter()) {
+            *byte ^= mul(basis, y);
+        }
+    }
+    let (secret, sum) = value.split_at(value.len() - CHECKSUM_LEN);
+    if !constant_time_eq(sum, checksum(secret)) {
+        return Err(share_error("the shares do not recover a secret"));
+    }
+    Ok(Zeroizing::new(secret.to_vec()))
+}
+
+fn checksum(secret: &[u8]) -> [u8; CHECKSUM_LEN] {
+    Sha256::digest(secret)[..CHECKSUM_LEN].try_into().expect("digest is long enough")
+}
+
+fn check(body: &str) -> [u8; 2] {
+    Sha256::digest(body.as_bytes())[..2].try_into().expect("digest is long enough")
+}
+
+/// The product in GF(2^8), without branches on the operands.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// The inverse in GF(2^8), `a^254`, of a nonzero `a`.
+fn inverse(a: u8) -> u8 {
+    let (mut result, mut base, mut exponent) = (1, a, 254u8);
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul(result, base);
+        }
+        base = mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn share_error(msg: &str) -> OtpError {
+    OtpError::ShareError(msg.into())
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::format;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    use crate::error::OtpError;
+
+    use super::{combine, inverse, mul, Share};
+    #[cfg(feature = "getrandom")]
+    use super::split;
+
+    #[test]
+    fn test_field() {
+        // FIPS 197, section 4.2
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        assert_eq!(mul(0x57, 0x13), 0xfe);
+        for a in 1..=255 {
+            assert_eq!(mul(a, inverse(a)), 1, "{}", a);
+        }
+    }
+
+    #[cfg(feature = "getrandom")]
+    #[test]
+    fn test_split_and_combine() {
+        let secret = b"12345678901234567890";
+        let shares = split(secret, 3, 5).unwrap();
+        assert!(shares.iter().all(|share| share.id() == shares[0].id() && share.threshold() == 3));
+        assert_eq!(shares.iter().map(Share::index).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        for i in 0..5 {
+            for j in i + 1..5 {
+                for k in j + 1..5 {
+                    let subset = [shares[k].clone(), shares[i].clone(), shares[j].clone()];
+                    assert_eq!(&combine(&subset).unwrap()[..], secret);
+                }
+            }
+        }
+        assert_eq!(&combine(&shares).unwrap()[..], secret);
+
+        let two = [shares[0].clone(), shares[1].clone(), shares[1].clone()];
+        assert_eq!(combine(&two).unwrap_err(), OtpError::ShareError("3 shares are needed, only 2 are given".into()));
+        let other = split(secret, 3, 5).unwrap();
+        assert!(matches!(combine(&[shares[0].clone(), shares[1].clone(), other[2].clone()]), Err(OtpError::ShareError(_))));
+        assert!(split(b"", 2, 3).is_err());
+        assert!(split(secret, 1, 3).is_err());
+        assert!(split(secret, 4, 3).is_err());
+    }
+
+    #[cfg(feature = "getrandom")]
+    #[test]
+    fn test_text() {
+        let shares = split(b"Hello!\xde\xad\xbe\xef", 2, 2).unwrap();
+        let text = shares[0].to_string();
+        assert!(text.starts_with("yotp-share-1-"));
+        assert_eq!(text.to_ascii_uppercase().parse::<Share>().unwrap(), shares[0]);
+        assert!(!format!("{:?}", shares[0]).contains(&text[20..]));
+
+        // a typo in the data
+        let typo = text.replacen("-2-1-", "-2-2-", 1);
+        assert_eq!(typo.parse::<Share>().unwrap_err(), OtpError::ShareError("the share has a typo".into()));
+        for text in ["", "yotp-share-1", "yotp-share-2-0000-2-1-00-0000", "hello-world"] {
+            assert!(text.parse::<Share>().is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::encoding::{self, CodeEncoding};
+use crate::code::Code;
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+
+const STEAM_CODE_LEN: usize = 5;
+const STEAM_INTERVAL: u64 = 30;
+
+/// Steam Guard code for the current system time.
+///
+/// Steam uses TOTP with HMAC-SHA-1 and a 30 seconds interval, but encodes
+/// the truncated value as 5 characters of its own alphabet instead of
+/// decimal digits.
+#[cfg(feature = "std")]
+pub fn steam_totp(key: impl AsRef<[u8]>) -> Result<Code, OtpError> {
+    steam_totp_at(key, crate::otp::now()?)
+}
+
+/// Steam Guard code for time `t` in seconds since UNIX epoch.
+pub fn steam_totp_at(key: impl AsRef<[u8]>, t: u64) -> Result<Code, OtpError> {
+    encoding::totp_at_encoded(key, t, 0, STEAM_INTERVAL, STEAM_CODE_LEN, Algorithm::Sha1, &CodeEncoding::steam())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::encoding::CodeEncoding;
+
+    use super::{steam_totp, steam_totp_at};
+
+    #[test]
+    fn test_steam_totp_at() {
+        let key = b"12345678901234567890";
+        assert_eq!(steam_totp_at(key, 59).unwrap(), "PV9M4");
+        assert_eq!(steam_totp_at(key, 1111111109).unwrap(), "PY4YB");
+        assert_eq!(steam_totp_at(key, 1234567890).unwrap(), "VHHQY");
+    }
+
+    #[test]
+    fn test_steam_totp() {
+        let code = steam_totp(b"12345678901234567890").unwrap();
+        assert_eq!(code.as_str().len(), 5);
+        assert!(code.as_str().chars().all(|c| CodeEncoding::steam().alphabet().contains(&c)));
+    }
+}
@@ -0,0 +1,195 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Remote copies of an encrypted vault, to use the vault on several
+//! machines.
+//!
+//! A [`SyncBackend`] keeps one blob, the encrypted vault file, along with
+//! an ETag that changes whenever the blob does. Pushes are conditional on
+//! the ETag of the last pull, so that a push never overwrites a copy it has
+//! not seen. The backends only ever see the encrypted vault.
+//!
+//! With the `vault` feature, `Vault::sync` pulls the remote copy, merges
+//! it, and pushes the merged vault. The `webdav` and `s3` features add
+//! backends for WebDAV servers and S3 compatible object stores.
+//!
+//! ```
+//! use yotp_core::sync::{MemorySyncBackend, SyncBackend, SyncError};
+//!
+//! let remote = MemorySyncBackend::new();
+//! let etag = remote.push(b"vault", None).unwrap();
+//! assert_eq!(remote.pull().unwrap().unwrap().etag, etag);
+//! assert!(matches!(remote.push(b"other", None), Err(SyncError::Conflict)));
+//! remote.push(b"newer", Some(&etag)).unwrap();
+//! ```
+
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::error::OtpError;
+
+/// The blob of a backend and its ETag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub data: Vec<u8>,
+    pub etag: String,
+}
+
+/// A remote place for the encrypted vault.
+pub trait SyncBackend {
+    /// The blob and its ETag, or `None` if nothing was pushed yet.
+    fn pull(&self) -> Result<Option<Remote>, SyncError>;
+
+    /// The ETag of the blob without downloading it, or `None` if nothing
+    /// was pushed yet.
+    fn etag(&self) -> Result<Option<String>, SyncError>;
+
+    /// Replace the blob with `data` if its ETag is still `etag`, or store
+    /// `data` if `etag` is `None` and there is no blob yet. Returns the new
+    /// ETag.
+    ///
+    /// Returns [`SyncError::Conflict`] if the blob changed, in which case
+    /// the caller should pull and merge again.
+    fn push(&self, data: &[u8], etag: Option<&str>) -> Result<String, SyncError>;
+}
+
+/// An error of a [`SyncBackend`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SyncError {
+    /// The remote blob is not the one of the given ETag.
+    Conflict,
+    /// The remote rejects the credentials.
+    Unauthorized,
+    /// The remote cannot be reached or answers unexpectedly.
+    Remote(String),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::Conflict => write!(f, "the remote vault changed since it was pulled"),
+            SyncError::Unauthorized => write!(f, "the remote rejects the credentials"),
+            SyncError::Remote(msg) => write!(f, "remote error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl From<SyncError> for OtpError {
+    fn from(e: SyncError) -> OtpError {
+        OtpError::SyncError(e.to_string())
+    }
+}
+
+/// A [`SyncBackend`] in memory, for tests.
+#[derive(Default)]
+pub struct MemorySyncBackend {
+    // the blob and how often it was pushed
+    blob: Mutex<Option<(Vec<u8>, u64)>>,
+}
+
+impl MemorySyncBackend {
+    pub fn new() -> MemorySyncBackend {
+        MemorySyncBackend::default()
+    }
+}
+
+fn memory_etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+impl SyncBackend for MemorySyncBackend {
+    fn pull(&self) -> Result<Option<Remote>, SyncError> {
+        let blob = self.blob.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(blob.as_ref().map(|(data, version)| Remote { data: data.clone(), etag: memory_etag(*version) }))
+    }
+
+    fn etag(&self) -> Result<Option<String>, SyncError> {
+        let blob = self.blob.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(blob.as_ref().map(|(_, version)| memory_etag(*version)))
+    }
+
+    fn push(&self, data: &[u8], etag: Option<&str>) -> Result<String, SyncError> {
+        let mut blob = self.blob.lock().unwrap_or_else(|e| e.into_inner());
+        let current = blob.as_ref().map(|(_, version)| memory_etag(*version));
+        if current.as_deref() != etag {
+            return Err(SyncError::Conflict);
+        }
+        let version = blob.as_ref().map_or(1, |(_, version)| version + 1);
+        *blob = Some((data.to_vec(), version));
+        Ok(memory_etag(version))
+    }
+}
+
+/// Run `request` with `body`, or without a body if `None`. Returns `None`
+/// for 404 Not Found, and the errors of the other failing statuses.
+#[cfg(any(feature = "webdav", feature = "s3"))]
+pub(crate) fn call(request: ureq::Request, body: Option<&[u8]>) -> Result<Option<ureq::Response>, SyncError> {
+    let result = match body {
+        Some(body) => request.send_bytes(body),
+        None => request.call(),
+    };
+    match result {
+        Ok(response) => Ok(Some(response)),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(ureq::Error::Status(401 | 403, _)) => Err(SyncError::Unauthorized),
+        // 409 is the answer of S3 to concurrent conditional writes
+        Err(ureq::Error::Status(409 | 412, _)) => Err(SyncError::Conflict),
+        Err(ureq::Error::Status(status, response)) => {
+            Err(SyncError::Remote(format!("{} {}", status, response.status_text())))
+        }
+        Err(ureq::Error::Transport(e)) => Err(SyncError::Remote(e.to_string())),
+    }
+}
+
+/// The ETag header of `response`.
+#[cfg(any(feature = "webdav", feature = "s3"))]
+pub(crate) fn etag_of(response: &ureq::Response) -> Result<String, SyncError> {
+    response.header("ETag").map(str::to_owned).ok_or_else(|| SyncError::Remote("the response has no ETag".into()))
+}
+
+/// The blob of `response` with its ETag.
+#[cfg(any(feature = "webdav", feature = "s3"))]
+pub(crate) fn remote_of(response: ureq::Response) -> Result<Remote, SyncError> {
+    use std::io::Read;
+
+    let etag = etag_of(&response)?;
+    let mut data = Vec::new();
+    response.into_reader().read_to_end(&mut data).map_err(|e| SyncError::Remote(e.to_string()))?;
+    Ok(Remote { data, etag })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MemorySyncBackend, SyncBackend, SyncError};
+
+    #[test]
+    fn test_memory_backend() {
+        let remote = MemorySyncBackend::new();
+        assert_eq!(remote.pull().unwrap(), None);
+        assert_eq!(remote.etag().unwrap(), None);
+        assert!(matches!(remote.push(b"a", Some("\"1\"")), Err(SyncError::Conflict)));
+        let first = remote.push(b"a", None).unwrap();
+        let second = remote.push(b"b", Some(&first)).unwrap();
+        assert_ne!(first, second);
+        assert!(matches!(remote.push(b"c", Some(&first)), Err(SyncError::Conflict)));
+        let pulled = remote.pull().unwrap().unwrap();
+        assert_eq!((pulled.data.as_slice(), pulled.etag), (&b"b"[..], second.clone()));
+        assert_eq!(remote.etag().unwrap(), Some(second));
+    }
+}
@@ -0,0 +1,198 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::OtpError;
+
+/// Parameters of [`Throttle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThrottlePolicy {
+    /// Delay in seconds after the first failure. It doubles after every
+    /// further consecutive failure.
+    pub base_delay: u64,
+    /// The maximal delay in seconds.
+    pub max_delay: u64,
+    /// Lock the account out after this many consecutive failures, until
+    /// [`Throttle::reset`] is called.
+    pub lockout_threshold: Option<u32>,
+}
+
+impl Default for ThrottlePolicy {
+    fn default() -> Self {
+        ThrottlePolicy {
+            base_delay: 1,
+            max_delay: 300,
+            lockout_threshold: Some(10),
+        }
+    }
+}
+
+impl ThrottlePolicy {
+    /// The delay in seconds after `failures` consecutive failures.
+    pub fn delay(&self, failures: u32) -> u64 {
+        if failures == 0 {
+            return 0;
+        }
+        let factor = 1u64.checked_shl(failures - 1).unwrap_or(u64::MAX);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Check whether an attempt is allowed at time `now` after `failures`
+    /// consecutive failures, the last one at time `last_failure`.
+    pub fn check(&self, failures: u32, last_failure: u64, now: u64) -> Result<(), OtpError> {
+        if let Some(threshold) = self.lockout_threshold {
+            if failures >= threshold {
+                return Err(OtpError::LockedOut);
+            }
+        }
+        let allowed_at = last_failure.saturating_add(self.delay(failures));
+        if now < allowed_at {
+            return Err(OtpError::Throttled(allowed_at - now));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FailureState {
+    failures: u32,
+    last_failure: u64,
+}
+
+/// Throttling of verification attempts as RFC 4226 §7.3 suggests.
+///
+/// It counts the consecutive failed verifications of each account and
+/// refuses new attempts until an exponentially growing delay has passed,
+/// or at all once the lockout threshold is reached.
+#[derive(Debug, Clone)]
+pub struct Throttle<K = String, C = SystemClock> {
+    policy: ThrottlePolicy,
+    clock: C,
+    states: HashMap<K, FailureState>,
+}
+
+impl<K: Eq + Hash> Throttle<K> {
+    pub fn new(policy: ThrottlePolicy) -> Throttle<K> {
+        Throttle::with_clock(policy, SystemClock)
+    }
+}
+
+impl<K: Eq + Hash, C: Clock> Throttle<K, C> {
+    /// Create a throttle reading the current time from `clock`.
+    pub fn with_clock(policy: ThrottlePolicy, clock: C) -> Throttle<K, C> {
+        Throttle {
+            policy,
+            clock,
+            states: HashMap::new(),
+        }
+    }
+
+    /// Check whether `id` may attempt a verification now. Returns
+    /// [`OtpError::LockedOut`] or [`OtpError::Throttled`] with the seconds
+    /// to wait otherwise.
+    pub fn check(&self, id: &K) -> Result<(), OtpError> {
+        match self.states.get(id) {
+            Some(state) => self.policy.check(state.failures, state.last_failure, self.clock.now_unix()),
+            None => Ok(())
+        }
+    }
+
+    /// Run `verify` for `id` if the throttle allows it, and record its
+    /// result.
+    pub fn attempt<F: FnOnce() -> bool>(&mut self, id: K, verify: F) -> Result<bool, OtpError> {
+        self.check(&id)?;
+        let ok = verify();
+        if ok {
+            self.record_success(&id);
+        } else {
+            self.record_failure(id);
+        }
+        Ok(ok)
+    }
+
+    /// Record a failed verification of `id`.
+    pub fn record_failure(&mut self, id: K) {
+        let now = self.clock.now_unix();
+        let state = self.states.entry(id).or_insert(FailureState { failures: 0, last_failure: now });
+        state.failures = state.failures.saturating_add(1);
+        state.last_failure = now;
+    }
+
+    /// Record a successful verification of `id`, which clears its failures.
+    pub fn record_success(&mut self, id: &K) {
+        self.states.remove(id);
+    }
+
+    /// Clear the failures of `id`, e.g. to unlock it after a lockout.
+    pub fn reset(&mut self, id: &K) {
+        self.states.remove(id);
+    }
+
+    /// The number of consecutive failures of `id`.
+    pub fn failures(&self, id: &K) -> u32 {
+        self.states.get(id).map(|s| s.failures).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::MockClock;
+    use crate::error::OtpError;
+
+    use super::{Throttle, ThrottlePolicy};
+
+    #[test]
+    fn test_exponential_delay() {
+        let clock = MockClock::new(1000);
+        let policy = ThrottlePolicy { base_delay: 2, max_delay: 10, lockout_threshold: None };
+        let mut throttle = Throttle::with_clock(policy, &clock);
+        assert_eq!(throttle.attempt("alice", || false), Ok(false));
+        assert_eq!(throttle.check(&"alice"), Err(OtpError::Throttled(2)));
+        clock.advance(2);
+        assert_eq!(throttle.attempt("alice", || false), Ok(false));
+        assert_eq!(throttle.check(&"alice"), Err(OtpError::Throttled(4)));
+        clock.advance(4);
+        assert_eq!(throttle.attempt("alice", || false), Ok(false));
+        clock.advance(4);
+        assert_eq!(throttle.check(&"alice"), Err(OtpError::Throttled(4)));
+        clock.advance(4);
+        assert_eq!(throttle.attempt("alice", || false), Ok(false));
+        // capped by max_delay
+        assert_eq!(throttle.check(&"alice"), Err(OtpError::Throttled(10)));
+        assert_eq!(throttle.check(&"bob"), Ok(()));
+        clock.advance(10);
+        assert_eq!(throttle.attempt("alice", || true), Ok(true));
+        assert_eq!(throttle.failures(&"alice"), 0);
+        assert_eq!(throttle.check(&"alice"), Ok(()));
+    }
+
+    #[test]
+    fn test_lockout() {
+        let clock = MockClock::new(1000);
+        let policy = ThrottlePolicy { base_delay: 0, max_delay: 0, lockout_threshold: Some(3) };
+        let mut throttle = Throttle::with_clock(policy, &clock);
+        for _i in 0..3 {
+            assert_eq!(throttle.attempt("alice", || false), Ok(false));
+        }
+        assert_eq!(throttle.attempt("alice", || true), Err(OtpError::LockedOut));
+        throttle.reset(&"alice");
+        assert_eq!(throttle.attempt("alice", || true), Ok(true));
+    }
+}
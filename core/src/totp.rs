@@ -0,0 +1,447 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::clock::Clock;
+#[cfg(feature = "std")]
+use crate::clock::SystemClock;
+use crate::code::{constant_time_eq, normalize_code, Code};
+use crate::error::OtpError;
+use crate::otp::{self, Algorithm, PreparedKey};
+use crate::secret::Secret;
+use crate::verify::StepMatch;
+
+/// A TOTP generator carrying the configuration of one account.
+///
+/// The current time is read from the [`Clock`] `C`, which is the system
+/// clock by default. Without the `std` feature there is no system clock,
+/// so the builder is started with [`TotpBuilder::with_clock`].
+///
+/// ```
+/// use yotp_core::{Algorithm, Totp};
+///
+/// let totp = Totp::builder()
+///     .secret(b"12345678901234567890")
+///     .period(30)
+///     .digits(8)
+///     .algorithm(Algorithm::Sha1)
+///     .build()
+///     .unwrap();
+/// assert_eq!(totp.generate_at(59).unwrap(), "94287082");
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Totp<C = SystemClock> {
+    secret: Secret,
+    t0: u64,
+    period: u64,
+    digits: usize,
+    algorithm: Algorithm,
+    clock: C,
+}
+
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Totp<C> {
+    secret: Secret,
+    t0: u64,
+    period: u64,
+    digits: usize,
+    algorithm: Algorithm,
+    clock: C,
+}
+
+#[cfg(feature = "std")]
+impl Totp {
+    /// Start building a [`Totp`] with the default parameters: 30 seconds
+    /// period, 6 digits, SHA-1, `t0` as 0 and the system clock.
+    pub fn builder() -> TotpBuilder {
+        TotpBuilder::default()
+    }
+}
+
+impl<C: Clock> Totp<C> {
+    /// Generate the code for the current time of the clock.
+    pub fn generate(&self) -> Result<Code, OtpError> {
+        self.generate_at(self.clock.now_unix())
+    }
+
+    /// Generate the code for time `t` in seconds since UNIX epoch.
+    pub fn generate_at(&self, t: u64) -> Result<Code, OtpError> {
+        otp::totp_at_with_algorithm(self.secret.as_bytes(), t, self.t0, self.period, self.digits, self.algorithm)
+    }
+
+    /// Seconds until the code of the current time expires.
+    pub fn time_remaining(&self) -> Result<u64, OtpError> {
+        self.time_remaining_at(self.clock.now_unix())
+    }
+
+    /// Seconds until the code of time `t` expires.
+    pub fn time_remaining_at(&self, t: u64) -> Result<u64, OtpError> {
+        match t.checked_sub(self.t0) {
+            Some(elapsed) => Ok(self.period - elapsed % self.period),
+            None => Err(OtpError::TimeError)
+        }
+    }
+
+    /// The time in seconds since UNIX epoch when the code of the current
+    /// time expires, i.e. when the next code becomes valid.
+    ///
+    /// Returns [`OtpError::TimeError`] if the time is before `t0` or the
+    /// expiry is past `u64::MAX`.
+    pub fn expires_at(&self) -> Result<u64, OtpError> {
+        let t = self.clock.now_unix();
+        t.checked_add(self.time_remaining_at(t)?).ok_or(OtpError::TimeError)
+    }
+
+    /// Check whether `code` is the code for the current time.
+    pub fn verify(&self, code: &str) -> bool {
+        self.verify_window(code, 0).is_some()
+    }
+
+    /// Check `code` against the codes of up to `window` time steps before
+    /// and after the current time, and return the step that matched.
+    pub fn verify_window(&self, code: &str, window: u64) -> Option<StepMatch> {
+        self.verify_window_at(code, self.clock.now_unix(), window)
+    }
+
+    /// Check `code` against the codes of up to `window` time steps before
+    /// and after time `t`, and return the step that matched. `code` is
+    /// normalized by [`normalize_code`] first.
+    ///
+    /// All steps in the window are compared in constant time without
+    /// returning early, so the time taken does not depend on which step
+    /// matched.
+    pub fn verify_window_at(&self, code: &str, t: u64, window: u64) -> Option<StepMatch> {
+        self.verify_with_drift_at(code, t, window, 0)
+    }
+
+    /// Like [`Totp::verify_window`], but the window is centered `drift`
+    /// steps away from the current step, to compensate the known clock
+    /// drift of the token. The offset of the returned match is still
+    /// relative to the current step, so it is the new drift to record.
+    pub fn verify_with_drift(&self, code: &str, window: u64, drift: i64) -> Option<StepMatch> {
+        self.verify_with_drift_at(code, self.clock.now_unix(), window, drift)
+    }
+
+    /// [`Totp::verify_with_drift`] at time `t`.
+    pub fn verify_with_drift_at(&self, code: &str, t: u64, window: u64, drift: i64) -> Option<StepMatch> {
+        self.verify_range_at(code, t, window, drift, 0)
+    }
+
+    /// Check `code` against the code of the current step, and also the
+    /// code of the previous step during the first `grace` seconds of the
+    /// current step. Unlike a symmetric window, it tolerates late typing
+    /// without accepting any code of the future.
+    pub fn verify_with_grace(&self, code: &str, grace: u64) -> Option<StepMatch> {
+        self.verify_with_grace_at(code, self.clock.now_unix(), grace)
+    }
+
+    /// [`Totp::verify_with_grace`] at time `t`.
+    pub fn verify_with_grace_at(&self, code: &str, t: u64, grace: u64) -> Option<StepMatch> {
+        self.verify_range_at(code, t, 0, 0, grace)
+    }
+
+    /// Check `code` against a window of `window` steps around `drift`
+    /// steps from the step of time `t`, extended by the previous step if
+    /// `t` is within `grace` seconds after the step boundary.
+    pub(crate) fn verify_range_at(&self, code: &str, t: u64, window: u64, drift: i64, grace: u64) -> Option<StepMatch> {
+        let code = normalize_code(code);
+        let current = otp::time_counter(t, self.t0, self.period).ok()?;
+        let center = current.saturating_add_signed(drift);
+        let mut first = center.saturating_sub(window);
+        if (t - self.t0) % self.period < grace {
+            first = first.saturating_sub(1);
+        }
+        self.verify_steps(&code, current, first, center.saturating_add(window))
+    }
+
+    /// Compare `code` with the codes of steps `first..=last` and return the
+    /// match relative to `current`.
+    fn verify_steps(&self, code: &str, current: u64, first: u64, last: u64) -> Option<StepMatch> {
+        let key = match PreparedKey::new(self.secret.as_bytes(), self.algorithm) {
+            Ok(key) => key,
+            Err(_) => return None
+        };
+        let mut matched = None;
+        for step in first..=last {
+            let expected = match key.hotp(step, self.digits) {
+                Ok(expected) => expected,
+                Err(_) => return None
+            };
+            if constant_time_eq(expected.as_str(), code) && matched.is_none() {
+                matched = Some(StepMatch { step, offset: step as i64 - current as i64 });
+            }
+        }
+        matched
+    }
+
+    pub fn secret(&self) -> &[u8] {
+        self.secret.as_bytes()
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn secret_ref(&self) -> &Secret {
+        &self.secret
+    }
+
+    pub fn t0(&self) -> u64 {
+        self.t0
+    }
+
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+}
+
+/// Builder of [`Totp`]. See [`Totp::builder`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct TotpBuilder<C = SystemClock> {
+    secret: Secret,
+    t0: u64,
+    period: u64,
+    digits: usize,
+    algorithm: Algorithm,
+    clock: C,
+}
+
+/// Builder of [`Totp`]. See [`TotpBuilder::with_clock`].
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone)]
+pub struct TotpBuilder<C> {
+    secret: Secret,
+    t0: u64,
+    period: u64,
+    digits: usize,
+    algorithm: Algorithm,
+    clock: C,
+}
+
+#[cfg(feature = "std")]
+impl Default for TotpBuilder {
+    fn default() -> Self {
+        TotpBuilder::with_clock(SystemClock)
+    }
+}
+
+impl<C: Clock> TotpBuilder<C> {
+    /// Start building a [`Totp`] that reads the current time from
+    /// `clock`, with the other parameters as in [`Totp::builder`].
+    pub fn with_clock(clock: C) -> Self {
+        TotpBuilder {
+            secret: Secret::default(),
+            t0: 0,
+            period: 30,
+            digits: 6,
+            algorithm: Algorithm::Sha1,
+            clock,
+        }
+    }
+
+    /// Set the shared secret as raw bytes, e.g. a [`Secret`].
+    pub fn secret(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.secret = Secret::new(secret.as_ref().to_vec());
+        self
+    }
+
+    /// Set the start time in seconds since UNIX epoch.
+    pub fn t0(mut self, t0: u64) -> Self {
+        self.t0 = t0;
+        self
+    }
+
+    /// Set the period (time step) in seconds.
+    pub fn period(mut self, period: u64) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Set the length of generated codes.
+    pub fn digits(mut self, digits: usize) -> Self {
+        self.digits = digits;
+        self
+    }
+
+    /// Set the hash function used by the HMAC.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Use `clock` as the source of the current time.
+    pub fn clock<C2: Clock>(self, clock: C2) -> TotpBuilder<C2> {
+        TotpBuilder {
+            secret: self.secret,
+            t0: self.t0,
+            period: self.period,
+            digits: self.digits,
+            algorithm: self.algorithm,
+            clock,
+        }
+    }
+
+    /// Validate the parameters and build the [`Totp`].
+    pub fn build(self) -> Result<Totp<C>, OtpError> {
+        if !otp::DIGITS_RANGE.contains(&self.digits) {
+            return Err(OtpError::InvalidDigits(self.digits));
+        }
+        if self.period == 0 {
+            return Err(OtpError::InvalidInterval);
+        }
+        if self.secret.is_empty() {
+            return Err(OtpError::KeyTooShort);
+        }
+        Ok(Totp {
+            secret: self.secret,
+            t0: self.t0,
+            period: self.period,
+            digits: self.digits,
+            algorithm: self.algorithm,
+            clock: self.clock,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::MockClock;
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+
+    use super::{Totp, TotpBuilder};
+
+    #[test]
+    fn test_builder_defaults() {
+        let totp = Totp::builder().secret(b"12345678901234567890").build().unwrap();
+        assert_eq!(totp.period(), 30);
+        assert_eq!(totp.digits(), 6);
+        assert_eq!(totp.algorithm(), Algorithm::Sha1);
+        assert_eq!(totp.generate_at(59).unwrap(), "287082");
+    }
+
+    #[test]
+    fn test_builder_invalid() {
+        assert_eq!(Totp::builder().build(), Err(OtpError::KeyTooShort));
+        assert_eq!(Totp::builder().secret(b"key").digits(11).build(), Err(OtpError::InvalidDigits(11)));
+        assert_eq!(Totp::builder().secret(b"key").period(0).build(), Err(OtpError::InvalidInterval));
+    }
+
+    #[test]
+    fn test_generate_sha256() {
+        let totp = Totp::builder()
+            .secret(b"12345678901234567890123456789012")
+            .digits(8)
+            .algorithm(Algorithm::Sha256)
+            .build()
+            .unwrap();
+        assert_eq!(totp.generate_at(1111111109).unwrap(), "68084774");
+    }
+
+    #[test]
+    fn test_time_remaining() {
+        let totp = Totp::builder().secret(b"12345678901234567890").t0(10).build().unwrap();
+        assert_eq!(totp.time_remaining_at(10).unwrap(), 30);
+        assert_eq!(totp.time_remaining_at(39).unwrap(), 1);
+        assert_eq!(totp.time_remaining_at(40).unwrap(), 30);
+        assert_eq!(totp.time_remaining_at(55).unwrap(), 15);
+        assert_eq!(totp.time_remaining_at(5), Err(OtpError::TimeError));
+        let remaining = totp.time_remaining().unwrap();
+        assert!((1..=30).contains(&remaining));
+        assert_eq!((totp.expires_at().unwrap() - 10) % 30, 0);
+    }
+
+    #[test]
+    fn test_verify() {
+        let totp = Totp::builder().secret(b"12345678901234567890").build().unwrap();
+        let code = totp.generate().unwrap();
+        assert!(totp.verify(code.as_str()));
+        assert!(!totp.verify("abcdef"));
+    }
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::new(59);
+        let totp = Totp::builder()
+            .secret(b"12345678901234567890")
+            .digits(8)
+            .clock(&clock)
+            .build()
+            .unwrap();
+        assert_eq!(totp.generate().unwrap(), "94287082");
+        assert_eq!(totp.time_remaining().unwrap(), 1);
+        assert_eq!(totp.expires_at().unwrap(), 60);
+        clock.set(u64::MAX);
+        assert_eq!(totp.expires_at(), Err(OtpError::TimeError));
+        clock.set(1111111109);
+        assert_eq!(totp.generate().unwrap(), "07081804");
+        assert!(totp.verify("07081804"));
+        clock.advance(30);
+        assert!(!totp.verify("07081804"));
+
+        let totp = TotpBuilder::with_clock(MockClock::new(59)).secret(b"12345678901234567890").digits(8).build().unwrap();
+        assert_eq!(totp.generate().unwrap(), "94287082");
+    }
+
+    #[test]
+    fn test_verify_window() {
+        let totp = Totp::builder().secret(b"12345678901234567890").digits(8).build().unwrap();
+        // 07081804 is the code of step 37037036 (t = 1111111109)
+        let t = 1111111109;
+        assert_eq!(totp.verify_window_at("07081804", t, 0).unwrap().offset, 0);
+        let matched = totp.verify_window_at("07081804", t + 60, 2).unwrap();
+        assert_eq!(matched.step, 37037036);
+        assert_eq!(matched.offset, -2);
+        assert_eq!(totp.verify_window_at("07081804", t - 30, 1).unwrap().offset, 1);
+        assert!(totp.verify_window_at("07081804", t + 90, 2).is_none());
+        assert!(totp.verify_window_at("07081804", 0, 2).is_none());
+        assert!(totp.verify_window_at("0708 1804", t, 0).is_some());
+    }
+
+    #[test]
+    fn test_verify_with_drift() {
+        let totp = Totp::builder().secret(b"12345678901234567890").digits(8).build().unwrap();
+        // 07081804 is the code of t = 1111111109, seen 3 steps later
+        let t = 1111111109 + 90;
+        assert!(totp.verify_with_drift_at("07081804", t, 1, 0).is_none());
+        let matched = totp.verify_with_drift_at("07081804", t, 1, -2).unwrap();
+        assert_eq!(matched.offset, -3);
+        assert_eq!(matched.step, 37037036);
+    }
+
+    #[test]
+    fn test_verify_with_grace() {
+        let totp = Totp::builder().secret(b"12345678901234567890").digits(8).build().unwrap();
+        // 07081804 is the code of step 37037036, which ends at 1111111110
+        assert_eq!(totp.verify_with_grace_at("07081804", 1111111109, 5).unwrap().offset, 0);
+        assert_eq!(totp.verify_with_grace_at("07081804", 1111111114, 5).unwrap().offset, -1);
+        assert!(totp.verify_with_grace_at("07081804", 1111111115, 5).is_none());
+        assert!(totp.verify_with_grace_at("07081804", 1111111110, 0).is_none());
+        // the code of the next step is never accepted early
+        assert!(totp.verify_with_grace_at("07081804", 1111111079, 29).is_none());
+    }
+}
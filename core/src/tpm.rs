@@ -0,0 +1,245 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Secrets sealed to the TPM 2.0 of the machine as a [`SecretStore`], so
+//! that they can only be unsealed on that machine.
+//!
+//! With the `vault` feature, `Vault::store_key` seals the key of a vault,
+//! which then opens with `Vault::open_from_store` on the enrolled machine
+//! only, even if the vault file and the sealed blobs are copied elsewhere.
+//!
+//! The secrets are sealed with `tpm2-tools` under the ECC primary key of
+//! the owner hierarchy, which must have no authorization value, as on
+//! most machines. The sealed blobs are files in a directory, useless
+//! without the TPM that sealed them. With [`TpmKeyProvider::with_pcrs`],
+//! the secrets unseal only while the PCRs have the values they had when
+//! sealed, e.g. with the same firmware and Secure Boot state.
+//!
+//! ```no_run
+//! use yotp_core::secret_store::SecretStore;
+//! use yotp_core::tpm::TpmKeyProvider;
+//! use yotp_core::Algorithm;
+//!
+//! let tpm = TpmKeyProvider::new("/home/alice/.config/yotp/tpm").with_pcrs(Algorithm::Sha256, &[0, 7]);
+//! tpm.set("accounts", b"Hello!\xde\xad\xbe\xef").unwrap();
+//! assert!(tpm.get("accounts").unwrap().is_some());
+//! ```
+
+use std::ffi::OsStr;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use zeroize::Zeroizing;
+
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret_store::SecretStore;
+
+/// The secrets sealed to the TPM in a directory, one pair of files
+/// `<name>.tpm.pub` and `<name>.tpm.priv` per secret.
+///
+/// A secret sealed with a PCR policy unseals only with a provider with
+/// the same PCRs.
+pub struct TpmKeyProvider {
+    dir: PathBuf,
+    pcrs: Option<(Algorithm, Vec<u8>)>,
+    tcti: Option<String>,
+}
+
+impl TpmKeyProvider {
+    /// The secrets sealed in `dir`, without a PCR policy.
+    pub fn new(dir: impl Into<PathBuf>) -> TpmKeyProvider {
+        TpmKeyProvider { dir: dir.into(), pcrs: None, tcti: None }
+    }
+
+    /// Seal the secrets to the values of `pcrs` in the PCR bank of
+    /// `bank`. The PCRs measure the boot, e.g. 0 the firmware and 7 the
+    /// Secure Boot state; the secrets must be sealed again when they
+    /// change, e.g. after a firmware update.
+    pub fn with_pcrs(mut self, bank: Algorithm, pcrs: &[u8]) -> TpmKeyProvider {
+        let mut pcrs = pcrs.to_vec();
+        pcrs.sort_unstable();
+        pcrs.dedup();
+        self.pcrs = Some((bank, pcrs));
+        self
+    }
+
+    /// Reach the TPM through `tcti`, e.g. `swtpm:port=2321` for a software
+    /// TPM, instead of the default of `tpm2-tools`.
+    pub fn with_tcti(mut self, tcti: &str) -> TpmKeyProvider {
+        self.tcti = Some(tcti.to_owned());
+        self
+    }
+
+    /// The directory of the sealed blobs.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The PCR selection of `tpm2-tools`, e.g. `sha256:0,7`.
+    fn pcr_selection(&self) -> Option<String> {
+        let (bank, pcrs) = self.pcrs.as_ref()?;
+        let pcrs: Vec<String> = pcrs.iter().map(u8::to_string).collect();
+        Some(format!("{}:{}", bank.name().to_lowercase(), pcrs.join(",")))
+    }
+
+    fn blob_paths(&self, name: &str) -> Result<(PathBuf, PathBuf), OtpError> {
+        if name.is_empty() || name.starts_with('.') || !name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+            return Err(store_error(&format!("invalid name {}", name)));
+        }
+        Ok((self.dir.join(format!("{}.tpm.pub", name)), self.dir.join(format!("{}.tpm.priv", name))))
+    }
+
+    /// A directory for the contexts of the commands, next to the blobs.
+    fn scratch(&self, name: &str) -> Result<Scratch, OtpError> {
+        let path = self.dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+        std::fs::create_dir_all(&path).map_err(|e| store_error(&e.to_string()))?;
+        Ok(Scratch(path))
+    }
+
+    /// Run `program` in `scratch` with `input` on its standard input, and
+    /// return its standard output.
+    fn run<S: AsRef<OsStr>>(&self, scratch: &Scratch, program: &str, args: &[S], input: &[u8]) -> Result<Zeroizing<Vec<u8>>, OtpError> {
+        let mut command = Command::new(program);
+        command.args(args).current_dir(&scratch.0).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        if let Some(tcti) = &self.tcti {
+            command.env("TPM2TOOLS_TCTI", tcti);
+        }
+        let mut child = command.spawn().map_err(|e| store_error(&format!("cannot run {}: {}", program, e)))?;
+        // the inputs are small secrets, which fit in the pipe
+        let written = child.stdin.take().expect("stdin is piped").write_all(input);
+        let output = child.wait_with_output().map_err(|e| store_error(&e.to_string()))?;
+        let stdout = Zeroizing::new(output.stdout);
+        if written.is_err() || !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(store_error(&format!("{} failed: {}", program, stderr.trim())));
+        }
+        Ok(stdout)
+    }
+
+    fn create_primary(&self, scratch: &Scratch) -> Result<(), OtpError> {
+        self.run(scratch, "tpm2_createprimary", &["-Q", "-C", "o", "-g", "sha256", "-G", "ecc", "-c", "primary.ctx"], b"")?;
+        Ok(())
+    }
+}
+
+impl SecretStore for TpmKeyProvider {
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, OtpError> {
+        let (public, private) = self.blob_paths(name)?;
+        if !public.exists() || !private.exists() {
+            return Ok(None);
+        }
+        // the commands run in the scratch directory
+        let absolute = |path: PathBuf| path.canonicalize().map_err(|e| store_error(&e.to_string()));
+        let (public, private) = (absolute(public)?, absolute(private)?);
+        let scratch = self.scratch(name)?;
+        self.create_primary(&scratch)?;
+        let load = [OsStr::new("-Q"), "-C".as_ref(), "primary.ctx".as_ref(), "-u".as_ref(), public.as_ref(), "-r".as_ref(), private.as_ref(), "-c".as_ref(), "seal.ctx".as_ref()];
+        self.run(&scratch, "tpm2_load", &load, b"")?;
+        let mut unseal = vec![String::from("-c"), String::from("seal.ctx")];
+        if let Some(selection) = self.pcr_selection() {
+            unseal.extend([String::from("-p"), format!("pcr:{}", selection)]);
+        }
+        Ok(Some(self.run(&scratch, "tpm2_unseal", &unseal, b"")?))
+    }
+
+    fn set(&self, name: &str, secret: &[u8]) -> Result<(), OtpError> {
+        let (public, private) = self.blob_paths(name)?;
+        let scratch = self.scratch(name)?;
+        self.create_primary(&scratch)?;
+        let mut create: Vec<String> = ["-Q", "-C", "primary.ctx", "-g", "sha256", "-i", "-", "-u", "seal.pub", "-r", "seal.priv"].map(String::from).to_vec();
+        match self.pcr_selection() {
+            Some(selection) => {
+                self.run(&scratch, "tpm2_createpolicy", &["-Q", "--policy-pcr", "-l", &selection, "-L", "policy.digest"], b"")?;
+                // without userwithauth only the policy unseals the secret
+                create.extend(["-L", "policy.digest", "-a", "fixedtpm|fixedparent"].map(String::from));
+            }
+            None => create.extend(["-a", "fixedtpm|fixedparent|userwithauth"].map(String::from)),
+        }
+        self.run(&scratch, "tpm2_create", &create, secret)?;
+        let rename = |from: &str, to: &Path| std::fs::rename(scratch.0.join(from), to).map_err(|e| store_error(&e.to_string()));
+        rename("seal.pub", &public)?;
+        rename("seal.priv", &private)
+    }
+
+    fn delete(&self, name: &str) -> Result<bool, OtpError> {
+        let (public, private) = self.blob_paths(name)?;
+        let mut found = false;
+        for path in [public, private] {
+            match std::fs::remove_file(path) {
+                Ok(()) => found = true,
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(store_error(&e.to_string())),
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// A directory removed when dropped.
+struct Scratch(PathBuf);
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn store_error(msg: &str) -> OtpError {
+    OtpError::SecretStoreError(format!("tpm: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::otp::Algorithm;
+    use crate::secret_store::SecretStore;
+
+    use super::TpmKeyProvider;
+
+    #[test]
+    fn test_blobs() {
+        let dir = std::env::temp_dir().join(format!("yotp-tpm-{}", std::process::id()));
+        let tpm = TpmKeyProvider::new(&dir).with_pcrs(Algorithm::Sha256, &[7, 0, 7]);
+        assert_eq!(tpm.pcr_selection().as_deref(), Some("sha256:0,7"));
+        assert_eq!(TpmKeyProvider::new(&dir).pcr_selection(), None);
+        assert_eq!(tpm.blob_paths("accounts").unwrap().1, dir.join("accounts.tpm.priv"));
+        assert!(tpm.blob_paths("../accounts").is_err() && tpm.blob_paths(".hidden").is_err() && tpm.blob_paths("").is_err());
+        // nothing sealed, so the TPM is not needed
+        assert_eq!(tpm.get("accounts").unwrap(), None);
+        assert!(!tpm.delete("accounts").unwrap());
+    }
+
+    #[test]
+    fn test_seal() {
+        // needs tpm2-tools and a TPM, e.g. swtpm with TPM2TOOLS_TCTI set
+        if std::env::var_os("TPM2TOOLS_TCTI").is_none() && !Path::new("/dev/tpmrm0").exists() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("yotp-tpm-seal-{}", std::process::id()));
+        let tpm = TpmKeyProvider::new(&dir);
+        tpm.set("accounts", b"Hello!\xde\xad\xbe\xef").unwrap();
+        assert_eq!(tpm.get("accounts").unwrap().as_deref().map(|s| &s[..]), Some(&b"Hello!\xde\xad\xbe\xef"[..]));
+        let pcrs = TpmKeyProvider::new(&dir).with_pcrs(Algorithm::Sha256, &[0, 7]);
+        pcrs.set("pcrs", b"secret").unwrap();
+        assert_eq!(pcrs.get("pcrs").unwrap().as_deref().map(|s| &s[..]), Some(&b"secret"[..]));
+        assert!(tpm.get("pcrs").is_err());
+        assert!(tpm.delete("accounts").unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The `.2fas` backups of 2FAS Authenticator.
+//!
+//! A backup is a JSON object whose `services` are the accounts. In a
+//! password protected backup `services` is empty and
+//! `servicesEncrypted` holds the Base 64 cipher text, salt and nonce
+//! separated by colons. The services are encrypted with AES-256-GCM
+//! under a key derived from the password by PBKDF2-HMAC-SHA256.
+//!
+//! ```
+//! use yotp_core::twofas;
+//!
+//! let accounts = twofas::import(r#"{"schemaVersion": 4, "services": [{"name": "Example",
+//!     "secret": "JBSWY3DPEHPK3PXP", "otp": {"account": "alice", "tokenType": "TOTP"}}]}"#, None).unwrap();
+//! assert_eq!(accounts[0].issuer.as_deref(), Some("Example"));
+//! ```
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use serde::Deserialize;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::base64;
+use crate::error::OtpError;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::uri::{OtpAuthUri, OtpType};
+
+/// The PBKDF2 iteration count of 2FAS, which backups do not record.
+const PBKDF2_ITERATIONS: u32 = 10_000;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Backup {
+    #[serde(default)]
+    services: Vec<Service>,
+    #[serde(default)]
+    services_encrypted: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Service {
+    #[serde(default)]
+    name: String,
+    secret: String,
+    #[serde(default)]
+    otp: Otp,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Otp {
+    #[serde(default)]
+    account: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    issuer: String,
+    digits: Option<usize>,
+    period: Option<u64>,
+    algorithm: Option<String>,
+    token_type: Option<String>,
+    counter: Option<u64>,
+}
+
+/// Read the accounts of a 2FAS backup. `password` is required if the
+/// backup is password protected.
+///
+/// Returns [`OtpError::ImportError`] if the backup is malformed, cannot be
+/// decrypted with `password`, or has services of other types such as
+/// Steam, which are not skipped silently.
+pub fn import(json: &str, password: Option<&str>) -> Result<Vec<OtpAuthUri>, OtpError> {
+    let backup: Backup = serde_json::from_str(json).map_err(|e| import_error(&e.to_string()))?;
+    let services = match backup.services_encrypted.filter(|encrypted| !encrypted.is_empty()) {
+        None => backup.services,
+        Some(encrypted) => {
+            let password = password.ok_or_else(|| import_error("backup is password protected"))?;
+            let services = Zeroizing::new(decrypt(&encrypted, password)?);
+            serde_json::from_slice(&services).map_err(|e| import_error(&e.to_string()))?
+        }
+    };
+    services.iter().map(import_service).collect()
+}
+
+fn decrypt(encrypted: &str, password: &str) -> Result<Vec<u8>, OtpError> {
+    let parts = encrypted
+        .split(':')
+        .map(base64::decode)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| import_error("encrypted services are not valid Base 64"))?;
+    let [cipher_text, salt, nonce] = &parts[..] else {
+        return Err(import_error("encrypted services should have three parts"));
+    };
+    if nonce.len() != 12 {
+        return Err(import_error("invalid nonce"));
+    }
+    let mut key = Zeroizing::new([0; 32]);
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, PBKDF2_ITERATIONS, &mut key[..]);
+    let cipher = Aes256Gcm::new_from_slice(&key[..]).expect("key has 32 bytes");
+    cipher
+        .decrypt(Nonce::from_slice(nonce), &cipher_text[..])
+        .map_err(|_| import_error("decryption failed, the password may be wrong"))
+}
+
+fn import_service(service: &Service) -> Result<OtpAuthUri, OtpError> {
+    let otp = &service.otp;
+    let otp_type = match otp.token_type.as_deref().unwrap_or("TOTP") {
+        "TOTP" => OtpType::Totp,
+        "HOTP" => OtpType::Hotp,
+        other => return Err(import_error(&format!("unsupported token type {}", other)))
+    };
+    let account = [&otp.account, &otp.label].into_iter().find(|account| !account.is_empty());
+    let issuer = [&otp.issuer, &service.name].into_iter().find(|issuer| !issuer.is_empty());
+    let algorithm = otp.algorithm.as_deref().unwrap_or("SHA1");
+    Ok(OtpAuthUri {
+        otp_type,
+        account: account.cloned().unwrap_or_default(),
+        issuer: issuer.cloned(),
+        secret: Secret::from_base32(&service.secret).map_err(|_| import_error("secret is not valid Base 32"))?,
+        algorithm: Algorithm::from_name(algorithm).ok_or_else(|| import_error("unsupported algorithm"))?,
+        digits: otp.digits.unwrap_or(6),
+        period: if otp_type == OtpType::Totp { otp.period.unwrap_or(30) } else { 30 },
+        counter: if otp_type == OtpType::Hotp { otp.counter.unwrap_or(0) } else { 0 },
+    })
+}
+
+fn import_error(msg: &str) -> OtpError {
+    OtpError::ImportError(format!("2FAS: {}", msg))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::base64;
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+    use crate::secret::Secret;
+    use crate::uri::{OtpAuthUri, OtpType};
+
+    use super::import;
+
+    // encrypted with the password "test", the salt 0, 1, ..., 255 and the
+    // nonce 0, 1, ..., 11
+    const CIPHER_TEXT: &str = concat!(
+        "JyJYqn/J0Ai9qLV/t61QLchgDEXh3Uh85T7zPVe31S9E5D7Jt7pTrgV/kPDeIFvULqi0U8Jb9g40ADgr1Y0qp+oB6on7zMEp",
+        "WsKOoWxza1OpMg6Eo0SqA0j8evEzZhiyjPaN4xEL9RVjqF9pCw8h8YcBskKeC3+akPi7NwxHQWR4q+5JN8MXQ33X6Brl7lBR",
+        "4Bc+eCqarMOqiNfkKIxN1BrER2NrQNHcT4cBpDH0c4I6WoL//xZjf0eJ7puEj7twNhJ1Fm546NllkinLIqBScfd79fz42PQq",
+        "62d33K9vwg9z/zRF1iU9EP17KlRUd7S+4r6Mh6xqENLhjqghXvouiFoJGpKYvB3tmMBBcsBpaODF24IbJyU1Of2XAySvIxhm",
+        "Pc+iSWkeAuiBBwm2eLSCxEWJ2/Asoj++ZMyEB5dlnubLXGoKWS8uH9Mnjwpen+QVpHPz7OCaeUaJ9LfrlbWbOuCsTvYOpS66",
+        "5YyY69KKmeBlZAyNv0XZuiYWLObwclojQLR4PrFH+LKjlj0a046vlLbVlmNdIqEtB6Xt/jZlu/1ib1B0D2Ocl6iwPI7DU09k",
+        "4p5jjt2Ts9MDbhgzS80ZyFxhvvvp32zqd7UH5Wq1PzshIp/HjZK076A1DxVzm8eTa1YMoCrJ9zUgUTW0opb0UA==",
+    );
+
+    fn accounts() -> Vec<OtpAuthUri> {
+        vec![
+            OtpAuthUri {
+                otp_type: OtpType::Totp,
+                account: "alice@example.com".into(),
+                issuer: Some("Example".into()),
+                secret: Secret::from_base32("JBSWY3DPEHPK3PXP").unwrap(),
+                algorithm: Algorithm::Sha256,
+                digits: 8,
+                period: 60,
+                counter: 0,
+            },
+            OtpAuthUri {
+                otp_type: OtpType::Hotp,
+                account: "bob".into(),
+                issuer: Some("bob service".into()),
+                secret: Secret::new(b"12345678901234567890".to_vec()),
+                algorithm: Algorithm::Sha1,
+                digits: 6,
+                period: 30,
+                counter: 5,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_import_encrypted() {
+        let salt = base64::encode(&(0..=255).collect::<Vec<u8>>());
+        let nonce = base64::encode(&(0..12).collect::<Vec<u8>>());
+        let json = format!(
+            r#"{{"services": [], "groups": [], "schemaVersion": 4, "servicesEncrypted": "{}:{}:{}"}}"#,
+            CIPHER_TEXT, salt, nonce
+        );
+        assert_eq!(import(&json, Some("test")).unwrap(), accounts());
+        assert!(matches!(import(&json, Some("wrong")), Err(OtpError::ImportError(_))));
+        assert!(matches!(import(&json, None), Err(OtpError::ImportError(_))));
+    }
+
+    #[test]
+    fn test_import() {
+        let json = r#"{"services": [{"name": "Steam", "secret": "JBSWY3DP", "otp": {"tokenType": "STEAM"}}]}"#;
+        assert!(matches!(import(json, None), Err(OtpError::ImportError(_))));
+        let json = r#"{"services": [], "servicesEncrypted": "AAAA:AAAA"}"#;
+        assert!(matches!(import(json, Some("test")), Err(OtpError::ImportError(_))));
+        assert_eq!(import(r#"{"services": []}"#, None).unwrap(), []);
+    }
+}
@@ -0,0 +1,658 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The `otpauth://` Key URI format that authenticators are provisioned
+//! with, usually through a QR code.
+//!
+//! ```
+//! use yotp_core::Algorithm;
+//! use yotp_core::uri::{OtpAuthUri, OtpType};
+//!
+//! let uri = OtpAuthUri::parse(
+//!     "otpauth://totp/ACME%20Co:john@example.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&digits=6&period=30"
+//! ).unwrap();
+//! assert_eq!(uri.otp_type, OtpType::Totp);
+//! assert_eq!(uri.issuer.as_deref(), Some("ACME Co"));
+//! assert_eq!(uri.account, "john@example.com");
+//! assert_eq!(uri.algorithm, Algorithm::Sha1);
+//! assert_eq!(
+//!     uri.to_string(),
+//!     "otpauth://totp/ACME%20Co:john@example.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co"
+//! );
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::clock::Clock;
+use crate::error::OtpError;
+#[cfg(feature = "std")]
+use crate::hotp::Hotp;
+use crate::otp::Algorithm;
+use crate::secret::Secret;
+use crate::totp::Totp;
+
+/// The kind of OTP an `otpauth://` URI provisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OtpType {
+    /// Time-based, `otpauth://totp/...`.
+    Totp,
+    /// Counter-based, `otpauth://hotp/...`.
+    Hotp,
+}
+
+/// Errors of [`OtpAuthUri::parse`]. Offsets are in bytes from the start
+/// of the URI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UriParseError {
+    /// The URI does not start with `otpauth://`.
+    InvalidScheme,
+    /// The type is neither `totp` nor `hotp`.
+    InvalidType,
+    /// The `secret` parameter is absent or empty.
+    MissingSecret,
+    /// The `secret` parameter at `index` is not valid Base 32.
+    InvalidSecret { index: usize },
+    /// The `algorithm` parameter at `index` names an unsupported hash.
+    UnsupportedAlgorithm { index: usize },
+    /// The issuer of the label prefix differs from the `issuer` parameter.
+    ConflictingIssuers,
+    /// A HOTP URI has no `counter` parameter.
+    MissingCounter,
+    /// The parameter at `index` has no value, or a value out of range.
+    MalformedParameter { index: usize },
+    /// The percent escape at `index` is invalid or does not decode to
+    /// UTF-8.
+    InvalidEscape { index: usize },
+}
+
+impl fmt::Display for UriParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UriParseError::InvalidScheme => write!(f, "URI scheme should be otpauth"),
+            UriParseError::InvalidType => write!(f, "OTP type should be totp or hotp"),
+            UriParseError::MissingSecret => write!(f, "URI has no secret"),
+            UriParseError::InvalidSecret { index } => write!(f, "invalid Base 32 secret at offset {}", index),
+            UriParseError::UnsupportedAlgorithm { index } => write!(f, "unsupported algorithm at offset {}", index),
+            UriParseError::ConflictingIssuers => write!(f, "issuer of the label differs from the issuer parameter"),
+            UriParseError::MissingCounter => write!(f, "HOTP URI has no counter"),
+            UriParseError::MalformedParameter { index } => write!(f, "malformed parameter at offset {}", index),
+            UriParseError::InvalidEscape { index } => write!(f, "invalid percent escape at offset {}", index),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UriParseError {}
+
+impl From<UriParseError> for OtpError {
+    fn from(e: UriParseError) -> OtpError {
+        match e {
+            UriParseError::InvalidSecret { .. } => OtpError::DecodeError,
+            _ => OtpError::InvalidUri
+        }
+    }
+}
+
+/// The account configuration carried by an `otpauth://` URI. Parameters
+/// absent from the URI take the defaults of the Key URI format: SHA-1,
+/// 6 digits, 30 seconds period and counter 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpAuthUri {
+    pub otp_type: OtpType,
+    /// The account name of the label, without the issuer prefix.
+    pub account: String,
+    /// The provider of the account, from the `issuer` parameter or the
+    /// prefix of the label. Empty issuers are `None`.
+    pub issuer: Option<String>,
+    pub secret: Secret,
+    pub algorithm: Algorithm,
+    pub digits: usize,
+    /// The period of TOTP in seconds.
+    pub period: u64,
+    /// The initial counter of HOTP.
+    pub counter: u64,
+}
+
+impl OtpAuthUri {
+    /// Parse an `otpauth://` URI, percent-decoding its label and
+    /// parameters. The scheme, the type and the algorithm are
+    /// case-insensitive and unknown parameters, such as `image`, are
+    /// ignored.
+    ///
+    /// Following the Key URI format, the issuer may be given as the
+    /// prefix of the label before a `:` (or `%3A`), as the `issuer`
+    /// parameter, or both, in which case they must be equal. `counter` is
+    /// required by HOTP URIs, and `period` and `counter` are ignored by
+    /// the type they do not apply to.
+    ///
+    /// The byte offsets in the returned [`UriParseError`] point into
+    /// `uri`.
+    pub fn parse(uri: &str) -> Result<OtpAuthUri, UriParseError> {
+        const SCHEME: &str = "otpauth://";
+        let rest = strip_prefix_ignore_case(uri, SCHEME).ok_or(UriParseError::InvalidScheme)?;
+        let (otp_type, rest) = rest.split_once('/').ok_or(UriParseError::InvalidType)?;
+        let label_start = SCHEME.len() + otp_type.len() + 1;
+        let otp_type = match otp_type.to_ascii_lowercase().as_str() {
+            "totp" => OtpType::Totp,
+            "hotp" => OtpType::Hotp,
+            _ => return Err(UriParseError::InvalidType)
+        };
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (prefix, account) = split_label(label);
+        let prefix = match prefix {
+            Some(prefix) => Some(percent_decode(prefix, false).map_err(|i| UriParseError::InvalidEscape { index: label_start + i })?),
+            None => None
+        };
+        let account_start = label_start + label.len() - account.len();
+        let account = percent_decode(account, false).map_err(|i| UriParseError::InvalidEscape { index: account_start + i })?;
+
+        let mut parsed = OtpAuthUri {
+            otp_type,
+            account: account.trim().into(),
+            issuer: None,
+            secret: Secret::default(),
+            algorithm: Algorithm::Sha1,
+            digits: 6,
+            period: 30,
+            counter: 0,
+        };
+        let mut has_secret = false;
+        let mut has_counter = false;
+        let mut index = label_start + label.len() + 1;
+        for param in query.split('&') {
+            let param_start = index;
+            index += param.len() + 1;
+            if param.is_empty() {
+                continue;
+            }
+            let malformed = UriParseError::MalformedParameter { index: param_start };
+            let (name, value) = param.split_once('=').ok_or(malformed.clone())?;
+            let value_start = param_start + name.len() + 1;
+            let value = zeroize::Zeroizing::new(
+                percent_decode(value, true).map_err(|i| UriParseError::InvalidEscape { index: value_start + i })?
+            );
+            match name {
+                "secret" => {
+                    parsed.secret = Secret::from_base32(&value).map_err(|_| UriParseError::InvalidSecret { index: value_start })?;
+                    has_secret = true;
+                }
+                "issuer" => parsed.issuer = Some(String::clone(&value)),
+                "algorithm" => {
+                    parsed.algorithm = Algorithm::from_name(&value).ok_or(UriParseError::UnsupportedAlgorithm { index: value_start })?;
+                }
+                "digits" => match value.parse() {
+                    Ok(digits) if crate::otp::DIGITS_RANGE.contains(&digits) => parsed.digits = digits,
+                    _ => return Err(malformed)
+                },
+                "period" => match value.parse() {
+                    Ok(period) if period > 0 => parsed.period = period,
+                    _ if otp_type == OtpType::Hotp => {}
+                    _ => return Err(malformed)
+                },
+                "counter" => {
+                    parsed.counter = value.parse().map_err(|_| malformed)?;
+                    has_counter = true;
+                }
+                _ => {}
+            }
+        }
+        parsed.issuer = resolve_issuer(prefix.as_deref(), parsed.issuer.as_deref())?;
+        match otp_type {
+            OtpType::Totp => parsed.counter = 0,
+            OtpType::Hotp => parsed.period = 30,
+        }
+        if !has_secret || parsed.secret.is_empty() {
+            return Err(UriParseError::MissingSecret);
+        }
+        if otp_type == OtpType::Hotp && !has_counter {
+            return Err(UriParseError::MissingCounter);
+        }
+        Ok(parsed)
+    }
+
+    /// The URI of a TOTP account. `t0` of `totp` is not part of the Key
+    /// URI format and is lost.
+    pub fn from_totp<C: Clock>(totp: &Totp<C>, account: &str, issuer: Option<&str>) -> OtpAuthUri {
+        OtpAuthUri {
+            otp_type: OtpType::Totp,
+            account: account.into(),
+            issuer: issuer.map(String::from),
+            secret: Secret::new(totp.secret().to_vec()),
+            algorithm: totp.algorithm(),
+            digits: totp.digits(),
+            period: totp.period(),
+            counter: 0,
+        }
+    }
+
+    /// The URI of a HOTP account, starting at the current counter of
+    /// `hotp`.
+    #[cfg(feature = "std")]
+    pub fn from_hotp(hotp: &Hotp, account: &str, issuer: Option<&str>) -> OtpAuthUri {
+        OtpAuthUri {
+            otp_type: OtpType::Hotp,
+            account: account.into(),
+            issuer: issuer.map(String::from),
+            secret: Secret::new(hotp.secret().to_vec()),
+            algorithm: hotp.algorithm(),
+            digits: hotp.digits(),
+            period: 30,
+            counter: hotp.counter(),
+        }
+    }
+
+    /// Build the [`Totp`] of the account, regardless of the type of the
+    /// URI.
+    #[cfg(feature = "std")]
+    pub fn to_totp(&self) -> Result<Totp, OtpError> {
+        Totp::builder()
+            .secret(&self.secret)
+            .algorithm(self.algorithm)
+            .digits(self.digits)
+            .period(self.period)
+            .build()
+    }
+
+    /// Build the [`Hotp`] of the account starting at its counter,
+    /// regardless of the type of the URI.
+    #[cfg(feature = "std")]
+    pub fn to_hotp(&self) -> Result<Hotp, OtpError> {
+        Hotp::builder()
+            .secret(&self.secret)
+            .algorithm(self.algorithm)
+            .digits(self.digits)
+            .counter(self.counter)
+            .build()
+    }
+}
+
+impl FromStr for OtpAuthUri {
+    type Err = UriParseError;
+
+    fn from_str(uri: &str) -> Result<OtpAuthUri, UriParseError> {
+        OtpAuthUri::parse(uri)
+    }
+}
+
+/// The accounts of a URI list read by [`parse_list`], and the errors of
+/// the lines that failed.
+#[derive(Debug, Default)]
+pub struct UriList {
+    pub accounts: Vec<OtpAuthUri>,
+    /// The failed lines, counting from 1, with their errors.
+    pub errors: Vec<(usize, OtpError)>,
+}
+
+/// Read a text with a URI per line, the plain text export of several
+/// authenticators. Besides `otpauth://` URIs, lines may hold the
+/// `otpauth-migration://` URIs of Google Authenticator, see
+/// [`crate::migration`]. Empty lines and lines starting with `#` are
+/// skipped.
+///
+/// A line that fails to parse does not stop the import, its error is
+/// reported in [`UriList::errors`] instead.
+///
+/// ```
+/// use yotp_core::uri;
+///
+/// let list = uri::parse_list("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP\n\n# old\notpauth://totp/bob\n");
+/// assert_eq!(list.accounts[0].account, "alice");
+/// assert_eq!(list.errors[0].0, 4);
+/// ```
+pub fn parse_list(text: &str) -> UriList {
+    let mut list = UriList::default();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = if strip_prefix_ignore_case(line, "otpauth-migration://").is_some() {
+            crate::migration::decode(line).map(|batch| batch.accounts)
+        } else {
+            OtpAuthUri::parse(line).map(|uri| alloc::vec![uri]).map_err(OtpError::from)
+        };
+        match parsed {
+            Ok(accounts) => list.accounts.extend(accounts),
+            Err(e) => list.errors.push((i + 1, e)),
+        }
+    }
+    list
+}
+
+/// Formats the URI with the secret in unpadded Base 32. The label and the
+/// issuer are percent-encoded, and only the parameters other than the
+/// defaults are emitted, except `counter`, which HOTP URIs require.
+///
+/// The output is normalized: parsing it gives back the same
+/// [`OtpAuthUri`] whenever the issuer and the account have no leading or
+/// trailing spaces, the account has no `:` and the issuer is not empty,
+/// which holds for any value returned by [`OtpAuthUri::parse`].
+impl fmt::Display for OtpAuthUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let otp_type = match self.otp_type {
+            OtpType::Totp => "totp",
+            OtpType::Hotp => "hotp",
+        };
+        write!(f, "otpauth://{}/", otp_type)?;
+        match &self.issuer {
+            Some(issuer) => {
+                percent_encode(f, issuer)?;
+                f.write_str(":")?;
+            }
+            // an empty prefix, so that the `%3A` of the account does not
+            // separate an issuer when parsed
+            None if self.account.contains(':') => f.write_str(":")?,
+            None => {}
+        }
+        percent_encode(f, &self.account)?;
+        let secret = zeroize::Zeroizing::new(self.secret.to_base32());
+        write!(f, "?secret={}", secret.as_str())?;
+        if let Some(issuer) = &self.issuer {
+            f.write_str("&issuer=")?;
+            percent_encode(f, issuer)?;
+        }
+        if self.algorithm != Algorithm::Sha1 {
+            write!(f, "&algorithm={}", self.algorithm.name())?;
+        }
+        if self.digits != 6 {
+            write!(f, "&digits={}", self.digits)?;
+        }
+        match self.otp_type {
+            OtpType::Totp if self.period != 30 => write!(f, "&period={}", self.period),
+            OtpType::Totp => Ok(()),
+            OtpType::Hotp => write!(f, "&counter={}", self.counter),
+        }
+    }
+}
+
+/// Write `value` with every byte other than the unreserved characters of
+/// RFC 3986 and `@` escaped as `%XX`.
+fn percent_encode(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    for &b in value.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~@".contains(&b) {
+            write!(f, "{}", b as char)?;
+        } else {
+            write!(f, "%{}{}", HEX[(b >> 4) as usize] as char, HEX[(b & 0xF) as usize] as char)?;
+        }
+    }
+    Ok(())
+}
+
+/// Split the raw label at the separator of the issuer prefix, a literal
+/// `:` or else the first `%3A`.
+fn split_label(label: &str) -> (Option<&str>, &str) {
+    if let Some((prefix, account)) = label.split_once(':') {
+        return (Some(prefix), account);
+    }
+    let lower = label.to_ascii_lowercase();
+    match lower.find("%3a") {
+        Some(i) => (Some(&label[..i]), &label[i + 3..]),
+        None => (None, label)
+    }
+}
+
+/// Merge the issuer of the label prefix with the `issuer` parameter,
+/// which must agree if both are present.
+fn resolve_issuer(prefix: Option<&str>, param: Option<&str>) -> Result<Option<String>, UriParseError> {
+    let prefix = prefix.map(str::trim).filter(|prefix| !prefix.is_empty());
+    let param = param.map(str::trim).filter(|param| !param.is_empty());
+    match (prefix, param) {
+        (Some(prefix), Some(param)) if prefix != param => Err(UriParseError::ConflictingIssuers),
+        (prefix, param) => Ok(param.or(prefix).map(String::from))
+    }
+}
+
+pub(crate) fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = value.get(..prefix.len())?;
+    if head.eq_ignore_ascii_case(prefix) {
+        Some(&value[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+/// Decode the `%XX` escapes of `value`, and `+` as a space if `plus` is
+/// set, as in the query of a URI. Returns the offset of the invalid
+/// escape on failure.
+pub(crate) fn percent_decode(value: &str, plus: bool) -> Result<String, usize> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3).ok_or(i)?;
+                let high = hex_value(hex[0]).ok_or(i)?;
+                let low = hex_value(hex[1]).ok_or(i)?;
+                out.push(high << 4 | low);
+                i += 3;
+            }
+            b'+' if plus => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).map_err(|e| {
+        // map the offset in the decoded bytes back to the escape in `value`
+        let mut decoded = 0;
+        let mut i = 0;
+        while decoded < e.utf8_error().valid_up_to() {
+            i += if bytes[i] == b'%' { 3 } else { 1 };
+            decoded += 1;
+        }
+        i
+    })
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::OtpError;
+    use crate::otp::Algorithm;
+
+    use crate::hotp::Hotp;
+    use crate::secret::Secret;
+    use crate::totp::Totp;
+
+    use super::{parse_list, percent_decode, OtpAuthUri, OtpType, UriParseError};
+
+    #[test]
+    fn test_parse_totp() {
+        let uri = OtpAuthUri::parse(
+            "otpauth://totp/Example:alice@google.com?secret=JBSWY3DPEHPK3PXP&issuer=Example&algorithm=SHA256&digits=8&period=60"
+        ).unwrap();
+        assert_eq!(uri.otp_type, OtpType::Totp);
+        assert_eq!(uri.account, "alice@google.com");
+        assert_eq!(uri.issuer.as_deref(), Some("Example"));
+        assert_eq!(uri.secret.as_bytes(), b"Hello!\xde\xad\xbe\xef");
+        assert_eq!(uri.algorithm, Algorithm::Sha256);
+        assert_eq!(uri.digits, 8);
+        assert_eq!(uri.period, 60);
+        assert_eq!(uri.to_totp().unwrap().period(), 60);
+    }
+
+    #[test]
+    fn test_parse_hotp() {
+        let uri = OtpAuthUri::parse("OTPAUTH://HOTP/alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=1&period=0").unwrap();
+        assert_eq!(uri.otp_type, OtpType::Hotp);
+        assert_eq!(uri.account, "alice");
+        assert_eq!(uri.issuer, None);
+        assert_eq!(uri.counter, 1);
+        assert_eq!(uri.period, 30);
+        assert_eq!(uri.to_hotp().unwrap().next().unwrap(), "287082");
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let uri = OtpAuthUri::parse("otpauth://totp/ACME%20Co:%20john?image=x&secret=jbswy3dpehpk3pxp").unwrap();
+        assert_eq!(uri.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(uri.account, "john");
+        assert_eq!(uri.algorithm, Algorithm::Sha1);
+        assert_eq!(uri.digits, 6);
+        assert_eq!(uri.period, 30);
+        assert_eq!(uri.counter, 0);
+    }
+
+    #[test]
+    fn test_parse_issuer() {
+        let parse = |uri: &str| OtpAuthUri::parse(uri).map(|uri| (uri.issuer, uri.account));
+        let expected = Ok((Some("ACME Co".into()), "john".into()));
+        assert_eq!(parse("otpauth://totp/ACME%20Co:john?secret=JBSWY3DP"), expected);
+        assert_eq!(parse("otpauth://totp/ACME%20Co%3Ajohn?secret=JBSWY3DP"), expected);
+        assert_eq!(parse("otpauth://totp/john?secret=JBSWY3DP&issuer=ACME+Co"), expected);
+        assert_eq!(parse("otpauth://totp/ACME%20Co:john?secret=JBSWY3DP&issuer=ACME%20Co"), expected);
+        assert_eq!(parse("otpauth://totp/:john?secret=JBSWY3DP&issuer="), Ok((None, "john".into())));
+        assert_eq!(parse("otpauth://totp/ACME:john?secret=JBSWY3DP&issuer=Other"), Err(UriParseError::ConflictingIssuers));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let parse = OtpAuthUri::parse;
+        assert_eq!(parse("https://totp/a?secret=JBSWY3DP"), Err(UriParseError::InvalidScheme));
+        assert_eq!(parse("otpauth://motp/a?secret=JBSWY3DP"), Err(UriParseError::InvalidType));
+        assert_eq!(parse("otpauth://totp"), Err(UriParseError::InvalidType));
+        assert_eq!(parse("otpauth://totp/a?issuer=x"), Err(UriParseError::MissingSecret));
+        assert_eq!(parse("otpauth://hotp/a?secret=JBSWY3DP"), Err(UriParseError::MissingCounter));
+        assert_eq!(parse("otpauth://totp/a?secret=JBSWY3D1"), Err(UriParseError::InvalidSecret { index: 24 }));
+        assert_eq!(parse("otpauth://totp/a?secret=JBSWY3DP&algorithm=MD5"), Err(UriParseError::UnsupportedAlgorithm { index: 43 }));
+        assert_eq!(parse("otpauth://totp/a?secret=JBSWY3DP&digits=4"), Err(UriParseError::MalformedParameter { index: 33 }));
+        assert_eq!(parse("otpauth://totp/a?secret=JBSWY3DP&period=0"), Err(UriParseError::MalformedParameter { index: 33 }));
+        assert_eq!(parse("otpauth://totp/a?secret=JBSWY3DP&&counter"), Err(UriParseError::MalformedParameter { index: 34 }));
+        assert_eq!(parse("otpauth://totp/a%2?secret=JBSWY3DP"), Err(UriParseError::InvalidEscape { index: 16 }));
+        assert_eq!(parse("otpauth://totp/a?secret=JBSWY3DP&issuer=x%FF"), Err(UriParseError::InvalidEscape { index: 41 }));
+        assert_eq!(OtpError::from(UriParseError::InvalidSecret { index: 24 }), OtpError::DecodeError);
+        assert_eq!(OtpError::from(UriParseError::MissingSecret), OtpError::InvalidUri);
+        assert_eq!(UriParseError::InvalidSecret { index: 24 }.to_string(), "invalid Base 32 secret at offset 24");
+    }
+
+    #[test]
+    fn test_from_totp() {
+        let totp = Totp::builder().secret(b"Hello!\xde\xad\xbe\xef").build().unwrap();
+        let uri = OtpAuthUri::from_totp(&totp, "alice@google.com", Some("Example"));
+        assert_eq!(uri.to_string(), "otpauth://totp/Example:alice@google.com?secret=JBSWY3DPEHPK3PXP&issuer=Example");
+        let totp = Totp::builder()
+            .secret(b"Hello!\xde\xad\xbe\xef")
+            .algorithm(Algorithm::Sha512)
+            .digits(8)
+            .period(60)
+            .build()
+            .unwrap();
+        let uri = OtpAuthUri::from_totp(&totp, "a b/c", None);
+        assert_eq!(
+            uri.to_string(),
+            "otpauth://totp/a%20b%2Fc?secret=JBSWY3DPEHPK3PXP&algorithm=SHA512&digits=8&period=60"
+        );
+        assert_eq!(OtpAuthUri::parse(&uri.to_string()).unwrap(), uri);
+    }
+
+    #[test]
+    fn test_from_hotp() {
+        let mut hotp = Hotp::builder().secret(b"Hello!\xde\xad\xbe\xef").build().unwrap();
+        let uri = OtpAuthUri::from_hotp(&hotp, "bob", Some("ACME Co"));
+        assert_eq!(uri.to_string(), "otpauth://hotp/ACME%20Co:bob?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co&counter=0");
+        hotp.set_counter(5);
+        assert_eq!(OtpAuthUri::from_hotp(&hotp, "bob", None).to_string(), "otpauth://hotp/bob?secret=JBSWY3DPEHPK3PXP&counter=5");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // xorshift, to cover many configurations reproducibly
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let mut next = move |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+        const CHARS: &[char] = &['a', 'Z', '0', ' ', '@', '%', '&', '=', '?', '+', '/', '#', ':', '\u{e9}', '\u{2713}'];
+        let text = |next: &mut dyn FnMut(u64) -> u64| -> String {
+            let len = next(8) as usize;
+            let text: String = (0..len).map(|_| CHARS[next(CHARS.len() as u64) as usize]).collect();
+            text.trim().into()
+        };
+        for _ in 0..500 {
+            let otp_type = if next(2) == 0 { OtpType::Totp } else { OtpType::Hotp };
+            let secret_len = 1 + next(40) as usize;
+            let issuer = text(&mut next);
+            let uri = OtpAuthUri {
+                otp_type,
+                account: text(&mut next),
+                issuer: Some(issuer).filter(|issuer| !issuer.is_empty()),
+                secret: Secret::new((0..secret_len).map(|_| next(256) as u8).collect()),
+                algorithm: [Algorithm::Sha1, Algorithm::Sha256, Algorithm::Sha512][next(3) as usize],
+                digits: 6 + next(5) as usize,
+                period: if otp_type == OtpType::Totp { 1 + next(120) } else { 30 },
+                counter: if otp_type == OtpType::Hotp { next(u64::MAX) } else { 0 },
+            };
+            let encoded = uri.to_string();
+            let parsed: OtpAuthUri = encoded.parse().unwrap();
+            assert_eq!(parsed, uri, "{}", encoded);
+            assert_eq!(parsed.to_string(), encoded);
+        }
+        let uri = OtpAuthUri::parse("otpauth://totp/:a:b?secret=JBSWY3DP").unwrap();
+        assert_eq!((uri.issuer.as_deref(), uri.account.as_str()), (None, "a:b"));
+        assert_eq!(uri.to_string(), "otpauth://totp/:a%3Ab?secret=JBSWY3DP");
+    }
+
+    #[test]
+    fn test_normalized() {
+        let uri = OtpAuthUri::parse("otpauth://TOTP/%20Example%20:%20alice?issuer=Example&period=30&secret=jbsw%20y3dp%3D%3D%3D&counter=3").unwrap();
+        assert_eq!(uri.to_string(), "otpauth://totp/Example:alice?secret=JBSWY3DP&issuer=Example");
+        assert_eq!(OtpAuthUri::parse(&uri.to_string()).unwrap(), uri);
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let text = "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP\r\n\
+                    \n\
+                    # comment\n\
+                    otpauth://totp/bob?secret=!\n\
+                    https://example.com\n  \
+                    otpauth://hotp/carol?secret=JBSWY3DPEHPK3PXP&counter=1  \n\
+                    otpauth-migration://offline?data=CjEKCkhlbGxvId6tvu8SGEV4YW1wbGU6YWxpY2VAZ29vZ2xlLmNvbRoHRXhhbXBsZSABKAEwAhABGAEgACgA\n";
+        let list = parse_list(text);
+        let accounts: Vec<&str> = list.accounts.iter().map(|uri| uri.account.as_str()).collect();
+        assert_eq!(accounts, ["alice", "carol", "alice@google.com"]);
+        assert_eq!(list.errors, [(4, OtpError::DecodeError), (5, OtpError::InvalidUri)]);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b+c", false).unwrap(), "a b+c");
+        assert_eq!(percent_decode("a%20b+c", true).unwrap(), "a b c");
+        assert_eq!(percent_decode("%E2%9C%93", false).unwrap(), "\u{2713}");
+        assert_eq!(percent_decode("ab%FF", false), Err(2));
+        assert_eq!(percent_decode("%zz", false), Err(0));
+    }
+}
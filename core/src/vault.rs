@@ -0,0 +1,1571 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! An encrypted file of accounts, unlocked with a passphrase, and
+//! optionally a key file.
+//!
+//! The accounts are encrypted with AES-256-GCM under a key derived from
+//! the passphrase with Argon2id. The key is derived once when the vault is
+//! opened, and every save encrypts with a fresh random nonce. Saves write
+//! a temporary file next to the vault and rename it over the vault, so
+//! that a crash leaves either the old or the new vault, never a partial
+//! one.
+//!
+//! With a [`KeyFile`], the vault opens only with both the passphrase and
+//! the key file, as with the composite keys of KeePass: the vault file
+//! alone is not enough even if the passphrase is weak. Argon2id then
+//! hashes `SHA-256(passphrase) || SHA-256(key file)` instead of the
+//! passphrase. The vault does not record whether it needs a key file, and
+//! a missing or wrong one is reported as a wrong passphrase.
+//!
+//! The whole file is authenticated, so that a truncated or modified vault
+//! is reported as [`VaultError::Tampered`] rather than opened with garbage
+//! accounts. A modified header changes the derived key, which is reported
+//! as [`VaultError::WrongPassphrase`].
+//!
+//! # File format
+//!
+//! All integers are big endian.
+//!
+//! | Offset | Size | Field                                             |
+//! |--------|------|---------------------------------------------------|
+//! | 0      | 8    | magic `YOTPVLT\0`                                 |
+//! | 8      | 2    | format version, 2                                 |
+//! | 10     | 4    | Argon2id memory cost in KiB                       |
+//! | 14     | 4    | Argon2id iterations                               |
+//! | 18     | 4    | Argon2id parallelism                              |
+//! | 22     | 16   | Argon2id salt                                     |
+//! | 38     | 12   | AES-256-GCM nonce                                 |
+//! | 50     | 32   | key check value                                   |
+//! | 82     |      | AES-256-GCM cipher text of the payload, and tag   |
+//! | end-32 | 32   | HMAC-SHA-256 of all the bytes before              |
+//!
+//! Argon2id derives the 32-byte vault key. The encryption key, the MAC
+//! key and the key check value are HMAC-SHA-256 of the vault key with the
+//! labels `yOTP vault encryption`, `yOTP vault MAC` and `yOTP vault key
+//! check`. The header, the first 82 bytes, is the associated data of
+//! AES-256-GCM.
+//!
+//! The payload is a JSON object with the schema `version` of the payload
+//! and the `accounts`, objects whose `uri` is the `otpauth://` URI of the
+//! account, see [`OtpAuthUri`], along with the metadata of the
+//! [`Account`], if any: the `label`, `notes` and `icon` strings, the
+//! `tags` array and the `last_used` UNIX time.
+//!
+//! ```json
+//! {"version": 4, "accounts": [{"uri": "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP", "tags": ["work"]}]}
+//! ```
+//!
+//! # Backups
+//!
+//! Every save first copies the vault file into the directory of its name
+//! with `.backups` appended, e.g. `accounts.vault.backups`, as
+//! `<milliseconds since the UNIX epoch>.vault`. The last 5 backups are
+//! kept by default, see [`Vault::set_backups`], and [`Vault::restore`]
+//! brings the accounts of one back, e.g. after deleting an account by
+//! mistake. Backups are vault files encrypted with the key of the vault,
+//! and [`Vault::rekey`] encrypts them again with the new key.
+//!
+//! # Single accounts
+//!
+//! [`Account::export_encrypted`] encrypts one account like a vault, for
+//! someone else or another device, in a text block of Base 64 between
+//! `-----BEGIN YOTP ACCOUNT-----` and `-----END YOTP ACCOUNT-----`. The
+//! block is the magic `YOTPACC\0`, the version 1, the KDF parameters, the
+//! salt and the nonce as in the header of a vault of format version 1,
+//! followed by the AES-256-GCM cipher text of the payload of the account,
+//! with the header as the associated data.
+//!
+//! # Versions
+//!
+//! The format of the file and the schema of the payload have versions of
+//! their own. Vaults of older versions are upgraded when opened: the
+//! payload is migrated one schema version at a time, and the vault is
+//! saved in the current format and schema. Vaults of newer versions are
+//! rejected.
+//!
+//! Format version 1 has neither the key check value nor the MAC, and the
+//! cipher text is encrypted with the vault key without associated data.
+//! Schema version 1 has no `version`, and its `accounts` are the URIs
+//! themselves. Schema version 2 has no metadata, and schema version 3 no
+//! `last_used`.
+//!
+//! ```no_run
+//! use yotp_core::uri::OtpAuthUri;
+//! use yotp_core::vault::Vault;
+//!
+//! let mut vault = Vault::create("accounts.vault", "correct horse battery staple").unwrap();
+//! vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+//! vault.save().unwrap();
+//!
+//! let vault = Vault::open("accounts.vault", "correct horse battery staple").unwrap();
+//! assert_eq!(vault.accounts()[0].uri.account, "alice");
+//! ```
+//!
+//! With a key file:
+//!
+//! ```no_run
+//! use yotp_core::vault::{Credentials, KeyFile, Vault};
+//!
+//! let key_file = KeyFile::generate("accounts.key").unwrap();
+//! Vault::create("accounts.vault", Credentials::with_key_file("hunter2", &key_file)).unwrap();
+//!
+//! let key_file = KeyFile::read("accounts.key").unwrap();
+//! let vault = Vault::open("accounts.vault", Credentials::with_key_file("hunter2", &key_file)).unwrap();
+//! ```
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload as AeadPayload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::account::{Account, AccountQuery, QueryPage};
+use crate::error::OtpError;
+use crate::secret_store::SecretStore;
+use crate::shamir::Share;
+use crate::sync::{SyncBackend, SyncError};
+use crate::uri::{OtpAuthUri, OtpType};
+
+const MAGIC: &[u8; 8] = b"YOTPVLT\0";
+const VERSION: u16 = 2;
+const SCHEMA_VERSION: u32 = 4;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const CHECK_LEN: usize = 32;
+const MAC_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const KEY_FILE_LEN: usize = 32;
+const DEFAULT_BACKUPS: usize = 5;
+const ACCOUNT_MAGIC: &[u8; 8] = b"YOTPACC\0";
+const ACCOUNT_VERSION: u16 = 1;
+/// An exported account has the header of a vault of format version 1.
+const ACCOUNT_HEADER_LEN: usize = HEADER_LEN_V1;
+const ARMOR_BEGIN: &str = "-----BEGIN YOTP ACCOUNT-----";
+const ARMOR_END: &str = "-----END YOTP ACCOUNT-----";
+/// The offset of the nonce, after the magic, the version, the KDF
+/// parameters and the salt.
+const NONCE_OFFSET: usize = 8 + 2 + 12 + SALT_LEN;
+const HEADER_LEN_V1: usize = NONCE_OFFSET + NONCE_LEN;
+const HEADER_LEN: usize = HEADER_LEN_V1 + CHECK_LEN;
+
+/// Errors of [`Vault`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum VaultError {
+    /// Reading or writing the vault file failed.
+    Io(io::Error),
+    /// The file is not a vault, or its header is malformed.
+    InvalidFormat,
+    /// The vault has a format version this version of yOTP cannot read.
+    UnsupportedVersion(u16),
+    /// The payload of the vault has a schema version this version of yOTP
+    /// cannot read.
+    UnsupportedSchema(u32),
+    /// The passphrase, the key file or the age identities are wrong. For
+    /// vaults of format version 1, the vault may be corrupted instead.
+    WrongPassphrase,
+    /// The vault is truncated or modified.
+    Tampered,
+    /// The encryption backend failed, e.g. a plugin of age.
+    Backend(String),
+    /// The remote copy of the vault cannot be pulled or pushed.
+    Sync(SyncError),
+    /// An account of the vault is invalid, or the random source failed.
+    Otp(OtpError),
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultError::Io(e) => write!(f, "vault I/O error: {}", e),
+            VaultError::InvalidFormat => write!(f, "file is not a valid vault"),
+            VaultError::UnsupportedVersion(version) => write!(f, "unsupported vault format version {}", version),
+            VaultError::UnsupportedSchema(version) => write!(f, "unsupported vault schema version {}", version),
+            VaultError::WrongPassphrase => write!(f, "wrong passphrase or key file"),
+            VaultError::Tampered => write!(f, "vault is truncated or modified"),
+            VaultError::Backend(msg) => write!(f, "vault backend error: {}", msg),
+            VaultError::Sync(e) => write!(f, "vault sync error: {}", e),
+            VaultError::Otp(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for VaultError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VaultError::Io(e) => Some(e),
+            VaultError::Sync(e) => Some(e),
+            VaultError::Otp(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for VaultError {
+    fn from(e: io::Error) -> VaultError {
+        VaultError::Io(e)
+    }
+}
+
+impl From<SyncError> for VaultError {
+    fn from(e: SyncError) -> VaultError {
+        VaultError::Sync(e)
+    }
+}
+
+impl From<OtpError> for VaultError {
+    fn from(e: OtpError) -> VaultError {
+        VaultError::Otp(e)
+    }
+}
+
+impl From<VaultError> for OtpError {
+    fn from(e: VaultError) -> OtpError {
+        match e {
+            VaultError::Otp(e) => e,
+            e => OtpError::VaultError(e.to_string()),
+        }
+    }
+}
+
+/// The cost of the Argon2id key derivation, at most
+/// [`KdfParams::MAX_MEMORY_KIB`], [`KdfParams::MAX_ITERATIONS`] and
+/// [`KdfParams::MAX_PARALLELISM`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// The memory cost in KiB.
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// 64 MiB, 3 iterations and 1 lane, the second recommended option of
+    /// RFC 9106 for memory constrained environments.
+    fn default() -> Self {
+        KdfParams { memory_kib: 64 * 1024, iterations: 3, parallelism: 1 }
+    }
+}
+
+impl KdfParams {
+    /// The largest memory cost accepted, 1 GiB, as for the scrypt cost of
+    /// Aegis backups.
+    pub const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+    /// The largest number of iterations accepted.
+    pub const MAX_ITERATIONS: u32 = 16;
+    /// The largest number of lanes accepted.
+    pub const MAX_PARALLELISM: u32 = 64;
+
+    /// Derive the key of `credentials`. The parameters are read from the
+    /// header before it is authenticated, so costs beyond the maximums
+    /// are rejected as [`VaultError::InvalidFormat`] instead of letting a
+    /// crafted file exhaust the memory.
+    fn derive_key(&self, credentials: Credentials<'_>, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, VaultError> {
+        if self.memory_kib > KdfParams::MAX_MEMORY_KIB
+            || self.iterations > KdfParams::MAX_ITERATIONS
+            || self.parallelism > KdfParams::MAX_PARALLELISM
+        {
+            return Err(VaultError::InvalidFormat);
+        }
+        let params = argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|_| VaultError::InvalidFormat)?;
+        let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+        let mut key = Zeroizing::new([0; 32]);
+        let password = credentials.password();
+        argon2.hash_password_into(&password, salt, &mut key[..]).map_err(|_| VaultError::InvalidFormat)?;
+        Ok(key)
+    }
+}
+
+/// What unlocks a vault: a passphrase, and a key file if the vault was
+/// created or rekeyed with one. A passphrase alone converts into
+/// credentials.
+#[derive(Clone, Copy)]
+pub struct Credentials<'a> {
+    passphrase: &'a str,
+    key_file: Option<&'a KeyFile>,
+}
+
+impl<'a> Credentials<'a> {
+    pub fn new(passphrase: &'a str) -> Credentials<'a> {
+        Credentials { passphrase, key_file: None }
+    }
+
+    /// The composite of `passphrase` and `key_file`, both of which are
+    /// needed to open the vault.
+    pub fn with_key_file(passphrase: &'a str, key_file: &'a KeyFile) -> Credentials<'a> {
+        Credentials { passphrase, key_file: Some(key_file) }
+    }
+
+    /// The password Argon2id hashes.
+    fn password(&self) -> Zeroizing<Vec<u8>> {
+        match self.key_file {
+            None => Zeroizing::new(self.passphrase.as_bytes().to_vec()),
+            Some(key_file) => {
+                let mut password = Zeroizing::new(Sha256::digest(self.passphrase).to_vec());
+                password.extend_from_slice(&Sha256::digest(&key_file.data[..]));
+                password
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Credentials<'a> {
+    fn from(passphrase: &'a str) -> Credentials<'a> {
+        Credentials::new(passphrase)
+    }
+}
+
+/// The contents of a key file, the second factor of [`Credentials`].
+///
+/// Any file can serve as a key file, as all of its bytes are hashed, but
+/// [`KeyFile::generate`] makes one of random bytes. The key file must not
+/// change while it is in use, and should be kept apart from the vault.
+pub struct KeyFile {
+    data: Zeroizing<Vec<u8>>,
+}
+
+impl KeyFile {
+    /// Write a key file of 32 random bytes at `path`, readable by the
+    /// owner only.
+    ///
+    /// Returns [`VaultError::Io`] if `path` exists.
+    pub fn generate(path: impl AsRef<Path>) -> Result<KeyFile, VaultError> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists", path.display())).into());
+        }
+        let key_file = KeyFile::from_bytes(&random::<KEY_FILE_LEN>()?);
+        write_atomic(path, &key_file.data)?;
+        Ok(key_file)
+    }
+
+    /// Read the key file at `path`.
+    pub fn read(path: impl AsRef<Path>) -> Result<KeyFile, VaultError> {
+        Ok(KeyFile { data: Zeroizing::new(fs::read(path)?) })
+    }
+
+    /// A key file of `data`, e.g. kept somewhere else than in a file.
+    pub fn from_bytes(data: &[u8]) -> KeyFile {
+        KeyFile { data: Zeroizing::new(data.to_vec()) }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    version: u32,
+    accounts: Vec<AccountEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccountEntry {
+    uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_used: Option<u64>,
+}
+
+impl Drop for AccountEntry {
+    fn drop(&mut self) {
+        self.uri.zeroize();
+        self.label.zeroize();
+        self.tags.zeroize();
+        self.notes.zeroize();
+        self.icon.zeroize();
+    }
+}
+
+/// A payload of any schema version, wiped when dropped.
+struct RawPayload(Value);
+
+impl Drop for RawPayload {
+    fn drop(&mut self) {
+        fn wipe(value: &mut Value) {
+            match value {
+                Value::String(s) => s.zeroize(),
+                Value::Array(values) => values.iter_mut().for_each(wipe),
+                Value::Object(map) => map.values_mut().for_each(wipe),
+                _ => {}
+            }
+        }
+        wipe(&mut self.0);
+    }
+}
+
+/// An upgrade of the payload by one schema version.
+type Migration = fn(&mut Value) -> Result<(), VaultError>;
+
+/// The upgrades of the payload, where `MIGRATIONS[i]` upgrades schema
+/// version `i + 1` to `i + 2`. A change of the payload appends an upgrade
+/// here, bumps [`SCHEMA_VERSION`] and keeps a vault of the old schema in
+/// the test data.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize - 1] = [migrate_1_to_2, migrate_2_to_3, migrate_3_to_4];
+
+/// Schema 2 keeps the accounts as objects, with room for more than the
+/// URIs.
+fn migrate_1_to_2(payload: &mut Value) -> Result<(), VaultError> {
+    let accounts = payload.get_mut("accounts").and_then(Value::as_array_mut).ok_or(VaultError::InvalidFormat)?;
+    for account in accounts {
+        *account = json!({ "uri": account.take() });
+    }
+    Ok(())
+}
+
+/// Schema 3 adds the optional metadata of the accounts, so schema 2
+/// accounts are valid as they are. The new version keeps older versions
+/// of yOTP from opening the vault and dropping the metadata when saving.
+fn migrate_2_to_3(_payload: &mut Value) -> Result<(), VaultError> {
+    Ok(())
+}
+
+/// Schema 4 adds the optional time an account was last used, for the
+/// same reason.
+fn migrate_3_to_4(_payload: &mut Value) -> Result<(), VaultError> {
+    Ok(())
+}
+
+/// The payload of `accounts` in the current schema.
+pub(crate) fn encode_payload(accounts: &[Account]) -> Zeroizing<Vec<u8>> {
+    let accounts = accounts
+        .iter()
+        .map(|account| AccountEntry {
+            uri: account.uri.to_string(),
+            label: account.label.clone(),
+            tags: account.tags.clone(),
+            notes: account.notes.clone(),
+            icon: account.icon.clone(),
+            last_used: account.last_used,
+        })
+        .collect();
+    let payload = Payload { version: SCHEMA_VERSION, accounts };
+    Zeroizing::new(serde_json::to_vec(&payload).expect("payload is valid JSON"))
+}
+
+/// The accounts of the payload `data` of any schema, and whether the
+/// schema is older than the current one.
+pub(crate) fn decode_payload(data: &[u8]) -> Result<(Vec<Account>, bool), VaultError> {
+    let mut raw = RawPayload(serde_json::from_slice(data).map_err(|_| VaultError::InvalidFormat)?);
+    let migrated = migrate(&mut raw.0)?;
+    let payload = Payload::deserialize(&raw.0).map_err(|_| VaultError::InvalidFormat)?;
+    let accounts = payload
+        .accounts
+        .iter()
+        .map(|entry| {
+            Ok(Account {
+                uri: OtpAuthUri::parse(&entry.uri).map_err(OtpError::from)?,
+                label: entry.label.clone(),
+                tags: entry.tags.clone(),
+                notes: entry.notes.clone(),
+                icon: entry.icon.clone(),
+                last_used: entry.last_used,
+            })
+        })
+        .collect::<Result<_, VaultError>>()?;
+    Ok((accounts, migrated))
+}
+
+/// Upgrade `payload` to the current schema. Returns whether it was of an
+/// older schema.
+fn migrate(payload: &mut Value) -> Result<bool, VaultError> {
+    let object = payload.as_object_mut().ok_or(VaultError::InvalidFormat)?;
+    let version = match object.get("version") {
+        None => 1,
+        Some(version) => version.as_u64().and_then(|v| u32::try_from(v).ok()).ok_or(VaultError::InvalidFormat)?,
+    };
+    if version == 0 || version > SCHEMA_VERSION {
+        return Err(VaultError::UnsupportedSchema(version));
+    }
+    for (i, upgrade) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        upgrade(payload)?;
+        payload["version"] = json!(i + 2);
+    }
+    Ok(version < SCHEMA_VERSION)
+}
+
+/// An opened vault. Changes to the accounts are kept in memory until
+/// [`Vault::save`].
+pub struct Vault {
+    path: PathBuf,
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+    key: Zeroizing<[u8; 32]>,
+    accounts: Vec<Account>,
+    keep_backups: usize,
+}
+
+impl Vault {
+    /// Create an empty vault at `path` with the default [`KdfParams`].
+    ///
+    /// Returns [`VaultError::Io`] if `path` exists.
+    pub fn create<'a>(path: impl AsRef<Path>, credentials: impl Into<Credentials<'a>>) -> Result<Vault, VaultError> {
+        Vault::create_with_params(path, credentials, KdfParams::default())
+    }
+
+    /// Create an empty vault at `path` whose key is derived with `params`.
+    pub fn create_with_params<'a>(
+        path: impl AsRef<Path>,
+        credentials: impl Into<Credentials<'a>>,
+        params: KdfParams,
+    ) -> Result<Vault, VaultError> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists", path.display())).into());
+        }
+        let salt = random::<SALT_LEN>()?;
+        let key = params.derive_key(credentials.into(), &salt)?;
+        let vault = Vault { path: path.to_owned(), params, salt, key, accounts: Vec::new(), keep_backups: DEFAULT_BACKUPS };
+        vault.save()?;
+        Ok(vault)
+    }
+
+    /// Open the vault at `path` with `credentials`. A vault of an older
+    /// format or schema is upgraded and saved.
+    ///
+    /// Returns [`VaultError::WrongPassphrase`] if the vault does not
+    /// decrypt with `credentials`, and [`VaultError::Tampered`] if the vault
+    /// is truncated or modified.
+    pub fn open<'a>(path: impl AsRef<Path>, credentials: impl Into<Credentials<'a>>) -> Result<Vault, VaultError> {
+        let file = VaultFile::read(path.as_ref())?;
+        let key = file.params.derive_key(credentials.into(), &file.salt)?;
+        file.decrypt(key)
+    }
+
+    /// Open the vault at `path` with the key kept in `store` under `name`
+    /// by [`Vault::store_key`], without the passphrase.
+    ///
+    /// Returns [`VaultError::WrongPassphrase`] if `store` has no key under
+    /// `name` or the key does not decrypt the vault.
+    pub fn open_from_store(path: impl AsRef<Path>, store: &dyn SecretStore, name: &str) -> Result<Vault, VaultError> {
+        let file = VaultFile::read(path.as_ref())?;
+        let stored = store.get(name)?.ok_or(VaultError::WrongPassphrase)?;
+        let key: [u8; 32] = stored[..].try_into().map_err(|_| VaultError::WrongPassphrase)?;
+        file.decrypt(Zeroizing::new(key))
+    }
+
+    /// Keep the key of the vault in `store` under `name`, so that
+    /// [`Vault::open_from_store`] opens the vault without the passphrase.
+    ///
+    /// Whoever can read the key from `store` can decrypt the vault, so
+    /// `store` should be at least as protected as the passphrase.
+    pub fn store_key(&self, store: &dyn SecretStore, name: &str) -> Result<(), OtpError> {
+        store.set(name, &self.key[..])
+    }
+
+    /// Split the key of the vault into `count` shares, any `threshold` of
+    /// which open the vault with [`Vault::open_with_shares`], e.g. given to
+    /// trusted people for recovery. See [`shamir`](crate::shamir).
+    ///
+    /// The shares are of the current key, [`Vault::rekey`] makes them
+    /// useless.
+    pub fn split_key(&self, threshold: u8, count: u8) -> Result<Vec<Share>, VaultError> {
+        Ok(crate::shamir::split(&self.key[..], threshold, count)?)
+    }
+
+    /// Open the vault at `path` with shares of its key by
+    /// [`Vault::split_key`], without the passphrase.
+    ///
+    /// Returns [`VaultError::Otp`] if the shares do not recover a key, and
+    /// [`VaultError::WrongPassphrase`] if the key does not open the vault.
+    pub fn open_with_shares(path: impl AsRef<Path>, shares: &[Share]) -> Result<Vault, VaultError> {
+        let file = VaultFile::read(path.as_ref())?;
+        let key = crate::shamir::combine(shares)?;
+        let key: [u8; 32] = key[..].try_into().map_err(|_| VaultError::WrongPassphrase)?;
+        file.decrypt(Zeroizing::new(key))
+    }
+
+    /// The path of the vault file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The parameters the key of the vault is derived with.
+    pub fn kdf_params(&self) -> KdfParams {
+        self.params
+    }
+
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    pub fn accounts_mut(&mut self) -> &mut Vec<Account> {
+        &mut self.accounts
+    }
+
+    /// The page of the accounts `query` asks for.
+    pub fn query(&self, query: &AccountQuery) -> QueryPage<'_> {
+        query.run(&self.accounts)
+    }
+
+    /// Add the accounts of `other`, e.g. a copy of the vault from another
+    /// machine, with [`merge_accounts`]. The merged vault is kept in memory
+    /// until [`Vault::save`].
+    pub fn merge(&mut self, other: &Vault) -> MergeReport {
+        merge_accounts(&mut self.accounts, &other.accounts)
+    }
+
+    /// Merge the copy of the vault at `backend`, then push the merged vault
+    /// and save it, so that both copies have the accounts of either. A
+    /// vault is pushed as is if `backend` has no copy yet.
+    ///
+    /// The remote copy must be encrypted with the key of the vault, i.e.
+    /// be pushed by this vault or copied from it; a copy rekeyed on
+    /// another machine is reported as [`VaultError::WrongPassphrase`]. If
+    /// the remote copy changes between the pull and the push, the vault
+    /// pulls and merges it again, a few times before it gives up with
+    /// [`SyncError::Conflict`].
+    ///
+    /// The report counts the accounts merged over all attempts, and has
+    /// the conflicts of the last one.
+    pub fn sync(&mut self, backend: &dyn SyncBackend) -> Result<MergeReport, VaultError> {
+        const ATTEMPTS: usize = 3;
+        let mut report = MergeReport::default();
+        for _ in 0..ATTEMPTS {
+            let remote = backend.pull()?;
+            let mut unchanged = false;
+            if let Some(remote) = &remote {
+                let (theirs, outdated) = VaultFile::parse(&self.path, remote.data.clone())?.accounts(&self.key)?;
+                let merged = merge_accounts(&mut self.accounts, &theirs);
+                report.added += merged.added;
+                report.advanced += merged.advanced;
+                report.conflicts = merged.conflicts;
+                unchanged = !outdated && theirs == self.accounts;
+            }
+            let data = self.encrypt()?;
+            if !unchanged {
+                match backend.push(&data, remote.as_ref().map(|remote| remote.etag.as_str())) {
+                    Ok(_) => {}
+                    Err(SyncError::Conflict) => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            self.write(&data)?;
+            return Ok(report);
+        }
+        Err(SyncError::Conflict.into())
+    }
+
+    /// Change the passphrase or the key file of the vault, keeping its
+    /// [`KdfParams`].
+    pub fn rekey<'a>(&mut self, credentials: impl Into<Credentials<'a>>) -> Result<(), VaultError> {
+        self.rekey_with_params(credentials, self.params)
+    }
+
+    /// Change the passphrase or the key file of the vault, deriving the
+    /// new key with a new salt and `params`, and save the vault encrypted
+    /// with the new key, along with any unsaved changes of the accounts.
+    ///
+    /// The vault file is replaced atomically like by [`Vault::save`]: if
+    /// the save fails, the file and the vault keep the old key. Otherwise
+    /// the old key is wiped from memory, and keys kept by
+    /// [`Vault::store_key`] no longer open the vault and should be stored
+    /// again.
+    ///
+    /// The file with the old key is not backed up, and the backups are
+    /// encrypted again with the new key, so that the old passphrase opens
+    /// none of them. Backups that the old key does not open either, from
+    /// older versions of yOTP, are removed. If that fails, the error is
+    /// returned although the vault has the new key.
+    pub fn rekey_with_params<'a>(
+        &mut self,
+        credentials: impl Into<Credentials<'a>>,
+        params: KdfParams,
+    ) -> Result<(), VaultError> {
+        let salt = random::<SALT_LEN>()?;
+        let key = params.derive_key(credentials.into(), &salt)?;
+        let old_params = core::mem::replace(&mut self.params, params);
+        let old_salt = core::mem::replace(&mut self.salt, salt);
+        let old_key = core::mem::replace(&mut self.key, key);
+        let saved = self.encrypt().and_then(|data| Ok(write_atomic(&self.path, &data)?));
+        if let Err(e) = saved {
+            self.params = old_params;
+            self.salt = old_salt;
+            self.key = old_key;
+            return Err(e);
+        }
+        for backup in self.backups()? {
+            match VaultFile::read(&backup.path)?.accounts(&old_key) {
+                Ok((accounts, _)) => write_atomic(&backup.path, &self.encrypt_accounts(&accounts)?)?,
+                Err(VaultError::WrongPassphrase | VaultError::Tampered) => fs::remove_file(&backup.path)?,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the last `count` backups of the vault file, or none if `count`
+    /// is 0. Older backups are removed on the next save.
+    pub fn set_backups(&mut self, count: usize) {
+        self.keep_backups = count;
+    }
+
+    /// The backups of the vault file, the newest first.
+    pub fn backups(&self) -> Result<Vec<Backup>, VaultError> {
+        let dir = backup_dir(&self.path)?;
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut backups = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let millis = name.to_str().and_then(|name| name.strip_suffix(".vault")).and_then(|millis| millis.parse().ok());
+            if let Some(millis) = millis {
+                backups.push(Backup { path: entry.path(), time: UNIX_EPOCH + Duration::from_millis(millis) });
+            }
+        }
+        backups.sort_by_key(|backup| core::cmp::Reverse(backup.time));
+        Ok(backups)
+    }
+
+    /// Replace the accounts with those of `backup`, and save the vault. The
+    /// vault file is backed up first, so a restore can be undone too.
+    ///
+    /// Returns [`VaultError::WrongPassphrase`] if `backup` is not
+    /// encrypted with the key of the vault, e.g. a copy made by hand
+    /// before a [`Vault::rekey`].
+    pub fn restore(&mut self, backup: &Backup) -> Result<(), VaultError> {
+        let (accounts, _) = VaultFile::read(&backup.path)?.accounts(&self.key)?;
+        self.accounts = accounts;
+        self.save()
+    }
+
+    /// Encrypt the accounts and replace the vault file atomically.
+    pub fn save(&self) -> Result<(), VaultError> {
+        self.write(&self.encrypt()?)
+    }
+
+    /// Back up the vault file and replace it with `data`.
+    fn write(&self, data: &[u8]) -> Result<(), VaultError> {
+        if self.keep_backups > 0 && self.path.exists() {
+            self.back_up()?;
+        }
+        write_atomic(&self.path, data)?;
+        Ok(())
+    }
+
+    /// Copy the vault file into the backup directory, and remove the
+    /// backups beyond the count to keep.
+    fn back_up(&self) -> Result<(), VaultError> {
+        let dir = backup_dir(&self.path)?;
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir)?;
+        let data = fs::read(&self.path)?;
+        let mut millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+        // two saves within a millisecond get consecutive names
+        let mut path = dir.join(format!("{}.vault", millis));
+        while path.exists() {
+            millis += 1;
+            path = dir.join(format!("{}.vault", millis));
+        }
+        let mut file = create_private(&path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        for backup in self.backups()?.iter().skip(self.keep_backups) {
+            fs::remove_file(&backup.path)?;
+        }
+        Ok(())
+    }
+
+    /// The vault file of the accounts.
+    fn encrypt(&self) -> Result<Vec<u8>, VaultError> {
+        self.encrypt_accounts(&self.accounts)
+    }
+
+    /// A vault file of `accounts` with the key of the vault.
+    fn encrypt_accounts(&self, accounts: &[Account]) -> Result<Vec<u8>, VaultError> {
+        let json = encode_payload(accounts);
+        let nonce = random::<NONCE_LEN>()?;
+
+        let mut data = Vec::with_capacity(HEADER_LEN + json.len() + TAG_LEN + MAC_LEN);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_be_bytes());
+        data.extend_from_slice(&self.params.memory_kib.to_be_bytes());
+        data.extend_from_slice(&self.params.iterations.to_be_bytes());
+        data.extend_from_slice(&self.params.parallelism.to_be_bytes());
+        data.extend_from_slice(&self.salt);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&subkey(&self.key, CHECK_LABEL)[..]);
+        let cipher = Aes256Gcm::new_from_slice(&subkey(&self.key, ENCRYPTION_LABEL)[..]).expect("key has 32 bytes");
+        let cipher_text = cipher
+            .encrypt(Nonce::from_slice(&nonce), AeadPayload { msg: &json, aad: &data })
+            .expect("payload is not too long");
+        data.extend_from_slice(&cipher_text);
+        let mac = mac(&self.key, &data);
+        data.extend_from_slice(&mac);
+        Ok(data)
+    }
+}
+
+impl Account {
+    /// The account encrypted with `passphrase`, as an armored text block
+    /// to hand one account to someone else or to another device. See
+    /// [`Account::import_encrypted`].
+    ///
+    /// The block is encrypted like a vault, with a key derived with the
+    /// default [`KdfParams`], and carries the metadata of the account.
+    pub fn export_encrypted(&self, passphrase: &str) -> Result<String, VaultError> {
+        self.export_encrypted_with_params(passphrase, KdfParams::default())
+    }
+
+    /// [`Account::export_encrypted`] with a key derived with `params`.
+    pub fn export_encrypted_with_params(&self, passphrase: &str, params: KdfParams) -> Result<String, VaultError> {
+        let salt = random::<SALT_LEN>()?;
+        let nonce = random::<NONCE_LEN>()?;
+        let key = params.derive_key(Credentials::new(passphrase), &salt)?;
+        let payload = encode_payload(core::slice::from_ref(self));
+
+        let mut data = Vec::with_capacity(ACCOUNT_HEADER_LEN + payload.len() + TAG_LEN);
+        data.extend_from_slice(ACCOUNT_MAGIC);
+        data.extend_from_slice(&ACCOUNT_VERSION.to_be_bytes());
+        data.extend_from_slice(&params.memory_kib.to_be_bytes());
+        data.extend_from_slice(&params.iterations.to_be_bytes());
+        data.extend_from_slice(&params.parallelism.to_be_bytes());
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&nonce);
+        let cipher = Aes256Gcm::new_from_slice(&subkey(&key, ENCRYPTION_LABEL)[..]).expect("key has 32 bytes");
+        let cipher_text = cipher
+            .encrypt(Nonce::from_slice(&nonce), AeadPayload { msg: &payload, aad: &data })
+            .expect("payload is not too long");
+        data.extend_from_slice(&cipher_text);
+
+        let mut armored = String::from(ARMOR_BEGIN);
+        armored.push('\n');
+        let encoded = crate::base64::encode(&data);
+        for line in encoded.as_bytes().chunks(64) {
+            armored.push_str(core::str::from_utf8(line).expect("Base 64 is ASCII"));
+            armored.push('\n');
+        }
+        armored.push_str(ARMOR_END);
+        armored.push('\n');
+        Ok(armored)
+    }
+
+    /// The account of a block of [`Account::export_encrypted`]. Text
+    /// around the block, e.g. of an email, is ignored.
+    ///
+    /// Returns [`VaultError::InvalidFormat`] if `armored` has no valid
+    /// block, and [`VaultError::WrongPassphrase`] if the block does not
+    /// decrypt with `passphrase`, which is also the error of a modified
+    /// block.
+    pub fn import_encrypted(armored: &str, passphrase: &str) -> Result<Account, VaultError> {
+        let start = armored.find(ARMOR_BEGIN).ok_or(VaultError::InvalidFormat)? + ARMOR_BEGIN.len();
+        let len = armored[start..].find(ARMOR_END).ok_or(VaultError::InvalidFormat)?;
+        let encoded: String = armored[start..start + len].chars().filter(|c| !c.is_whitespace()).collect();
+        let data = crate::base64::decode(&encoded).ok_or(VaultError::InvalidFormat)?;
+        if data.len() < ACCOUNT_HEADER_LEN + TAG_LEN || &data[..8] != ACCOUNT_MAGIC {
+            return Err(VaultError::InvalidFormat);
+        }
+        let version = u16::from_be_bytes([data[8], data[9]]);
+        if version != ACCOUNT_VERSION {
+            return Err(VaultError::UnsupportedVersion(version));
+        }
+        let params = KdfParams {
+            memory_kib: read_u32(&data[10..]),
+            iterations: read_u32(&data[14..]),
+            parallelism: read_u32(&data[18..]),
+        };
+        let key = params.derive_key(Credentials::new(passphrase), &data[22..22 + SALT_LEN])?;
+        let (header, cipher_text) = data.split_at(ACCOUNT_HEADER_LEN);
+        let cipher = Aes256Gcm::new_from_slice(&subkey(&key, ENCRYPTION_LABEL)[..]).expect("key has 32 bytes");
+        let payload = cipher
+            .decrypt(Nonce::from_slice(&header[NONCE_OFFSET..]), AeadPayload { msg: cipher_text, aad: header })
+            .map_err(|_| VaultError::WrongPassphrase)?;
+        let (mut accounts, _) = decode_payload(&Zeroizing::new(payload))?;
+        if accounts.len() != 1 {
+            return Err(VaultError::InvalidFormat);
+        }
+        Ok(accounts.remove(0))
+    }
+}
+
+/// A copy of the vault file from before a save, see [`Vault::backups`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    path: PathBuf,
+    time: SystemTime,
+}
+
+impl Backup {
+    /// The path of the backup, a vault file itself.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// When the backup was made.
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+}
+
+/// The directory of the backups of the vault at `path`, `.backups`
+/// appended to the name of the vault.
+fn backup_dir(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut dir_name = name.to_owned();
+    dir_name.push(".backups");
+    Ok(path.with_file_name(dir_name))
+}
+
+/// How the accounts of two vaults were merged by [`merge_accounts`].
+#[derive(Debug, Default)]
+pub struct MergeReport {
+    /// The number of accounts added from the other vault.
+    pub added: usize,
+    /// The number of HOTP accounts whose counter was advanced to the one
+    /// of the other vault.
+    pub advanced: usize,
+    /// The accounts that differ between the vaults and were kept as they
+    /// are, for the caller to resolve.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// An account of the other vault that [`merge_accounts`] cannot merge
+/// with an account of the vault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    /// The account of the vault, which was kept.
+    pub ours: Account,
+    /// The account of the other vault, which was not added.
+    pub theirs: Account,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConflictKind {
+    /// The accounts have the same secret, but a different issuer, account
+    /// name, type or parameters, e.g. after a rename on one machine.
+    SameSecret,
+    /// The accounts have the same issuer and account name, but different
+    /// secrets, e.g. after enrolling again on one machine.
+    SameLabel,
+}
+
+/// Add the accounts of `theirs` to `ours`.
+///
+/// An account with the secret of an account of `ours` is the same
+/// account. If their issuers, account names, types and parameters are
+/// the same, it is a duplicate and is skipped. Otherwise it is reported as
+/// a [`ConflictKind::SameSecret`] conflict. Either way, an HOTP counter is
+/// advanced to the higher of the two, so that no code is accepted twice,
+/// and the account of `ours` gets the tags it lacks, the label, notes
+/// and icon it has not set, and the later time of last use.
+///
+/// An account with the issuer and account name but not the secret of an
+/// account of `ours` is reported as a [`ConflictKind::SameLabel`]
+/// conflict. Other accounts are added at the end.
+pub fn merge_accounts(ours: &mut Vec<Account>, theirs: &[Account]) -> MergeReport {
+    let mut report = MergeReport::default();
+    for account in theirs {
+        let uri = &account.uri;
+        if let Some(existing) = ours.iter_mut().find(|existing| existing.uri.secret == uri.secret) {
+            let same_type = existing.uri.otp_type == uri.otp_type;
+            if same_type && uri.otp_type == OtpType::Hotp && uri.counter > existing.uri.counter {
+                existing.uri.counter = uri.counter;
+                report.advanced += 1;
+            }
+            for tag in &account.tags {
+                existing.add_tag(tag);
+            }
+            existing.last_used = existing.last_used.max(account.last_used);
+            for (field, theirs) in [
+                (&mut existing.label, &account.label),
+                (&mut existing.notes, &account.notes),
+                (&mut existing.icon, &account.icon),
+            ] {
+                if field.is_none() {
+                    field.clone_from(theirs);
+                }
+            }
+            let same = same_type
+                && existing.uri.issuer == uri.issuer
+                && existing.uri.account == uri.account
+                && existing.uri.algorithm == uri.algorithm
+                && existing.uri.digits == uri.digits
+                && (uri.otp_type == OtpType::Hotp || existing.uri.period == uri.period);
+            if !same {
+                report.conflicts.push(Conflict { kind: ConflictKind::SameSecret, ours: existing.clone(), theirs: account.clone() });
+            }
+        } else if let Some(existing) =
+            ours.iter().find(|existing| existing.uri.issuer == uri.issuer && existing.uri.account == uri.account)
+        {
+            report.conflicts.push(Conflict { kind: ConflictKind::SameLabel, ours: existing.clone(), theirs: account.clone() });
+        } else {
+            ours.push(account.clone());
+            report.added += 1;
+        }
+    }
+    report
+}
+
+/// The header and the cipher text of a vault file.
+struct VaultFile {
+    path: PathBuf,
+    version: u16,
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+    data: Vec<u8>,
+}
+
+impl VaultFile {
+    fn read(path: &Path) -> Result<VaultFile, VaultError> {
+        VaultFile::parse(path, fs::read(path)?)
+    }
+
+    /// The vault file `data`, e.g. a copy of the vault at `path`.
+    fn parse(path: &Path, data: Vec<u8>) -> Result<VaultFile, VaultError> {
+        if data.len() < 10 || &data[..8] != MAGIC {
+            return Err(VaultError::InvalidFormat);
+        }
+        let version = u16::from_be_bytes([data[8], data[9]]);
+        let min_len = match version {
+            1 => HEADER_LEN_V1 + TAG_LEN,
+            VERSION => HEADER_LEN + TAG_LEN + MAC_LEN,
+            _ => return Err(VaultError::UnsupportedVersion(version)),
+        };
+        if data.len() < min_len {
+            return Err(if version == 1 { VaultError::InvalidFormat } else { VaultError::Tampered });
+        }
+        let params = KdfParams {
+            memory_kib: read_u32(&data[10..]),
+            iterations: read_u32(&data[14..]),
+            parallelism: read_u32(&data[18..]),
+        };
+        let salt = data[22..22 + SALT_LEN].try_into().expect("salt length");
+        Ok(VaultFile { path: path.to_owned(), version, params, salt, data })
+    }
+
+    fn decrypt(self, key: Zeroizing<[u8; 32]>) -> Result<Vault, VaultError> {
+        let (accounts, outdated) = self.accounts(&key)?;
+        let vault = Vault { path: self.path, params: self.params, salt: self.salt, key, accounts, keep_backups: DEFAULT_BACKUPS };
+        if outdated {
+            vault.save()?;
+        }
+        Ok(vault)
+    }
+
+    /// The accounts of the file, and whether the file is of an older format
+    /// or schema.
+    fn accounts(&self, key: &[u8; 32]) -> Result<(Vec<Account>, bool), VaultError> {
+        let nonce = Nonce::from_slice(&self.data[NONCE_OFFSET..HEADER_LEN_V1]);
+        let payload = if self.version == 1 {
+            let cipher = Aes256Gcm::new_from_slice(&key[..]).expect("key has 32 bytes");
+            cipher.decrypt(nonce, &self.data[HEADER_LEN_V1..]).map_err(|_| VaultError::WrongPassphrase)?
+        } else {
+            let mut checker = <Hmac<Sha256> as Mac>::new_from_slice(&key[..]).expect("HMAC takes any key");
+            checker.update(CHECK_LABEL);
+            checker.verify_slice(&self.data[HEADER_LEN_V1..HEADER_LEN]).map_err(|_| VaultError::WrongPassphrase)?;
+            let (data, tag) = self.data.split_at(self.data.len() - MAC_LEN);
+            let mut verifier = <Hmac<Sha256> as Mac>::new_from_slice(&subkey(key, MAC_LABEL)[..]).expect("HMAC takes any key");
+            verifier.update(data);
+            verifier.verify_slice(tag).map_err(|_| VaultError::Tampered)?;
+            let cipher = Aes256Gcm::new_from_slice(&subkey(key, ENCRYPTION_LABEL)[..]).expect("key has 32 bytes");
+            let (header, cipher_text) = data.split_at(HEADER_LEN);
+            cipher.decrypt(nonce, AeadPayload { msg: cipher_text, aad: header }).map_err(|_| VaultError::Tampered)?
+        };
+        let (accounts, migrated) = decode_payload(&Zeroizing::new(payload))?;
+        Ok((accounts, migrated || self.version < VERSION))
+    }
+}
+
+const ENCRYPTION_LABEL: &[u8] = b"yOTP vault encryption";
+const MAC_LABEL: &[u8] = b"yOTP vault MAC";
+const CHECK_LABEL: &[u8] = b"yOTP vault key check";
+
+/// The key for `label` derived from the vault key.
+fn subkey(key: &[u8; 32], label: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes any key");
+    mac.update(label);
+    Zeroizing::new(mac.finalize().into_bytes().into())
+}
+
+fn mac(key: &[u8; 32], data: &[u8]) -> [u8; MAC_LEN] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&subkey(key, MAC_LABEL)[..]).expect("HMAC takes any key");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Write `data` to a temporary file in the directory of `path`, flush it
+/// to the disk and rename it over `path`.
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+    let result = (|| {
+        let mut file = create_private(&temp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+    // make the rename durable too
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Create `path` readable and writable by the owner only.
+fn create_private(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data[..4].try_into().expect("4 bytes"))
+}
+
+fn random<const N: usize>() -> Result<[u8; N], OtpError> {
+    let mut bytes = [0; N];
+    getrandom::fill(&mut bytes).map_err(|_| OtpError::RandomError)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::account::{Account, AccountQuery};
+    use crate::error::OtpError;
+    use crate::secret_store::{MemorySecretStore, SecretStore};
+    use crate::sync::{MemorySyncBackend, SyncBackend};
+    use crate::uri::OtpAuthUri;
+
+    use serde_json::json;
+
+    use super::{merge_accounts, migrate, ConflictKind, Credentials, KdfParams, KeyFile, Vault, VaultError, HEADER_LEN, MAC_LEN, SCHEMA_VERSION, VERSION};
+
+    // cheap parameters to keep the tests fast
+    const TEST_PARAMS: KdfParams = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("yotp-vault-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("accounts.vault")
+    }
+
+    #[test]
+    fn test_create_and_open() {
+        let path = temp_path("open");
+        let mut vault = Vault::create_with_params(&path, "passphrase", TEST_PARAMS).unwrap();
+        assert_eq!(Vault::open(&path, "passphrase").unwrap().accounts(), []);
+
+        let mut account = Account::new(OtpAuthUri::parse("otpauth://hotp/Example:alice?secret=JBSWY3DPEHPK3PXP&counter=5").unwrap());
+        account.label = Some("Alice".into());
+        account.add_tag("work");
+        account.notes = Some("recovery codes in the safe".into());
+        account.icon = Some("example".into());
+        account.last_used = Some(1_700_000_000);
+        vault.accounts_mut().push(account.clone());
+        vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/bob?secret=GEZDGNBVGY3TQOJQ").unwrap().into());
+        vault.save().unwrap();
+        let opened = Vault::open(&path, "passphrase").unwrap();
+        assert_eq!(opened.accounts(), vault.accounts());
+        assert_eq!(opened.accounts()[0], account);
+        assert_eq!(opened.query(&AccountQuery::new().tag("work")).accounts, [&account]);
+        assert_eq!(opened.kdf_params(), TEST_PARAMS);
+        // only the vault and its backups are left in the directory
+        let mut names: Vec<_> = std::fs::read_dir(path.parent().unwrap()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        names.sort();
+        assert_eq!(names, ["accounts.vault", "accounts.vault.backups"]);
+        assert!(!std::fs::read(&path).unwrap().windows(16).any(|w| w == b"JBSWY3DPEHPK3PXP"));
+
+        assert!(matches!(Vault::open(&path, "wrong"), Err(VaultError::WrongPassphrase)));
+        assert!(matches!(Vault::create_with_params(&path, "passphrase", TEST_PARAMS), Err(VaultError::Io(_))));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let path = temp_path("invalid");
+        Vault::create_with_params(&path, "passphrase", TEST_PARAMS).unwrap();
+        let data = std::fs::read(&path).unwrap();
+
+        let mut version = data.clone();
+        version[9] = 9;
+        std::fs::write(&path, &version).unwrap();
+        assert!(matches!(Vault::open(&path, "passphrase"), Err(VaultError::UnsupportedVersion(9))));
+
+        // costs just above the maximums, then a memory cost of 4 TiB
+        let costs = [
+            (10, KdfParams::MAX_MEMORY_KIB + 1),
+            (14, KdfParams::MAX_ITERATIONS + 1),
+            (18, KdfParams::MAX_PARALLELISM + 1),
+            (10, u32::MAX),
+        ];
+        for (offset, cost) in costs {
+            let mut costly = data.clone();
+            costly[offset..offset + 4].copy_from_slice(&cost.to_be_bytes());
+            std::fs::write(&path, &costly).unwrap();
+            assert!(matches!(Vault::open(&path, "passphrase"), Err(VaultError::InvalidFormat)));
+        }
+
+        for data in [&b"not a vault"[..], &data[..9]] {
+            std::fs::write(&path, data).unwrap();
+            assert!(matches!(Vault::open(&path, "passphrase"), Err(VaultError::InvalidFormat)));
+        }
+        assert!(matches!(Vault::open(path.with_file_name("missing"), "passphrase"), Err(VaultError::Io(_))));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_tampered() {
+        let path = temp_path("tampered");
+        let mut vault = Vault::create_with_params(&path, "passphrase", TEST_PARAMS).unwrap();
+        vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+        vault.save().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let open = |data: &[u8]| {
+            std::fs::write(&path, data).unwrap();
+            Vault::open(&path, "passphrase")
+        };
+
+        // the cipher text, its tag and the MAC
+        for i in [HEADER_LEN, data.len() - MAC_LEN - 1, data.len() - 1] {
+            let mut flipped = data.clone();
+            flipped[i] ^= 1;
+            assert!(matches!(open(&flipped), Err(VaultError::Tampered)), "{}", i);
+        }
+        for len in [HEADER_LEN, data.len() - 1, data.len() - MAC_LEN] {
+            assert!(matches!(open(&data[..len]), Err(VaultError::Tampered)), "{}", len);
+        }
+        let mut extended = data.clone();
+        extended.push(0);
+        assert!(matches!(open(&extended), Err(VaultError::Tampered)));
+        // the header changes the key
+        let mut params = data.clone();
+        params[13] ^= 1;
+        assert!(matches!(open(&params), Err(VaultError::WrongPassphrase)));
+        assert_eq!(open(&data).unwrap().accounts(), vault.accounts());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_old_versions() {
+        // vaults saved by older versions of yOTP with the passphrase
+        // "passphrase" and TEST_PARAMS, to be kept as they are
+        let fixtures: [(&str, &[u8]); 4] = [
+            ("format 1, schema 1", include_bytes!("../testdata/vault-v1.vault")),
+            ("format 2, schema 1", include_bytes!("../testdata/vault-v2.vault")),
+            ("format 2, schema 2", include_bytes!("../testdata/vault-v2-schema2.vault")),
+            ("format 2, schema 3", include_bytes!("../testdata/vault-v2-schema3.vault")),
+        ];
+        for (name, fixture) in fixtures {
+            let path = temp_path("old");
+            std::fs::write(&path, fixture).unwrap();
+            assert!(matches!(Vault::open(&path, "wrong"), Err(VaultError::WrongPassphrase)), "{}", name);
+            assert_eq!(std::fs::read(&path).unwrap(), fixture, "{}", name);
+
+            let vault = Vault::open(&path, "passphrase").unwrap();
+            let accounts: Vec<String> = vault.accounts().iter().map(|account| account.uri.to_string()).collect();
+            assert_eq!(accounts, [
+                "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example",
+                "otpauth://hotp/bob?secret=GEZDGNBVGY3TQOJQ&counter=5",
+            ], "{}", name);
+            // saved in the current format, and not saved again
+            let upgraded = std::fs::read(&path).unwrap();
+            assert_eq!(upgraded[8..10], VERSION.to_be_bytes(), "{}", name);
+            assert_eq!(Vault::open(&path, "passphrase").unwrap().accounts(), vault.accounts(), "{}", name);
+            assert_eq!(std::fs::read(&path).unwrap(), upgraded, "{}", name);
+            std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        }
+
+        // the metadata of schema 3 is kept
+        let path = temp_path("old-metadata");
+        std::fs::write(&path, include_bytes!("../testdata/vault-v2-schema3.vault")).unwrap();
+        let vault = Vault::open(&path, "passphrase").unwrap();
+        let alice = &vault.accounts()[0];
+        assert_eq!((alice.label.as_deref(), &alice.tags[..], alice.notes.as_deref(), alice.icon.as_deref()), (Some("Alice"), &["work".to_string()][..], Some("notes"), Some("example")));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_migrate() {
+        let uri = "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP";
+        let mut payload = json!({ "accounts": [uri] });
+        assert!(migrate(&mut payload).unwrap());
+        assert_eq!(payload, json!({ "version": SCHEMA_VERSION, "accounts": [{ "uri": uri }] }));
+        assert!(!migrate(&mut payload).unwrap());
+
+        assert!(matches!(migrate(&mut json!({ "version": 99, "accounts": [] })), Err(VaultError::UnsupportedSchema(99))));
+        assert!(matches!(migrate(&mut json!({ "version": 0, "accounts": [] })), Err(VaultError::UnsupportedSchema(0))));
+        for mut payload in [json!([]), json!({ "version": "2" }), json!({ "accounts": {} })] {
+            assert!(matches!(migrate(&mut payload), Err(VaultError::InvalidFormat)), "{}", payload);
+        }
+    }
+
+    #[test]
+    fn test_store_key() {
+        let path = temp_path("store");
+        let mut vault = Vault::create_with_params(&path, "passphrase", TEST_PARAMS).unwrap();
+        vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/a?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+        vault.save().unwrap();
+        let store = MemorySecretStore::new();
+        assert!(matches!(Vault::open_from_store(&path, &store, "vault"), Err(VaultError::WrongPassphrase)));
+        vault.store_key(&store, "vault").unwrap();
+        assert_eq!(Vault::open_from_store(&path, &store, "vault").unwrap().accounts(), vault.accounts());
+        store.set("vault", &[0; 32]).unwrap();
+        assert!(matches!(Vault::open_from_store(&path, &store, "vault"), Err(VaultError::WrongPassphrase)));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rekey() {
+        let path = temp_path("rekey");
+        let mut vault = Vault::create_with_params(&path, "old", TEST_PARAMS).unwrap();
+        let store = MemorySecretStore::new();
+        vault.store_key(&store, "vault").unwrap();
+        vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/a?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+        let params = KdfParams { memory_kib: 128, ..TEST_PARAMS };
+        vault.rekey_with_params("new", params).unwrap();
+
+        assert!(matches!(Vault::open(&path, "old"), Err(VaultError::WrongPassphrase)));
+        assert!(matches!(Vault::open_from_store(&path, &store, "vault"), Err(VaultError::WrongPassphrase)));
+        let opened = Vault::open(&path, "new").unwrap();
+        assert_eq!((opened.accounts(), opened.kdf_params()), (vault.accounts(), params));
+
+        // a failed save keeps the old key
+        std::fs::create_dir(path.with_file_name(".accounts.vault.tmp")).unwrap();
+        assert!(matches!(vault.rekey("newer"), Err(VaultError::Io(_))));
+        std::fs::remove_dir(path.with_file_name(".accounts.vault.tmp")).unwrap();
+        vault.save().unwrap();
+        assert!(Vault::open(&path, "new").is_ok());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_key_file() {
+        let path = temp_path("key-file");
+        let key_path = path.with_file_name("accounts.key");
+        let key_file = KeyFile::generate(&key_path).unwrap();
+        assert_eq!(std::fs::read(&key_path).unwrap().len(), 32);
+        assert!(matches!(KeyFile::generate(&key_path), Err(VaultError::Io(_))));
+        Vault::create_with_params(&path, Credentials::with_key_file("weak", &key_file), TEST_PARAMS).unwrap();
+
+        let key_file = KeyFile::read(&key_path).unwrap();
+        assert!(Vault::open(&path, Credentials::with_key_file("weak", &key_file)).is_ok());
+        let other = KeyFile::from_bytes(b"other");
+        for credentials in [Credentials::new("weak"), Credentials::with_key_file("wrong", &key_file), Credentials::with_key_file("weak", &other)] {
+            assert!(matches!(Vault::open(&path, credentials), Err(VaultError::WrongPassphrase)));
+        }
+
+        let mut vault = Vault::open(&path, Credentials::with_key_file("weak", &key_file)).unwrap();
+        vault.rekey("strong").unwrap();
+        assert!(Vault::open(&path, "strong").is_ok());
+        assert!(matches!(Vault::open(&path, Credentials::with_key_file("strong", &key_file)), Err(VaultError::WrongPassphrase)));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_shares() {
+        let path = temp_path("shares");
+        let mut vault = Vault::create_with_params(&path, "passphrase", TEST_PARAMS).unwrap();
+        vault.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").unwrap().into());
+        vault.save().unwrap();
+        let shares = vault.split_key(2, 3).unwrap();
+        assert_eq!(Vault::open_with_shares(&path, &shares[1..]).unwrap().accounts(), vault.accounts());
+        assert!(matches!(Vault::open_with_shares(&path, &shares[..1]), Err(VaultError::Otp(OtpError::ShareError(_)))));
+
+        vault.rekey("new").unwrap();
+        assert!(matches!(Vault::open_with_shares(&path, &shares), Err(VaultError::WrongPassphrase)));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_merge() {
+        let parse = |uri| Account::new(OtpAuthUri::parse(uri).unwrap());
+        let mut ours = vec![
+            parse("otpauth://hotp/Example:alice?secret=JBSWY3DPEHPK3PXP&counter=5"),
+            parse("otpauth://totp/GitHub:alice?secret=GEZDGNBVGY3TQOJQ"),
+            parse("otpauth://totp/GitLab:alice?secret=MFRGGZDFMZTWQ2LK"),
+        ];
+        let theirs = [
+            parse("otpauth://hotp/Example:alice?secret=JBSWY3DPEHPK3PXP&counter=9"),
+            parse("otpauth://totp/GitHub:alice%40work?secret=GEZDGNBVGY3TQOJQ"),
+            parse("otpauth://totp/GitLab:alice?secret=KRUGS4ZANFZSAYJA"),
+            parse("otpauth://totp/Example:bob?secret=ONSWG4TFORPWK6DB"),
+            parse("otpauth://totp/Example:bob?secret=ONSWG4TFORPWK6DB"),
+        ];
+        let report = merge_accounts(&mut ours, &theirs);
+        assert_eq!((report.added, report.advanced), (1, 1));
+        assert_eq!(ours.len(), 4);
+        assert_eq!((ours[0].uri.counter, &ours[1].uri.account, &ours[3].uri.account), (9, &"alice".to_string(), &"bob".to_string()));
+        let kinds: Vec<_> = report.conflicts.iter().map(|conflict| (conflict.kind, conflict.theirs.uri.account.as_str())).collect();
+        assert_eq!(kinds, [(ConflictKind::SameSecret, "alice@work"), (ConflictKind::SameLabel, "alice")]);
+
+        // a lower counter is not taken, and merging again changes nothing
+        let report = merge_accounts(&mut ours, &[parse("otpauth://hotp/Example:alice?secret=JBSWY3DPEHPK3PXP&counter=1")]);
+        assert_eq!((report.added, report.advanced, report.conflicts.len(), ours[0].uri.counter), (0, 0, 0, 9));
+
+        // the metadata of the same account is completed, not replaced
+        ours[0].label = Some("Alice".into());
+        ours[0].add_tag("work");
+        let mut theirs = parse("otpauth://hotp/Example:alice?secret=JBSWY3DPEHPK3PXP&counter=9");
+        theirs.label = Some("Alice at Example".into());
+        theirs.tags = vec!["Work".into(), "bank".into()];
+        theirs.notes = Some("notes".into());
+        let report = merge_accounts(&mut ours, &[theirs]);
+        assert_eq!((report.added, report.conflicts.len()), (0, 0));
+        assert_eq!((ours[0].label.as_deref(), &ours[0].tags[..], ours[0].notes.as_deref()), (Some("Alice"), &["work".to_string(), "bank".to_string()][..], Some("notes")));
+
+        let merged = ours.clone();
+        let report = merge_accounts(&mut ours, &merged);
+        assert_eq!((report.added, report.advanced, report.conflicts.len()), (0, 0, 0));
+        assert_eq!(ours, merged);
+    }
+
+    #[test]
+    fn test_sync() {
+        let path = temp_path("sync");
+        let mut laptop = Vault::create_with_params(&path, "passphrase", TEST_PARAMS).unwrap();
+        laptop.accounts_mut().push(OtpAuthUri::parse("otpauth://hotp/Example:alice?secret=JBSWY3DPEHPK3PXP&counter=5").unwrap().into());
+        laptop.save().unwrap();
+        let phone_path = path.with_file_name("phone.vault");
+        std::fs::copy(&path, &phone_path).unwrap();
+        let mut phone = Vault::open(&phone_path, "passphrase").unwrap();
+
+        let remote = MemorySyncBackend::new();
+        assert_eq!(laptop.sync(&remote).unwrap().added, 0);
+        phone.accounts_mut()[0].uri.counter = 8;
+        phone.accounts_mut().push(OtpAuthUri::parse("otpauth://totp/Example:bob?secret=GEZDGNBVGY3TQOJQ").unwrap().into());
+        let report = phone.sync(&remote).unwrap();
+        assert_eq!((report.added, report.advanced), (0, 0));
+        let report = laptop.sync(&remote).unwrap();
+        assert_eq!((report.added, report.advanced), (1, 1));
+        assert_eq!(laptop.accounts(), phone.accounts());
+        assert_eq!(Vault::open(&path, "passphrase").unwrap().accounts(), phone.accounts());
+
+        // nothing is pushed when the remote copy is up to date
+        let etag = remote.etag().unwrap();
+        phone.sync(&remote).unwrap();
+        assert_eq!(remote.etag().unwrap(), etag);
+
+        phone.rekey_with_params("new", TEST_PARAMS).unwrap();
+        phone.accounts_mut().clear();
+        let other = MemorySyncBackend::new();
+        phone.sync(&other).unwrap();
+        assert!(matches!(laptop.sync(&other), Err(VaultError::WrongPassphrase)));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_backups() {
+        let path = temp_path("backups");
+        let mut vault = Vault::create_with_params(&path, "passphrase", TEST_PARAMS).unwrap();
+        assert!(vault.backups().unwrap().is_empty());
+        vault.set_backups(2);
+        for secret in ["JBSWY3DPEHPK3PXP", "GEZDGNBVGY3TQOJQ", "MFRGGZDFMZTWQ2LK"] {
+            vault.accounts_mut().push(OtpAuthUri::parse(&format!("otpauth://totp/alice?secret={}", secret)).unwrap().into());
+            vault.save().unwrap();
+        }
+        let backups = vault.backups().unwrap();
+        assert_eq!(backups.len(), 2);
+        assert!(backups[0].time() > backups[1].time());
+        assert!(backups[0].path().starts_with(path.with_file_name("accounts.vault.backups")));
+        assert_eq!(Vault::open(backups[0].path(), "passphrase").unwrap().accounts().len(), 2);
+
+        vault.accounts_mut().clear();
+        vault.restore(&backups[1]).unwrap();
+        assert_eq!(Vault::open(&path, "passphrase").unwrap().accounts().len(), 1);
+        // the restore is undone with the backup it made
+        vault.restore(&vault.backups().unwrap()[0]).unwrap();
+        assert_eq!(vault.accounts().len(), 3);
+
+        // a backup of another key, as made by older versions on rekey
+        let stale = path.with_file_name("accounts.vault.backups").join("1.vault");
+        let other = Vault::create_with_params(path.with_file_name("other.vault"), "passphrase", TEST_PARAMS).unwrap();
+        std::fs::write(&stale, other.encrypt().unwrap()).unwrap();
+        let before: Vec<_> = vault.backups().unwrap().into_iter().filter(|backup| backup.path() != stale).collect();
+        vault.rekey("new").unwrap();
+        // the old key opens no backup, and the file with it is not backed up
+        let backups = vault.backups().unwrap();
+        assert_eq!(backups, before);
+        for backup in &backups {
+            assert!(matches!(Vault::open(backup.path(), "passphrase"), Err(VaultError::WrongPassphrase)));
+            assert!(Vault::open(backup.path(), "new").is_ok());
+        }
+        vault.restore(&backups[0]).unwrap();
+        assert_eq!(vault.accounts().len(), 1);
+        let kept = vault.backups().unwrap();
+        vault.set_backups(0);
+        vault.save().unwrap();
+        assert_eq!(vault.backups().unwrap(), kept);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_export_encrypted() {
+        let mut account = Account::new(OtpAuthUri::parse("otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP").unwrap());
+        account.label = Some("Work".into());
+        account.add_tag("work");
+        let armored = account.export_encrypted_with_params("passphrase", TEST_PARAMS).unwrap();
+        assert!(armored.starts_with("-----BEGIN YOTP ACCOUNT-----\n"));
+        assert!(armored.ends_with("-----END YOTP ACCOUNT-----\n"));
+        assert!(armored.lines().all(|line| line.len() <= 64));
+        assert_ne!(armored, account.export_encrypted_with_params("passphrase", TEST_PARAMS).unwrap());
+
+        let quoted = format!("Here is the account:\n\n{}\nBob", armored);
+        assert_eq!(Account::import_encrypted(&quoted, "passphrase").unwrap(), account);
+        assert!(matches!(Account::import_encrypted(&armored, "wrong"), Err(VaultError::WrongPassphrase)));
+        assert!(matches!(Account::import_encrypted("no block", "passphrase"), Err(VaultError::InvalidFormat)));
+        let mut costly = crate::base64::decode(&armored.lines().filter(|line| !line.starts_with("-----")).collect::<String>()).unwrap();
+        costly[10..14].copy_from_slice(&u32::MAX.to_be_bytes());
+        let costly = format!("-----BEGIN YOTP ACCOUNT-----\n{}\n-----END YOTP ACCOUNT-----\n", crate::base64::encode(&costly));
+        assert!(matches!(Account::import_encrypted(&costly, "passphrase"), Err(VaultError::InvalidFormat)));
+        let truncated = armored.replacen("\n-----END", "AAAA\n-----END", 1);
+        assert!(Account::import_encrypted(&truncated, "passphrase").is_err());
+    }
+
+    #[test]
+    fn test_error() {
+        assert_eq!(OtpError::from(VaultError::WrongPassphrase), OtpError::VaultError("wrong passphrase or key file".into()));
+        assert_eq!(OtpError::from(VaultError::Otp(OtpError::InvalidUri)), OtpError::InvalidUri);
+    }
+}
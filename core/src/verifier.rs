@@ -0,0 +1,455 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::OtpError;
+use crate::hotp::Hotp;
+use crate::throttle::ThrottlePolicy;
+use crate::totp::Totp;
+use crate::verify::StepMatch;
+
+/// The verification state of one account.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccountState {
+    /// The next HOTP counter to accept.
+    pub counter: u64,
+    /// The last accepted TOTP step, to reject replayed codes.
+    pub last_step: Option<u64>,
+    /// The learned TOTP clock drift in steps.
+    pub drift: i64,
+    /// The number of consecutive failed verifications.
+    pub failures: u32,
+    /// The time of the last failed verification.
+    pub last_failure: u64,
+}
+
+/// Persistence of [`AccountState`] for the verifiers.
+///
+/// Implement it on top of a database to share the verification logic of
+/// [`TotpVerifier`] and [`HotpVerifier`]. Storage failures should be
+/// reported as [`OtpError::StoreError`].
+pub trait VerifierStore {
+    /// Load the state of `id`. Unknown accounts have the default state.
+    fn load(&self, id: &str) -> Result<AccountState, OtpError>;
+
+    /// Save the state of `id`.
+    fn save(&mut self, id: &str, state: &AccountState) -> Result<(), OtpError>;
+
+    /// Whether the TOTP code of `step` was already consumed by `id`.
+    fn is_consumed(&self, id: &str, step: u64) -> Result<bool, OtpError> {
+        Ok(self.load(id)?.last_step.is_some_and(|last| step <= last))
+    }
+
+    /// Mark the TOTP code of `step` as consumed by `id`, so it is never
+    /// accepted again. Returns false if it was already consumed.
+    ///
+    /// Codes of earlier steps are consumed as well. Stores shared between
+    /// processes should override it with an atomic check-and-set, so that
+    /// two concurrent verifications cannot both accept the same code.
+    fn consume(&mut self, id: &str, step: u64) -> Result<bool, OtpError> {
+        let mut state = self.load(id)?;
+        if state.last_step.is_some_and(|last| step <= last) {
+            return Ok(false);
+        }
+        state.last_step = Some(step);
+        self.save(id, &state)?;
+        Ok(true)
+    }
+}
+
+/// A [`VerifierStore`] keeping the states in memory.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryStore {
+    states: HashMap<String, AccountState>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl VerifierStore for MemoryStore {
+    fn load(&self, id: &str) -> Result<AccountState, OtpError> {
+        Ok(self.states.get(id).copied().unwrap_or_default())
+    }
+
+    fn save(&mut self, id: &str, state: &AccountState) -> Result<(), OtpError> {
+        self.states.insert(id.to_owned(), *state);
+        Ok(())
+    }
+}
+
+/// Verifier of TOTP codes with throttling, replay protection and drift
+/// compensation, keeping the state of accounts in a [`VerifierStore`].
+#[derive(Debug, Clone)]
+pub struct TotpVerifier<S, C = SystemClock> {
+    store: S,
+    window: u64,
+    grace: u64,
+    policy: ThrottlePolicy,
+    clock: C,
+}
+
+impl<S: VerifierStore> TotpVerifier<S> {
+    /// Create a verifier with a window of 1 step, the default throttling
+    /// policy and the system clock.
+    pub fn new(store: S) -> TotpVerifier<S> {
+        TotpVerifier {
+            store,
+            window: 1,
+            grace: 0,
+            policy: ThrottlePolicy::default(),
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<S: VerifierStore, C: Clock> TotpVerifier<S, C> {
+    /// Accept codes up to `window` steps around the drift of the account.
+    pub fn window(mut self, window: u64) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Also accept the code before the window during the first `grace`
+    /// seconds of a step. See [`Totp::verify_with_grace`].
+    pub fn grace_period(mut self, grace: u64) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Throttle failed verifications with `policy`.
+    pub fn throttle(mut self, policy: ThrottlePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Read the current time from `clock`, which overrides the clock of
+    /// the [`Totp`] passed to [`TotpVerifier::verify`].
+    pub fn clock<C2: Clock>(self, clock: C2) -> TotpVerifier<S, C2> {
+        TotpVerifier {
+            store: self.store,
+            window: self.window,
+            grace: self.grace,
+            policy: self.policy,
+            clock,
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// Verify `code` of account `id` configured by `totp`.
+    ///
+    /// Returns the matched step, or `None` if the code is wrong or was
+    /// already used. Returns [`OtpError::Throttled`] or
+    /// [`OtpError::LockedOut`] without checking the code if the account
+    /// has failed too often.
+    pub fn verify<T: Clock>(&mut self, id: &str, totp: &Totp<T>, code: &str) -> Result<Option<StepMatch>, OtpError> {
+        let (window, grace) = (self.window, self.grace);
+        let matched = self.check(id, |now, drift| totp.verify_range_at(code, now, window, drift, grace).map(|m| ((), m)))?;
+        Ok(matched.map(|(_, m)| m))
+    }
+
+    /// Verify `code` of account `id` whose secret is being rotated, see
+    /// [`TotpRotation`], with the replay protection, drift and throttling
+    /// of [`TotpVerifier::verify`] shared by both secrets.
+    ///
+    /// Returns the matched step and which secret it matched. Once a code
+    /// of the new secret matches, the user has switched, and the old
+    /// secret may be retired early.
+    pub fn verify_rotation<T: Clock>(
+        &mut self,
+        id: &str,
+        rotation: &TotpRotation<T>,
+        code: &str,
+    ) -> Result<Option<(RotatedSecret, StepMatch)>, OtpError> {
+        let (window, grace) = (self.window, self.grace);
+        self.check(id, |now, drift| {
+            let new = rotation.new.verify_range_at(code, now, window, drift, grace);
+            if rotation.is_retired_at(now) {
+                return new.map(|m| (RotatedSecret::New, m));
+            }
+            // check both secrets, so that the time taken does not tell
+            // which one the user has
+            let old = rotation.old.verify_range_at(code, now, window, drift, grace);
+            new.map(|m| (RotatedSecret::New, m)).or(old.map(|m| (RotatedSecret::Old, m)))
+        })
+    }
+
+    /// Throttle, run `matcher` with the current time and the drift of
+    /// `id`, consume the matched step and update the state of `id`.
+    fn check<R>(
+        &mut self,
+        id: &str,
+        matcher: impl FnOnce(u64, i64) -> Option<(R, StepMatch)>,
+    ) -> Result<Option<(R, StepMatch)>, OtpError> {
+        let state = self.store.load(id)?;
+        let now = self.clock.now_unix();
+        self.policy.check(state.failures, state.last_failure, now)?;
+        let matched = match matcher(now, state.drift) {
+            Some((r, m)) if self.store.consume(id, m.step)? => Some((r, m)),
+            _ => None
+        };
+        // reload the state as consuming the step has changed it
+        let mut state = self.store.load(id)?;
+        match &matched {
+            Some((_, m)) => {
+                state.drift = m.offset;
+                state.failures = 0;
+            }
+            None => {
+                state.failures = state.failures.saturating_add(1);
+                state.last_failure = now;
+            }
+        }
+        self.store.save(id, &state)?;
+        Ok(matched)
+    }
+
+    /// Burn the code of `step` for `id`, e.g. after it was accepted by
+    /// another path, so that it cannot be accepted by this verifier.
+    /// Returns false if it was already consumed.
+    pub fn burn(&mut self, id: &str, step: u64) -> Result<bool, OtpError> {
+        self.store.consume(id, step)
+    }
+}
+
+/// The secret of a TOTP account being replaced by a new one, so that
+/// servers can rotate secrets without locking users out: codes of either
+/// secret are accepted until `retire_at`, and only codes of the new
+/// secret after. See [`TotpVerifier::verify_rotation`].
+///
+/// The secrets should have the same period and `t0`, as they share the
+/// drift and the consumed steps of the account.
+#[derive(Debug, Clone)]
+pub struct TotpRotation<C = SystemClock> {
+    pub old: Totp<C>,
+    pub new: Totp<C>,
+    /// The time in seconds since UNIX epoch when the old secret is
+    /// retired.
+    pub retire_at: u64,
+}
+
+impl<C: Clock> TotpRotation<C> {
+    /// Rotate from `old` to `new`, accepting both for `overlap` seconds
+    /// from now, by the clock of `new`.
+    pub fn new(old: Totp<C>, new: Totp<C>, overlap: u64) -> TotpRotation<C> {
+        let retire_at = new.clock().now_unix().saturating_add(overlap);
+        TotpRotation { old, new, retire_at }
+    }
+
+    /// Whether only the new secret is accepted at time `t`.
+    pub fn is_retired_at(&self, t: u64) -> bool {
+        t >= self.retire_at
+    }
+
+    /// End the rotation, keeping the new secret.
+    pub fn into_new(self) -> Totp<C> {
+        self.new
+    }
+}
+
+/// Which secret of a [`TotpRotation`] a code matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotatedSecret {
+    Old,
+    New,
+}
+
+/// Verifier of HOTP codes with throttling and look-ahead
+/// resynchronization, keeping the counters in a [`VerifierStore`].
+#[derive(Debug, Clone)]
+pub struct HotpVerifier<S, C = SystemClock> {
+    store: S,
+    look_ahead: u64,
+    policy: ThrottlePolicy,
+    clock: C,
+}
+
+impl<S: VerifierStore> HotpVerifier<S> {
+    /// Create a verifier with a look-ahead of 10 counters, the default
+    /// throttling policy and the system clock.
+    pub fn new(store: S) -> HotpVerifier<S> {
+        HotpVerifier {
+            store,
+            look_ahead: 10,
+            policy: ThrottlePolicy::default(),
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<S: VerifierStore, C: Clock> HotpVerifier<S, C> {
+    /// Accept codes up to `look_ahead` counters after the stored one.
+    pub fn look_ahead(mut self, look_ahead: u64) -> Self {
+        self.look_ahead = look_ahead;
+        self
+    }
+
+    /// Throttle failed verifications with `policy`.
+    pub fn throttle(mut self, policy: ThrottlePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Read the current time of throttling from `clock`.
+    pub fn clock<C2: Clock>(self, clock: C2) -> HotpVerifier<S, C2> {
+        HotpVerifier {
+            store: self.store,
+            look_ahead: self.look_ahead,
+            policy: self.policy,
+            clock,
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// Verify `code` of account `id` configured by `hotp`. The counter
+    /// of `hotp` is ignored in favor of the stored one.
+    ///
+    /// Throttling errors are the same as [`TotpVerifier::verify`].
+    pub fn verify(&mut self, id: &str, hotp: &Hotp, code: &str) -> Result<bool, OtpError> {
+        let mut state = self.store.load(id)?;
+        let now = self.clock.now_unix();
+        self.policy.check(state.failures, state.last_failure, now)?;
+        let mut hotp = hotp.clone();
+        hotp.set_counter(state.counter);
+        let ok = hotp.verify(code, self.look_ahead)?;
+        if ok {
+            state.counter = hotp.counter();
+            state.failures = 0;
+        } else {
+            state.failures = state.failures.saturating_add(1);
+            state.last_failure = now;
+        }
+        self.store.save(id, &state)?;
+        Ok(ok)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::{Clock, MockClock};
+    use crate::error::OtpError;
+    use crate::hotp::Hotp;
+    use crate::throttle::ThrottlePolicy;
+    use crate::totp::Totp;
+
+    use super::{HotpVerifier, MemoryStore, RotatedSecret, TotpRotation, TotpVerifier, VerifierStore};
+
+    #[test]
+    fn test_totp_verifier() {
+        let clock = MockClock::new(1111111109 + 30);
+        let totp = Totp::builder().secret(b"12345678901234567890").digits(8).build().unwrap();
+        let policy = ThrottlePolicy { base_delay: 5, max_delay: 5, lockout_threshold: Some(3) };
+        let mut verifier = TotpVerifier::new(MemoryStore::new()).throttle(policy).clock(&clock);
+
+        let matched = verifier.verify("alice", &totp, "07081804").unwrap().unwrap();
+        assert_eq!(matched.offset, -1);
+        let state = verifier.store().load("alice").unwrap();
+        assert_eq!(state.last_step, Some(37037036));
+        assert_eq!(state.drift, -1);
+
+        // replay is rejected and counted as a failure
+        assert_eq!(verifier.verify("alice", &totp, "07081804").unwrap(), None);
+        assert_eq!(verifier.verify("alice", &totp, "07081804"), Err(OtpError::Throttled(5)));
+        assert_eq!(verifier.store().load("alice").unwrap().failures, 1);
+    }
+
+    #[test]
+    fn test_hotp_verifier() {
+        let clock = MockClock::new(1000);
+        let hotp = Hotp::builder().secret(b"12345678901234567890").build().unwrap();
+        let policy = ThrottlePolicy { base_delay: 0, max_delay: 0, lockout_threshold: Some(2) };
+        let mut verifier = HotpVerifier::new(MemoryStore::new()).look_ahead(3).throttle(policy).clock(&clock);
+
+        // "969429" is the RFC 4226 code of counter 3
+        assert_eq!(verifier.verify("alice", &hotp, "969429"), Ok(true));
+        assert_eq!(verifier.store().load("alice").unwrap().counter, 4);
+        assert_eq!(verifier.verify("alice", &hotp, "969429"), Ok(false));
+        assert_eq!(verifier.verify("alice", &hotp, "338314"), Ok(true));
+        assert_eq!(verifier.verify("alice", &hotp, "000000"), Ok(false));
+        assert_eq!(verifier.verify("alice", &hotp, "000000"), Ok(false));
+        assert_eq!(verifier.verify("alice", &hotp, "254676"), Err(OtpError::LockedOut));
+    }
+
+    #[test]
+    fn test_burn() {
+        let clock = MockClock::new(1111111109);
+        let totp = Totp::builder().secret(b"12345678901234567890").digits(8).build().unwrap();
+        let mut verifier = TotpVerifier::new(MemoryStore::new()).clock(&clock);
+        assert_eq!(verifier.burn("alice", 37037036), Ok(true));
+        assert_eq!(verifier.burn("alice", 37037036), Ok(false));
+        assert_eq!(verifier.store().is_consumed("alice", 37037035), Ok(true));
+        assert_eq!(verifier.store().is_consumed("alice", 37037037), Ok(false));
+        assert_eq!(verifier.verify("alice", &totp, "07081804"), Ok(None));
+        assert_eq!(verifier.store().is_consumed("bob", 37037036), Ok(false));
+        assert!(verifier.verify("bob", &totp, "07081804").unwrap().is_some());
+        assert_eq!(verifier.store().is_consumed("bob", 37037036), Ok(true));
+    }
+
+    #[test]
+    fn test_grace_period() {
+        let clock = MockClock::new(1111111112);
+        let totp = Totp::builder().secret(b"12345678901234567890").digits(8).build().unwrap();
+        let mut verifier = TotpVerifier::new(MemoryStore::new()).window(0).clock(&clock);
+        assert_eq!(verifier.verify("alice", &totp, "07081804"), Ok(None));
+        let mut verifier = verifier.grace_period(5);
+        assert_eq!(verifier.verify("bob", &totp, "07081804").unwrap().unwrap().offset, -1);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let clock = MockClock::new(1111111109);
+        let old = Totp::builder().secret(b"12345678901234567890").digits(8).clock(&clock).build().unwrap();
+        let new = Totp::builder().secret(b"abcdefghijklmnopqrst").digits(8).clock(&clock).build().unwrap();
+        let rotation = TotpRotation::new(old.clone(), new.clone(), 90);
+        assert_eq!(rotation.retire_at, 1111111199);
+        let mut verifier = TotpVerifier::new(MemoryStore::new()).window(0).clock(&clock);
+
+        // "07081804" is the RFC 6238 code of the old secret at 1111111109
+        let (secret, matched) = verifier.verify_rotation("alice", &rotation, "07081804").unwrap().unwrap();
+        assert_eq!((secret, matched.step), (RotatedSecret::Old, 37037036));
+        assert_eq!(verifier.verify_rotation("alice", &rotation, "07081804"), Ok(None));
+        clock.advance(30);
+        let code = new.generate().unwrap();
+        assert_eq!(verifier.verify_rotation("alice", &rotation, code.as_str()).unwrap().unwrap().0, RotatedSecret::New);
+
+        clock.advance(60);
+        assert!(rotation.is_retired_at(clock.now_unix()));
+        assert_eq!(verifier.verify_rotation("carol", &rotation, old.generate().unwrap().as_str()), Ok(None));
+        let code = new.generate().unwrap();
+        assert_eq!(verifier.verify_rotation("bob", &rotation, code.as_str()).unwrap().unwrap().0, RotatedSecret::New);
+        assert_eq!(rotation.into_new().secret(), new.secret());
+    }
+}
@@ -0,0 +1,159 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+#[cfg(feature = "rayon")]
+use crate::code::constant_time_eq;
+use crate::code::normalize_code;
+use crate::error::OtpError;
+use crate::hotp::Hotp;
+#[cfg(feature = "rayon")]
+use crate::otp::{Algorithm, PreparedKey, DIGITS_RANGE};
+#[cfg(feature = "std")]
+use crate::totp::Totp;
+
+/// The time step at which a TOTP code matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StepMatch {
+    /// The matched time step, i.e. the counter of the underlying HOTP.
+    pub step: u64,
+    /// The matched step relative to the current step, e.g. -1 when the
+    /// code of the previous period matched.
+    pub offset: i64,
+}
+
+/// Verify a TOTP code of the current system time, accepting the codes of
+/// up to `window` time steps before and after the current one.
+///
+/// The RFC 6238 defaults are used: `t0` as 0, 30 seconds interval and
+/// HMAC-SHA-1. `digits` is the code length of the account; a `code` of
+/// another length after [`normalize_code`] matches nothing, so a client
+/// cannot make the code shorter to guess it. Every step in the window is
+/// always checked, so the time taken does not tell which step matched.
+#[cfg(feature = "std")]
+pub fn verify_totp(key: impl AsRef<[u8]>, code: &str, digits: usize, window: u64) -> Result<Option<StepMatch>, OtpError> {
+    let code = normalize_code(code);
+    let totp = Totp::builder().secret(key).digits(digits).build()?;
+    if code.len() != digits {
+        return Ok(None);
+    }
+    Ok(totp.verify_window(&code, window))
+}
+
+/// Verify an HMAC-SHA-1 HOTP code against the counters `counter` to
+/// `counter + look_ahead`, as the resynchronization of RFC 4226 §7.4
+/// describes. `digits` is the code length of the account; a `code` of
+/// another length after [`normalize_code`] matches nothing.
+///
+/// On success it returns the new counter to persist, which is the one
+/// after the matched counter, so the same code cannot be accepted again.
+/// A code of the counter `u64::MAX` returns [`OtpError::CounterOverflow`].
+pub fn verify_hotp(key: impl AsRef<[u8]>, code: &str, digits: usize, counter: u64, look_ahead: u64) -> Result<Option<u64>, OtpError> {
+    let code = normalize_code(code);
+    let mut hotp = Hotp::builder().secret(key).counter(counter).digits(digits).build()?;
+    if code.len() != digits {
+        return Ok(None);
+    }
+    if hotp.verify(&code, look_ahead)? {
+        Ok(Some(hotp.counter()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// [`verify_hotp`] with the counters checked across the threads of the
+/// rayon global pool, for look-ahead windows of thousands of counters such
+/// as recovering a hardware token that was pressed many times.
+///
+/// Every counter in the window is checked and the smallest matching one
+/// wins, so the result is the same as the one of [`verify_hotp`].
+#[cfg(feature = "rayon")]
+pub fn verify_hotp_parallel(key: impl AsRef<[u8]>, code: &str, digits: usize, counter: u64, look_ahead: u64) -> Result<Option<u64>, OtpError> {
+    let code = normalize_code(code);
+    if !DIGITS_RANGE.contains(&digits) {
+        return Err(OtpError::InvalidDigits(digits));
+    }
+    if code.len() != digits {
+        return Ok(None);
+    }
+    let key = PreparedKey::new(key, Algorithm::Sha1)?;
+    let last = counter.saturating_add(look_ahead);
+    let matched = (counter..=last)
+        .into_par_iter()
+        .filter(|&c| {
+            let expected = key.hotp(c, digits).expect("digits are checked");
+            constant_time_eq(expected.as_str(), &code)
+        })
+        .min();
+    matched.map(|c| c.checked_add(1).ok_or(OtpError::CounterOverflow)).transpose()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::otp::totp;
+
+    use super::{verify_hotp, verify_totp};
+
+    #[test]
+    fn test_verify_totp() {
+        let key = b"12345678901234567890";
+        let code = totp(key, 0, 30, 6).unwrap();
+        let matched = verify_totp(key, code.as_str(), 6, 1).unwrap().unwrap();
+        // the step may change between generating and verifying the code
+        assert!(matched.offset == 0 || matched.offset == -1);
+        assert_eq!(verify_totp(key, "000000", 6, 0).unwrap().is_some(), code == "000000");
+        // the 6 digits of a code of 8 digits do not match
+        let long = totp(key, 0, 30, 8).unwrap();
+        assert!(verify_totp(key, long.as_str(), 8, 1).unwrap().is_some());
+        assert_eq!(verify_totp(key, &long.as_str()[2..], 8, 1).unwrap(), None);
+        assert_eq!(verify_totp(key, "12345", 6, 1).unwrap(), None);
+        assert!(verify_totp(key, "12345", 5, 1).is_err());
+    }
+
+    #[test]
+    fn test_verify_hotp() {
+        // "162583" is the RFC 4226 code of counter 7
+        let key = b"12345678901234567890";
+        assert_eq!(verify_hotp(key, "162583", 6, 7, 0).unwrap(), Some(8));
+        assert_eq!(verify_hotp(key, "162 583", 6, 7, 0).unwrap(), Some(8));
+        assert_eq!(verify_hotp(key, "162583", 6, 4, 3).unwrap(), Some(8));
+        assert_eq!(verify_hotp(key, "162583", 6, 4, 2).unwrap(), None);
+        assert_eq!(verify_hotp(key, "162583", 6, 8, 10).unwrap(), None);
+        // the last 6 digits of the code of 8 digits
+        assert_eq!(verify_hotp(key, "162583", 8, 7, 0).unwrap(), None);
+        assert!(verify_hotp(key, "162583", 5, 7, 0).is_err());
+        let last = crate::otp::hotp(key, u64::MAX, 6).unwrap();
+        assert_eq!(verify_hotp(key, last.as_str(), 6, u64::MAX - 2, 5), Err(crate::error::OtpError::CounterOverflow));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_verify_hotp_parallel() {
+        use super::verify_hotp_parallel;
+
+        let key = b"12345678901234567890";
+        assert_eq!(verify_hotp_parallel(key, "162583", 6, 0, 10000).unwrap(), Some(8));
+        assert_eq!(verify_hotp_parallel(key, "162 583", 6, 7, 0).unwrap(), Some(8));
+        assert_eq!(verify_hotp_parallel(key, "162583", 6, 8, 1000).unwrap(), verify_hotp(key, "162583", 6, 8, 1000).unwrap());
+        assert_eq!(verify_hotp_parallel(key, "162583", 8, 7, 0).unwrap(), None);
+        assert!(verify_hotp_parallel(key, "12345", 5, 0, 10).is_err());
+        let last = crate::otp::hotp(key, u64::MAX, 6).unwrap();
+        assert_eq!(verify_hotp_parallel(key, last.as_str(), 6, u64::MAX - 2, 5), Err(crate::error::OtpError::CounterOverflow));
+    }
+}
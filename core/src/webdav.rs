@@ -0,0 +1,175 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! A WebDAV server as the remote of a vault, e.g. Nextcloud or a plain
+//! Apache `mod_dav`.
+//!
+//! The vault is one file on the server, fetched with `GET` and replaced
+//! with a conditional `PUT`, so the server must support ETags, as
+//! RFC 4918 requires.
+//!
+//! ```no_run
+//! use yotp_core::vault::Vault;
+//! use yotp_core::webdav::WebDavBackend;
+//!
+//! let remote = WebDavBackend::with_basic_auth("https://dav.example.com/yotp/accounts.vault", "alice", "app password");
+//! let mut vault = Vault::open("accounts.vault", "passphrase").unwrap();
+//! vault.sync(&remote).unwrap();
+//! ```
+
+use crate::sync::{call, etag_of, remote_of, Remote, SyncBackend, SyncError};
+
+/// The vault file at a WebDAV URL.
+pub struct WebDavBackend {
+    url: String,
+    authorization: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl WebDavBackend {
+    /// The file at `url`, without authentication.
+    pub fn new(url: &str) -> WebDavBackend {
+        WebDavBackend { url: url.to_owned(), authorization: None, agent: ureq::Agent::new() }
+    }
+
+    /// The file at `url`, with HTTP basic authentication.
+    pub fn with_basic_auth(url: &str, user: &str, password: &str) -> WebDavBackend {
+        let credentials = crate::base64::encode(format!("{}:{}", user, password).as_bytes());
+        WebDavBackend { authorization: Some(format!("Basic {}", credentials)), ..WebDavBackend::new(url) }
+    }
+
+    /// The URL of the file.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn request(&self, method: &str) -> ureq::Request {
+        let request = self.agent.request(method, &self.url);
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+}
+
+impl SyncBackend for WebDavBackend {
+    fn pull(&self) -> Result<Option<Remote>, SyncError> {
+        call(self.request("GET"), None)?.map(remote_of).transpose()
+    }
+
+    fn etag(&self) -> Result<Option<String>, SyncError> {
+        call(self.request("HEAD"), None)?.map(|response| etag_of(&response)).transpose()
+    }
+
+    fn push(&self, data: &[u8], etag: Option<&str>) -> Result<String, SyncError> {
+        let request = self.request("PUT").set("Content-Type", "application/octet-stream");
+        let request = match etag {
+            Some(etag) => request.set("If-Match", etag),
+            None => request.set("If-None-Match", "*"),
+        };
+        let response = call(request, Some(data))?.ok_or_else(|| SyncError::Remote("the collection of the vault does not exist".into()))?;
+        // servers may leave out the ETag of a PUT, as the body may be
+        // altered when stored. A HEAD could see the push of another
+        // client already, whose ETag the next push would then overwrite.
+        response
+            .header("ETag")
+            .map(str::to_owned)
+            .ok_or_else(|| SyncError::Remote("the server returns no ETag for the pushed vault, pull it again".into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    use crate::sync::{SyncBackend, SyncError};
+
+    use super::WebDavBackend;
+
+    /// A server answering one connection per response, which returns the
+    /// requests it got: the request line and the headers in lower case,
+    /// then the body.
+    fn serve(responses: &[&'static str]) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/yotp/accounts.vault", listener.local_addr().unwrap());
+        let responses = responses.to_vec();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    let line = line.to_lowercase();
+                    if let Some(value) = line.strip_prefix("content-length: ") {
+                        len = value.trim().parse().unwrap();
+                    }
+                    request.push_str(&line);
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+                requests.push(request);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn test_requests() {
+        let (url, server) = serve(&[
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 201 Created\r\nETag: \"1\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"1\"\r\nContent-Length: 5\r\nConnection: close\r\n\r\nvault",
+            "HTTP/1.1 412 Precondition Failed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let remote = WebDavBackend::with_basic_auth(&url, "alice", "secret");
+        assert_eq!(remote.pull().unwrap(), None);
+        assert_eq!(remote.push(b"vault", None).unwrap(), "\"1\"");
+        let pulled = remote.pull().unwrap().unwrap();
+        assert_eq!((pulled.data.as_slice(), pulled.etag.as_str()), (&b"vault"[..], "\"1\""));
+        assert!(matches!(remote.push(b"newer", Some("\"1\"")), Err(SyncError::Conflict)));
+        assert!(matches!(remote.etag(), Err(SyncError::Unauthorized)));
+
+        let requests = server.join().unwrap();
+        assert!(requests.iter().all(|request| request.contains("\r\nauthorization: basic ywxpy2u6c2vjcmv0\r\n")));
+        assert!(requests[0].starts_with("get /yotp/accounts.vault http/1.1\r\n"));
+        assert!(requests[1].starts_with("put ") && requests[1].contains("\r\nif-none-match: *\r\n") && requests[1].ends_with("\r\nvault"));
+        assert!(requests[3].contains("\r\nif-match: \"1\"\r\n") && requests[3].ends_with("\r\nnewer"));
+        assert!(requests[4].starts_with("head "));
+    }
+
+    #[test]
+    fn test_push_without_etag() {
+        // the server answers the PUT only, so no HEAD may follow it
+        let (url, server) = serve(&["HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n"]);
+        let remote = WebDavBackend::new(&url);
+        assert!(matches!(remote.push(b"vault", Some("\"1\"")), Err(SyncError::Remote(_))));
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("authorization"));
+    }
+}
@@ -0,0 +1,722 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The credentials of the OATH applet of a YubiKey, over PC/SC as Yubico
+//! Authenticator does.
+//!
+//! The secrets stay on the YubiKey, which computes the codes. Accounts of
+//! a vault are added with [`YubiKeyOath::put`], and the credentials are
+//! named after the issuer and the account name as accounts are, see
+//! [`OathCredential::display_label`]. A TOTP credential is also a
+//! [`KeyBackend`] with [`YubiKeyOath::key`].
+//!
+//! Credentials added with `touch` compute codes only after the YubiKey
+//! is touched, and an applet protected with a password computes nothing
+//! before [`YubiKeyOath::unlock`].
+//!
+//! ```no_run
+//! use yotp_core::account::Account;
+//! use yotp_core::uri::OtpAuthUri;
+//! use yotp_core::yubikey::YubiKeyOath;
+//!
+//! let mut oath = YubiKeyOath::open().unwrap();
+//! if oath.is_locked() {
+//!     oath.unlock("password").unwrap();
+//! }
+//! let account = Account::new(OtpAuthUri::parse("otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP").unwrap());
+//! oath.put(&account, false).unwrap();
+//! for (credential, code) in oath.calculate_all(1700000000).unwrap() {
+//!     println!("{}: {}", credential.display_label(), code.map_or("touch or HOTP".into(), |code| code.to_string()));
+//! }
+//! ```
+
+use std::ffi::CString;
+
+use pcsc::{Card, Context, Protocols, Scope, ShareMode};
+use sha1::Sha1;
+use zeroize::Zeroizing;
+
+use crate::account::Account;
+use crate::code::{constant_time_eq, Code};
+use crate::error::OtpError;
+use crate::key_backend::KeyBackend;
+use crate::otp::{big_endian_u64, format_decimal, hmac, time_counter, Algorithm};
+use crate::uri::OtpType;
+
+const AID: [u8; 7] = [0xa0, 0x00, 0x00, 0x05, 0x27, 0x21, 0x01];
+
+const INS_PUT: u8 = 0x01;
+const INS_DELETE: u8 = 0x02;
+const INS_SELECT: u8 = 0xa4;
+const INS_LIST: u8 = 0xa1;
+const INS_CALCULATE: u8 = 0xa2;
+const INS_VALIDATE: u8 = 0xa3;
+const INS_CALCULATE_ALL: u8 = 0xa4;
+const INS_SEND_REMAINING: u8 = 0xa5;
+
+const TAG_NAME: u8 = 0x71;
+const TAG_NAME_LIST: u8 = 0x72;
+const TAG_KEY: u8 = 0x73;
+const TAG_CHALLENGE: u8 = 0x74;
+const TAG_RESPONSE: u8 = 0x75;
+const TAG_TRUNCATED: u8 = 0x76;
+const TAG_HOTP: u8 = 0x77;
+const TAG_PROPERTY: u8 = 0x78;
+const TAG_VERSION: u8 = 0x79;
+const TAG_IMF: u8 = 0x7a;
+const TAG_ALGORITHM: u8 = 0x7b;
+const TAG_TOUCH: u8 = 0x7c;
+
+const PROPERTY_REQUIRE_TOUCH: u8 = 0x02;
+const DEFAULT_PERIOD: u64 = 30;
+/// The longest name the applet stores, in bytes.
+const MAX_NAME_LEN: usize = 64;
+/// The applet rejects shorter keys, which are padded with zeros as the HMAC
+/// pads them anyway.
+const MIN_KEY_LEN: usize = 14;
+
+/// A credential of the OATH applet. The secret cannot be read back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OathCredential {
+    /// The name on the YubiKey, `[period/][issuer:]account`.
+    pub name: String,
+    pub issuer: Option<String>,
+    pub account: String,
+    pub otp_type: OtpType,
+    pub algorithm: Algorithm,
+    /// The period of TOTP codes in seconds, 30 for HOTP credentials.
+    pub period: u64,
+}
+
+impl OathCredential {
+    /// The credential of `name` on the YubiKey. A prefix `0/` is not a
+    /// period, but a part of the account name.
+    fn parse(name: &str, otp_type: OtpType, algorithm: Algorithm) -> OathCredential {
+        let mut rest = name;
+        let mut period = DEFAULT_PERIOD;
+        if otp_type == OtpType::Totp {
+            if let Some((prefix, after)) = name.split_once('/') {
+                if let Some(value) = prefix.parse().ok().filter(|&value| value > 0) {
+                    period = value;
+                    rest = after;
+                }
+            }
+        }
+        let (issuer, account) = match rest.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.to_owned()), account.to_owned()),
+            None => (None, rest.to_owned()),
+        };
+        OathCredential { name: name.to_owned(), issuer, account, otp_type, algorithm, period }
+    }
+
+    /// The name to show for the credential, as [`Account::display_label`]
+    /// of an account without a label.
+    pub fn display_label(&self) -> String {
+        match &self.issuer {
+            Some(issuer) => format!("{}: {}", issuer, self.account),
+            None => self.account.clone(),
+        }
+    }
+}
+
+/// How the applet is reached, the PC/SC card or a fake in the tests.
+trait Transport {
+    /// Send `apdu` and return the response with its status word.
+    fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, OtpError>;
+}
+
+impl Transport for Card {
+    fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, OtpError> {
+        let mut buffer = Zeroizing::new([0; pcsc::MAX_BUFFER_SIZE]);
+        Ok(Card::transmit(self, apdu, &mut buffer[..]).map_err(pcsc_error)?.to_vec())
+    }
+}
+
+/// A session with the OATH applet of a YubiKey.
+pub struct YubiKeyOath {
+    transport: Box<dyn Transport>,
+    version: (u8, u8, u8),
+    /// The salt of the key of the password.
+    salt: Vec<u8>,
+    /// The challenge of a locked applet and its algorithm.
+    challenge: Option<(Vec<u8>, Algorithm)>,
+}
+
+impl YubiKeyOath {
+    /// The names of the PC/SC readers, e.g. `Yubico YubiKey OTP+FIDO+CCID
+    /// 00 00`.
+    ///
+    /// Returns [`OtpError::YubiKeyError`] if the PC/SC service is not
+    /// running.
+    pub fn readers() -> Result<Vec<String>, OtpError> {
+        let context = Context::establish(Scope::User).map_err(pcsc_error)?;
+        match context.list_readers_owned() {
+            Ok(readers) => Ok(readers.iter().map(|reader| reader.to_string_lossy().into_owned()).collect()),
+            Err(pcsc::Error::NoReadersAvailable) => Ok(Vec::new()),
+            Err(e) => Err(pcsc_error(e)),
+        }
+    }
+
+    /// The applet of the first YubiKey found.
+    ///
+    /// Returns [`OtpError::YubiKeyError`] if no YubiKey is plugged in, or
+    /// for the reasons of [`YubiKeyOath::open_reader`].
+    pub fn open() -> Result<YubiKeyOath, OtpError> {
+        let reader = YubiKeyOath::readers()?
+            .into_iter()
+            .find(|reader| reader.to_lowercase().contains("yubi"))
+            .ok_or_else(|| yubikey_error("no YubiKey found"))?;
+        YubiKeyOath::open_reader(&reader)
+    }
+
+    /// The applet of the YubiKey in the reader named `reader`.
+    ///
+    /// Returns [`OtpError::YubiKeyError`] if the reader has no card or the
+    /// card has no OATH applet.
+    pub fn open_reader(reader: &str) -> Result<YubiKeyOath, OtpError> {
+        let context = Context::establish(Scope::User).map_err(pcsc_error)?;
+        let name = CString::new(reader).map_err(|_| yubikey_error("invalid reader name"))?;
+        let card = context.connect(&name, ShareMode::Shared, Protocols::ANY).map_err(pcsc_error)?;
+        YubiKeyOath::select(Box::new(card))
+    }
+
+    fn select(transport: Box<dyn Transport>) -> Result<YubiKeyOath, OtpError> {
+        let response = send(&*transport, INS_SELECT, 0x04, 0x00, &AID)?;
+        let mut oath = YubiKeyOath { transport, version: (0, 0, 0), salt: Vec::new(), challenge: None };
+        let mut challenge = None;
+        let mut algorithm = Algorithm::Sha1;
+        for (tag, value) in parse_tlvs(&response)? {
+            match (tag, value) {
+                (TAG_VERSION, [major, minor, patch]) => oath.version = (*major, *minor, *patch),
+                (TAG_NAME, _) => oath.salt = value.to_vec(),
+                (TAG_CHALLENGE, _) => challenge = Some(value.to_vec()),
+                (TAG_ALGORITHM, [byte]) => algorithm = algorithm_of(*byte)?,
+                _ => {}
+            }
+        }
+        oath.challenge = challenge.map(|challenge| (challenge, algorithm));
+        Ok(oath)
+    }
+
+    /// The firmware version of the applet.
+    pub fn version(&self) -> (u8, u8, u8) {
+        self.version
+    }
+
+    /// Whether the applet has a password and is not unlocked yet.
+    pub fn is_locked(&self) -> bool {
+        self.challenge.is_some()
+    }
+
+    /// Unlock an applet protected with `password`. The YubiKey proves it
+    /// knows the password too, so that a fake one cannot ask for codes.
+    ///
+    /// Returns [`OtpError::YubiKeyError`] if `password` is wrong.
+    pub fn unlock(&mut self, password: &str) -> Result<(), OtpError> {
+        let Some((challenge, algorithm)) = &self.challenge else {
+            return Ok(());
+        };
+        let key = password_key(password, &self.salt);
+        let mut ours = [0; 8];
+        getrandom::fill(&mut ours).map_err(|_| OtpError::RandomError)?;
+        let data = [tlv(TAG_RESPONSE, &hmac(&key[..], challenge, *algorithm)), tlv(TAG_CHALLENGE, &ours)].concat();
+        let response = match send(&*self.transport, INS_VALIDATE, 0x00, 0x00, &data) {
+            Ok(response) => response,
+            Err(OtpError::YubiKeyError(_)) => return Err(yubikey_error("wrong password")),
+            Err(e) => return Err(e),
+        };
+        let proof = find_tag(&response, TAG_RESPONSE)?;
+        if !constant_time_eq(proof, hmac(&key[..], &ours, *algorithm)) {
+            return Err(yubikey_error("the YubiKey does not know the password"));
+        }
+        self.challenge = None;
+        Ok(())
+    }
+
+    /// The credentials of the applet.
+    pub fn list(&self) -> Result<Vec<OathCredential>, OtpError> {
+        let response = self.send(INS_LIST, 0x00, 0x00, &[])?;
+        parse_tlvs(&response)?
+            .into_iter()
+            .filter(|(tag, _)| *tag == TAG_NAME_LIST)
+            .map(|(_, value)| {
+                let (&kind, name) = value.split_first().ok_or_else(|| yubikey_error("empty credential"))?;
+                let otp_type = if kind & 0xf0 == 0x10 { OtpType::Hotp } else { OtpType::Totp };
+                let name = std::str::from_utf8(name).map_err(|_| yubikey_error("credential name is not UTF-8"))?;
+                Ok(OathCredential::parse(name, otp_type, algorithm_of(kind)?))
+            })
+            .collect()
+    }
+
+    /// Add `account` to the applet, replacing the credential of the same
+    /// name. With `touch`, its codes are computed only after the YubiKey
+    /// is touched. The metadata of the account stays in the vault.
+    ///
+    /// Returns [`OtpError::ExportError`] if the name is longer than the
+    /// 64 bytes the applet stores or the account has more than 8 digits.
+    pub fn put(&self, account: &Account, touch: bool) -> Result<OathCredential, OtpError> {
+        let uri = &account.uri;
+        let mut name = String::new();
+        if uri.otp_type == OtpType::Totp && uri.period != DEFAULT_PERIOD {
+            name.push_str(&format!("{}/", uri.period));
+        }
+        if let Some(issuer) = &uri.issuer {
+            name.push_str(&format!("{}:", issuer));
+        }
+        name.push_str(&uri.account);
+        if name.len() > MAX_NAME_LEN {
+            return Err(OtpError::ExportError(format!("the name {} is longer than {} bytes", name, MAX_NAME_LEN)));
+        }
+        if !(6..=8).contains(&uri.digits) {
+            return Err(OtpError::ExportError(format!("the YubiKey has no codes of {} digits", uri.digits)));
+        }
+        let kind = match uri.otp_type {
+            OtpType::Hotp => 0x10,
+            OtpType::Totp => 0x20,
+        } | algorithm_byte(uri.algorithm);
+        let key = shorten_key(uri.secret.as_bytes(), uri.algorithm);
+        let mut data = Zeroizing::new(tlv(TAG_NAME, name.as_bytes()));
+        data.extend_from_slice(&tlv(TAG_KEY, &Zeroizing::new([&[kind, uri.digits as u8][..], &key].concat())));
+        if touch {
+            data.extend_from_slice(&[TAG_PROPERTY, PROPERTY_REQUIRE_TOUCH]);
+        }
+        if uri.otp_type == OtpType::Hotp && uri.counter > 0 {
+            let counter = u32::try_from(uri.counter).map_err(|_| OtpError::ExportError("the HOTP counter is too large".into()))?;
+            data.extend_from_slice(&tlv(TAG_IMF, &counter.to_be_bytes()));
+        }
+        self.send(INS_PUT, 0x00, 0x00, &data)?;
+        Ok(OathCredential::parse(&name, uri.otp_type, uri.algorithm))
+    }
+
+    /// Remove `credential` from the applet.
+    pub fn delete(&self, credential: &OathCredential) -> Result<(), OtpError> {
+        self.send(INS_DELETE, 0x00, 0x00, &tlv(TAG_NAME, credential.name.as_bytes()))?;
+        Ok(())
+    }
+
+    /// The code of `credential` at the time `t` in seconds since the UNIX
+    /// epoch. The code of an HOTP credential is of the counter kept on the
+    /// YubiKey, which is then incremented.
+    ///
+    /// Waits for the YubiKey to be touched if the credential requires it,
+    /// and returns [`OtpError::YubiKeyError`] if it is not touched in
+    /// time.
+    pub fn calculate(&self, credential: &OathCredential, t: u64) -> Result<Code, OtpError> {
+        let response = self.send(INS_CALCULATE, 0x00, 0x01, &calculate_data(credential, t)?)?;
+        truncated_code(find_tag(&response, TAG_TRUNCATED)?)
+    }
+
+    /// The codes of all the credentials at the time `t`, without waiting
+    /// for a touch. The code of a credential is `None` if it is HOTP or
+    /// requires a touch, for [`YubiKeyOath::calculate`].
+    pub fn calculate_all(&self, t: u64) -> Result<Vec<(OathCredential, Option<Code>)>, OtpError> {
+        let credentials = self.list()?;
+        let challenge = big_endian_u64(time_counter(t, 0, DEFAULT_PERIOD)?);
+        let response = self.send(INS_CALCULATE_ALL, 0x00, 0x01, &tlv(TAG_CHALLENGE, &challenge))?;
+        let tlvs = parse_tlvs(&response)?;
+        let mut codes = Vec::with_capacity(credentials.len());
+        for credential in credentials {
+            let result = tlvs
+                .chunks(2)
+                .find(|pair| pair[0] == (TAG_NAME, credential.name.as_bytes()))
+                .and_then(|pair| pair.get(1))
+                .copied();
+            let code = match result {
+                // codes of other periods are of the wrong time
+                Some((TAG_TRUNCATED, _)) if credential.period != DEFAULT_PERIOD => Some(self.calculate(&credential, t)?),
+                Some((TAG_TRUNCATED, value)) => Some(truncated_code(value)?),
+                Some((TAG_HOTP | TAG_TOUCH, _)) | None => None,
+                Some((tag, _)) => return Err(yubikey_error(&format!("unexpected tag {:#04x}", tag))),
+            };
+            codes.push((credential, code));
+        }
+        Ok(codes)
+    }
+
+    /// The TOTP `credential` as a [`KeyBackend`], whose HMACs the YubiKey
+    /// computes, e.g. for [`totp_at_with_backend`](crate::totp_at_with_backend).
+    pub fn key(&self, credential: &OathCredential) -> OathKey<'_> {
+        OathKey { oath: self, credential: credential.clone() }
+    }
+
+    fn send(&self, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<Vec<u8>, OtpError> {
+        send(&*self.transport, ins, p1, p2, data)
+    }
+}
+
+/// A TOTP credential of [`YubiKeyOath::key`].
+pub struct OathKey<'a> {
+    oath: &'a YubiKeyOath,
+    credential: OathCredential,
+}
+
+impl KeyBackend for OathKey<'_> {
+    fn algorithm(&self) -> Algorithm {
+        self.credential.algorithm
+    }
+
+    /// The HMAC of `message`, the counter of a time step.
+    ///
+    /// Returns [`OtpError::YubiKeyError`] for HOTP credentials, whose
+    /// counter the YubiKey keeps, see [`YubiKeyOath::calculate`].
+    fn hmac(&self, message: &[u8]) -> Result<Vec<u8>, OtpError> {
+        if self.credential.otp_type == OtpType::Hotp {
+            return Err(yubikey_error("the YubiKey keeps the counter of HOTP credentials"));
+        }
+        let data = [tlv(TAG_NAME, self.credential.name.as_bytes()), tlv(TAG_CHALLENGE, message)].concat();
+        let response = self.oath.send(INS_CALCULATE, 0x00, 0x00, &data)?;
+        // the number of digits, then the HMAC
+        let value = find_tag(&response, TAG_RESPONSE)?;
+        Ok(value.get(1..).unwrap_or_default().to_vec())
+    }
+}
+
+/// Send the command `ins`, collecting the parts of long responses.
+fn send(transport: &dyn Transport, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<Vec<u8>, OtpError> {
+    let len = u8::try_from(data.len()).map_err(|_| yubikey_error("command is too long"))?;
+    let apdu = Zeroizing::new([&[0x00, ins, p1, p2, len][..], data].concat());
+    let mut response = transport.transmit(&apdu)?;
+    let mut body = Vec::new();
+    loop {
+        if response.len() < 2 {
+            return Err(yubikey_error("truncated response"));
+        }
+        let status = response.split_off(response.len() - 2);
+        body.extend_from_slice(&response);
+        match (status[0], status[1]) {
+            (0x90, 0x00) => return Ok(body),
+            (0x61, _) => response = transport.transmit(&[0x00, INS_SEND_REMAINING, 0x00, 0x00])?,
+            (0x69, 0x82) => return Err(yubikey_error("the applet is locked, unlock it with its password")),
+            (0x69, 0x84) => return Err(yubikey_error("no such credential")),
+            (0x69, 0x85) => return Err(yubikey_error("the YubiKey was not touched")),
+            (0x6a, 0x84) => return Err(yubikey_error("the applet is full")),
+            (sw1, sw2) => return Err(yubikey_error(&format!("status {:02x}{:02x}", sw1, sw2))),
+        }
+    }
+}
+
+fn calculate_data(credential: &OathCredential, t: u64) -> Result<Vec<u8>, OtpError> {
+    let challenge = match credential.otp_type {
+        OtpType::Totp => big_endian_u64(time_counter(t, 0, credential.period)?).to_vec(),
+        OtpType::Hotp => Vec::new(),
+    };
+    Ok([tlv(TAG_NAME, credential.name.as_bytes()), tlv(TAG_CHALLENGE, &challenge)].concat())
+}
+
+/// The code of a truncated response: the number of digits and the 31-bit
+/// value of the dynamic truncation.
+fn truncated_code(value: &[u8]) -> Result<Code, OtpError> {
+    match value {
+        [digits, a, b, c, d] => Ok(format_decimal(u32::from_be_bytes([*a, *b, *c, *d]) & 0x7fff_ffff, *digits as usize)),
+        _ => Err(yubikey_error("invalid code")),
+    }
+}
+
+/// The key of `password`, as the applet derives it.
+fn password_key(password: &str, salt: &[u8]) -> Zeroizing<[u8; 16]> {
+    let mut key = Zeroizing::new([0; 16]);
+    pbkdf2::pbkdf2_hmac::<Sha1>(password.as_bytes(), salt, 1000, &mut key[..]);
+    key
+}
+
+/// A key of the same HMACs as `key` that the applet accepts: hashed
+/// if longer than the block of the hash function, and padded to
+/// [`MIN_KEY_LEN`] bytes.
+fn shorten_key(key: &[u8], algorithm: Algorithm) -> Zeroizing<Vec<u8>> {
+    use sha2::Digest;
+
+    let block_len = if algorithm == Algorithm::Sha512 { 128 } else { 64 };
+    let mut key = if key.len() > block_len {
+        match algorithm {
+            Algorithm::Sha1 => Sha1::digest(key).to_vec(),
+            Algorithm::Sha256 => sha2::Sha256::digest(key).to_vec(),
+            Algorithm::Sha512 => sha2::Sha512::digest(key).to_vec(),
+        }
+    } else {
+        key.to_vec()
+    };
+    if key.len() < MIN_KEY_LEN {
+        key.resize(MIN_KEY_LEN, 0);
+    }
+    Zeroizing::new(key)
+}
+
+fn algorithm_byte(algorithm: Algorithm) -> u8 {
+    match algorithm {
+        Algorithm::Sha1 => 0x01,
+        Algorithm::Sha256 => 0x02,
+        Algorithm::Sha512 => 0x03,
+    }
+}
+
+fn algorithm_of(byte: u8) -> Result<Algorithm, OtpError> {
+    match byte & 0x0f {
+        0x01 => Ok(Algorithm::Sha1),
+        0x02 => Ok(Algorithm::Sha256),
+        0x03 => Ok(Algorithm::Sha512),
+        _ => Err(yubikey_error(&format!("unknown algorithm {:#04x}", byte))),
+    }
+}
+
+fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if value.len() >= 0x80 {
+        out.push(0x81);
+    }
+    out.push(value.len() as u8);
+    out.extend_from_slice(value);
+    out
+}
+
+fn parse_tlvs(mut data: &[u8]) -> Result<Vec<(u8, &[u8])>, OtpError> {
+    let invalid = || yubikey_error("invalid response");
+    let mut tlvs = Vec::new();
+    while let [tag, rest @ ..] = data {
+        let (len, rest) = match rest {
+            [0x81, len, rest @ ..] => (*len as usize, rest),
+            [0x82, high, low, rest @ ..] => (u16::from_be_bytes([*high, *low]) as usize, rest),
+            [len, rest @ ..] if *len < 0x80 => (*len as usize, rest),
+            _ => return Err(invalid()),
+        };
+        if rest.len() < len {
+            return Err(invalid());
+        }
+        tlvs.push((*tag, &rest[..len]));
+        data = &rest[len..];
+    }
+    Ok(tlvs)
+}
+
+fn find_tag(data: &[u8], tag: u8) -> Result<&[u8], OtpError> {
+    parse_tlvs(data)?
+        .into_iter()
+        .find(|(t, _)| *t == tag)
+        .map(|(_, value)| value)
+        .ok_or_else(|| yubikey_error("invalid response"))
+}
+
+fn pcsc_error(e: pcsc::Error) -> OtpError {
+    yubikey_error(&e.to_string())
+}
+
+fn yubikey_error(msg: &str) -> OtpError {
+    OtpError::YubiKeyError(msg.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use crate::account::Account;
+    use crate::error::OtpError;
+    use crate::otp::{big_endian_u64, dynamic_truncate, hmac, totp_at, totp_at_with_algorithm, Algorithm};
+    use crate::uri::{OtpAuthUri, OtpType};
+    use crate::{hotp, totp_at_with_backend};
+
+    use super::*;
+
+    /// The credentials as the applet keeps them: the name, the type and
+    /// algorithm byte, the digits, the key, touch and the HOTP counter.
+    type Stored = (Vec<u8>, u8, u8, Vec<u8>, bool, u64);
+
+    /// An OATH applet in memory, with the password `password` if locked.
+    struct FakeApplet {
+        credentials: RefCell<Vec<Stored>>,
+        locked: RefCell<bool>,
+    }
+
+    impl FakeApplet {
+        fn handle(&self, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<Vec<u8>, [u8; 2]> {
+            // the data of SELECT is the AID
+            let (data, touch) = match data.windows(2).position(|w| w == [TAG_PROPERTY, PROPERTY_REQUIRE_TOUCH]) {
+                // the property has no length
+                Some(i) if ins == INS_PUT => ([&data[..i], &data[i + 2..]].concat(), true),
+                _ => (data.to_vec(), false),
+            };
+            let tlvs = if (ins, p1) == (INS_SELECT, 0x04) { Vec::new() } else { parse_tlvs(&data).unwrap() };
+            let value = |tag| tlvs.iter().find(|(t, _)| *t == tag).map(|(_, value)| value.to_vec());
+            let mut credentials = self.credentials.borrow_mut();
+            let position = |name: &[u8]| credentials.iter().position(|c| c.0 == name);
+            if *self.locked.borrow() && ![INS_SELECT, INS_VALIDATE].contains(&ins) {
+                return Err([0x69, 0x82]);
+            }
+            match (ins, p1) {
+                (INS_SELECT, 0x04) => {
+                    let mut response = [tlv(TAG_VERSION, &[5, 7, 1]), tlv(TAG_NAME, b"salt")].concat();
+                    if *self.locked.borrow() {
+                        response.extend([tlv(TAG_CHALLENGE, b"device"), tlv(TAG_ALGORITHM, &[0x01])].concat());
+                    }
+                    Ok(response)
+                }
+                (INS_VALIDATE, _) => {
+                    let key = password_key("password", b"salt");
+                    if value(TAG_RESPONSE).unwrap() != hmac(&key[..], b"device", Algorithm::Sha1) {
+                        return Err([0x69, 0x84]);
+                    }
+                    *self.locked.borrow_mut() = false;
+                    Ok(tlv(TAG_RESPONSE, &hmac(&key[..], &value(TAG_CHALLENGE).unwrap(), Algorithm::Sha1)))
+                }
+                (INS_LIST, _) => Ok(credentials.iter().flat_map(|c| tlv(TAG_NAME_LIST, &[&[c.1][..], &c.0].concat())).collect()),
+                (INS_PUT, _) => {
+                    let name = value(TAG_NAME).unwrap();
+                    let key = value(TAG_KEY).unwrap();
+                    let counter = value(TAG_IMF).map_or(0, |imf| u32::from_be_bytes(imf[..].try_into().unwrap()) as u64);
+                    if let Some(i) = position(&name) {
+                        credentials.remove(i);
+                    }
+                    credentials.push((name, key[0], key[1], key[2..].to_vec(), touch, counter));
+                    Ok(Vec::new())
+                }
+                (INS_DELETE, _) => {
+                    let i = position(&value(TAG_NAME).unwrap()).ok_or([0x69, 0x84])?;
+                    credentials.remove(i);
+                    Ok(Vec::new())
+                }
+                (INS_CALCULATE, _) => {
+                    let i = position(&value(TAG_NAME).unwrap()).ok_or([0x69, 0x84])?;
+                    let c = &mut credentials[i];
+                    if c.4 {
+                        // the test never touches the YubiKey
+                        return Err([0x69, 0x85]);
+                    }
+                    let challenge = if c.1 & 0xf0 == 0x10 {
+                        c.5 += 1;
+                        big_endian_u64(c.5 - 1).to_vec()
+                    } else {
+                        value(TAG_CHALLENGE).unwrap()
+                    };
+                    let hash = hmac(&c.3, &challenge, algorithm_of(c.1).unwrap());
+                    match p2 {
+                        0x01 => Ok(tlv(TAG_TRUNCATED, &[&[c.2][..], &dynamic_truncate(&hash).to_be_bytes()].concat())),
+                        _ => Ok(tlv(TAG_RESPONSE, &[&[c.2][..], &hash].concat())),
+                    }
+                }
+                (INS_CALCULATE_ALL, 0x00) => {
+                    let challenge = value(TAG_CHALLENGE).unwrap();
+                    Ok(credentials
+                        .iter()
+                        .flat_map(|c| {
+                            let result = if c.1 & 0xf0 == 0x10 {
+                                tlv(TAG_HOTP, &[c.2])
+                            } else if c.4 {
+                                tlv(TAG_TOUCH, &[c.2])
+                            } else {
+                                let hash = hmac(&c.3, &challenge, algorithm_of(c.1).unwrap());
+                                tlv(TAG_TRUNCATED, &[&[c.2][..], &dynamic_truncate(&hash).to_be_bytes()].concat())
+                            };
+                            [tlv(TAG_NAME, &c.0), result].concat()
+                        })
+                        .collect())
+                }
+                _ => Err([0x6d, 0x00]),
+            }
+        }
+    }
+
+    impl Transport for FakeApplet {
+        fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, OtpError> {
+            assert_eq!(apdu[4] as usize, apdu.len() - 5);
+            let (mut response, status) = match self.handle(apdu[1], apdu[2], apdu[3], &apdu[5..]) {
+                Ok(response) => (response, [0x90, 0x00]),
+                Err(status) => (Vec::new(), status),
+            };
+            response.extend_from_slice(&status);
+            Ok(response)
+        }
+    }
+
+    fn applet(locked: bool) -> YubiKeyOath {
+        YubiKeyOath::select(Box::new(FakeApplet { credentials: RefCell::new(Vec::new()), locked: RefCell::new(locked) })).unwrap()
+    }
+
+    fn account(uri: &str) -> Account {
+        Account::new(OtpAuthUri::parse(uri).unwrap())
+    }
+
+    #[test]
+    fn test_credentials() {
+        let oath = applet(false);
+        assert_eq!((oath.version(), oath.is_locked()), ((5, 7, 1), false));
+        // the secrets of RFC 4226 and RFC 6238
+        let alice = oath.put(&account("otpauth://totp/GitHub:alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&digits=8"), false).unwrap();
+        let bob = oath
+            .put(&account("otpauth://totp/bob?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA&algorithm=SHA256&period=60"), false)
+            .unwrap();
+        let carol = oath.put(&account("otpauth://hotp/Acme:carol?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=1"), false).unwrap();
+        let dave = oath.put(&account("otpauth://totp/Acme:dave?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"), true).unwrap();
+        assert_eq!(oath.list().unwrap(), [alice.clone(), bob.clone(), carol.clone(), dave.clone()]);
+        assert_eq!((bob.name.as_str(), bob.period, bob.algorithm, bob.issuer.as_deref()), ("60/bob", 60, Algorithm::Sha256, None));
+        assert_eq!((carol.display_label(), carol.otp_type), ("Acme: carol".to_string(), OtpType::Hotp));
+
+        let secret = b"12345678901234567890";
+        assert_eq!(oath.calculate(&alice, 59).unwrap(), "94287082");
+        assert_eq!(oath.calculate(&carol, 0).unwrap(), hotp(secret, 1, 6).unwrap());
+        assert_eq!(oath.calculate(&carol, 0).unwrap(), hotp(secret, 2, 6).unwrap());
+        assert!(matches!(oath.calculate(&dave, 59), Err(OtpError::YubiKeyError(msg)) if msg.contains("touched")));
+
+        let codes = oath.calculate_all(1111111109).unwrap();
+        let bob_code = totp_at_with_algorithm(b"12345678901234567890123456789012", 1111111109, 0, 60, 6, Algorithm::Sha256).unwrap();
+        assert_eq!(codes[0], (alice.clone(), Some(totp_at(secret, 1111111109, 0, 30, 8).unwrap())));
+        assert_eq!(codes[1], (bob.clone(), Some(bob_code)));
+        assert_eq!((codes[2].1.clone(), codes[3].1.clone()), (None, None));
+
+        assert_eq!(totp_at_with_backend(&oath.key(&alice), 59, 0, 30, 8).unwrap(), "94287082");
+        assert!(totp_at_with_backend(&oath.key(&carol), 59, 0, 30, 6).is_err());
+
+        oath.delete(&bob).unwrap();
+        assert_eq!(oath.list().unwrap().len(), 3);
+        assert!(oath.delete(&bob).is_err());
+        assert!(oath.put(&account("otpauth://totp/alice?secret=GEZDGNBVGY3TQOJQ&digits=10"), false).is_err());
+    }
+
+    #[test]
+    fn test_zero_period() {
+        let oath = applet(false);
+        let eve = oath.put(&account("otpauth://totp/0%2Feve?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"), false).unwrap();
+        assert_eq!((eve.name.as_str(), eve.period), ("0/eve", 30));
+        let codes = oath.calculate_all(59).unwrap();
+        assert_eq!(codes, [(eve, Some(totp_at(b"12345678901234567890", 59, 0, 30, 6).unwrap()))]);
+    }
+
+    #[test]
+    fn test_password() {
+        let mut oath = applet(true);
+        assert!(oath.is_locked());
+        assert!(matches!(oath.list(), Err(OtpError::YubiKeyError(msg)) if msg.contains("locked")));
+        assert_eq!(oath.unlock("wrong"), Err(OtpError::YubiKeyError("wrong password".into())));
+        assert!(oath.is_locked());
+        oath.unlock("password").unwrap();
+        assert!(!oath.is_locked());
+        assert_eq!(oath.list().unwrap(), []);
+    }
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(shorten_key(&[1; 10], Algorithm::Sha1).len(), MIN_KEY_LEN);
+        assert_eq!(hmac(&shorten_key(&[1; 10], Algorithm::Sha1), b"message", Algorithm::Sha1), hmac(&[1; 10], b"message", Algorithm::Sha1));
+        assert_eq!(hmac(&shorten_key(&[1; 100], Algorithm::Sha256), b"message", Algorithm::Sha256), hmac(&[1; 100], b"message", Algorithm::Sha256));
+        assert_eq!(tlv(TAG_NAME, &[0; 200])[..3], [TAG_NAME, 0x81, 200]);
+        assert_eq!(parse_tlvs(&[0x71, 0x81, 0x02, 1, 2, 0x72, 0x00]).unwrap(), [(0x71, &[1, 2][..]), (0x72, &[][..])]);
+        assert!(parse_tlvs(&[0x71, 0x05, 1]).is_err());
+        let credential = OathCredential::parse("15/Acme:alice:work", OtpType::Totp, Algorithm::Sha1);
+        assert_eq!((credential.period, credential.issuer.as_deref(), credential.account.as_str()), (15, Some("Acme"), "alice:work"));
+        let credential = OathCredential::parse("0/alice", OtpType::Totp, Algorithm::Sha1);
+        assert_eq!((credential.period, credential.account.as_str()), (30, "0/alice"));
+        // HOTP names have no period
+        let credential = OathCredential::parse("15/alice", OtpType::Hotp, Algorithm::Sha1);
+        assert_eq!((credential.period, credential.issuer, credential.account.as_str()), (30, None, "15/alice"));
+    }
+}
@@ -0,0 +1,93 @@
+/*
+Copyright 2023, Mengxiao Lin <linmx0130@gmail.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Procedural macros of yOTP. Use them through the `macros` feature of
+//! `yotp-core`.
+
+use proc_macro::{TokenStream, TokenTree};
+
+const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Decode a Base 32 secret literal at compile time into a
+/// `&'static [u8]`, so that a malformed secret fails the build.
+///
+/// Lower case letters, whitespaces and the padding are accepted as by
+/// `yotp_core::base32::decode_lenient`.
+#[proc_macro]
+pub fn totp_secret(input: TokenStream) -> TokenStream {
+    match expand(input) {
+        Ok(bytes) => {
+            let items: Vec<String> = bytes.iter().map(|b| format!("{:#04x}u8", b)).collect();
+            format!("{{ const SECRET: &[u8] = &[{}]; SECRET }}", items.join(", ")).parse().unwrap()
+        }
+        Err(message) => format!("::core::compile_error!({:?})", message).parse().unwrap(),
+    }
+}
+
+fn expand(input: TokenStream) -> Result<Vec<u8>, String> {
+    let mut tokens = input.into_iter();
+    let literal = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => literal.to_string(),
+        _ => return Err("totp_secret! expects a single string literal".to_owned()),
+    };
+    let value = literal
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or("totp_secret! expects a plain string literal")?;
+    decode(value)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut bits = 0u32;
+    let mut bit_len = 0;
+    let data = value.split('=').next().unwrap_or("");
+    if value[data.len()..].chars().any(|c| c != '=') {
+        return Err("invalid Base 32 secret: characters after the padding".to_owned());
+    }
+    for c in data.chars().filter(|c| !c.is_whitespace() && *c != '-') {
+        let v = ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())
+            .ok_or_else(|| format!("invalid Base 32 character {:?} in secret", c))?;
+        bits = (bits << 5) | v as u32;
+        bit_len += 5;
+        if bit_len >= 8 {
+            bit_len -= 8;
+            bytes.push((bits >> bit_len) as u8);
+            bits &= (1 << bit_len) - 1;
+        }
+    }
+    if bytes.is_empty() {
+        return Err("the secret should not be empty".to_owned());
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::decode;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("JBSWY3DPEHPK3PXP").unwrap(), b"Hello!\xde\xad\xbe\xef");
+        assert_eq!(decode("jbsw y3dp ehpk 3pxp").unwrap(), b"Hello!\xde\xad\xbe\xef");
+        assert_eq!(decode("MZXW6YQ=").unwrap(), b"foob");
+        assert!(decode("JBSWY3DP1").unwrap_err().contains("'1'"));
+        assert!(decode("MZXW6YQ=M").is_err());
+        assert!(decode("").is_err());
+    }
+}